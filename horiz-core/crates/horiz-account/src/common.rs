@@ -0,0 +1,330 @@
+// --- アカウント管理コマンドの共通処理 ---
+
+use std::fs;
+use std::io;
+use std::os::unix::fs::{lchown, MetadataExt, PermissionsExt};
+use std::path::Path;
+
+use horiz_auth::config;
+use horiz_auth::group::GroupEntry;
+use horiz_auth::userdb::Transaction;
+
+pub const CONFIG_PATH: &str = "/etc/horiz/account.conf";
+
+/// /etc/horiz/account.conf の設定
+///
+/// ```text
+/// uid_min=1000        # 一般ユーザーの UID の範囲
+/// uid_max=60000
+/// sys_uid_min=100     # システムアカウント (-r) の UID の範囲
+/// sys_uid_max=999
+/// gid_min=1000        # グループの GID の範囲
+/// gid_max=60000
+/// sys_gid_min=100
+/// sys_gid_max=999
+/// home_base=/home     # ホームディレクトリの親
+/// shell=/bin/sh       # 既定のログインシェル
+/// skel=/etc/skel      # ホームディレクトリの雛形
+/// create_home=yes     # useradd で -m を省略してもホームを作成する
+/// home_mode=0700
+/// pass_min_days=0     # 新規ユーザーのパスワード有効期限
+/// pass_max_days=99999
+/// pass_warn_days=7
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountConfig {
+    pub uid_range: (u32, u32),
+    pub sys_uid_range: (u32, u32),
+    pub gid_range: (u32, u32),
+    pub sys_gid_range: (u32, u32),
+    pub home_base: String,
+    pub shell: String,
+    pub skel: String,
+    pub create_home: bool,
+    pub home_mode: u32,
+    pub pass_min_days: i64,
+    pub pass_max_days: i64,
+    pub pass_warn_days: i64,
+}
+
+impl Default for AccountConfig {
+    fn default() -> Self {
+        AccountConfig {
+            uid_range: (1000, 60000),
+            sys_uid_range: (100, 999),
+            gid_range: (1000, 60000),
+            sys_gid_range: (100, 999),
+            home_base: "/home".to_string(),
+            shell: "/bin/sh".to_string(),
+            skel: "/etc/skel".to_string(),
+            create_home: true,
+            home_mode: 0o700,
+            pass_min_days: 0,
+            pass_max_days: 99999,
+            pass_warn_days: 7,
+        }
+    }
+}
+
+impl AccountConfig {
+    pub fn load() -> Self {
+        Self::from_config(&config::load_key_values(CONFIG_PATH))
+    }
+
+    /// 未指定・不正な値は既定値を使う
+    pub fn from_config(pairs: &[(String, String)]) -> Self {
+        let mut cfg = Self::default();
+        let num = |key: &str| config::get(pairs, key).and_then(|v| v.parse::<u32>().ok());
+        let range = |min: &str, max: &str, default: (u32, u32)| {
+            let range = (num(min).unwrap_or(default.0), num(max).unwrap_or(default.1));
+            if range.0 <= range.1 { range } else { default }
+        };
+        cfg.uid_range = range("uid_min", "uid_max", cfg.uid_range);
+        cfg.sys_uid_range = range("sys_uid_min", "sys_uid_max", cfg.sys_uid_range);
+        cfg.gid_range = range("gid_min", "gid_max", cfg.gid_range);
+        cfg.sys_gid_range = range("sys_gid_min", "sys_gid_max", cfg.sys_gid_range);
+
+        if let Some(v) = config::get(pairs, "home_base") { cfg.home_base = v.trim_end_matches('/').to_string(); }
+        if let Some(v) = config::get(pairs, "shell") { cfg.shell = v.to_string(); }
+        if let Some(v) = config::get(pairs, "skel") { cfg.skel = v.to_string(); }
+        if let Some(v) = config::get(pairs, "create_home") { cfg.create_home = v == "yes"; }
+        if let Some(v) = config::get(pairs, "home_mode").and_then(|v| u32::from_str_radix(v, 8).ok()) {
+            cfg.home_mode = v & 0o7777;
+        }
+        let days = |key: &str| config::get(pairs, key).and_then(|v| v.parse::<i64>().ok());
+        if let Some(v) = days("pass_min_days") { cfg.pass_min_days = v; }
+        if let Some(v) = days("pass_max_days") { cfg.pass_max_days = v; }
+        if let Some(v) = days("pass_warn_days") { cfg.pass_warn_days = v; }
+        cfg
+    }
+}
+
+// --- カスタム引数パーサー (getopt 相当) ---
+
+pub struct Opts {
+    /// 指定されたオプションとその値 (値を取らないオプションは空文字列)
+    pub options: Vec<(char, String)>,
+    pub operands: Vec<String>,
+}
+
+impl Opts {
+    pub fn has(&self, c: char) -> bool {
+        self.options.iter().any(|(o, _)| *o == c)
+    }
+
+    /// 最後に指定された値
+    pub fn value(&self, c: char) -> Option<&str> {
+        self.options.iter().rev().find(|(o, _)| *o == c).map(|(_, v)| v.as_str())
+    }
+}
+
+/// `-m` / `-u 1000` / `-u1000` / `-mr` 形式の引数を解析する。
+/// `with_value` は値を取るオプション、`flags` は値を取らないオプションの文字の並び。
+pub fn getopt(args: &[String], with_value: &str, flags: &str) -> Result<Opts, String> {
+    let mut options = Vec::new();
+    let mut operands = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = &args[i];
+        i += 1;
+        if arg == "--" {
+            operands.extend(args[i..].iter().cloned());
+            break;
+        }
+        let Some(chars) = arg.strip_prefix('-').filter(|s| !s.is_empty()) else {
+            operands.push(arg.clone());
+            continue;
+        };
+        for (pos, c) in chars.char_indices() {
+            if flags.contains(c) {
+                options.push((c, String::new()));
+            } else if with_value.contains(c) {
+                let rest = &chars[pos + c.len_utf8()..];
+                let value = if !rest.is_empty() {
+                    rest.to_string()
+                } else if i < args.len() {
+                    i += 1;
+                    args[i - 1].clone()
+                } else {
+                    return Err(format!("Missing value for -{}", c));
+                };
+                options.push((c, value));
+                break;
+            } else {
+                return Err(format!("Unknown argument: -{}", c));
+            }
+        }
+    }
+    Ok(Opts { options, operands })
+}
+
+/// ユーザー名・グループ名として使用できるか (shadow-utils の既定の規則)
+pub fn is_valid_name(name: &str) -> bool {
+    let body = name.strip_suffix('$').unwrap_or(name);
+    let mut chars = body.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_lowercase() || c == '_' => {}
+        _ => return false,
+    }
+    name.len() <= 32 && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+/// `/etc/passwd` の GECOS・ホーム・シェルに使用できるか
+pub fn is_valid_field(value: &str) -> bool {
+    !value.contains([':', '\n'])
+}
+
+/// 範囲内で未使用の ID を割り当てる。使用中の最大値の次を優先し、範囲の上限に達した場合は空きを探す。
+pub fn allocate_id(used: &[u32], (min, max): (u32, u32)) -> Option<u32> {
+    let highest = used.iter().copied().filter(|id| (min..=max).contains(id)).max();
+    match highest {
+        None => Some(min),
+        Some(h) if h < max => Some(h + 1),
+        Some(_) => (min..=max).find(|id| !used.contains(id)),
+    }
+}
+
+/// グループ名または GID を解決する
+pub fn resolve_group<'a>(tx: &'a Transaction, spec: &str) -> Option<&'a GroupEntry> {
+    match spec.parse::<u32>() {
+        Ok(gid) => tx.group.by_gid(gid),
+        Err(_) => tx.group.get(spec),
+    }
+}
+
+/// `YYYY-MM-DD` を 1970-01-01 からの日数に変換する
+pub fn parse_date(s: &str) -> Option<i64> {
+    let mut parts = s.splitn(3, '-');
+    let y: i64 = parts.next()?.parse().ok()?;
+    let m: i64 = parts.next()?.parse().ok()?;
+    let d: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+    // civil_from_days の逆変換
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = (m + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some(era * 146_097 + doe - 719_468)
+}
+
+/// `src` (スケルトン) を `dst` に再帰的にコピーし、すべて uid:gid の所有とする
+pub fn copy_skel(src: &Path, dst: &Path, uid: u32, gid: u32) -> io::Result<()> {
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let from = entry.path();
+        let to = dst.join(entry.file_name());
+        let meta = fs::symlink_metadata(&from)?;
+        let file_type = meta.file_type();
+        if file_type.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(&from)?, &to)?;
+        } else if file_type.is_dir() {
+            fs::create_dir(&to)?;
+            copy_skel(&from, &to, uid, gid)?;
+            fs::set_permissions(&to, fs::Permissions::from_mode(meta.mode() & 0o7777))?;
+        } else if file_type.is_file() {
+            fs::copy(&from, &to)?;
+        } else {
+            // デバイスファイル等はコピーしない
+            continue;
+        }
+        lchown(&to, Some(uid), Some(gid))?;
+    }
+    Ok(())
+}
+
+/// ホームディレクトリを作成し、スケルトンをコピーする
+pub fn create_home(home: &Path, skel: &Path, uid: u32, gid: u32, mode: u32) -> io::Result<()> {
+    fs::create_dir_all(home.parent().unwrap_or(Path::new("/")))?;
+    fs::create_dir(home)?;
+    std::os::unix::fs::chown(home, Some(uid), Some(gid))?;
+    if skel.is_dir() {
+        copy_skel(skel, home, uid, gid)?;
+    }
+    fs::set_permissions(home, fs::Permissions::from_mode(mode))
+}
+
+/// `dir` 以下で `old_uid` が所有するファイルの所有者を `new_uid` に変更する
+pub fn chown_tree(dir: &Path, old_uid: u32, new_uid: u32) -> io::Result<()> {
+    let meta = fs::symlink_metadata(dir)?;
+    if meta.uid() == old_uid {
+        lchown(dir, Some(new_uid), None)?;
+    }
+    if meta.file_type().is_dir() {
+        for entry in fs::read_dir(dir)? {
+            chown_tree(&entry?.path(), old_uid, new_uid)?;
+        }
+    }
+    Ok(())
+}
+
+/// ホームディレクトリとして削除・移動してよいか。
+/// `/` 直下のディレクトリ (`/usr`, `/home` など) と `uid` 以外が所有するディレクトリは対象外とする。
+pub fn is_removable_home(home: &Path, uid: u32) -> bool {
+    let Ok(canonical) = home.canonicalize() else { return false; };
+    let Ok(meta) = fs::metadata(&canonical) else { return false; };
+    canonical.components().count() > 2 && meta.is_dir() && meta.uid() == uid
+}
+
+/// テスト用の passwd / group / shadow を一時ディレクトリに用意する
+#[cfg(test)]
+pub fn test_dir(name: &str) -> horiz_auth::testutil::TempDir {
+    let dir = horiz_auth::testutil::TempDir::new(&format!("account-{}", name));
+    fs::write(dir.join("passwd"), "root:x:0:0:root:/root:/bin/sh\nhoriz:x:1000:1000::/home/horiz:/bin/sh\n").unwrap();
+    fs::write(dir.join("group"), "root:x:0:\nwheel:x:10:root\nhoriz:x:1000:\n").unwrap();
+    fs::write(dir.join("shadow"), "root:!:19000:0:99999:7:::\nhoriz:!:19000:0:99999:7:::\n").unwrap();
+    dir
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_getopt() {
+        let opts = getopt(&args(&["-mr", "-u1001", "-G", "wheel,audio", "alice"]), "uG", "mr").unwrap();
+        assert!(opts.has('m') && opts.has('r'));
+        assert_eq!(opts.value('u'), Some("1001"));
+        assert_eq!(opts.value('G'), Some("wheel,audio"));
+        assert_eq!(opts.operands, vec!["alice"]);
+        assert!(getopt(&args(&["-x"]), "u", "m").is_err());
+        assert!(getopt(&args(&["-u"]), "u", "m").is_err());
+    }
+
+    #[test]
+    fn test_names_ids_and_dates() {
+        assert!(is_valid_name("alice"));
+        assert!(is_valid_name("_svc-1"));
+        assert!(is_valid_name("machine$"));
+        assert!(!is_valid_name("Alice"));
+        assert!(!is_valid_name("1user"));
+        assert!(!is_valid_name("a:b"));
+        assert!(!is_valid_name(""));
+
+        assert_eq!(allocate_id(&[0, 1000, 1001], (1000, 60000)), Some(1002));
+        assert_eq!(allocate_id(&[0], (1000, 60000)), Some(1000));
+        assert_eq!(allocate_id(&[1000, 1002], (1000, 1002)), Some(1001));
+        assert_eq!(allocate_id(&[1000, 1001], (1000, 1001)), None);
+
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2022-01-08"), Some(19000));
+        assert_eq!(parse_date("2022-13-01"), None);
+    }
+
+    #[test]
+    fn test_config() {
+        let pairs = config::parse_key_values("uid_min=2000\nuid_max=100\nhome_base=/srv/home/\ncreate_home=no\nhome_mode=0750\n");
+        let cfg = AccountConfig::from_config(&pairs);
+        assert_eq!(cfg.uid_range, (1000, 60000));
+        assert_eq!(cfg.home_base, "/srv/home");
+        assert!(!cfg.create_home);
+        assert_eq!(cfg.home_mode, 0o750);
+    }
+}
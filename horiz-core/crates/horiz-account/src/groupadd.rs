@@ -0,0 +1,67 @@
+// --- groupadd: グループの追加 ---
+
+use horiz_auth::group::GroupEntry;
+use horiz_auth::userdb::Transaction;
+
+use crate::common::{self, AccountConfig};
+
+pub const USAGE: &str = "Usage: groupadd [-g GID] [-r] GROUP";
+
+/// group にグループを追加し、GID を返す (ファイルへの書き込みは `tx.commit()` で行う)
+pub fn apply(tx: &mut Transaction, name: &str, gid: Option<u32>, system: bool, cfg: &AccountConfig) -> Result<u32, String> {
+    if !common::is_valid_name(name) {
+        return Err(format!("不正なグループ名: {}", name));
+    }
+    if tx.group.get(name).is_some() {
+        return Err(format!("グループ {} は既に存在します", name));
+    }
+    let used: Vec<u32> = tx.group.entries().map(|g| g.gid).collect();
+    let gid = match gid {
+        Some(gid) if used.contains(&gid) => return Err(format!("GID {} は既に使用されています", gid)),
+        Some(gid) => gid,
+        None => {
+            let range = if system { cfg.sys_gid_range } else { cfg.gid_range };
+            common::allocate_id(&used, range).ok_or("割り当て可能な GID がありません")?
+        }
+    };
+    tx.group.insert(GroupEntry { name: name.to_string(), password: "x".into(), gid, members: Vec::new() });
+    Ok(gid)
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let opts = common::getopt(args, "g", "r")?;
+    let [name] = opts.operands.as_slice() else {
+        return Err("グループ名を 1 つ指定してください".into());
+    };
+    let gid = match opts.value('g') {
+        Some(v) => Some(v.parse().map_err(|_| format!("不正な GID: {}", v))?),
+        None => None,
+    };
+
+    let mut tx = Transaction::begin().map_err(|e| e.to_string())?;
+    let gid = apply(&mut tx, name, gid, opts.has('r'), &AccountConfig::load())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    crate::audit_log(&format!("groupadd: new group: name={}, gid={}", name, gid));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_add_group() {
+        let dir = common::test_dir("groupadd");
+        let cfg = AccountConfig::default();
+
+        let mut tx = Transaction::begin_in(&dir).unwrap();
+        assert_eq!(apply(&mut tx, "staff", None, false, &cfg), Ok(1001));
+        assert_eq!(apply(&mut tx, "daemon", None, true, &cfg), Ok(100));
+        assert!(apply(&mut tx, "staff", None, false, &cfg).is_err());
+        assert!(apply(&mut tx, "other", Some(10), false, &cfg).is_err());
+        assert!(apply(&mut tx, "Bad", None, false, &cfg).is_err());
+        tx.commit().unwrap();
+        assert!(fs::read_to_string(dir.join("group")).unwrap().ends_with("staff:x:1001:\ndaemon:x:100:\n"));
+    }
+}
@@ -0,0 +1,56 @@
+// --- horiz-account: アカウント管理コマンド (useradd / userdel / usermod / groupadd) ---
+//
+// horiz-utils と同様のマルチコールバイナリで、呼び出された名前 (argv[0]) でコマンドを切り替える。
+
+mod common;
+mod groupadd;
+mod useradd;
+mod userdel;
+mod usermod;
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use horiz_auth::audit::audit_log;
+
+type Command = fn(&[String]) -> Result<(), String>;
+
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let mut cmd = args
+        .first()
+        .and_then(|arg0| Path::new(arg0).file_name())
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // `horiz-account useradd ...` の形式でも呼び出せる
+    if cmd == "horiz-account" && args.len() > 1 {
+        cmd = args.remove(1);
+    }
+    let args = &args[1..];
+
+    let (usage, run): (&str, Command) = match cmd.as_str() {
+        "useradd" => (useradd::USAGE, useradd::run),
+        "userdel" => (userdel::USAGE, userdel::run),
+        "usermod" => (usermod::USAGE, usermod::run),
+        "groupadd" => (groupadd::USAGE, groupadd::run),
+        _ => {
+            eprintln!("Unknown command: {}", cmd);
+            eprintln!("Usage: horiz-account (useradd | userdel | usermod | groupadd) [OPTIONS]");
+            process::exit(2);
+        }
+    };
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", usage);
+        return;
+    }
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("{}: 権限がありません (root で実行してください)", cmd);
+        process::exit(1);
+    }
+    if let Err(e) = run(args) {
+        eprintln!("{}: {}", cmd, e);
+        eprintln!("{}", usage);
+        process::exit(1);
+    }
+}
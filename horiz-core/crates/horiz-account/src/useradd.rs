@@ -0,0 +1,243 @@
+// --- useradd: ユーザーの追加 ---
+
+use std::path::Path;
+
+use horiz_auth::group::GroupEntry;
+use horiz_auth::passwd::PasswdEntry;
+use horiz_auth::shadow::ShadowEntry;
+use horiz_auth::userdb::Transaction;
+
+use crate::common::{self, AccountConfig};
+
+pub const USAGE: &str =
+    "Usage: useradd [-u UID] [-g GROUP] [-G GROUP,...] [-d HOME] [-s SHELL] [-c COMMENT] [-k SKEL] [-m | -M] [-r] USER";
+
+#[derive(Debug, Default)]
+pub struct Request {
+    pub name: String,
+    pub uid: Option<u32>,
+    /// 主グループ (省略時はユーザー名と同名のグループを作成する)
+    pub group: Option<String>,
+    pub groups: Vec<String>,
+    pub home: Option<String>,
+    pub shell: Option<String>,
+    pub comment: String,
+    pub skel: Option<String>,
+    /// `-m` / `-M` の指定 (省略時は設定ファイルに従う)
+    pub create_home: Option<bool>,
+    /// `-r`: システムアカウント
+    pub system: bool,
+}
+
+pub fn parse_args(args: &[String]) -> Result<Request, String> {
+    let opts = common::getopt(args, "ugGdsck", "mMr")?;
+    let [name] = opts.operands.as_slice() else {
+        return Err("ユーザー名を 1 つ指定してください".into());
+    };
+    if opts.has('m') && opts.has('M') {
+        return Err("-m と -M は同時に指定できません".into());
+    }
+    let uid = match opts.value('u') {
+        Some(v) => Some(v.parse().map_err(|_| format!("不正な UID: {}", v))?),
+        None => None,
+    };
+    Ok(Request {
+        name: name.clone(),
+        uid,
+        group: opts.value('g').map(str::to_string),
+        groups: opts.value('G').map(split_groups).unwrap_or_default(),
+        home: opts.value('d').map(str::to_string),
+        shell: opts.value('s').map(str::to_string),
+        comment: opts.value('c').unwrap_or("").to_string(),
+        skel: opts.value('k').map(str::to_string),
+        create_home: if opts.has('m') { Some(true) } else if opts.has('M') { Some(false) } else { None },
+        system: opts.has('r'),
+    })
+}
+
+/// `-G a,b,c` の分割
+pub fn split_groups(list: &str) -> Vec<String> {
+    list.split(',').filter(|g| !g.is_empty()).map(str::to_string).collect()
+}
+
+/// 補助グループのメンバーに `user` を追加する (存在しないグループがあれば何も変更しない)
+pub fn add_to_groups(tx: &mut Transaction, user: &str, groups: &[String]) -> Result<(), String> {
+    let mut names = Vec::new();
+    for spec in groups {
+        let group = common::resolve_group(tx, spec).ok_or_else(|| format!("グループ {} は存在しません", spec))?;
+        names.push(group.name.clone());
+    }
+    for name in names {
+        let group = tx.group.get_mut(&name).expect("resolved above");
+        if !group.members.iter().any(|m| m == user) {
+            group.members.push(user.to_string());
+        }
+    }
+    Ok(())
+}
+
+/// 追加したユーザーの情報
+#[derive(Debug, PartialEq, Eq)]
+pub struct Created {
+    pub uid: u32,
+    pub gid: u32,
+    pub home: String,
+}
+
+/// passwd / group / shadow にユーザーを追加する (ファイルへの書き込みは `tx.commit()` で行う)
+pub fn apply(tx: &mut Transaction, req: &Request, cfg: &AccountConfig, today: i64) -> Result<Created, String> {
+    if !common::is_valid_name(&req.name) {
+        return Err(format!("不正なユーザー名: {}", req.name));
+    }
+    if tx.passwd.get(&req.name).is_some() {
+        return Err(format!("ユーザー {} は既に存在します", req.name));
+    }
+    let home = req.home.clone().unwrap_or_else(|| format!("{}/{}", cfg.home_base, req.name));
+    let shell = req.shell.clone().unwrap_or_else(|| cfg.shell.clone());
+    for value in [&req.comment, &home, &shell] {
+        if !common::is_valid_field(value) {
+            return Err(format!("使用できない文字が含まれています: {:?}", value));
+        }
+    }
+    // 変更を始める前に補助グループの存在を確認する
+    if let Some(spec) = req.groups.iter().find(|spec| common::resolve_group(tx, spec).is_none()) {
+        return Err(format!("グループ {} は存在しません", spec));
+    }
+
+    let used_uids: Vec<u32> = tx.passwd.entries().map(|e| e.uid).collect();
+    let uid = match req.uid {
+        Some(uid) if used_uids.contains(&uid) => return Err(format!("UID {} は既に使用されています", uid)),
+        Some(uid) => uid,
+        None => {
+            let range = if req.system { cfg.sys_uid_range } else { cfg.uid_range };
+            common::allocate_id(&used_uids, range).ok_or("割り当て可能な UID がありません")?
+        }
+    };
+
+    let gid = match &req.group {
+        Some(spec) => common::resolve_group(tx, spec).ok_or_else(|| format!("グループ {} は存在しません", spec))?.gid,
+        None => {
+            // ユーザー名と同名の主グループを作成する (可能なら GID = UID とする)
+            if tx.group.get(&req.name).is_some() {
+                return Err(format!("グループ {} は既に存在します。-g で主グループを指定してください", req.name));
+            }
+            let used_gids: Vec<u32> = tx.group.entries().map(|e| e.gid).collect();
+            let range = if req.system { cfg.sys_gid_range } else { cfg.gid_range };
+            let gid = if used_gids.contains(&uid) {
+                common::allocate_id(&used_gids, range).ok_or("割り当て可能な GID がありません")?
+            } else {
+                uid
+            };
+            tx.group.insert(GroupEntry { name: req.name.clone(), password: "x".into(), gid, members: Vec::new() });
+            gid
+        }
+    };
+    add_to_groups(tx, &req.name, &req.groups)?;
+
+    tx.passwd.insert(PasswdEntry {
+        name: req.name.clone(),
+        password: "x".into(),
+        uid,
+        gid,
+        gecos: req.comment.clone(),
+        home: home.clone(),
+        shell,
+    });
+    // パスワードは未設定 (ロック状態)。passwd で設定するまでログインできない。
+    // システムアカウントにはパスワードの有効期限を設けない。
+    let aging = |days: i64| if req.system { None } else { Some(days) };
+    tx.shadow.remove(&req.name);
+    tx.shadow.insert(ShadowEntry {
+        name: req.name.clone(),
+        password: "!".into(),
+        last_change: Some(today),
+        min_days: aging(cfg.pass_min_days),
+        max_days: aging(cfg.pass_max_days),
+        warn_days: aging(cfg.pass_warn_days),
+        inactive_days: None,
+        expire_date: None,
+        reserved: String::new(),
+    });
+    Ok(Created { uid, gid, home })
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let req = parse_args(args)?;
+    let cfg = AccountConfig::load();
+
+    let mut tx = Transaction::begin().map_err(|e| e.to_string())?;
+    let created = apply(&mut tx, &req, &cfg, horiz_auth::shadow::today())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    crate::audit_log(&format!("useradd: new user: name={}, uid={}, gid={}, home={}", req.name, created.uid, created.gid, created.home));
+
+    if req.create_home.unwrap_or(cfg.create_home && !req.system) {
+        let home = Path::new(&created.home);
+        if home.exists() {
+            eprintln!("useradd: ホームディレクトリ {} は既に存在します。スケルトンはコピーしません。", created.home);
+        } else {
+            let skel = req.skel.as_deref().unwrap_or(&cfg.skel);
+            common::create_home(home, Path::new(skel), created.uid, created.gid, cfg.home_mode)
+                .map_err(|e| format!("ホームディレクトリ {} を作成できません: {}", created.home, e))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_add_user_with_private_group() {
+        let dir = common::test_dir("useradd");
+        let cfg = AccountConfig::default();
+
+        let mut tx = Transaction::begin_in(&dir).unwrap();
+        let req = parse_args(&args(&["-G", "wheel", "-c", "Alice", "alice"])).unwrap();
+        let created = apply(&mut tx, &req, &cfg, 20000).unwrap();
+        assert_eq!(created, Created { uid: 1001, gid: 1001, home: "/home/alice".into() });
+        tx.commit().unwrap();
+
+        let passwd = fs::read_to_string(dir.join("passwd")).unwrap();
+        assert!(passwd.ends_with("alice:x:1001:1001:Alice:/home/alice:/bin/sh\n"));
+        let group = fs::read_to_string(dir.join("group")).unwrap();
+        assert_eq!(group, "root:x:0:\nwheel:x:10:root,alice\nhoriz:x:1000:\nalice:x:1001:\n");
+        let shadow = fs::read_to_string(dir.join("shadow")).unwrap();
+        assert!(shadow.ends_with("alice:!:20000:0:99999:7:::\n"));
+
+        // 重複・存在しないグループ・GID の衝突
+        let mut tx = Transaction::begin_in(&dir).unwrap();
+        assert!(apply(&mut tx, &parse_args(&args(&["alice"])).unwrap(), &cfg, 20000).is_err());
+        assert!(apply(&mut tx, &parse_args(&args(&["-G", "nogroup", "bob"])).unwrap(), &cfg, 20000).is_err());
+        tx.group.insert(GroupEntry { name: "staff".into(), password: "x".into(), gid: 1002, members: Vec::new() });
+        let created = apply(&mut tx, &parse_args(&args(&["-r", "bob"])).unwrap(), &cfg, 20000).unwrap();
+        assert_eq!((created.uid, created.gid), (100, 100));
+        let created = apply(&mut tx, &parse_args(&args(&["carol"])).unwrap(), &cfg, 20000).unwrap();
+        assert_eq!((created.uid, created.gid), (1002, 1003));
+        assert_eq!(tx.shadow.get("bob").unwrap().max_days, None);
+    }
+
+    #[test]
+    fn test_create_home_copies_skel() {
+        let dir = common::test_dir("useradd-home");
+        let skel = dir.join("skel");
+        fs::create_dir_all(skel.join(".config")).unwrap();
+        fs::write(skel.join(".profile"), "export PS1='$ '\n").unwrap();
+        fs::write(skel.join(".config/app"), "x=1\n").unwrap();
+
+        let (uid, gid) = unsafe { (libc::geteuid(), libc::getegid()) };
+        let home = dir.join("home/alice");
+        common::create_home(&home, &skel, uid, gid, 0o700).unwrap();
+        assert_eq!(fs::read_to_string(home.join(".profile")).unwrap(), "export PS1='$ '\n");
+        assert_eq!(fs::read_to_string(home.join(".config/app")).unwrap(), "x=1\n");
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&home).unwrap().permissions().mode() & 0o777, 0o700);
+        assert!(common::is_removable_home(&home, uid));
+        assert!(!common::is_removable_home(Path::new("/"), 0));
+    }
+}
@@ -0,0 +1,103 @@
+// --- userdel: ユーザーの削除 ---
+
+use std::fs;
+use std::path::Path;
+
+use horiz_auth::userdb::Transaction;
+
+use crate::common;
+
+pub const USAGE: &str = "Usage: userdel [-r] USER";
+
+/// 削除したユーザーの情報
+#[derive(Debug, PartialEq, Eq)]
+pub struct Removed {
+    pub uid: u32,
+    pub home: String,
+    /// 同時に削除した主グループ
+    pub group: Option<String>,
+}
+
+/// passwd / shadow / group からユーザーを削除する (ファイルへの書き込みは `tx.commit()` で行う)
+pub fn apply(tx: &mut Transaction, name: &str) -> Result<Removed, String> {
+    // 拒否する場合はトランザクションを変更しない
+    let user = tx.passwd.get(name).ok_or_else(|| format!("ユーザー {} は存在しません", name))?;
+    if user.uid == 0 {
+        return Err("UID 0 のユーザーは削除できません".into());
+    }
+    let user = tx.passwd.remove(name).expect("found above");
+    tx.shadow.remove(name);
+
+    let group_names: Vec<String> = tx.group.entries().map(|g| g.name.clone()).collect();
+    for group_name in group_names {
+        let group = tx.group.get_mut(&group_name).expect("listed above");
+        if group.members.iter().any(|m| m == name) {
+            group.members.retain(|m| m != name);
+        }
+    }
+
+    // 同名の主グループは、他のユーザーが使用していなければ削除する
+    let private_group = tx.group.get(name).filter(|g| {
+        g.gid == user.gid && g.members.is_empty() && !tx.passwd.entries().any(|u| u.gid == g.gid)
+    });
+    let group = match private_group {
+        Some(_) => tx.group.remove(name).map(|g| g.name),
+        None => None,
+    };
+    Ok(Removed { uid: user.uid, home: user.home, group })
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let opts = common::getopt(args, "", "r")?;
+    let [name] = opts.operands.as_slice() else {
+        return Err("ユーザー名を 1 つ指定してください".into());
+    };
+
+    let mut tx = Transaction::begin().map_err(|e| e.to_string())?;
+    let removed = apply(&mut tx, name)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    crate::audit_log(&format!("userdel: delete user: name={}, uid={}", name, removed.uid));
+    if let Some(group) = &removed.group {
+        crate::audit_log(&format!("userdel: delete group: name={}", group));
+    }
+
+    if opts.has('r') {
+        let home = Path::new(&removed.home);
+        if !home.exists() {
+            eprintln!("userdel: ホームディレクトリ {} は存在しません。", removed.home);
+        } else if !common::is_removable_home(home, removed.uid) {
+            return Err(format!("ホームディレクトリ {} は {} の所有ではないため削除しません", removed.home, name));
+        } else {
+            fs::remove_dir_all(home).map_err(|e| format!("ホームディレクトリ {} を削除できません: {}", removed.home, e))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_user_and_private_group() {
+        let dir = common::test_dir("userdel");
+        fs::write(dir.join("group"), "root:x:0:\nwheel:x:10:root,horiz\nhoriz:x:1000:\n").unwrap();
+
+        let mut tx = Transaction::begin_in(&dir).unwrap();
+        assert!(apply(&mut tx, "root").is_err());
+        assert!(tx.passwd.get("root").is_some() && tx.shadow.get("root").is_some());
+        assert!(apply(&mut tx, "nobody").is_err());
+        let removed = apply(&mut tx, "horiz").unwrap();
+        assert_eq!(removed, Removed { uid: 1000, home: "/home/horiz".into(), group: Some("horiz".into()) });
+        drop(tx);
+
+        // 主グループを他のユーザーも使っている場合は残す
+        fs::write(dir.join("passwd"), "root:x:0:0:root:/root:/bin/sh\nhoriz:x:1000:1000::/home/horiz:/bin/sh\nguest:x:1001:1000::/home/guest:/bin/sh\n").unwrap();
+        let mut tx = Transaction::begin_in(&dir).unwrap();
+        assert_eq!(apply(&mut tx, "horiz").unwrap().group, None);
+        tx.commit().unwrap();
+        assert_eq!(fs::read_to_string(dir.join("passwd")).unwrap(), "root:x:0:0:root:/root:/bin/sh\nguest:x:1001:1000::/home/guest:/bin/sh\n");
+        assert_eq!(fs::read_to_string(dir.join("group")).unwrap(), "root:x:0:\nwheel:x:10:root\nhoriz:x:1000:\n");
+        assert_eq!(fs::read_to_string(dir.join("shadow")).unwrap(), "root:!:19000:0:99999:7:::\n");
+    }
+}
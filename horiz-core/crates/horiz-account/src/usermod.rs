@@ -0,0 +1,251 @@
+// --- usermod: ユーザー情報の変更 ---
+
+use std::fs;
+use std::path::Path;
+
+use horiz_auth::userdb::Transaction;
+
+use crate::common;
+use crate::useradd;
+
+pub const USAGE: &str = "Usage: usermod [-c COMMENT] [-d HOME [-m]] [-g GROUP] [-G GROUP,... [-a]] [-s SHELL] [-u UID] [-l NEW_NAME] [-L | -U] [-e YYYY-MM-DD] USER";
+
+#[derive(Debug, Default)]
+pub struct Request {
+    pub name: String,
+    pub comment: Option<String>,
+    pub home: Option<String>,
+    /// `-m`: ホームディレクトリの内容を新しい場所に移動する
+    pub move_home: bool,
+    pub group: Option<String>,
+    pub groups: Option<Vec<String>>,
+    /// `-a`: `-G` のグループを既存の補助グループに追加する (省略時は置き換え)
+    pub append: bool,
+    pub shell: Option<String>,
+    pub uid: Option<u32>,
+    pub new_name: Option<String>,
+    pub lock: Option<bool>,
+    /// `-e`: アカウントの有効期限 (Some(None) で解除)
+    pub expire: Option<Option<i64>>,
+}
+
+pub fn parse_args(args: &[String]) -> Result<Request, String> {
+    let opts = common::getopt(args, "cdgGsule", "maLU")?;
+    let [name] = opts.operands.as_slice() else {
+        return Err("ユーザー名を 1 つ指定してください".into());
+    };
+    if opts.has('L') && opts.has('U') {
+        return Err("-L と -U は同時に指定できません".into());
+    }
+    if opts.has('m') && !opts.has('d') {
+        return Err("-m は -d と共に指定してください".into());
+    }
+    if opts.has('a') && !opts.has('G') {
+        return Err("-a は -G と共に指定してください".into());
+    }
+    let uid = match opts.value('u') {
+        Some(v) => Some(v.parse().map_err(|_| format!("不正な UID: {}", v))?),
+        None => None,
+    };
+    let expire = match opts.value('e') {
+        Some("") | Some("-1") => Some(None),
+        Some(v) => Some(Some(common::parse_date(v).ok_or_else(|| format!("不正な日付: {}", v))?)),
+        None => None,
+    };
+    Ok(Request {
+        name: name.clone(),
+        comment: opts.value('c').map(str::to_string),
+        home: opts.value('d').map(str::to_string),
+        move_home: opts.has('m'),
+        group: opts.value('g').map(str::to_string),
+        groups: opts.value('G').map(useradd::split_groups),
+        append: opts.has('a'),
+        shell: opts.value('s').map(str::to_string),
+        uid,
+        new_name: opts.value('l').map(str::to_string),
+        lock: if opts.has('L') { Some(true) } else if opts.has('U') { Some(false) } else { None },
+        expire,
+    })
+}
+
+/// 変更後の処理 (ホームディレクトリの移動・所有者の変更) に必要な情報
+#[derive(Debug, PartialEq, Eq)]
+pub struct Modified {
+    pub old_uid: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub old_home: String,
+    pub home: String,
+}
+
+/// passwd / shadow / group を変更する (ファイルへの書き込みは `tx.commit()` で行う)
+pub fn apply(tx: &mut Transaction, req: &Request) -> Result<Modified, String> {
+    let user = tx.passwd.get(&req.name).cloned().ok_or_else(|| format!("ユーザー {} は存在しません", req.name))?;
+    let mut updated = user.clone();
+
+    if let Some(comment) = &req.comment {
+        updated.gecos = comment.clone();
+    }
+    if let Some(home) = &req.home {
+        updated.home = home.clone();
+    }
+    if let Some(shell) = &req.shell {
+        updated.shell = shell.clone();
+    }
+    for value in [&updated.gecos, &updated.home, &updated.shell] {
+        if !common::is_valid_field(value) {
+            return Err(format!("使用できない文字が含まれています: {:?}", value));
+        }
+    }
+    if let Some(uid) = req.uid
+        && uid != user.uid {
+        if tx.passwd.by_uid(uid).is_some() {
+            return Err(format!("UID {} は既に使用されています", uid));
+        }
+        updated.uid = uid;
+    }
+    if let Some(spec) = &req.group {
+        updated.gid = common::resolve_group(tx, spec).ok_or_else(|| format!("グループ {} は存在しません", spec))?.gid;
+    }
+    if let Some(new_name) = &req.new_name
+        && *new_name != user.name {
+        if !common::is_valid_name(new_name) {
+            return Err(format!("不正なユーザー名: {}", new_name));
+        }
+        if tx.passwd.get(new_name).is_some() {
+            return Err(format!("ユーザー {} は既に存在します", new_name));
+        }
+        updated.name = new_name.clone();
+    }
+
+    // 補助グループ (名前の変更より先に、旧名で所属を整理する)
+    if let Some(groups) = &req.groups {
+        if let Some(spec) = groups.iter().find(|spec| common::resolve_group(tx, spec).is_none()) {
+            return Err(format!("グループ {} は存在しません", spec));
+        }
+        if !req.append {
+            let group_names: Vec<String> = tx.group.entries().map(|g| g.name.clone()).collect();
+            for group_name in group_names {
+                tx.group.get_mut(&group_name).expect("listed above").members.retain(|m| *m != user.name);
+            }
+        }
+        useradd::add_to_groups(tx, &user.name, groups)?;
+    }
+    if updated.name != user.name {
+        let group_names: Vec<String> = tx.group.entries().map(|g| g.name.clone()).collect();
+        for group_name in group_names {
+            for member in tx.group.get_mut(&group_name).expect("listed above").members.iter_mut() {
+                if *member == user.name {
+                    *member = updated.name.clone();
+                }
+            }
+        }
+    }
+
+    if let Some(shadow) = tx.shadow.get_mut(&user.name) {
+        shadow.name = updated.name.clone();
+        match req.lock {
+            Some(true) if !shadow.password.starts_with('!') => shadow.password.insert(0, '!'),
+            Some(false) => {
+                // passwd -u と同様、パスワードなしのアカウントになる解除は拒否する
+                let rest = shadow.password.trim_start_matches('!');
+                if rest.is_empty() {
+                    return Err("ロックを解除するとパスワードなしのアカウントになります".into());
+                }
+                shadow.password = rest.to_string();
+            }
+            _ => {}
+        }
+        if let Some(expire) = req.expire {
+            shadow.expire_date = expire;
+        }
+    } else if req.lock.is_some() || req.expire.is_some() {
+        return Err(format!("ユーザー {} はシャドウに存在しません", req.name));
+    }
+
+    let modified = Modified {
+        old_uid: user.uid,
+        uid: updated.uid,
+        gid: updated.gid,
+        old_home: user.home.clone(),
+        home: updated.home.clone(),
+    };
+    *tx.passwd.get_mut(&user.name).expect("looked up above") = updated;
+    Ok(modified)
+}
+
+pub fn run(args: &[String]) -> Result<(), String> {
+    let req = parse_args(args)?;
+
+    let mut tx = Transaction::begin().map_err(|e| e.to_string())?;
+    let modified = apply(&mut tx, &req)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    crate::audit_log(&format!("usermod: change user: name={}{}", req.name, match &req.new_name {
+        Some(new_name) => format!(", new name={}", new_name),
+        None => String::new(),
+    }));
+
+    let old_home = Path::new(&modified.old_home);
+    if req.move_home && modified.home != modified.old_home && old_home.exists() {
+        if !common::is_removable_home(old_home, modified.old_uid) {
+            return Err(format!("ホームディレクトリ {} は {} の所有ではないため移動しません", modified.old_home, req.name));
+        }
+        if Path::new(&modified.home).exists() {
+            return Err(format!("移動先 {} は既に存在します", modified.home));
+        }
+        fs::rename(old_home, &modified.home)
+            .map_err(|e| format!("ホームディレクトリを {} に移動できません: {}", modified.home, e))?;
+    }
+    let home = Path::new(&modified.home);
+    if modified.uid != modified.old_uid && home.is_dir() {
+        common::chown_tree(home, modified.old_uid, modified.uid)
+            .map_err(|e| format!("{} の所有者を変更できません: {}", modified.home, e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let req = parse_args(&args(&["-aG", "wheel,audio", "-e", "2022-01-08", "horiz"])).unwrap();
+        assert!(req.append);
+        assert_eq!(req.groups, Some(vec!["wheel".to_string(), "audio".to_string()]));
+        assert_eq!(req.expire, Some(Some(19000)));
+        assert_eq!(parse_args(&args(&["-e", "", "horiz"])).unwrap().expire, Some(None));
+        assert!(parse_args(&args(&["-m", "horiz"])).is_err());
+        assert!(parse_args(&args(&["-L", "-U", "horiz"])).is_err());
+    }
+
+    #[test]
+    fn test_modify_groups_rename_and_lock() {
+        let dir = common::test_dir("usermod");
+        fs::write(dir.join("group"), "root:x:0:\nwheel:x:10:root\naudio:x:29:horiz\nhoriz:x:1000:\n").unwrap();
+
+        let mut tx = Transaction::begin_in(&dir).unwrap();
+        apply(&mut tx, &parse_args(&args(&["-a", "-G", "wheel", "horiz"])).unwrap()).unwrap();
+        assert_eq!(tx.group.get("wheel").unwrap().members, vec!["root", "horiz"]);
+        assert_eq!(tx.group.get("audio").unwrap().members, vec!["horiz"]);
+
+        apply(&mut tx, &parse_args(&args(&["-G", "wheel", "horiz"])).unwrap()).unwrap();
+        assert!(tx.group.get("audio").unwrap().members.is_empty());
+
+        assert!(apply(&mut tx, &parse_args(&args(&["-u", "0", "horiz"])).unwrap()).is_err());
+        assert!(apply(&mut tx, &parse_args(&args(&["-l", "root", "horiz"])).unwrap()).is_err());
+        assert!(apply(&mut tx, &parse_args(&args(&["-U", "horiz"])).unwrap()).is_err());
+
+        let modified = apply(&mut tx, &parse_args(&args(&["-l", "hz", "-u", "1500", "-d", "/home/hz", "-L", "-e", "2022-01-08", "horiz"])).unwrap()).unwrap();
+        assert_eq!(modified, Modified { old_uid: 1000, uid: 1500, gid: 1000, old_home: "/home/horiz".into(), home: "/home/hz".into() });
+        tx.commit().unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join("passwd")).unwrap(), "root:x:0:0:root:/root:/bin/sh\nhz:x:1500:1000::/home/hz:/bin/sh\n");
+        assert_eq!(fs::read_to_string(dir.join("group")).unwrap(), "root:x:0:\nwheel:x:10:root,hz\naudio:x:29:\nhoriz:x:1000:\n");
+        assert!(fs::read_to_string(dir.join("shadow")).unwrap().ends_with("hz:!:19000:0:99999:7::19000:\n"));
+    }
+}
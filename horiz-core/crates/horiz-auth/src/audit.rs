@@ -0,0 +1,47 @@
+// --- 監査ログ (/var/log/audit.log) ---
+//
+// horiz-init と同じ `[時刻] [AUDIT] メッセージ` の形式で追記する。
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+use crate::time;
+
+pub const AUDIT_LOG_PATH: &str = "/var/log/audit.log";
+
+/// 監査ログに 1 行記録する。書き込めない場合は何もしない。
+pub fn audit_log(message: &str) {
+    append(Path::new(AUDIT_LOG_PATH), message, time::now());
+}
+
+/// シンボリックリンクになっている場合は、リンク先への意図せぬ書き込みを防ぐため書き込まない
+fn append(path: &Path, message: &str, ts: u64) {
+    if let Ok(metadata) = fs::symlink_metadata(path) && metadata.file_type().is_symlink() {
+        return;
+    }
+    if let Ok(mut f) = OpenOptions::new().create(true).append(true).open(path) {
+        let _ = writeln!(f, "[{}] [AUDIT] {}", ts, message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_append() {
+        let dir = TempDir::new("audit");
+        let path = dir.join("audit.log");
+        append(&path, "first", 100);
+        append(&path, "second", 200);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[100] [AUDIT] first\n[200] [AUDIT] second\n");
+
+        let target = dir.join("target");
+        let link = dir.join("link");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        append(&link, "evil", 300);
+        assert!(!target.exists());
+    }
+}
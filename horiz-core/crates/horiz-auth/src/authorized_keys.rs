@@ -0,0 +1,314 @@
+// --- 公開鍵による認証 (~/.horiz/authorized_keys) ---
+//
+// OpenSSH の authorized_keys と同じく 1 行に 1 つの公開鍵を記述する。扱うのは ssh-ed25519 の鍵のみ:
+//
+//   ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAI... comment
+//
+// 先頭にオプション (from=, command= など) のある行は、その制限を解釈できないため使用しない。
+// 他の種類の鍵の行と解析できない行も読み飛ばす。
+//
+// 呼び出し側 (ローカルのデーモンなど) が generate_challenge で作ったチャレンジに対して、次のいずれかの署名を受け付ける:
+//
+// - チャレンジそのものへの Ed25519 署名 (64 バイト)
+// - `ssh-keygen -Y sign -n <namespace>` による SSHSIG 形式の署名 (テキスト)。namespace が一致するもののみ有効。
+
+use std::fs;
+use std::io::{self, Read};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::error::AuthError;
+use crate::{base64_decode, base64_encode, ed25519, passwd, sha256, sha512};
+
+/// ホームディレクトリからの相対パス
+pub const AUTHORIZED_KEYS: &str = ".horiz/authorized_keys";
+pub const CHALLENGE_LEN: usize = 32;
+
+const KEY_TYPE: &str = "ssh-ed25519";
+const SSHSIG_MAGIC: &[u8] = b"SSHSIG";
+const SSHSIG_BEGIN: &str = "-----BEGIN SSH SIGNATURE-----";
+const SSHSIG_END: &str = "-----END SSH SIGNATURE-----";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey {
+    pub key: [u8; ed25519::PUBLIC_KEY_LEN],
+    pub comment: String,
+}
+
+impl PublicKey {
+    /// authorized_keys の 1 行を解析する
+    pub fn parse(line: &str) -> Option<Self> {
+        let line = line.trim();
+        let mut fields = line.split_whitespace();
+        if fields.next()? != KEY_TYPE {
+            return None;
+        }
+        let blob = base64_decode(fields.next()?)?;
+        let key = parse_key_blob(&blob)?;
+        Some(PublicKey { key, comment: fields.collect::<Vec<_>>().join(" ") })
+    }
+
+    /// SSH の公開鍵の形式 (string "ssh-ed25519", string key)
+    pub fn blob(&self) -> Vec<u8> {
+        let mut blob = Vec::new();
+        put_string(&mut blob, KEY_TYPE.as_bytes());
+        put_string(&mut blob, &self.key);
+        blob
+    }
+
+    /// `ssh-keygen -l` と同じ SHA256 フィンガープリント
+    pub fn fingerprint(&self) -> String {
+        format!("SHA256:{}", base64_encode(&sha256(&self.blob())).trim_end_matches('='))
+    }
+
+    /// `challenge` への署名を検証する。`namespace` は SSHSIG 形式の署名でのみ使う。
+    pub fn verify(&self, namespace: &str, challenge: &[u8], signature: &Signature) -> bool {
+        match signature {
+            Signature::Raw(sig) => ed25519::verify(&self.key, challenge, sig),
+            Signature::SshSig(sig) => {
+                if sig.key != self.key || sig.namespace.is_empty() || sig.namespace != namespace {
+                    return false;
+                }
+                let digest = match sig.hash_algorithm.as_str() {
+                    "sha256" => sha256(challenge).to_vec(),
+                    "sha512" => sha512::sha512(challenge).to_vec(),
+                    _ => return false,
+                };
+                let mut signed = SSHSIG_MAGIC.to_vec();
+                put_string(&mut signed, sig.namespace.as_bytes());
+                put_string(&mut signed, &sig.reserved);
+                put_string(&mut signed, sig.hash_algorithm.as_bytes());
+                put_string(&mut signed, &digest);
+                ed25519::verify(&self.key, &signed, &sig.signature)
+            }
+        }
+    }
+}
+
+/// 受け付ける署名の形式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Signature {
+    Raw([u8; ed25519::SIGNATURE_LEN]),
+    SshSig(SshSig),
+}
+
+/// `ssh-keygen -Y sign` の署名 (OpenSSH の PROTOCOL.sshsig)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SshSig {
+    pub key: [u8; ed25519::PUBLIC_KEY_LEN],
+    pub namespace: String,
+    reserved: Vec<u8>,
+    pub hash_algorithm: String,
+    signature: [u8; ed25519::SIGNATURE_LEN],
+}
+
+impl Signature {
+    /// 64 バイトの署名、または `-----BEGIN SSH SIGNATURE-----` で始まるテキストを解析する
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if let Ok(raw) = <[u8; ed25519::SIGNATURE_LEN]>::try_from(data) {
+            return Some(Signature::Raw(raw));
+        }
+        let text = std::str::from_utf8(data).ok()?.trim();
+        let body = text.strip_prefix(SSHSIG_BEGIN)?.strip_suffix(SSHSIG_END)?;
+        let blob = base64_decode(body)?;
+
+        let mut r = WireReader(&blob);
+        if r.bytes(SSHSIG_MAGIC.len())? != SSHSIG_MAGIC || r.u32()? != 1 {
+            return None;
+        }
+        let key = parse_key_blob(r.string()?)?;
+        let namespace = String::from_utf8(r.string()?.to_vec()).ok()?;
+        let reserved = r.string()?.to_vec();
+        let hash_algorithm = String::from_utf8(r.string()?.to_vec()).ok()?;
+        let mut inner = WireReader(r.string()?);
+        if !r.0.is_empty() || inner.string()? != KEY_TYPE.as_bytes() {
+            return None;
+        }
+        let signature = inner.string()?.try_into().ok()?;
+        if !inner.0.is_empty() {
+            return None;
+        }
+        Some(Signature::SshSig(SshSig { key, namespace, reserved, hash_algorithm, signature }))
+    }
+}
+
+/// SSH のワイヤー形式 (uint32 の長さ + データ) の読み取り
+struct WireReader<'a>(&'a [u8]);
+
+impl<'a> WireReader<'a> {
+    fn bytes(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.0.len() < n {
+            return None;
+        }
+        let (head, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Some(head)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn string(&mut self) -> Option<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+}
+
+fn put_string(out: &mut Vec<u8>, data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+fn parse_key_blob(blob: &[u8]) -> Option<[u8; ed25519::PUBLIC_KEY_LEN]> {
+    let mut r = WireReader(blob);
+    if r.string()? != KEY_TYPE.as_bytes() {
+        return None;
+    }
+    let key = r.string()?.try_into().ok()?;
+    r.0.is_empty().then_some(key)
+}
+
+/// 使用できる鍵の一覧 (解析できない行や他の種類の鍵は読み飛ばす)
+pub fn parse_keys(contents: &str) -> Vec<PublicKey> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .filter_map(PublicKey::parse)
+        .collect()
+}
+
+/// 所有者が `uid` または root で、グループ・他者が書き込めないことを確認する
+fn check_owner(path: &Path, uid: u32) -> Result<(), AuthError> {
+    let meta = fs::metadata(path)?;
+    if (meta.uid() != uid && meta.uid() != 0) || meta.mode() & 0o022 != 0 {
+        return Err(AuthError::InsecurePermissions { path: path.to_path_buf() });
+    }
+    Ok(())
+}
+
+/// `home` 以下の authorized_keys のパス
+pub fn path_for(home: &Path) -> PathBuf {
+    home.join(AUTHORIZED_KEYS)
+}
+
+/// `home` の authorized_keys を読み込む。ファイルがない場合は空とする。
+///
+/// 他のユーザーが鍵を追加できないよう、OpenSSH の StrictModes と同様にファイル・`~/.horiz`・ホームディレクトリの
+/// 所有者とパーミッションを確認する。
+pub fn load(home: &Path, uid: u32) -> Result<Vec<PublicKey>, AuthError> {
+    let path = path_for(home);
+    let contents = match fs::read_to_string(&path) {
+        Ok(c) => c,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    for p in path.ancestors().skip(1).take_while(|p| p.starts_with(home)) {
+        check_owner(p, uid)?;
+    }
+    check_owner(&path, uid)?;
+    Ok(parse_keys(&contents))
+}
+
+/// 1 回限りのチャレンジを生成する
+pub fn generate_challenge() -> io::Result<[u8; CHALLENGE_LEN]> {
+    let mut challenge = [0u8; CHALLENGE_LEN];
+    fs::File::open("/dev/urandom")?.read_exact(&mut challenge)?;
+    Ok(challenge)
+}
+
+/// `username` の authorized_keys のいずれかの鍵で `challenge` への署名を検証し、一致した鍵を返す。
+///
+/// 存在しないユーザーと鍵のないユーザーは `None` とする。チャレンジは呼び出し側が
+/// generate_challenge で毎回生成し、同じものを再び受け付けないようにすること。
+pub fn verify_user(username: &str, namespace: &str, challenge: &[u8], signature: &Signature) -> Result<Option<PublicKey>, AuthError> {
+    let Some(user) = passwd::getpwnam(username)? else {
+        return Ok(None);
+    };
+    let keys = load(Path::new(&user.home), user.uid)?;
+    Ok(keys.into_iter().find(|key| key.verify(namespace, challenge, signature)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    // ssh-keygen -t ed25519 -C horiz@test で生成した鍵と、チャレンジ "challenge-0123456789abcdef" への署名
+    const KEY_LINE: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIE6yfGdmq3YO9u3ZuXPNnTPOnYchg+WZLlYLwQZFuT+r horiz@test";
+    const CHALLENGE: &[u8] = b"challenge-0123456789abcdef";
+    const RAW_SIGNATURE: &str = "aPWfLN7aFIxwJX3JyDL/+btY+KfUqNMTcbEW5F/x+jO+euFZahv6qwGsK2tR0sTjfLE1tkN3+qla3TGzhW0lDg==";
+    // ssh-keygen -Y sign -n horiz-login (既定の sha512)
+    const SSHSIG_SHA512: &str = "-----BEGIN SSH SIGNATURE-----
+U1NIU0lHAAAAAQAAADMAAAALc3NoLWVkMjU1MTkAAAAgTrJ8Z2ardg727dm5c82dM86dhy
+GD5ZkuVgvBBkW5P6sAAAALaG9yaXotbG9naW4AAAAAAAAABnNoYTUxMgAAAFMAAAALc3No
+LWVkMjU1MTkAAABA5aJekx9kl6Iibd7kVf/X2JAwSAMuaWZgUO6lAkW4SEtThT3LiRAtum
+TDuUq3fcwruvM9SftxtP16B/YES9YoAA==
+-----END SSH SIGNATURE-----
+";
+    // ssh-keygen -Y sign -n horiz-login -O hashalg=sha256
+    const SSHSIG_SHA256: &str = "-----BEGIN SSH SIGNATURE-----
+U1NIU0lHAAAAAQAAADMAAAALc3NoLWVkMjU1MTkAAAAgTrJ8Z2ardg727dm5c82dM86dhy
+GD5ZkuVgvBBkW5P6sAAAALaG9yaXotbG9naW4AAAAAAAAABnNoYTI1NgAAAFMAAAALc3No
+LWVkMjU1MTkAAABAer0L9SGh34Bh6jLkhOPFPSZ1hMclXvDFy1BwREqVNGAK5EIvobazNK
+TIVf0HgriQJAdqh0vJs4iIRGLHV213Bg==
+-----END SSH SIGNATURE-----";
+
+    #[test]
+    fn test_parse_keys() {
+        let key = PublicKey::parse(KEY_LINE).unwrap();
+        assert_eq!(key.comment, "horiz@test");
+        assert_eq!(key.fingerprint(), "SHA256:jqCVUDZw3yKFbPmH51MbIxagW+HOQdBukXZ0AhXigjI");
+        assert_eq!(format!("{} {}", KEY_TYPE, base64_encode(&key.blob())), KEY_LINE.rsplit_once(' ').unwrap().0);
+
+        let contents = format!(
+            "# comment\n\nssh-rsa AAAAB3NzaC1yc2E= other\nfrom=\"10.0.0.1\" {line}\nssh-ed25519 AAAA broken\n{line}\n",
+            line = KEY_LINE
+        );
+        assert_eq!(parse_keys(&contents), vec![key]);
+    }
+
+    #[test]
+    fn test_verify_signatures() {
+        let key = PublicKey::parse(KEY_LINE).unwrap();
+        let raw = Signature::parse(&base64_decode(RAW_SIGNATURE).unwrap()).unwrap();
+        assert!(key.verify("horiz-login", CHALLENGE, &raw));
+        assert!(!key.verify("horiz-login", b"challenge-other", &raw));
+
+        for text in [SSHSIG_SHA512, SSHSIG_SHA256] {
+            let sig = Signature::parse(text.as_bytes()).unwrap();
+            assert!(key.verify("horiz-login", CHALLENGE, &sig));
+            // namespace が異なる用途の署名は受け付けない
+            assert!(!key.verify("file", CHALLENGE, &sig));
+            assert!(!key.verify("horiz-login", b"challenge-other", &sig));
+        }
+
+        let other = PublicKey { key: [1; 32], comment: String::new() };
+        assert!(!other.verify("horiz-login", CHALLENGE, &Signature::parse(SSHSIG_SHA512.as_bytes()).unwrap()));
+        assert!(Signature::parse(b"-----BEGIN SSH SIGNATURE-----\nAAAA\n-----END SSH SIGNATURE-----").is_none());
+        assert!(Signature::parse(b"short").is_none());
+    }
+
+    #[test]
+    fn test_load_checks_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let home = TempDir::new("authkeys");
+        // テストを実行しているユーザーの UID (libc に依存しないよう、作成したディレクトリの所有者から得る)
+        let uid = fs::metadata(&home).unwrap().uid();
+
+        fs::set_permissions(&home, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(load(&home, uid).unwrap().is_empty());
+
+        fs::create_dir_all(home.join(".horiz")).unwrap();
+        fs::set_permissions(home.join(".horiz"), fs::Permissions::from_mode(0o700)).unwrap();
+        fs::write(path_for(&home), format!("{}\n", KEY_LINE)).unwrap();
+        fs::set_permissions(path_for(&home), fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(load(&home, uid).unwrap().len(), 1);
+
+        fs::set_permissions(path_for(&home), fs::Permissions::from_mode(0o620)).unwrap();
+        assert!(matches!(load(&home, uid), Err(AuthError::InsecurePermissions { .. })));
+        fs::set_permissions(path_for(&home), fs::Permissions::from_mode(0o600)).unwrap();
+        fs::set_permissions(&home, fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(matches!(load(&home, uid), Err(AuthError::InsecurePermissions { path }) if path == home.path()));
+    }
+}
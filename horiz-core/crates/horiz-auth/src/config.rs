@@ -0,0 +1,31 @@
+// --- 設定ファイルの簡易パーサー (key=value 形式, Zero-Dependency) ---
+
+use std::fs;
+
+/// `key=value` 形式の行を読み取る。`#` 以降はコメント、空行と不正な行は無視する。
+pub fn parse_key_values(contents: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    for line in contents.lines() {
+        let line = match line.find('#') {
+            Some(i) => &line[..i],
+            None => line,
+        };
+        if let Some((key, value)) = line.split_once('=') {
+            let key = key.trim();
+            if !key.is_empty() {
+                pairs.push((key.to_string(), value.trim().to_string()));
+            }
+        }
+    }
+    pairs
+}
+
+/// 設定ファイルを読み込む (存在しない・読めない場合は空)
+pub fn load_key_values(path: &str) -> Vec<(String, String)> {
+    fs::read_to_string(path).map(|s| parse_key_values(&s)).unwrap_or_default()
+}
+
+/// `key` の値を返す (同じキーが複数ある場合は後の行を優先する)
+pub fn get<'a>(pairs: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    pairs.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+}
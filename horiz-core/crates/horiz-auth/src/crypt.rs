@@ -0,0 +1,214 @@
+// --- crypt(3) 互換ハッシュ ($5$ / $6$ / $y$, Zero-Dependency) ---
+//
+// 他の Linux システムから移行したシャドウエントリを検証するために使用する。
+// SHA-256-crypt / SHA-512-crypt は Ulrich Drepper による仕様 (glibc) に従う。
+
+use crate::sha512::sha512;
+use crate::yescrypt;
+
+const ITOA64: &[u8; 64] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+const ROUNDS_DEFAULT: u32 = 5000;
+const ROUNDS_MIN: u32 = 1000;
+const ROUNDS_MAX: u32 = 999_999_999;
+const SALT_LEN_MAX: usize = 16;
+
+/// crypt(3) のハッシュ方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CryptMethod {
+    /// `$5$`
+    Sha256Crypt,
+    /// `$6$`
+    Sha512Crypt,
+    /// `$y$`
+    Yescrypt,
+}
+
+type HashFn = fn(&[u8]) -> Vec<u8>;
+
+/// SHA-256-crypt の出力バイト順 (3 バイトずつ 4 文字に変換)
+const SHA256_ORDER: [(usize, usize, usize); 10] = [
+    (0, 10, 20), (21, 1, 11), (12, 22, 2), (3, 13, 23), (24, 4, 14),
+    (15, 25, 5), (6, 16, 26), (27, 7, 17), (18, 28, 8), (9, 19, 29),
+];
+
+/// SHA-512-crypt の出力バイト順
+const SHA512_ORDER: [(usize, usize, usize); 21] = [
+    (0, 21, 42), (22, 43, 1), (44, 2, 23), (3, 24, 45), (25, 46, 4),
+    (47, 5, 26), (6, 27, 48), (28, 49, 7), (50, 8, 29), (9, 30, 51),
+    (31, 52, 10), (53, 11, 32), (12, 33, 54), (34, 55, 13), (56, 14, 35),
+    (15, 36, 57), (37, 58, 16), (59, 17, 38), (18, 39, 60), (40, 61, 19),
+    (62, 20, 41),
+];
+
+fn b64_from_24bit(out: &mut String, b2: u8, b1: u8, b0: u8, n: usize) {
+    let mut w = ((b2 as u32) << 16) | ((b1 as u32) << 8) | b0 as u32;
+    for _ in 0..n {
+        out.push(ITOA64[(w & 0x3f) as usize] as char);
+        w >>= 6;
+    }
+}
+
+/// `len` バイトになるまで `digest` を繰り返す
+fn repeat_to(digest: &[u8], len: usize) -> Vec<u8> {
+    digest.iter().cycle().take(len).copied().collect()
+}
+
+/// SHA-crypt の中核部分 (ハッシュ関数に依らない共通処理)
+fn sha_crypt_digest(password: &[u8], salt: &[u8], rounds: u32, hash: HashFn) -> Vec<u8> {
+    // B = H(P + S + P)
+    let mut ctx = password.to_vec();
+    ctx.extend_from_slice(salt);
+    ctx.extend_from_slice(password);
+    let b = hash(&ctx);
+
+    // A = H(P + S + B[..len(P)] + (len(P) のビットに応じて B または P))
+    let mut ctx = password.to_vec();
+    ctx.extend_from_slice(salt);
+    ctx.extend_from_slice(&repeat_to(&b, password.len()));
+    let mut cnt = password.len();
+    while cnt > 0 {
+        if cnt & 1 != 0 {
+            ctx.extend_from_slice(&b);
+        } else {
+            ctx.extend_from_slice(password);
+        }
+        cnt >>= 1;
+    }
+    let a = hash(&ctx);
+
+    // P' = H(P を len(P) 回) を len(P) バイトに伸長
+    let dp = hash(&password.repeat(password.len()));
+    let p_bytes = repeat_to(&dp, password.len());
+
+    // S' = H(S を 16 + A[0] 回) を len(S) バイトに伸長
+    let ds = hash(&salt.repeat(16 + a[0] as usize));
+    let s_bytes = repeat_to(&ds, salt.len());
+
+    let mut c = a;
+    for i in 0..rounds {
+        let mut ctx = Vec::with_capacity(2 * (c.len() + p_bytes.len()) + s_bytes.len());
+        if i & 1 != 0 { ctx.extend_from_slice(&p_bytes); } else { ctx.extend_from_slice(&c); }
+        if i % 3 != 0 { ctx.extend_from_slice(&s_bytes); }
+        if i % 7 != 0 { ctx.extend_from_slice(&p_bytes); }
+        if i & 1 != 0 { ctx.extend_from_slice(&c); } else { ctx.extend_from_slice(&p_bytes); }
+        c = hash(&ctx);
+    }
+    c
+}
+
+/// `$5$` / `$6$` の設定文字列を (rounds, rounds 指定の有無, ソルト) に分解する
+fn parse_sha_setting<'a>(setting: &'a str, prefix: &str) -> Option<(u32, bool, &'a str)> {
+    let mut rest = setting.strip_prefix(prefix)?;
+    let mut rounds = ROUNDS_DEFAULT;
+    let mut custom = false;
+
+    if let Some(r) = rest.strip_prefix("rounds=") {
+        let (num, after) = r.split_once('$')?;
+        if num.is_empty() || !num.bytes().all(|b| b.is_ascii_digit()) { return None; }
+        // 仕様に従い範囲外の値は丸める
+        rounds = num.parse::<u64>().unwrap_or(u64::MAX).clamp(ROUNDS_MIN as u64, ROUNDS_MAX as u64) as u32;
+        custom = true;
+        rest = after;
+    }
+
+    let salt_end = rest.find('$').unwrap_or(rest.len());
+    let salt = &rest[..salt_end.min(SALT_LEN_MAX)];
+    if salt.contains(['\n', ':']) { return None; }
+    Some((rounds, custom, salt))
+}
+
+fn sha_crypt(password: &[u8], setting: &str, method: CryptMethod) -> Option<String> {
+    let (prefix, hash, order): (&str, HashFn, &[(usize, usize, usize)]) = match method {
+        CryptMethod::Sha256Crypt => ("$5$", |d| crate::sha256(d).to_vec(), &SHA256_ORDER),
+        CryptMethod::Sha512Crypt => ("$6$", |d| sha512(d).to_vec(), &SHA512_ORDER),
+        CryptMethod::Yescrypt => return None,
+    };
+    let (rounds, custom, salt) = parse_sha_setting(setting, prefix)?;
+    let digest = sha_crypt_digest(password, salt.as_bytes(), rounds, hash);
+
+    let mut out = String::from(prefix);
+    if custom {
+        out.push_str(&format!("rounds={}$", rounds));
+    }
+    out.push_str(salt);
+    out.push('$');
+    for &(i2, i1, i0) in order {
+        b64_from_24bit(&mut out, digest[i2], digest[i1], digest[i0], 4);
+    }
+    // 端数バイト
+    match method {
+        CryptMethod::Sha256Crypt => b64_from_24bit(&mut out, 0, digest[31], digest[30], 3),
+        _ => b64_from_24bit(&mut out, 0, 0, digest[63], 2),
+    }
+    Some(out)
+}
+
+/// ハッシュ文字列の方式を判定する (未対応・不正なら None)
+pub fn method_of(encoded: &str) -> Option<CryptMethod> {
+    if encoded.starts_with("$5$") {
+        parse_sha_setting(encoded, "$5$").map(|_| CryptMethod::Sha256Crypt)
+    } else if encoded.starts_with("$6$") {
+        parse_sha_setting(encoded, "$6$").map(|_| CryptMethod::Sha512Crypt)
+    } else if encoded.starts_with("$y$") && yescrypt::is_valid_setting(encoded) {
+        Some(CryptMethod::Yescrypt)
+    } else {
+        None
+    }
+}
+
+/// crypt(3) 互換: 設定文字列 (または既存のハッシュ) に従って password をハッシュする
+pub fn crypt(password: &[u8], setting: &str) -> Option<String> {
+    match method_of(setting)? {
+        CryptMethod::Yescrypt => yescrypt::yescrypt(password, setting),
+        method => sha_crypt(password, setting, method),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_crypt_vectors() {
+        // Drepper の仕様書のテストベクタ
+        assert_eq!(
+            crypt(b"Hello world!", "$5$saltstring").unwrap(),
+            "$5$saltstring$5B8vYYiY.CVt1RlTTf8KbXBH3hsxY/GNooZaBBGWEc5"
+        );
+        assert_eq!(
+            crypt(b"Hello world!", "$5$rounds=10000$saltstringsaltstring").unwrap(),
+            "$5$rounds=10000$saltstringsaltst$3xv.VbSHBb41AL9AvLeujZkZRBAwqFMz2.opqey6IcA"
+        );
+        assert_eq!(
+            crypt(b"", "$5$rounds=1000$emptypw$R/wgStrZGb87DR54ZFXPAjKAgm.1oPmQZlw4WtZza5B").unwrap(),
+            "$5$rounds=1000$emptypw$R/wgStrZGb87DR54ZFXPAjKAgm.1oPmQZlw4WtZza5B"
+        );
+    }
+
+    #[test]
+    fn test_sha512_crypt_vectors() {
+        assert_eq!(
+            crypt(b"Hello world!", "$6$saltstring").unwrap(),
+            "$6$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1"
+        );
+        assert_eq!(
+            crypt(b"Hello world!", "$6$rounds=10000$saltstringsaltstring").unwrap(),
+            "$6$rounds=10000$saltstringsaltst$OW1/O6BYHV6BcXZu8QVeXbDWra3Oeqh0sbHbbMCVNSnCM/UrjmM0Dp8vOuZeHBy/YTBmSK6H9qs/y3RnOaw5v."
+        );
+        // rounds の下限 (1000) への丸め
+        assert_eq!(
+            crypt(b"the minimum number is still observed", "$6$rounds=10$roundstoolow").unwrap(),
+            "$6$rounds=1000$roundstoolow$kUMsbe306n21p9R.FRkW3IGn.S9NPN0x50YhH1xhLsPuWGsUSklZt58jaTfF4ZEQpyUNGc0dqbpBYYBaHHrsX."
+        );
+    }
+
+    #[test]
+    fn test_method_of() {
+        assert_eq!(method_of("$5$salt$hash"), Some(CryptMethod::Sha256Crypt));
+        assert_eq!(method_of("$6$rounds=5000$salt$hash"), Some(CryptMethod::Sha512Crypt));
+        assert_eq!(method_of("$y$j9T$saltsaltsalt$hash"), Some(CryptMethod::Yescrypt));
+        assert_eq!(method_of("$6$rounds=abc$salt$hash"), None);
+        assert_eq!(method_of("$1$salt$hash"), None);
+    }
+}
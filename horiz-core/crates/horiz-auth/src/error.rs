@@ -0,0 +1,43 @@
+// --- 認証処理のエラー ---
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum AuthError {
+    /// ファイルの読み書きに失敗した
+    Io(io::Error),
+    /// 対象ユーザーの行を解析できない (行番号は 1 から)
+    MalformedEntry { path: PathBuf, line: usize },
+    /// パスワードフィールドのハッシュ形式に対応していない
+    UnsupportedFormat { user: String },
+    /// 所有者が対象ユーザー・root 以外、またはグループ・他者が書き込める (authorized_keys など)
+    InsecurePermissions { path: PathBuf },
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::Io(e) => write!(f, "{}", e),
+            AuthError::MalformedEntry { path, line } => write!(f, "{}: {}行目の形式が不正です", path.display(), line),
+            AuthError::UnsupportedFormat { user } => write!(f, "ユーザー {} のパスワードは対応していない形式です", user),
+            AuthError::InsecurePermissions { path } => write!(f, "{}: 所有者またはパーミッションが安全ではありません", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AuthError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AuthError {
+    fn from(e: io::Error) -> Self {
+        AuthError::Io(e)
+    }
+}
@@ -0,0 +1,89 @@
+// --- /etc/group ---
+//
+// name:password:gid:member1,member2,...
+
+use std::io;
+use std::path::Path;
+
+use crate::userdb::{Database, Entry};
+
+pub const GROUP_PATH: &str = "/etc/group";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupEntry {
+    pub name: String,
+    pub password: String,
+    pub gid: u32,
+    /// 補助グループとして所属するユーザー名
+    pub members: Vec<String>,
+}
+
+impl Entry for GroupEntry {
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.len() != 4 || fields[0].is_empty() {
+            return None;
+        }
+        Some(GroupEntry {
+            name: fields[0].to_string(),
+            password: fields[1].to_string(),
+            gid: fields[2].parse().ok()?,
+            members: fields[3].split(',').filter(|m| !m.is_empty()).map(str::to_string).collect(),
+        })
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![self.name.clone(), self.password.clone(), self.gid.to_string(), self.members.join(",")]
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Database<GroupEntry> {
+    pub fn by_gid(&self, gid: u32) -> Option<&GroupEntry> {
+        self.entries().find(|e| e.gid == gid)
+    }
+
+    /// `user` の所属グループ一覧。先頭は `base_gid` で、重複は取り除く。
+    pub fn groups_of(&self, user: &str, base_gid: u32) -> Vec<u32> {
+        let mut gids = vec![base_gid];
+        for group in self.entries() {
+            if group.members.iter().any(|m| m == user) && !gids.contains(&group.gid) {
+                gids.push(group.gid);
+            }
+        }
+        gids
+    }
+}
+
+/// グループ名で検索する (getgrnam 相当)
+pub fn getgrnam(name: &str) -> io::Result<Option<GroupEntry>> {
+    Ok(Database::<GroupEntry>::load(Path::new(GROUP_PATH))?.get(name).cloned())
+}
+
+/// GID で検索する (getgrgid 相当)
+pub fn getgrgid(gid: u32) -> io::Result<Option<GroupEntry>> {
+    Ok(Database::<GroupEntry>::load(Path::new(GROUP_PATH))?.by_gid(gid).cloned())
+}
+
+/// ユーザーの所属グループの GID 一覧 (getgrouplist 相当)
+pub fn getgrouplist(user: &str, base_gid: u32) -> io::Result<Vec<u32>> {
+    Ok(Database::<GroupEntry>::load(Path::new(GROUP_PATH))?.groups_of(user, base_gid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_and_membership() {
+        let db = Database::<GroupEntry>::parse("root:x:0:\nwheel:x:10:root,horiz\nhoriz:x:1000:\naudio:x:29:horiz\n");
+        assert_eq!(db.get("wheel").unwrap().members, vec!["root", "horiz"]);
+        assert!(db.get("root").unwrap().members.is_empty());
+        assert_eq!(db.by_gid(29).unwrap().name, "audio");
+        assert_eq!(db.groups_of("horiz", 1000), vec![1000, 10, 29]);
+        assert_eq!(db.groups_of("root", 10), vec![10]);
+    }
+}
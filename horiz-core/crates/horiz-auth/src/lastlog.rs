@@ -0,0 +1,174 @@
+// --- ログイン記録 (lastlog / faillog 相当, Zero-Dependency) ---
+//
+// 1行1ユーザーのテキスト形式で、最終ログインと失敗回数を保持する。
+// 形式: username:last_login:tty:failures:last_failure
+
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+pub const LASTLOG_PATH: &str = "/var/log/lastlog";
+
+/// 1ユーザー分のログイン記録
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoginRecord {
+    pub username: String,
+    /// 最終ログイン成功時刻 (UNIX 秒, 0 は未ログイン)
+    pub last_login: u64,
+    /// 最終ログイン成功時の端末名
+    pub tty: String,
+    /// 最終ログイン成功以降の失敗回数
+    pub failures: u32,
+    /// 最終ログイン失敗時刻 (UNIX 秒, 0 は失敗なし)
+    pub last_failure: u64,
+}
+
+impl LoginRecord {
+    fn parse(line: &str) -> Option<Self> {
+        let parts: Vec<&str> = line.split(':').collect();
+        if parts.len() != 5 || parts[0].is_empty() { return None; }
+        Some(LoginRecord {
+            username: parts[0].to_string(),
+            last_login: parts[1].parse().ok()?,
+            tty: parts[2].to_string(),
+            failures: parts[3].parse().ok()?,
+            last_failure: parts[4].parse().ok()?,
+        })
+    }
+
+    fn to_line(&self) -> String {
+        format!("{}:{}:{}:{}:{}", self.username, self.last_login, self.tty, self.failures, self.last_failure)
+    }
+}
+
+/// ログイン記録ファイルへのハンドル
+pub struct LastLog {
+    path: PathBuf,
+}
+
+impl LastLog {
+    /// システム標準の記録ファイル (/var/log/lastlog) を使用する
+    pub fn system() -> Self {
+        Self::at(LASTLOG_PATH)
+    }
+
+    pub fn at<P: AsRef<Path>>(path: P) -> Self {
+        LastLog { path: path.as_ref().to_path_buf() }
+    }
+
+    fn load(&self) -> io::Result<Vec<LoginRecord>> {
+        match fs::read_to_string(&self.path) {
+            Ok(contents) => Ok(contents.lines().filter_map(LoginRecord::parse).collect()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 一時ファイルに書いてからリネームする (原子的な置換)
+    fn store(&self, records: &[LoginRecord]) -> io::Result<()> {
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push("+");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let mut contents = String::new();
+        for r in records {
+            contents.push_str(&r.to_line());
+            contents.push('\n');
+        }
+
+        let mut f = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&tmp_path)?;
+        f.write_all(contents.as_bytes())?;
+        f.sync_all()?;
+        fs::rename(&tmp_path, &self.path)
+    }
+
+    /// 指定ユーザーの記録を取得する
+    pub fn get(&self, username: &str) -> io::Result<Option<LoginRecord>> {
+        Ok(self.load()?.into_iter().find(|r| r.username == username))
+    }
+
+    /// ログイン成功を記録し、更新前の記録を返す
+    pub fn record_success(&self, username: &str, tty: &str, timestamp: u64) -> io::Result<Option<LoginRecord>> {
+        check_field(username)?;
+        check_field(tty)?;
+        let mut records = self.load()?;
+        let previous = records.iter().find(|r| r.username == username).cloned();
+        records.retain(|r| r.username != username);
+        records.push(LoginRecord {
+            username: username.to_string(),
+            last_login: timestamp,
+            tty: tty.to_string(),
+            failures: 0,
+            last_failure: previous.as_ref().map(|p| p.last_failure).unwrap_or(0),
+        });
+        self.store(&records)?;
+        Ok(previous)
+    }
+
+    /// ログイン失敗を記録し、累計の失敗回数を返す
+    pub fn record_failure(&self, username: &str, timestamp: u64) -> io::Result<u32> {
+        check_field(username)?;
+        let mut records = self.load()?;
+        let failures = match records.iter_mut().find(|r| r.username == username) {
+            Some(r) => {
+                r.failures = r.failures.saturating_add(1);
+                r.last_failure = timestamp;
+                r.failures
+            }
+            None => {
+                records.push(LoginRecord {
+                    username: username.to_string(),
+                    failures: 1,
+                    last_failure: timestamp,
+                    ..Default::default()
+                });
+                1
+            }
+        };
+        self.store(&records)?;
+        Ok(failures)
+    }
+}
+
+/// フィールド区切り文字や改行の混入による記録の改ざんを防ぐ
+fn check_field(value: &str) -> io::Result<()> {
+    if value.contains(':') || value.contains('\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "ログイン記録に使用できない文字が含まれています"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_record_roundtrip() {
+        let dir = TempDir::new("lastlog");
+        let path = dir.join("lastlog");
+        let log = LastLog::at(&path);
+
+        assert_eq!(log.get("horiz").unwrap(), None);
+        assert_eq!(log.record_failure("horiz", 100).unwrap(), 1);
+        assert_eq!(log.record_failure("horiz", 110).unwrap(), 2);
+
+        let prev = log.record_success("horiz", "tty1", 120).unwrap().unwrap();
+        assert_eq!(prev.failures, 2);
+        assert_eq!(prev.last_login, 0);
+
+        let now = log.get("horiz").unwrap().unwrap();
+        assert_eq!(now.last_login, 120);
+        assert_eq!(now.tty, "tty1");
+        assert_eq!(now.failures, 0);
+        assert_eq!(now.last_failure, 110);
+
+        assert!(log.record_failure("evil:0:x", 130).is_err());
+    }
+}
@@ -1,133 +1,49 @@
 use std::fs;
-use std::io::{self, BufRead};
-
-// --- カスタム SHA-256 実装 (依存関係なし) ---
-
-const K: [u32; 64] = [
-    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
-    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
-    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
-    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
-    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
-    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
-    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
-    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
-];
-
-fn sha256_compress(h: &mut [u32; 8], chunk: &[u8; 64]) {
-    let mut w = [0u32; 64];
-    for i in 0..16 {
-        w[i] = u32::from_be_bytes(chunk[i * 4..i * 4 + 4].try_into().unwrap());
-    }
-    for i in 16..64 {
-        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
-        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
-        w[i] = w[i - 16].wrapping_add(s0).wrapping_add(w[i - 7]).wrapping_add(s1);
-    }
-
-    let mut a = h[0];
-    let mut b = h[1];
-    let mut c = h[2];
-    let mut d = h[3];
-    let mut e = h[4];
-    let mut f = h[5];
-    let mut g = h[6];
-    let mut h_var = h[7];
-
-    for i in 0..64 {
-        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
-        let ch = (e & f) ^ ((!e) & g);
-        let temp1 = h_var.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
-        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
-        let maj = (a & b) ^ (a & c) ^ (b & c);
-        let temp2 = s0.wrapping_add(maj);
-
-        h_var = g;
-        g = f;
-        f = e;
-        e = d.wrapping_add(temp1);
-        d = c;
-        c = b;
-        b = a;
-        a = temp1.wrapping_add(temp2);
-    }
-
-    h[0] = h[0].wrapping_add(a);
-    h[1] = h[1].wrapping_add(b);
-    h[2] = h[2].wrapping_add(c);
-    h[3] = h[3].wrapping_add(d);
-    h[4] = h[4].wrapping_add(e);
-    h[5] = h[5].wrapping_add(f);
-    h[6] = h[6].wrapping_add(g);
-    h[7] = h[7].wrapping_add(h_var);
-}
-
-pub fn sha256(data: &[u8]) -> [u8; 32] {
-    let mut h = [
-        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
-    ];
-
-    let mut padded = data.to_vec();
-    let bit_len = (data.len() as u64) * 8;
-    padded.push(0x80);
-    while (padded.len() + 8) % 64 != 0 {
-        padded.push(0x00);
-    }
-    padded.extend_from_slice(&bit_len.to_be_bytes());
-
-    for chunk in padded.chunks_exact(64) {
-        sha256_compress(&mut h, chunk.try_into().unwrap());
-    }
-
-    let mut result = [0u8; 32];
-    for i in 0..8 {
-        result[i * 4..i * 4 + 4].copy_from_slice(&h[i].to_be_bytes());
-    }
-    result
-}
-
-// --- カスタム Base64 実装 (依存関係なし) ---
-
-const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
-
-pub fn base64_encode(data: &[u8]) -> String {
-    let mut result = String::new();
-    for chunk in data.chunks(3) {
-        let b = match chunk.len() {
-            3 => [chunk[0], chunk[1], chunk[2]],
-            2 => [chunk[0], chunk[1], 0],
-            1 => [chunk[0], 0, 0],
-            _ => unreachable!(),
-        };
-
-        let i0 = (b[0] >> 2) as usize;
-        let i1 = (((b[0] & 0x03) << 4) | (b[1] >> 4)) as usize;
-        let i2 = (((b[1] & 0x0f) << 2) | (b[2] >> 6)) as usize;
-        let i3 = (b[2] & 0x3f) as usize;
-
-        result.push(BASE64_ALPHABET[i0] as char);
-        result.push(BASE64_ALPHABET[i1] as char);
-        if chunk.len() >= 2 {
-            result.push(BASE64_ALPHABET[i2] as char);
-        } else {
-            result.push('=');
-        }
-        if chunk.len() >= 3 {
-            result.push(BASE64_ALPHABET[i3] as char);
-        } else {
-            result.push('=');
-        }
-    }
-    result
-}
+use std::io;
+use std::path::Path;
+
+pub mod audit;
+pub mod authorized_keys;
+pub mod config;
+pub mod crypt;
+pub mod error;
+pub mod group;
+pub mod lastlog;
+pub mod modules;
+pub mod opasswd;
+pub mod passwd;
+pub mod password;
+pub mod pbkdf2;
+pub mod quality;
+pub mod scrypt;
+pub mod secret;
+pub mod sha1;
+pub mod shadow;
+pub mod stack;
+#[cfg(any(test, feature = "testutil"))]
+pub mod testutil;
+pub mod time;
+pub mod totp;
+pub mod tty;
+pub mod userdb;
+pub mod yescrypt;
+
+pub use error::AuthError;
+// 暗号の基本処理は horiz-pkg と共用する horiz-crypto にある
+pub use horiz_crypto::base64::{decode as base64_decode, encode as base64_encode};
+pub use horiz_crypto::sha256::sha256;
+pub use horiz_crypto::{ed25519, sha512};
+pub use password::{HashPolicy, verify_password};
+pub use secret::{SecretBytes, SecretString};
 
 // --- HorizOS 認証ロジック (依存関係なしで復元) ---
 
+/// 旧形式 (`$hz$`) のハッシュ計算。新規のハッシュは [`HashPolicy::hash`] で生成する。
 pub fn hash_password(password: &str, salt: &str) -> String {
-    let mut input = Vec::new();
+    let mut input = SecretBytes::with_capacity(salt.len() + password.len());
     input.extend_from_slice(salt.as_bytes());
     input.extend_from_slice(password.as_bytes());
-    let mut result = sha256(&input);
+    let mut result = sha256(input.expose());
 
     // 10,000回のストレッチング
     for _ in 0..10000 {
@@ -137,54 +53,90 @@ pub fn hash_password(password: &str, salt: &str) -> String {
     base64_encode(&result)
 }
 
-pub fn verify_login(username: &str, password: &str) -> io::Result<bool> {
-    let file = fs::File::open("/etc/shadow")?;
-    let reader = io::BufReader::new(file);
-
-    let mut user_found = false;
-    let mut is_valid = false;
-    let mut target_salt = String::from("dummy_salt_for_timing_mitigation");
-    let mut target_hash = String::new();
-
-    for line in reader.lines() {
-        let line = line?;
-        let parts: Vec<&str> = line.split(':').collect();
-        if parts.len() < 2 { continue; }
-
-        if parts[0] == username {
-            let encoded_pwd = parts[1];
-            if !encoded_pwd.starts_with("$hz$") { continue; }
-            let segments: Vec<&str> = encoded_pwd.split('$').collect();
-            if segments.len() < 4 { continue; }
-
-            target_salt = segments[2].to_string();
-            target_hash = segments[3].to_string();
-            user_found = true;
-            break; // ユーザーを見つけたらループを抜ける
-        }
+/// XOR による定数時間比較 (長さの違いのみ即座に判定する)
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut res = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        res |= x ^ y;
     }
+    res == 0
+}
 
-    // ユーザーの有無に関わらず、常にハッシュ計算を実行する (定数時間)
-    let computed_hash = hash_password(password, &target_salt);
-
-    if user_found {
-        if computed_hash.len() == target_hash.len() {
-            let mut res = 0u8;
-            let a_bytes = computed_hash.as_bytes();
-            let b_bytes = target_hash.as_bytes();
-            for i in 0..a_bytes.len() {
-                res |= a_bytes[i] ^ b_bytes[i];
-            }
-            is_valid = res == 0;
+/// ログイン検証の結果
+///
+/// `BadPassword` 以外の状態は、パスワードが一致した場合にのみ返す
+/// (パスワードを知らない相手にアカウントの状態を明かさないため)。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginStatus {
+    /// 認証成功。`needs_rehash` はハッシュが現在のポリシーと異なる (旧方式・旧コスト) ことを示す。
+    /// `expires_in` は警告期間内の場合のみ、パスワードの有効期限までの日数を示す。
+    Ok { needs_rehash: bool, expires_in: Option<i64> },
+    /// ユーザーが存在しない、またはパスワードが一致しない
+    BadPassword,
+    /// パスワードフィールドが `!` / `*` でロックされている
+    Locked,
+    /// アカウントの有効期限 (expire)、またはパスワード期限切れ後の猶予期間を過ぎている
+    AccountExpired,
+    /// パスワードの有効期限が切れている。新しいパスワードを設定すればログインできる。
+    PasswordExpired,
+}
+
+pub fn verify_login(username: &str, password: &SecretString) -> Result<LoginStatus, AuthError> {
+    let entry = shadow::read_entry(Path::new(shadow::SHADOW_PATH), username)?;
+    evaluate_login(entry.as_ref(), password.expose(), shadow::today(), &HashPolicy::load())
+}
+
+/// パスワードフィールドが `*` で始まる、または `!` のみの場合はログインを無効にしたアカウントとみなす。
+/// それ以外で形式を判定できない場合は、照合できないため `UnsupportedFormat` とする。
+pub(crate) fn evaluate_login(
+    entry: Option<&shadow::ShadowEntry>,
+    password: &str,
+    today: i64,
+    policy: &HashPolicy,
+) -> Result<LoginStatus, AuthError> {
+    // ロックされたエントリも、ロック前のハッシュで照合する
+    let encoded = entry.map(|e| e.password.trim_start_matches('!'));
+    let supported = encoded.and_then(password::scheme_of).is_some();
+    let matched = match (entry, encoded) {
+        // 空のパスワードフィールドは空のパスワードのみ受け付ける
+        (Some(e), _) if e.password.is_empty() => password.is_empty(),
+        (Some(_), Some(encoded)) if supported => verify_password(password, encoded),
+        _ => {
+            // ユーザーの有無・ハッシュの有無に関わらず、常にハッシュ計算を実行する (定数時間)
+            let _ = policy.hash(password, "dummy_salt_for_timing_mitigation");
+            false
         }
+    };
+    let (Some(entry), Some(encoded)) = (entry, encoded) else { return Ok(LoginStatus::BadPassword); };
+    if !supported && !encoded.is_empty() && !encoded.starts_with('*') {
+        return Err(AuthError::UnsupportedFormat { user: entry.name.clone() });
+    }
+    if !matched {
+        return Ok(LoginStatus::BadPassword);
     }
 
-    Ok(is_valid)
+    if entry.is_locked() {
+        return Ok(LoginStatus::Locked);
+    }
+    if entry.is_account_expired(today) {
+        return Ok(LoginStatus::AccountExpired);
+    }
+    Ok(match entry.password_aging(today) {
+        shadow::PasswordAging::Inactive => LoginStatus::AccountExpired,
+        shadow::PasswordAging::MustChange => LoginStatus::PasswordExpired,
+        shadow::PasswordAging::Valid { expires_in } => LoginStatus::Ok {
+            needs_rehash: !encoded.is_empty() && password::needs_rehash(encoded, policy),
+            expires_in,
+        },
+    })
 }
 
+/// 現在のハッシュポリシー (/etc/horiz/password.conf) に従ってシャドウエントリを生成する
 pub fn generate_shadow_entry(password: &str, salt: &str) -> String {
-    let hash = hash_password(password, salt);
-    format!("$hz${}${}", salt, hash)
+    HashPolicy::load().hash(password, salt)
 }
 
 /// セキュアなソルトを生成 (CSPRNG - Zero-Dependency)
@@ -198,17 +150,45 @@ pub fn generate_salt() -> io::Result<String> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::userdb::Entry;
 
     #[test]
-    fn test_sha256() {
-        let hash = sha256(b"hello");
-        // echo -n "hello" | sha256sum -> 2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824
-        assert_eq!(hex::encode(hash), "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824");
-    }
-
-    #[test]
-    fn test_base64() {
-        assert_eq!(base64_encode(b"any car"), "YW55IGNhcg==");
+    fn test_evaluate_login_states() {
+        let policy = HashPolicy { algorithm: password::Algorithm::Pbkdf2Sha256 { iterations: 10 } };
+        let hash = policy.hash("secret", "salt");
+        let entry = |fields: &str| shadow::ShadowEntry::parse(&format!("horiz:{}", fields)).unwrap();
+        let today = 19000;
+
+        let ok = entry(&format!("{}:18990:0:99999:7:::", hash));
+        assert_eq!(evaluate_login(Some(&ok), "secret", today, &policy).unwrap(), LoginStatus::Ok { needs_rehash: false, expires_in: None });
+        assert_eq!(evaluate_login(Some(&ok), "wrong", today, &policy).unwrap(), LoginStatus::BadPassword);
+        assert_eq!(evaluate_login(None, "secret", today, &policy).unwrap(), LoginStatus::BadPassword);
+
+        let locked = entry(&format!("!{}:18990:0:99999:7:::", hash));
+        assert_eq!(evaluate_login(Some(&locked), "secret", today, &policy).unwrap(), LoginStatus::Locked);
+        assert_eq!(evaluate_login(Some(&locked), "wrong", today, &policy).unwrap(), LoginStatus::BadPassword);
+        assert_eq!(evaluate_login(Some(&entry("*:::::::")), "", today, &policy).unwrap(), LoginStatus::BadPassword);
+        assert_eq!(evaluate_login(Some(&entry("!:::::::")), "", today, &policy).unwrap(), LoginStatus::BadPassword);
+
+        // 形式を判定できないハッシュは照合できない
+        assert!(matches!(
+            evaluate_login(Some(&entry("$md5$x$y:::::::")), "secret", today, &policy),
+            Err(AuthError::UnsupportedFormat { user }) if user == "horiz"
+        ));
+        assert!(evaluate_login(Some(&entry("!$md5$x$y:::::::")), "secret", today, &policy).is_err());
+
+        let empty = entry(":::::::");
+        assert_eq!(evaluate_login(Some(&empty), "", today, &policy).unwrap(), LoginStatus::Ok { needs_rehash: false, expires_in: None });
+        assert_eq!(evaluate_login(Some(&empty), "x", today, &policy).unwrap(), LoginStatus::BadPassword);
+
+        let expired = entry(&format!("{}:18990:0:99999:7::18999:", hash));
+        assert_eq!(evaluate_login(Some(&expired), "secret", today, &policy).unwrap(), LoginStatus::AccountExpired);
+        let must_change = entry(&format!("{}:0:0:99999:7:::", hash));
+        assert_eq!(evaluate_login(Some(&must_change), "secret", today, &policy).unwrap(), LoginStatus::PasswordExpired);
+        let warn = entry(&format!("{}:18970:0:33:7:::", hash));
+        assert_eq!(evaluate_login(Some(&warn), "secret", today, &policy).unwrap(), LoginStatus::Ok { needs_rehash: false, expires_in: Some(3) });
+        let inactive = entry(&format!("{}:18900:0:30:7:10::", hash));
+        assert_eq!(evaluate_login(Some(&inactive), "secret", today, &policy).unwrap(), LoginStatus::AccountExpired);
     }
 
     #[test]
@@ -217,11 +197,3 @@ mod tests {
         println!("horiz: {}", generate_shadow_entry("horiz", "horiz_salt"));
     }
 }
-
-// 簡易的なhexエンコード (テスト用)
-mod hex {
-    #[allow(dead_code)]
-    pub fn encode(data: [u8; 32]) -> String {
-        data.iter().map(|b| format!("{:02x}", b)).collect()
-    }
-}
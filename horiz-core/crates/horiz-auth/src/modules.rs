@@ -0,0 +1,483 @@
+// --- 認証スタックのモジュール ---
+//
+//   shadow   /etc/shadow のパスワード (file=)
+//   totp     登録済みのユーザーに確認コードを求める (file=)
+//   lockout  認証の失敗が続いたユーザーを一時的に拒否する (deny=, unlock_time=, dir=)
+//   time     許可する曜日・時間帯 (UTC) を制限する (days=, hours=, users=)
+//   tty      ユーザーごとにログインできる端末を制限する (file=)
+
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::PathBuf;
+
+use crate::config;
+use crate::error::AuthError;
+use crate::password::HashPolicy;
+use crate::shadow;
+use crate::stack::{AuthContext, AuthModule, Conversation, Failure, Verdict};
+use crate::totp::{self, TotpConfig, TotpStatus};
+use crate::{LoginStatus, evaluate_login};
+
+pub const FAILLOCK_DIR: &str = "/var/lib/horiz/faillock";
+pub const TTY_ALLOW_PATH: &str = "/etc/horiz/tty.allow";
+
+/// モジュール名とオプションからモジュールを生成する
+pub fn build(name: &str, options: &[(String, String)]) -> Result<Box<dyn AuthModule>, String> {
+    let known: &[&str] = match name {
+        "shadow" | "totp" | "tty" => &["file"],
+        "lockout" => &["deny", "unlock_time", "dir"],
+        "time" => &["days", "hours", "users"],
+        _ => return Err(format!("不明なモジュール: {}", name)),
+    };
+    if let Some((key, _)) = options.iter().find(|(key, _)| !known.contains(&key.as_str())) {
+        return Err(format!("{}: 不明なオプション: {}", name, key));
+    }
+    let get = |key: &str| config::get(options, key);
+    let path = |default: &str| PathBuf::from(get("file").unwrap_or(default));
+    let number = |key: &str, default: u64| match get(key) {
+        None => Ok(default),
+        Some(v) => v.parse().ok().filter(|n| *n > 0).ok_or_else(|| format!("{}: 不正な {}: {}", name, key, v)),
+    };
+
+    Ok(match name {
+        "shadow" => Box::new(ShadowModule { path: path(shadow::SHADOW_PATH) }),
+        "totp" => Box::new(TotpModule { path: path(totp::TOTP_PATH) }),
+        "tty" => Box::new(TtyModule { path: path(TTY_ALLOW_PATH) }),
+        "lockout" => Box::new(LockoutModule {
+            deny: number("deny", 5)?.min(u32::MAX as u64) as u32,
+            unlock_time: number("unlock_time", 15 * 60)?,
+            dir: PathBuf::from(get("dir").unwrap_or(FAILLOCK_DIR)),
+        }),
+        _ => Box::new(TimeModule::from_options(get("days"), get("hours"), get("users"))?),
+    })
+}
+
+// --- shadow ---
+
+pub struct ShadowModule {
+    path: PathBuf,
+}
+
+impl AuthModule for ShadowModule {
+    fn name(&self) -> &'static str {
+        "shadow"
+    }
+
+    fn authenticate(&self, ctx: &mut AuthContext, conv: &mut dyn Conversation) -> Verdict {
+        let password = match conv.prompt_secret(&ctx.password_prompt) {
+            Ok(password) => password,
+            Err(e) => return Verdict::Failure(Failure::System(format!("パスワードを読み取れません: {}", e))),
+        };
+        let entry = match shadow::read_entry(&self.path, &ctx.user) {
+            Ok(entry) => entry,
+            Err(AuthError::Io(e)) => return Verdict::Failure(Failure::System(format!("{}: {}", self.path.display(), e))),
+            Err(e) => return Verdict::Failure(Failure::System(e.to_string())),
+        };
+        ctx.known_user = entry.is_some();
+        let status = evaluate_login(entry.as_ref(), password.expose(), ctx.now.div_euclid(86400) as i64, &HashPolicy::load());
+        ctx.password = Some(password);
+        let status = match status {
+            Ok(status) => status,
+            Err(e) => return Verdict::Failure(Failure::System(e.to_string())),
+        };
+        match status {
+            LoginStatus::Ok { needs_rehash, expires_in } => {
+                ctx.needs_rehash = needs_rehash;
+                ctx.expires_in = expires_in;
+                Verdict::Success
+            }
+            LoginStatus::PasswordExpired => {
+                ctx.password_expired = true;
+                Verdict::Success
+            }
+            LoginStatus::BadPassword => Verdict::Failure(Failure::BadCredentials),
+            LoginStatus::Locked => Verdict::Failure(Failure::Locked),
+            LoginStatus::AccountExpired => Verdict::Failure(Failure::AccountExpired),
+        }
+    }
+}
+
+// --- totp ---
+
+/// 登録されていないユーザーには何も尋ねない (Ignore)。設定ファイルを読めない場合は失敗とする。
+pub struct TotpModule {
+    path: PathBuf,
+}
+
+impl AuthModule for TotpModule {
+    fn name(&self) -> &'static str {
+        "totp"
+    }
+
+    fn authenticate(&self, ctx: &mut AuthContext, conv: &mut dyn Conversation) -> Verdict {
+        match totp::is_enrolled(&self.path, &ctx.user) {
+            Ok(false) => return Verdict::Ignore,
+            Ok(true) => {}
+            Err(e) => return Verdict::Failure(Failure::System(format!("2 要素認証の設定を読み込めません: {}", e))),
+        }
+        let code = match conv.prompt("verification code: ") {
+            Ok(code) => code,
+            Err(e) => return Verdict::Failure(Failure::System(format!("確認コードを読み取れません: {}", e))),
+        };
+        match totp::verify(&self.path, &ctx.user, &code, ctx.now, TotpConfig::load().window) {
+            Ok(TotpStatus::Valid) => Verdict::Success,
+            Ok(TotpStatus::NotEnrolled) => Verdict::Ignore,
+            Ok(TotpStatus::Invalid) => {
+                conv.message("確認コードが正しくありません。");
+                Verdict::Failure(Failure::BadCredentials)
+            }
+            Err(e) => Verdict::Failure(Failure::System(format!("確認コードの検証に失敗: {}", e))),
+        }
+    }
+}
+
+// --- lockout ---
+
+/// 続けて `deny` 回失敗すると、最後の失敗から `unlock_time` 秒の間は拒否する。
+///
+/// 状態は `dir/<ユーザー名>` に `回数 最後の失敗時刻` の形式で置く。
+/// 存在しないユーザー名で状態ファイルが増えないよう、既知のユーザーの失敗のみ記録する。
+pub struct LockoutModule {
+    deny: u32,
+    unlock_time: u64,
+    dir: PathBuf,
+}
+
+impl LockoutModule {
+    /// ファイル名として安全なユーザー名のみ扱う
+    fn state_path(&self, user: &str) -> Option<PathBuf> {
+        let valid = !user.is_empty()
+            && !user.starts_with('.')
+            && user.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+        valid.then(|| self.dir.join(user))
+    }
+
+    fn read_failures(&self, user: &str) -> (u32, u64) {
+        let contents = self.state_path(user).and_then(|p| fs::read_to_string(p).ok()).unwrap_or_default();
+        let mut fields = contents.split_whitespace().map(|f| f.parse::<u64>().ok());
+        match (fields.next().flatten(), fields.next().flatten()) {
+            (Some(count), Some(last)) => (count.min(u32::MAX as u64) as u32, last),
+            _ => (0, 0),
+        }
+    }
+
+    /// 拒否する残り秒数 (ロックされていなければ None)
+    fn remaining(&self, count: u32, last: u64, now: u64) -> Option<u64> {
+        let until = last.saturating_add(self.unlock_time);
+        (count >= self.deny && now < until).then(|| until - now)
+    }
+
+    /// 状態ディレクトリを用意する。他のユーザーが書き込めるディレクトリは使用しない。
+    fn open_dir(&self) -> io::Result<()> {
+        match DirBuilder::new().recursive(true).mode(0o700).create(&self.dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+        let metadata = fs::symlink_metadata(&self.dir)?;
+        if !metadata.is_dir() || metadata.mode() & 0o022 != 0 {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} が安全ではありません", self.dir.display())));
+        }
+        Ok(())
+    }
+
+    /// 失敗を記録し、続けて失敗した回数を返す。前回の失敗から unlock_time 以上経っていれば数え直す。
+    fn record_failure(&self, user: &str, now: u64) -> io::Result<u32> {
+        let Some(path) = self.state_path(user) else { return Ok(0); };
+        self.open_dir()?;
+        let (count, last) = self.read_failures(user);
+        let count = if now.saturating_sub(last) >= self.unlock_time { 1 } else { count.saturating_add(1) };
+        // 既存のファイルを置き換える (シンボリックリンクを辿らない)
+        let _ = fs::remove_file(&path);
+        let mut f = OpenOptions::new().write(true).create_new(true).mode(0o600).open(&path)?;
+        f.write_all(format!("{} {}\n", count, now).as_bytes())?;
+        Ok(count)
+    }
+
+    fn clear_failures(&self, user: &str) {
+        if let Some(path) = self.state_path(user) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl AuthModule for LockoutModule {
+    fn name(&self) -> &'static str {
+        "lockout"
+    }
+
+    fn authenticate(&self, ctx: &mut AuthContext, _conv: &mut dyn Conversation) -> Verdict {
+        let (count, last) = self.read_failures(&ctx.user);
+        match self.remaining(count, last, ctx.now) {
+            Some(remaining) => Verdict::Failure(Failure::TooManyFailures(remaining)),
+            None => Verdict::Ignore,
+        }
+    }
+
+    fn finish(&self, ctx: &AuthContext, result: &Result<(), Failure>) {
+        match result {
+            Ok(()) => self.clear_failures(&ctx.user),
+            Err(Failure::BadCredentials) if ctx.known_user => {
+                let _ = self.record_failure(&ctx.user, ctx.now);
+            }
+            Err(_) => {}
+        }
+    }
+}
+
+// --- time ---
+
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// `days` の曜日の `hours` の時間帯のみ許可する (時刻は UTC)。
+///
+/// `days=mon-fri` / `days=sat,sun`、`hours=09:00-18:00` (22:00-06:00 のように日をまたいでもよい)。
+/// `users=` を指定した場合は、そのユーザーのみ制限する。
+pub struct TimeModule {
+    /// 日曜日を 0 とする曜日ごとの許可
+    days: [bool; 7],
+    /// 許可する時間帯 (0 時からの分, 終了は含まない)
+    hours: Option<(u32, u32)>,
+    users: Option<Vec<String>>,
+}
+
+impl TimeModule {
+    fn from_options(days: Option<&str>, hours: Option<&str>, users: Option<&str>) -> Result<Self, String> {
+        let weekday = |s: &str| WEEKDAYS.iter().position(|d| d.eq_ignore_ascii_case(s)).ok_or_else(|| format!("time: 不明な曜日: {}", s));
+        let mut allowed = [days.is_none(); 7];
+        for part in days.unwrap_or("").split(',').filter(|p| !p.is_empty()) {
+            let (from, to) = match part.split_once('-') {
+                Some((from, to)) => (weekday(from)?, weekday(to)?),
+                None => (weekday(part)?, weekday(part)?),
+            };
+            let mut day = from;
+            loop {
+                allowed[day] = true;
+                if day == to {
+                    break;
+                }
+                day = (day + 1) % 7;
+            }
+        }
+
+        let minutes = |s: &str| -> Option<u32> {
+            let (h, m) = s.split_once(':')?;
+            let (h, m) = (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?);
+            (h < 24 && m < 60).then_some(h * 60 + m)
+        };
+        let hours = match hours {
+            None => None,
+            Some(v) => Some(
+                v.split_once('-')
+                    .and_then(|(from, to)| Some((minutes(from)?, minutes(to)?)))
+                    .ok_or_else(|| format!("time: 不正な時間帯: {}", v))?,
+            ),
+        };
+        let users = users.map(|v| v.split(',').filter(|u| !u.is_empty()).map(String::from).collect());
+        Ok(TimeModule { days: allowed, hours, users })
+    }
+
+    fn allows(&self, now: u64) -> bool {
+        // 1970-01-01 は木曜日
+        let weekday = ((now / 86400 + 4) % 7) as usize;
+        let minute = (now % 86400 / 60) as u32;
+        let in_hours = match self.hours {
+            None => true,
+            Some((from, to)) if from <= to => from <= minute && minute < to,
+            Some((from, to)) => minute >= from || minute < to,
+        };
+        self.days[weekday] && in_hours
+    }
+}
+
+impl AuthModule for TimeModule {
+    fn name(&self) -> &'static str {
+        "time"
+    }
+
+    fn authenticate(&self, ctx: &mut AuthContext, _conv: &mut dyn Conversation) -> Verdict {
+        let applies = self.users.as_ref().is_none_or(|users| users.contains(&ctx.user));
+        if !applies || self.allows(ctx.now) {
+            Verdict::Ignore
+        } else {
+            Verdict::Failure(Failure::Denied("この時間帯はログインできません".into()))
+        }
+    }
+}
+
+// --- tty ---
+
+/// `user:tty[,tty...]` の行で、ユーザーがログインできる端末を列挙する。
+///
+/// ユーザー名の行がなければ `*` の行を使い、どちらもなければ拒否する。端末名の `*` はすべての端末を表す。
+///
+/// ```text
+/// root:tty1,console
+/// *:*
+/// ```
+pub struct TtyModule {
+    path: PathBuf,
+}
+
+/// 許可リストの内容から、`user` が `tty` を使えるかを判定する
+fn tty_allowed(contents: &str, user: &str, tty: &str) -> bool {
+    let mut wildcard = None;
+    for line in contents.lines() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        let Some((name, ttys)) = line.split_once(':') else { continue; };
+        let matches = || ttys.split(',').map(str::trim).any(|t| t == "*" || (!tty.is_empty() && t == tty));
+        match name.trim() {
+            n if n == user => return matches(),
+            "*" if wildcard.is_none() => wildcard = Some(matches()),
+            _ => {}
+        }
+    }
+    wildcard.unwrap_or(false)
+}
+
+impl AuthModule for TtyModule {
+    fn name(&self) -> &'static str {
+        "tty"
+    }
+
+    fn authenticate(&self, ctx: &mut AuthContext, _conv: &mut dyn Conversation) -> Verdict {
+        match fs::read_to_string(&self.path) {
+            Ok(contents) if tty_allowed(&contents, &ctx.user, &ctx.tty) => Verdict::Ignore,
+            Ok(_) => Verdict::Failure(Failure::Denied("この端末からはログインできません".into())),
+            // 許可リストを読めない場合は拒否する
+            Err(e) => Verdict::Failure(Failure::System(format!("{}: {}", self.path.display(), e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::password::Algorithm;
+    use crate::stack::{AuthStack, Scripted};
+    use crate::testutil::TempDir;
+    use crate::totp::{Algorithm as TotpAlgorithm, TotpEntry};
+
+    /// 1970-01-05 (月曜日) の 0 時
+    const MONDAY: u64 = 4 * 86400;
+
+    fn context(user: &str, tty: &str, now: u64) -> AuthContext {
+        let mut ctx = AuthContext::new("login", user, tty);
+        ctx.now = now;
+        ctx
+    }
+
+    #[test]
+    fn test_build_options() {
+        assert!(build("shadow", &[("file".into(), "/tmp/x".into())]).is_ok());
+        assert!(build("shadow", &[("deny".into(), "3".into())]).is_err());
+        assert!(build("lockout", &[("deny".into(), "0".into())]).is_err());
+        assert!(build("time", &[("days".into(), "funday".into())]).is_err());
+        assert!(build("time", &[("hours".into(), "25:00-26:00".into())]).is_err());
+        assert!(build("nosuch", &[]).is_err());
+    }
+
+    #[test]
+    fn test_shadow_totp_and_lockout() {
+        let dir = TempDir::new("auth-modules");
+        let hash = HashPolicy { algorithm: Algorithm::Pbkdf2Sha256 { iterations: 10 } }.hash("secret", "salt");
+        let shadow_path = dir.join("shadow");
+        fs::write(&shadow_path, format!("horiz:{}:0:0:99999:7:::\nguest:{}:100:0:99999:7:::\n", hash, hash)).unwrap();
+        let totp_path = dir.join("totp");
+        let entry = TotpEntry::new("guest", b"12345678901234567890", TotpAlgorithm::Sha1, 6, 30);
+        totp::enroll(&totp_path, entry.clone(), false).unwrap();
+
+        let conf = format!(
+            "login requisite lockout deny=2 dir={}\nlogin requisite shadow file={}\nlogin required totp file={}\n",
+            dir.join("faillock").display(),
+            shadow_path.display(),
+            totp_path.display()
+        );
+        let stack = AuthStack::parse(&conf, "login").unwrap();
+        let now = 200 * 86400;
+        let login = |user: &str, inputs: &[&str], now: u64| {
+            let mut ctx = context(user, "tty1", now);
+            let mut conv = Scripted::new(inputs);
+            let result = stack.authenticate(&mut ctx, &mut conv);
+            (result, ctx, conv)
+        };
+
+        // 期限切れのパスワードも認証は成功とし、変更を呼び出し側に任せる
+        let (result, ctx, conv) = login("horiz", &["secret"], now);
+        assert_eq!(result, Ok(()));
+        assert!(ctx.password_expired && ctx.known_user);
+        assert_eq!(ctx.password.as_ref().map(|p| p.expose()), Some("secret"));
+        assert_eq!(conv.prompts, ["password: "]);
+
+        // 登録済みのユーザーにはパスワードの後で確認コードを求める
+        let (result, _, conv) = login("guest", &["secret", &entry.code_at(now)], now);
+        assert_eq!(result, Ok(()));
+        assert_eq!(conv.prompts, ["password: ", "verification code: "]);
+        let (result, _, conv) = login("guest", &["secret", &entry.code_at(now)], now);
+        assert_eq!(result, Err(Failure::BadCredentials));
+        assert_eq!(conv.messages, ["確認コードが正しくありません。"]);
+        // パスワードを誤った場合は確認コードを尋ねない (requisite)
+        let (result, _, conv) = login("guest", &["wrong"], now);
+        assert_eq!(result, Err(Failure::BadCredentials));
+        assert_eq!(conv.prompts.len(), 1);
+
+        // 2 回の失敗でロックされ、正しいパスワードも受け付けない
+        let (result, _, conv) = login("guest", &["secret"], now);
+        assert_eq!(result, Err(Failure::TooManyFailures(15 * 60)));
+        assert!(conv.prompts.is_empty());
+        let (result, _, _) = login("guest", &["secret", &entry.code_at(now + 15 * 60)], now + 15 * 60);
+        assert_eq!(result, Ok(()));
+        assert!(!dir.join("faillock/guest").exists());
+
+        // 存在しないユーザーの失敗は記録しない
+        let (result, ctx, _) = login("nobody", &["secret"], now);
+        assert_eq!(result, Err(Failure::BadCredentials));
+        assert!(!ctx.known_user);
+        assert!(!dir.join("faillock/nobody").exists());
+
+        // 入力を読み取れない場合 (入力の終わりなど) は空のパスワードとみなさず、システムエラーとする
+        let (result, _, _) = login("horiz", &[], now);
+        assert!(matches!(result, Err(Failure::System(_))));
+    }
+
+    #[test]
+    fn test_time() {
+        let module = TimeModule::from_options(Some("mon-fri"), Some("09:00-18:00"), None).unwrap();
+        assert!(module.allows(MONDAY + 9 * 3600));
+        assert!(!module.allows(MONDAY + 18 * 3600));
+        assert!(!module.allows(MONDAY + 8 * 3600 + 59 * 60));
+        // 1970-01-04 は日曜日
+        assert!(!module.allows(MONDAY - 86400 + 12 * 3600));
+
+        let night = TimeModule::from_options(Some("fri-mon"), Some("22:00-06:00"), Some("guest")).unwrap();
+        assert!(night.allows(MONDAY + 23 * 3600));
+        assert!(night.allows(MONDAY + 5 * 3600));
+        assert!(!night.allows(MONDAY + 12 * 3600));
+        assert!(!night.allows(MONDAY + 86400 + 23 * 3600));
+
+        // users= に含まれないユーザーは制限しない
+        let mut conv = Scripted::new(&[]);
+        let noon = MONDAY + 12 * 3600;
+        assert_eq!(night.authenticate(&mut context("horiz", "tty1", noon), &mut conv), Verdict::Ignore);
+        assert!(matches!(night.authenticate(&mut context("guest", "tty1", noon), &mut conv), Verdict::Failure(Failure::Denied(_))));
+    }
+
+    #[test]
+    fn test_tty_allowed() {
+        let list = "# comment\nroot:tty1, console\nguest:\n*:tty1,tty2\n";
+        assert!(tty_allowed(list, "root", "console"));
+        assert!(!tty_allowed(list, "root", "tty2"));
+        assert!(!tty_allowed(list, "guest", "tty1"));
+        assert!(tty_allowed(list, "horiz", "tty2"));
+        assert!(!tty_allowed(list, "horiz", "ttyS0"));
+        assert!(!tty_allowed(list, "horiz", ""));
+        assert!(tty_allowed("horiz:*", "horiz", ""));
+        assert!(!tty_allowed("root:*", "horiz", "tty1"));
+
+        // 許可リストがない場合は拒否する
+        let module = TtyModule { path: PathBuf::from("/nonexistent/tty.allow") };
+        let verdict = module.authenticate(&mut context("root", "tty1", 0), &mut Scripted::new(&[]));
+        assert!(matches!(verdict, Verdict::Failure(Failure::System(_))));
+    }
+}
@@ -0,0 +1,133 @@
+// --- 過去のパスワードの履歴 (/etc/security/opasswd) ---
+//
+// pam_pwhistory と同じ形式で、変更前のハッシュを古い順に記録する:
+//
+//   name:uid:count:hash1,hash2,...
+//
+// root のみが読み書きできるファイル (0600) とし、同じディレクトリの `.pwd.lock` の下で更新する。
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::error::AuthError;
+use crate::password::{self, verify_password};
+use crate::userdb::{Database, DbFile, Entry, PwdLock};
+
+pub const OPASSWD_PATH: &str = "/etc/security/opasswd";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpasswdEntry {
+    pub name: String,
+    pub uid: u32,
+    /// 古い順
+    pub hashes: Vec<String>,
+}
+
+impl Entry for OpasswdEntry {
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.len() != 4 || fields[0].is_empty() {
+            return None;
+        }
+        let hashes: Vec<String> = fields[3].split(',').filter(|h| !h.is_empty()).map(String::from).collect();
+        // count はハッシュの数と一致しなくてもよい (pam_pwhistory も参照しない)
+        let _count: usize = fields[2].parse().ok()?;
+        Some(OpasswdEntry { name: fields[0].to_string(), uid: fields[1].parse().ok()?, hashes })
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![self.name.clone(), self.uid.to_string(), self.hashes.len().to_string(), self.hashes.join(",")]
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// ユーザーの履歴 (古い順)。ファイルがない場合は空とする。
+pub fn read_history(path: &Path, username: &str) -> Result<Vec<String>, AuthError> {
+    let db = match Database::<OpasswdEntry>::load(path) {
+        Ok(db) => db,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    match (db.get(username), db.malformed_line(username)) {
+        (None, Some(line)) => Err(AuthError::MalformedEntry { path: path.to_path_buf(), line }),
+        (entry, _) => Ok(entry.map(|e| e.hashes.clone()).unwrap_or_default()),
+    }
+}
+
+/// `password` が現在のハッシュ `current`、または直近 `remember` 個の履歴のいずれかと一致するか
+pub fn is_reused(path: &Path, username: &str, password: &str, current: Option<&str>, remember: usize) -> Result<bool, AuthError> {
+    let history = read_history(path, username)?;
+    let recent = &history[history.len().saturating_sub(remember)..];
+    let current = current.map(|c| c.trim_start_matches('!'));
+    Ok(current.into_iter().chain(recent.iter().map(String::as_str)).any(|hash| verify_password(password, hash)))
+}
+
+/// 変更前のハッシュを履歴に追加し、直近 `remember` 個のみ残す。
+///
+/// 照合できない値 (空, `*`, `!` のみなど) は記録しない。`remember` が 0 の場合はユーザーの履歴を削除する。
+pub fn record(path: &Path, username: &str, uid: u32, old_hash: &str, remember: usize) -> io::Result<()> {
+    let old_hash = old_hash.trim_start_matches('!');
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    let _lock = PwdLock::acquire(dir)?;
+    let mut file = DbFile::<OpasswdEntry>::open(path, 0o600)?;
+
+    if remember == 0 {
+        file.remove(username);
+    } else if password::scheme_of(old_hash).is_some() {
+        if file.get(username).is_none() {
+            file.insert(OpasswdEntry { name: username.to_string(), uid, hashes: Vec::new() });
+        }
+        let entry = file.get_mut(username).expect("inserted above");
+        entry.uid = uid;
+        entry.hashes.push(old_hash.to_string());
+        let excess = entry.hashes.len().saturating_sub(remember);
+        entry.hashes.drain(..excess);
+    }
+    if !file.is_modified() {
+        return Ok(());
+    }
+    file.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::password::{Algorithm, HashPolicy};
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_record_and_reuse() {
+        let dir = TempDir::new("opasswd");
+        let path = dir.join("security/opasswd");
+        let policy = HashPolicy { algorithm: Algorithm::Pbkdf2Sha256 { iterations: 10 } };
+        let hash = |p: &str| policy.hash(p, "salt");
+
+        assert!(read_history(&path, "horiz").unwrap().is_empty());
+        record(&path, "horiz", 1000, &hash("first"), 2).unwrap();
+        record(&path, "horiz", 1000, "!", 2).unwrap();
+        record(&path, "horiz", 1000, &format!("!{}", hash("second")), 2).unwrap();
+        record(&path, "horiz", 1000, &hash("third"), 2).unwrap();
+        assert_eq!(read_history(&path, "horiz").unwrap(), [hash("second"), hash("third")]);
+        assert!(fs::read_to_string(&path).unwrap().starts_with("horiz:1000:2:"));
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        assert!(is_reused(&path, "horiz", "third", None, 5).unwrap());
+        assert!(!is_reused(&path, "horiz", "first", None, 5).unwrap());
+        // remember より古い履歴は照合しない
+        assert!(!is_reused(&path, "horiz", "second", None, 1).unwrap());
+        assert!(is_reused(&path, "horiz", "current", Some(&format!("!{}", hash("current"))), 0).unwrap());
+        assert!(!is_reused(&path, "root", "third", None, 5).unwrap());
+
+        record(&path, "horiz", 1000, &hash("fourth"), 0).unwrap();
+        assert!(read_history(&path, "horiz").unwrap().is_empty());
+
+        fs::write(&path, "horiz:x:1:abc\n").unwrap();
+        assert!(matches!(read_history(&path, "horiz"), Err(AuthError::MalformedEntry { line: 1, .. })));
+    }
+}
@@ -0,0 +1,88 @@
+// --- /etc/passwd ---
+//
+// name:password:uid:gid:gecos:home:shell
+
+use std::io;
+use std::path::Path;
+
+use crate::userdb::{Database, Entry};
+
+pub const PASSWD_PATH: &str = "/etc/passwd";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PasswdEntry {
+    pub name: String,
+    /// 通常は `x` (ハッシュは /etc/shadow に置く)
+    pub password: String,
+    pub uid: u32,
+    pub gid: u32,
+    pub gecos: String,
+    pub home: String,
+    pub shell: String,
+}
+
+impl Entry for PasswdEntry {
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.len() != 7 || fields[0].is_empty() {
+            return None;
+        }
+        Some(PasswdEntry {
+            name: fields[0].to_string(),
+            password: fields[1].to_string(),
+            uid: fields[2].parse().ok()?,
+            gid: fields[3].parse().ok()?,
+            gecos: fields[4].to_string(),
+            home: fields[5].to_string(),
+            shell: fields[6].to_string(),
+        })
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.password.clone(),
+            self.uid.to_string(),
+            self.gid.to_string(),
+            self.gecos.clone(),
+            self.home.clone(),
+            self.shell.clone(),
+        ]
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Database<PasswdEntry> {
+    pub fn by_uid(&self, uid: u32) -> Option<&PasswdEntry> {
+        self.entries().find(|e| e.uid == uid)
+    }
+}
+
+/// ユーザー名で検索する (getpwnam 相当)
+pub fn getpwnam(name: &str) -> io::Result<Option<PasswdEntry>> {
+    Ok(Database::<PasswdEntry>::load(Path::new(PASSWD_PATH))?.get(name).cloned())
+}
+
+/// UID で検索する (getpwuid 相当)
+pub fn getpwuid(uid: u32) -> io::Result<Option<PasswdEntry>> {
+    Ok(Database::<PasswdEntry>::load(Path::new(PASSWD_PATH))?.by_uid(uid).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let db = Database::<PasswdEntry>::parse("root:x:0:0:root:/root:/bin/sh\nhoriz:x:1000:1000:horiz:/home/horiz:/bin/sh\n");
+        let horiz = db.get("horiz").unwrap();
+        assert_eq!((horiz.uid, horiz.gid, horiz.home.as_str()), (1000, 1000, "/home/horiz"));
+        assert_eq!(db.by_uid(0).unwrap().name, "root");
+        assert!(db.by_uid(1).is_none());
+        assert!(PasswdEntry::parse("root:x:zero:0:root:/root:/bin/sh").is_none());
+        assert!(PasswdEntry::parse("root:x:0:0:/root:/bin/sh").is_none());
+    }
+}
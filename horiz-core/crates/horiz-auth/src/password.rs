@@ -0,0 +1,275 @@
+// --- パスワードハッシュ形式 ($hz$ 旧形式 / $hz2$ バージョン付き形式) ---
+//
+// $hz2$alg=<アルゴリズム>$cost=<コスト>$<ソルト>$<Base64ハッシュ>
+//   alg=pbkdf2-sha256  cost=<反復回数>
+//   alg=scrypt         cost=<log2 N>,<r>,<p>
+//
+// 他システムから移行したエントリのため、crypt(3) 形式 ($5$ / $6$ / $y$) の検証にも対応する。
+
+use crate::config;
+use crate::crypt::{self, CryptMethod};
+use crate::pbkdf2::pbkdf2_hmac_sha256;
+use crate::scrypt::scrypt;
+use crate::{base64_encode, constant_time_eq, hash_password};
+
+pub const POLICY_PATH: &str = "/etc/horiz/password.conf";
+
+const HASH_LEN: usize = 32;
+
+// 不正なシャドウエントリによる過大な計算・メモリ消費を防ぐための上限
+const MAX_PBKDF2_ITERATIONS: u32 = 10_000_000;
+const MAX_SCRYPT_LOG_N: u8 = 20;
+const MAX_SCRYPT_R: u32 = 32;
+const MAX_SCRYPT_P: u32 = 16;
+
+/// 鍵導出アルゴリズムとそのコストパラメータ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Pbkdf2Sha256 { iterations: u32 },
+    Scrypt { log_n: u8, r: u32, p: u32 },
+}
+
+impl Algorithm {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Pbkdf2Sha256 { .. } => "pbkdf2-sha256",
+            Algorithm::Scrypt { .. } => "scrypt",
+        }
+    }
+
+    fn cost(&self) -> String {
+        match self {
+            Algorithm::Pbkdf2Sha256 { iterations } => iterations.to_string(),
+            Algorithm::Scrypt { log_n, r, p } => format!("{},{},{}", log_n, r, p),
+        }
+    }
+
+    fn from_parts(name: &str, cost: &str) -> Option<Self> {
+        let alg = match name {
+            "pbkdf2-sha256" => Algorithm::Pbkdf2Sha256 { iterations: cost.parse().ok()? },
+            "scrypt" => {
+                let mut it = cost.split(',');
+                let log_n = it.next()?.parse().ok()?;
+                let r = it.next()?.parse().ok()?;
+                let p = it.next()?.parse().ok()?;
+                if it.next().is_some() { return None; }
+                Algorithm::Scrypt { log_n, r, p }
+            }
+            _ => return None,
+        };
+        if alg.is_within_limits() { Some(alg) } else { None }
+    }
+
+    fn is_within_limits(&self) -> bool {
+        match *self {
+            Algorithm::Pbkdf2Sha256 { iterations } => (1..=MAX_PBKDF2_ITERATIONS).contains(&iterations),
+            Algorithm::Scrypt { log_n, r, p } => {
+                (1..=MAX_SCRYPT_LOG_N).contains(&log_n)
+                    && (1..=MAX_SCRYPT_R).contains(&r)
+                    && (1..=MAX_SCRYPT_P).contains(&p)
+            }
+        }
+    }
+
+    fn derive(&self, password: &str, salt: &str) -> Vec<u8> {
+        match *self {
+            Algorithm::Pbkdf2Sha256 { iterations } => {
+                pbkdf2_hmac_sha256(password.as_bytes(), salt.as_bytes(), iterations, HASH_LEN)
+            }
+            Algorithm::Scrypt { log_n, r, p } => scrypt(password.as_bytes(), salt.as_bytes(), log_n, r, p, HASH_LEN),
+        }
+    }
+}
+
+/// 新規に生成するハッシュの方式 (/etc/horiz/password.conf で設定可能)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashPolicy {
+    pub algorithm: Algorithm,
+}
+
+impl Default for HashPolicy {
+    /// scrypt (N = 2^15, r = 8, p = 1: 約 32 MiB)
+    fn default() -> Self {
+        HashPolicy { algorithm: Algorithm::Scrypt { log_n: 15, r: 8, p: 1 } }
+    }
+}
+
+impl HashPolicy {
+    /// 設定ファイルからポリシーを読み込む。未指定・不正な値は既定値を使う。
+    ///
+    /// ```text
+    /// algorithm=scrypt          # scrypt | pbkdf2-sha256
+    /// pbkdf2_iterations=600000
+    /// scrypt_log_n=15
+    /// scrypt_r=8
+    /// scrypt_p=1
+    /// ```
+    pub fn load() -> Self {
+        Self::from_config(&config::load_key_values(POLICY_PATH))
+    }
+
+    fn from_config(pairs: &[(String, String)]) -> Self {
+        let get = |key: &str| config::get(pairs, key);
+
+        let mut iterations = 600_000u32;
+        let (mut log_n, mut r, mut p) = (15u8, 8u32, 1u32);
+        if let Some(v) = get("pbkdf2_iterations").and_then(|v| v.parse().ok()) { iterations = v; }
+        if let Some(v) = get("scrypt_log_n").and_then(|v| v.parse().ok()) { log_n = v; }
+        if let Some(v) = get("scrypt_r").and_then(|v| v.parse().ok()) { r = v; }
+        if let Some(v) = get("scrypt_p").and_then(|v| v.parse().ok()) { p = v; }
+
+        let algorithm = match get("algorithm") {
+            Some("pbkdf2-sha256") => Algorithm::Pbkdf2Sha256 { iterations },
+            _ => Algorithm::Scrypt { log_n, r, p },
+        };
+        if algorithm.is_within_limits() { HashPolicy { algorithm } } else { Self::default() }
+    }
+
+    /// `$hz2$` 形式のシャドウエントリを生成する
+    pub fn hash(&self, password: &str, salt: &str) -> String {
+        let hash = self.algorithm.derive(password, salt);
+        format!(
+            "$hz2$alg={}$cost={}${}${}",
+            self.algorithm.name(),
+            self.algorithm.cost(),
+            salt,
+            base64_encode(&hash)
+        )
+    }
+}
+
+/// シャドウのパスワードフィールドが示すハッシュ方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    /// `$hz$salt$hash` (SHA-256 の 10,000 回ストレッチング)
+    Legacy,
+    Hz2(Algorithm),
+    /// crypt(3) 互換形式 (検証のみ。ログイン時に現在のポリシーへ移行される)
+    Crypt(CryptMethod),
+}
+
+struct Parsed<'a> {
+    scheme: Scheme,
+    salt: &'a str,
+    hash: &'a str,
+}
+
+fn parse(encoded: &str) -> Option<Parsed<'_>> {
+    if let Some(rest) = encoded.strip_prefix("$hz$") {
+        let (salt, hash) = rest.split_once('$')?;
+        if hash.contains('$') { return None; }
+        return Some(Parsed { scheme: Scheme::Legacy, salt, hash });
+    }
+
+    // crypt(3) 形式はソルトを含むハッシュ文字列全体で比較する
+    if !encoded.starts_with("$hz2$") {
+        let method = crypt::method_of(encoded)?;
+        return Some(Parsed { scheme: Scheme::Crypt(method), salt: "", hash: encoded });
+    }
+
+    let rest = encoded.strip_prefix("$hz2$")?;
+    let segments: Vec<&str> = rest.split('$').collect();
+    if segments.len() != 4 { return None; }
+    let name = segments[0].strip_prefix("alg=")?;
+    let cost = segments[1].strip_prefix("cost=")?;
+    let algorithm = Algorithm::from_parts(name, cost)?;
+    Some(Parsed { scheme: Scheme::Hz2(algorithm), salt: segments[2], hash: segments[3] })
+}
+
+/// パスワードフィールドのハッシュ方式を判定する (未対応・不正なら None)
+pub fn scheme_of(encoded: &str) -> Option<Scheme> {
+    parse(encoded).map(|p| p.scheme)
+}
+
+/// ハッシュが現在のポリシーと異なる方式・コストで生成されているか判定する
+///
+/// 検証に成功したパスワードをこの判定に従って再ハッシュすることで、
+/// ポリシー変更時にパスワードの再設定なしで移行できる。
+pub fn needs_rehash(encoded: &str, policy: &HashPolicy) -> bool {
+    scheme_of(encoded) != Some(Scheme::Hz2(policy.algorithm))
+}
+
+/// パスワードをシャドウのパスワードフィールドと照合する (定数時間比較)
+pub fn verify_password(password: &str, encoded: &str) -> bool {
+    let Some(parsed) = parse(encoded) else { return false; };
+    let computed = match parsed.scheme {
+        Scheme::Legacy => hash_password(password, parsed.salt),
+        Scheme::Hz2(alg) => base64_encode(&alg.derive(password, parsed.salt)),
+        Scheme::Crypt(_) => match crypt::crypt(password.as_bytes(), encoded) {
+            Some(computed) => computed,
+            None => return false,
+        },
+    };
+    constant_time_eq(computed.as_bytes(), parsed.hash.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // テストでは計算量の小さいパラメータを使う
+    const FAST_SCRYPT: HashPolicy = HashPolicy { algorithm: Algorithm::Scrypt { log_n: 4, r: 1, p: 1 } };
+    const FAST_PBKDF2: HashPolicy = HashPolicy { algorithm: Algorithm::Pbkdf2Sha256 { iterations: 10 } };
+
+    #[test]
+    fn test_hz2_roundtrip() {
+        for policy in [FAST_SCRYPT, FAST_PBKDF2] {
+            let encoded = policy.hash("secret", "c2FsdA==");
+            assert!(encoded.starts_with(&format!("$hz2$alg={}$cost=", policy.algorithm.name())));
+            assert_eq!(scheme_of(&encoded), Some(Scheme::Hz2(policy.algorithm)));
+            assert!(verify_password("secret", &encoded));
+            assert!(!verify_password("Secret", &encoded));
+        }
+    }
+
+    #[test]
+    fn test_legacy_format_still_verifies() {
+        let encoded = format!("$hz$root_salt${}", hash_password("root", "root_salt"));
+        assert_eq!(scheme_of(&encoded), Some(Scheme::Legacy));
+        assert!(verify_password("root", &encoded));
+        assert!(!verify_password("toor", &encoded));
+        // rootfs テンプレートの既存エントリ
+        assert!(verify_password("root", "$hz$root_salt$BBokeezCQGs8b+hGmQc0VQxF4I6eYAA/xH3goB2fCLs="));
+    }
+
+    #[test]
+    fn test_crypt_formats_verify_and_need_rehash() {
+        let sha512 = "$6$saltstring$svn8UoSVapNtMuq1ukKS4tPQd8iKwSMHWjl/O817G3uBnIFNjnQJuesI68u4OTLiBFdcbYEdFCoEOfaS35inz1";
+        let yescrypt = "$y$j75$LdJMENpBABJJ3hIHjB1Bi.$YHtrg5JwVUTWV0NoyCEtFoufzPCIyF7PGqZxL86OqT7";
+        assert_eq!(scheme_of(sha512), Some(Scheme::Crypt(CryptMethod::Sha512Crypt)));
+        assert!(verify_password("Hello world!", sha512));
+        assert!(!verify_password("hello world!", sha512));
+        assert!(needs_rehash(sha512, &FAST_SCRYPT));
+        assert_eq!(scheme_of(yescrypt), Some(Scheme::Crypt(CryptMethod::Yescrypt)));
+        assert!(verify_password("test", yescrypt));
+        assert!(!verify_password("wrong", "$5$saltstring$5B8vYYiY.CVt1RlTTf8KbXBH3hsxY/GNooZaBBGWEc5"));
+        assert!(verify_password("Hello world!", "$5$saltstring$5B8vYYiY.CVt1RlTTf8KbXBH3hsxY/GNooZaBBGWEc5"));
+    }
+
+    #[test]
+    fn test_rejects_malformed_and_excessive_cost() {
+        assert_eq!(scheme_of("$hz2$alg=md5$cost=1$salt$hash"), None);
+        assert_eq!(scheme_of("$hz2$alg=scrypt$cost=30,8,1$salt$hash"), None);
+        assert_eq!(scheme_of("$hz2$alg=pbkdf2-sha256$cost=0$salt$hash"), None);
+        assert_eq!(scheme_of("$hz2$alg=scrypt$cost=4,1,1$salt"), None);
+        assert!(!verify_password("", "!"));
+    }
+
+    #[test]
+    fn test_needs_rehash() {
+        let current = FAST_SCRYPT.hash("secret", "salt");
+        assert!(!needs_rehash(&current, &FAST_SCRYPT));
+        assert!(needs_rehash(&current, &FAST_PBKDF2));
+        let weaker = HashPolicy { algorithm: Algorithm::Scrypt { log_n: 3, r: 1, p: 1 } }.hash("secret", "salt");
+        assert!(needs_rehash(&weaker, &FAST_SCRYPT));
+        assert!(needs_rehash("$hz$salt$hash", &FAST_SCRYPT));
+    }
+
+    #[test]
+    fn test_policy_from_config() {
+        let pairs = config::parse_key_values("algorithm = pbkdf2-sha256\npbkdf2_iterations=1000 # comment\n");
+        assert_eq!(HashPolicy::from_config(&pairs).algorithm, Algorithm::Pbkdf2Sha256 { iterations: 1000 });
+        let pairs = config::parse_key_values("scrypt_log_n=99\n");
+        assert_eq!(HashPolicy::from_config(&pairs), HashPolicy::default());
+    }
+}
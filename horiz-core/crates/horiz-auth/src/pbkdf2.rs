@@ -0,0 +1,52 @@
+// --- PBKDF2-HMAC-SHA-256 (Zero-Dependency, RFC 8018) ---
+//
+// PBKDF2 は反復ごとに HMAC を計算するため、鍵を事前処理した HmacSha256 を使い回して
+// SHA-256 の圧縮回数を半分に抑える。
+
+use horiz_crypto::hmac::HmacSha256;
+
+/// PBKDF2-HMAC-SHA-256 (RFC 8018 §5.2)
+pub fn pbkdf2_hmac_sha256(password: &[u8], salt: &[u8], iterations: u32, dk_len: usize) -> Vec<u8> {
+    let prf = HmacSha256::new(password);
+    let mut out = Vec::with_capacity(dk_len);
+    let mut block_index = 1u32;
+
+    while out.len() < dk_len {
+        let mut msg = salt.to_vec();
+        msg.extend_from_slice(&block_index.to_be_bytes());
+        let mut u = prf.mac(&msg);
+        let mut t = u;
+        for _ in 1..iterations {
+            u = prf.mac(&u);
+            for (a, b) in t.iter_mut().zip(u.iter()) {
+                *a ^= b;
+            }
+        }
+        out.extend_from_slice(&t);
+        block_index += 1;
+    }
+
+    out.truncate(dk_len);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_hex(data: &[u8]) -> String {
+        data.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_pbkdf2_rfc7914() {
+        // RFC 7914 §11
+        let dk = pbkdf2_hmac_sha256(b"passwd", b"salt", 1, 64);
+        assert_eq!(
+            to_hex(&dk),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+        );
+        let dk = pbkdf2_hmac_sha256(b"password", b"salt", 4096, 32);
+        assert_eq!(to_hex(&dk), "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a");
+    }
+}
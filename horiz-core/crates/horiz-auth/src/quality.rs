@@ -0,0 +1,297 @@
+// --- 新しいパスワードの品質ポリシー ---
+//
+// 長さ・文字の種類・ユーザー名との類似・辞書の単語・過去のパスワードの再利用を検査する。
+// 設定は /etc/horiz/pwquality.conf (key=value)。
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::error::AuthError;
+use crate::opasswd;
+
+pub const CONFIG_PATH: &str = "/etc/horiz/pwquality.conf";
+pub const DICTIONARY_PATH: &str = "/usr/share/dict/words";
+
+/// 辞書の単語のうち、これより短いものは照合しない
+const MIN_WORD_LEN: usize = 4;
+
+/// 品質ポリシーに違反した理由
+#[derive(Debug)]
+pub enum QualityError {
+    TooShort { min: usize },
+    TooFewClasses { required: usize, found: usize },
+    /// ユーザー名 (またはその逆順) を含む
+    BasedOnUsername,
+    /// 辞書の単語 (大文字小文字・前後の数字や記号・よくある置き換えを無視して) と一致する
+    DictionaryWord,
+    /// 現在のパスワード、または直近 `remember` 回のパスワードと同じ
+    Reused { remember: usize },
+    /// 辞書や履歴を読めない
+    Auth(AuthError),
+}
+
+impl fmt::Display for QualityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QualityError::TooShort { min } => write!(f, "パスワードが短すぎます ({} 文字以上にしてください)", min),
+            QualityError::TooFewClasses { required, found } => write!(
+                f,
+                "文字の種類が足りません (英小文字・英大文字・数字・記号のうち {} 種類以上が必要ですが、{} 種類です)",
+                required, found
+            ),
+            QualityError::BasedOnUsername => write!(f, "ユーザー名に基づくパスワードは使用できません"),
+            QualityError::DictionaryWord => write!(f, "辞書の単語に基づくパスワードは使用できません"),
+            QualityError::Reused { remember: 0 } => write!(f, "現在と同じパスワードは使用できません"),
+            QualityError::Reused { remember } => {
+                write!(f, "最近使用したパスワードは使用できません (現在と直近 {} 回のパスワード)", remember)
+            }
+            QualityError::Auth(e) => write!(f, "パスワードを検査できません: {}", e),
+        }
+    }
+}
+
+impl From<AuthError> for QualityError {
+    fn from(e: AuthError) -> Self {
+        QualityError::Auth(e)
+    }
+}
+
+/// ```text
+/// min_length=8          # 最小の文字数
+/// min_classes=2         # 英小文字・英大文字・数字・記号のうち必要な種類の数 (1〜4)
+/// reject_username=yes   # ユーザー名を含むパスワードを拒否する
+/// dictionary=/usr/share/dict/words   # 空にすると辞書の検査を行わない
+/// remember=5            # 再利用を禁止する過去のパスワードの数 (0 で履歴を残さない)
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QualityPolicy {
+    pub min_length: usize,
+    pub min_classes: usize,
+    pub reject_username: bool,
+    /// 存在しない場合は検査しない
+    pub dictionary: Option<PathBuf>,
+    pub remember: usize,
+    pub history_path: PathBuf,
+}
+
+impl Default for QualityPolicy {
+    fn default() -> Self {
+        QualityPolicy {
+            min_length: 8,
+            min_classes: 2,
+            reject_username: true,
+            dictionary: Some(PathBuf::from(DICTIONARY_PATH)),
+            remember: 5,
+            history_path: PathBuf::from(opasswd::OPASSWD_PATH),
+        }
+    }
+}
+
+impl QualityPolicy {
+    pub fn load() -> Self {
+        Self::from_config(&config::load_key_values(CONFIG_PATH))
+    }
+
+    /// 未指定・範囲外の値は既定値を使う
+    pub fn from_config(pairs: &[(String, String)]) -> Self {
+        let mut policy = Self::default();
+        if let Some(v) = config::get(pairs, "min_length").and_then(|v| v.parse().ok()).filter(|v| (1..=256).contains(v)) {
+            policy.min_length = v;
+        }
+        if let Some(v) = config::get(pairs, "min_classes").and_then(|v| v.parse().ok()).filter(|v| (1..=4).contains(v)) {
+            policy.min_classes = v;
+        }
+        match config::get(pairs, "reject_username") {
+            Some("yes" | "true" | "1") => policy.reject_username = true,
+            Some("no" | "false" | "0") => policy.reject_username = false,
+            _ => {}
+        }
+        if let Some(v) = config::get(pairs, "dictionary") {
+            policy.dictionary = (!v.is_empty()).then(|| PathBuf::from(v));
+        }
+        if let Some(v) = config::get(pairs, "remember").and_then(|v| v.parse().ok()).filter(|v| *v <= 400) {
+            policy.remember = v;
+        }
+        policy
+    }
+
+    /// 履歴を除く検査
+    pub fn check(&self, username: &str, password: &str) -> Result<(), QualityError> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return Err(QualityError::TooShort { min: self.min_length });
+        }
+        let found = character_classes(password);
+        if found < self.min_classes {
+            return Err(QualityError::TooFewClasses { required: self.min_classes, found });
+        }
+        if self.reject_username && is_based_on(password, username) {
+            return Err(QualityError::BasedOnUsername);
+        }
+        if let Some(dictionary) = &self.dictionary
+            && in_dictionary(dictionary, password).map_err(AuthError::from)?
+        {
+            return Err(QualityError::DictionaryWord);
+        }
+        Ok(())
+    }
+
+    /// すべての検査。`current` は現在のパスワードフィールド (同じパスワードへの変更を拒否する)。
+    pub fn check_with_history(&self, username: &str, password: &str, current: Option<&str>) -> Result<(), QualityError> {
+        self.check(username, password)?;
+        if opasswd::is_reused(&self.history_path, username, password, current, self.remember)? {
+            return Err(QualityError::Reused { remember: self.remember });
+        }
+        Ok(())
+    }
+
+    /// 変更前のハッシュを履歴に記録する
+    pub fn record_history(&self, username: &str, uid: u32, old_hash: &str) -> io::Result<()> {
+        opasswd::record(&self.history_path, username, uid, old_hash, self.remember)
+    }
+}
+
+/// 英小文字・英大文字・数字・その他 (記号や ASCII 以外) のうち含まれる種類の数
+fn character_classes(password: &str) -> usize {
+    let classes = [
+        password.chars().any(|c| c.is_ascii_lowercase()),
+        password.chars().any(|c| c.is_ascii_uppercase()),
+        password.chars().any(|c| c.is_ascii_digit()),
+        password.chars().any(|c| !c.is_ascii_alphanumeric()),
+    ];
+    classes.iter().filter(|&&c| c).count()
+}
+
+/// 小文字にし、よくある置き換え (p@ssw0rd など) を元に戻す
+fn normalize(s: &str) -> String {
+    s.chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            '0' => 'o',
+            '1' | '!' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' => 't',
+            c => c,
+        })
+        .collect()
+}
+
+/// ユーザー名 (またはその逆順) を含むか。2 文字以下のユーザー名は完全一致のみ拒否する。
+fn is_based_on(password: &str, username: &str) -> bool {
+    if username.is_empty() {
+        return false;
+    }
+    let name = username.to_lowercase();
+    let reversed: String = name.chars().rev().collect();
+    let candidates = [password.to_lowercase(), normalize(password)];
+    if name.chars().count() < 3 {
+        return candidates.iter().any(|c| *c == name || *c == reversed);
+    }
+    candidates.iter().any(|c| c.contains(&name) || c.contains(&reversed))
+}
+
+/// 辞書と照合する候補: 前後の英字以外・数字や記号を除いたもの、その置き換えを戻したもの、それらの逆順
+fn dictionary_candidates(password: &str) -> Vec<String> {
+    let not_alphabetic = |c: char| !c.is_alphabetic();
+    let core = password.trim_matches(not_alphabetic);
+    // 先頭の $ や @ は置き換えとみなす ($ecret1 → secret)
+    let affixed = password.trim_start_matches(|c: char| c.is_ascii_digit()).trim_end_matches(|c: char| c.is_ascii_digit() || c.is_ascii_punctuation());
+    let mut candidates = vec![core.to_lowercase(), normalize(core), normalize(affixed)];
+    let reversed: Vec<String> = candidates.iter().map(|c| c.chars().rev().collect()).collect();
+    candidates.extend(reversed);
+    candidates.retain(|c| c.chars().count() >= MIN_WORD_LEN);
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+/// 単語リスト (1 行 1 語) に含まれるか。辞書がない場合は false。
+fn in_dictionary(path: &Path, password: &str) -> io::Result<bool> {
+    let candidates = dictionary_candidates(password);
+    if candidates.is_empty() {
+        return Ok(false);
+    }
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    for word in BufReader::new(file).split(b'\n') {
+        let word = word?;
+        let Ok(word) = std::str::from_utf8(&word) else { continue; };
+        let word = word.trim();
+        if word.chars().count() >= MIN_WORD_LEN && candidates.iter().any(|c| c.eq_ignore_ascii_case(word)) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::password::{Algorithm, HashPolicy};
+    use crate::testutil::TempDir;
+    use std::fs;
+
+    fn policy(dir: &Path) -> QualityPolicy {
+        let dictionary = dir.join("words");
+        fs::write(&dictionary, "password\nDragon\nabc\nsunshine\n").unwrap();
+        QualityPolicy { dictionary: Some(dictionary), history_path: dir.join("opasswd"), remember: 2, ..QualityPolicy::default() }
+    }
+
+    #[test]
+    fn test_check() {
+        let dir = TempDir::new("quality");
+        let policy = policy(&dir);
+
+        assert!(policy.check("horiz", "Tr0ub4dor&3x").is_ok());
+        assert!(matches!(policy.check("horiz", "Short1"), Err(QualityError::TooShort { min: 8 })));
+        // 文字数は char 単位で数える
+        assert!(policy.check("horiz", "パスワード1234").is_ok());
+        assert!(matches!(policy.check("horiz", "alllowercase"), Err(QualityError::TooFewClasses { required: 2, found: 1 })));
+
+        assert!(matches!(policy.check("horiz", "myHoriz2024"), Err(QualityError::BasedOnUsername)));
+        assert!(matches!(policy.check("horiz", "ziroh-2024"), Err(QualityError::BasedOnUsername)));
+        assert!(matches!(policy.check("horiz", "H0r1z!2024"), Err(QualityError::BasedOnUsername)));
+        assert!(policy.check("al", "Always-2024").is_ok());
+
+        for word in ["Password123", "p@ssw0rd!!", "DRAGON-99", "2024enihsnus", "$un$hine1"] {
+            assert!(matches!(policy.check("horiz", word), Err(QualityError::DictionaryWord)), "{}", word);
+        }
+        assert!(policy.check("horiz", "Dragonfly-99").is_ok());
+        let without = QualityPolicy { dictionary: Some(dir.join("missing")), ..policy.clone() };
+        assert!(without.check("horiz", "Password123").is_ok());
+    }
+
+    #[test]
+    fn test_history() {
+        let dir = TempDir::new("quality-history");
+        let policy = policy(&dir);
+        let hash = |p: &str| HashPolicy { algorithm: Algorithm::Pbkdf2Sha256 { iterations: 10 } }.hash(p, "salt");
+
+        policy.record_history("horiz", 1000, &hash("Old-pass-1")).unwrap();
+        assert!(matches!(policy.check_with_history("horiz", "Old-pass-1", None), Err(QualityError::Reused { remember: 2 })));
+        assert!(matches!(
+            policy.check_with_history("horiz", "Current-1", Some(&hash("Current-1"))),
+            Err(QualityError::Reused { .. })
+        ));
+        assert!(policy.check_with_history("horiz", "Brand-new-1", Some(&hash("Current-1"))).is_ok());
+    }
+
+    #[test]
+    fn test_config() {
+        let policy = QualityPolicy::from_config(&config::parse_key_values(
+            "min_length=12\nmin_classes=5\nreject_username=no\ndictionary=\nremember=0\n",
+        ));
+        assert_eq!(policy.min_length, 12);
+        assert_eq!(policy.min_classes, 2);
+        assert!(!policy.reject_username);
+        assert_eq!(policy.dictionary, None);
+        assert_eq!(policy.remember, 0);
+    }
+}
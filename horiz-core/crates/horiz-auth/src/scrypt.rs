@@ -0,0 +1,134 @@
+// --- scrypt (Zero-Dependency, RFC 7914) ---
+//
+// メモリハード KDF。Salsa20/8 コアによる BlockMix と ROMix を独自実装する。
+
+use crate::pbkdf2::pbkdf2_hmac_sha256;
+
+/// Salsa20 のダブルラウンド (列ラウンド + 行ラウンド)
+pub(crate) fn salsa20_double_round(x: &mut [u32; 16]) {
+    // 列ラウンド
+    x[4] ^= x[0].wrapping_add(x[12]).rotate_left(7);
+    x[8] ^= x[4].wrapping_add(x[0]).rotate_left(9);
+    x[12] ^= x[8].wrapping_add(x[4]).rotate_left(13);
+    x[0] ^= x[12].wrapping_add(x[8]).rotate_left(18);
+    x[9] ^= x[5].wrapping_add(x[1]).rotate_left(7);
+    x[13] ^= x[9].wrapping_add(x[5]).rotate_left(9);
+    x[1] ^= x[13].wrapping_add(x[9]).rotate_left(13);
+    x[5] ^= x[1].wrapping_add(x[13]).rotate_left(18);
+    x[14] ^= x[10].wrapping_add(x[6]).rotate_left(7);
+    x[2] ^= x[14].wrapping_add(x[10]).rotate_left(9);
+    x[6] ^= x[2].wrapping_add(x[14]).rotate_left(13);
+    x[10] ^= x[6].wrapping_add(x[2]).rotate_left(18);
+    x[3] ^= x[15].wrapping_add(x[11]).rotate_left(7);
+    x[7] ^= x[3].wrapping_add(x[15]).rotate_left(9);
+    x[11] ^= x[7].wrapping_add(x[3]).rotate_left(13);
+    x[15] ^= x[11].wrapping_add(x[7]).rotate_left(18);
+    // 行ラウンド
+    x[1] ^= x[0].wrapping_add(x[3]).rotate_left(7);
+    x[2] ^= x[1].wrapping_add(x[0]).rotate_left(9);
+    x[3] ^= x[2].wrapping_add(x[1]).rotate_left(13);
+    x[0] ^= x[3].wrapping_add(x[2]).rotate_left(18);
+    x[6] ^= x[5].wrapping_add(x[4]).rotate_left(7);
+    x[7] ^= x[6].wrapping_add(x[5]).rotate_left(9);
+    x[4] ^= x[7].wrapping_add(x[6]).rotate_left(13);
+    x[5] ^= x[4].wrapping_add(x[7]).rotate_left(18);
+    x[11] ^= x[10].wrapping_add(x[9]).rotate_left(7);
+    x[8] ^= x[11].wrapping_add(x[10]).rotate_left(9);
+    x[9] ^= x[8].wrapping_add(x[11]).rotate_left(13);
+    x[10] ^= x[9].wrapping_add(x[8]).rotate_left(18);
+    x[12] ^= x[15].wrapping_add(x[14]).rotate_left(7);
+    x[13] ^= x[12].wrapping_add(x[15]).rotate_left(9);
+    x[14] ^= x[13].wrapping_add(x[12]).rotate_left(13);
+    x[15] ^= x[14].wrapping_add(x[13]).rotate_left(18);
+}
+
+/// Salsa20/8 コア (RFC 7914 §3)
+fn salsa20_8(b: &mut [u32; 16]) {
+    let mut x = *b;
+    for _ in 0..4 {
+        salsa20_double_round(&mut x);
+    }
+    for (a, b) in b.iter_mut().zip(x.iter()) {
+        *a = a.wrapping_add(*b);
+    }
+}
+
+/// scryptBlockMix (RFC 7914 §4)。`b` は 2r 個の 16 ワードブロック。
+fn block_mix(b: &[u32], out: &mut [u32], r: usize) {
+    let mut x: [u32; 16] = b[(2 * r - 1) * 16..].try_into().unwrap();
+    for i in 0..2 * r {
+        for (xj, bj) in x.iter_mut().zip(&b[i * 16..i * 16 + 16]) {
+            *xj ^= bj;
+        }
+        salsa20_8(&mut x);
+        // 偶数番目を前半、奇数番目を後半に並べ替える
+        let dst = (i / 2 + (i % 2) * r) * 16;
+        out[dst..dst + 16].copy_from_slice(&x);
+    }
+}
+
+/// scryptROMix (RFC 7914 §5)
+fn ro_mix(b: &mut [u32], n: usize, r: usize) {
+    let words = 32 * r;
+    let mut v = vec![0u32; n * words];
+    let mut y = vec![0u32; words];
+
+    for i in 0..n {
+        v[i * words..(i + 1) * words].copy_from_slice(b);
+        block_mix(b, &mut y, r);
+        b.copy_from_slice(&y);
+    }
+    for _ in 0..n {
+        // Integerify: 最後のブロックの先頭 64 ビット (n は 2 の冪なので下位ワードで十分)
+        let j = (b[(2 * r - 1) * 16] as usize) & (n - 1);
+        for (bk, vk) in b.iter_mut().zip(&v[j * words..(j + 1) * words]) {
+            *bk ^= vk;
+        }
+        block_mix(b, &mut y, r);
+        b.copy_from_slice(&y);
+    }
+}
+
+/// scrypt(P, S, N = 2^log_n, r, p, dkLen)
+pub fn scrypt(password: &[u8], salt: &[u8], log_n: u8, r: u32, p: u32, dk_len: usize) -> Vec<u8> {
+    let n = 1usize << log_n;
+    let r = r as usize;
+    let p = p as usize;
+    let block_len = 128 * r;
+
+    let mut b = pbkdf2_hmac_sha256(password, salt, 1, p * block_len);
+    for chunk in b.chunks_exact_mut(block_len) {
+        let mut words: Vec<u32> = chunk
+            .chunks_exact(4)
+            .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+            .collect();
+        ro_mix(&mut words, n, r);
+        for (dst, w) in chunk.chunks_exact_mut(4).zip(words.iter()) {
+            dst.copy_from_slice(&w.to_le_bytes());
+        }
+    }
+
+    pbkdf2_hmac_sha256(password, &b, 1, dk_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_hex(data: &[u8]) -> String {
+        data.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_scrypt_rfc7914() {
+        // RFC 7914 §12 テストベクタ
+        assert_eq!(
+            to_hex(&scrypt(b"", b"", 4, 1, 1, 64)),
+            "77d6576238657b203b19ca42c18a0497f16b4844e3074ae8dfdffa3fede21442fcd0069ded0948f8326a753a0fc81f17e8d3e0fb2e0d3628cf35e20c38d18906"
+        );
+        assert_eq!(
+            to_hex(&scrypt(b"password", b"NaCl", 10, 8, 16, 64)),
+            "fdbabe1c9d3472007856e7190d01e9fe7c6ad7cbc8237830e77376634b3731622eaf30d92e22a3886ff109279d9830dac727afb94a83ee6d8360cbdfa2cc0640"
+        );
+    }
+}
@@ -0,0 +1,228 @@
+// --- パスワードなどの秘密情報を保持する型 ---
+//
+// 破棄時にバッファ全体 (確保済みの未使用領域を含む) をゼロで上書きする。
+// コンパイラに書き込みを省略されないよう、volatile 書き込みとフェンスを使う。
+// 複製によって消去されないコピーが残らないよう、Clone は実装しない。
+
+use std::fmt;
+use std::io::{self, Read};
+use std::mem::MaybeUninit;
+use std::ptr;
+use std::sync::atomic::{Ordering, compiler_fence};
+
+use crate::constant_time_eq;
+
+/// 入力の 1 行の長さの上限 (これを超えた行は受け付けない)
+pub const MAX_LINE: usize = 4096;
+
+fn zeroize(buf: &mut Vec<u8>) {
+    for b in buf.iter_mut() {
+        unsafe { ptr::write_volatile(b, 0) };
+    }
+    for b in buf.spare_capacity_mut() {
+        unsafe { ptr::write_volatile(b, MaybeUninit::new(0)) };
+    }
+    compiler_fence(Ordering::SeqCst);
+    buf.clear();
+}
+
+/// 破棄時に消去されるバイト列
+#[derive(Default)]
+pub struct SecretBytes(Vec<u8>);
+
+impl SecretBytes {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        SecretBytes(bytes)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        SecretBytes(Vec::with_capacity(capacity))
+    }
+
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// 末尾に追加する。容量が足りない場合は新しい領域へ移し、古い領域を消去する。
+    pub fn extend_from_slice(&mut self, data: &[u8]) {
+        if self.0.len() + data.len() > self.0.capacity() {
+            let mut grown = Vec::with_capacity((self.0.len() + data.len()).max(self.0.capacity() * 2));
+            grown.extend_from_slice(&self.0);
+            zeroize(&mut self.0);
+            self.0 = grown;
+        }
+        self.0.extend_from_slice(data);
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        zeroize(&mut self.0);
+    }
+}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretBytes([REDACTED; {}])", self.0.len())
+    }
+}
+
+/// 破棄時に消去される文字列 (パスワードなど)
+#[derive(Default)]
+pub struct SecretString(SecretBytes);
+
+impl SecretString {
+    /// `s` の領域をそのまま引き継ぐ (コピーしない)
+    pub fn new(s: String) -> Self {
+        SecretString(SecretBytes(s.into_bytes()))
+    }
+
+    pub fn expose(&self) -> &str {
+        // SecretString は String からのみ作られ、UTF-8 であることが保証される
+        std::str::from_utf8(self.0.expose()).unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// 前後の空白を取り除く (同じ領域の中で移動し、コピーを作らない)
+    fn trim_in_place(&mut self) {
+        let s = self.expose();
+        let end = s.trim_end().len();
+        let start = end - s[..end].trim_start().len();
+        let buf = &mut (self.0).0;
+        buf.truncate(end);
+        buf.copy_within(start.., 0);
+        buf.truncate(end - start);
+    }
+
+    /// 改行までの 1 行を読み取り、前後の空白を取り除く。
+    ///
+    /// 呼び出し側のバッファに内容が残らないよう、1 バイトずつ読み取る (バッファリングしない読み取り元を渡すこと)。
+    /// MAX_LINE を超える行と UTF-8 として不正な行は、行末まで読み捨てて `InvalidData` を返す
+    /// (途中で切ったり空の入力とみなしたりしない)。何も読まずに入力が終わった場合は `UnexpectedEof` を返す。
+    pub fn read_line(reader: &mut impl Read) -> io::Result<Self> {
+        let mut line = SecretBytes::with_capacity(128);
+        let mut byte = [0u8; 1];
+        let mut too_long = false;
+        let mut eof = false;
+        loop {
+            match reader.read(&mut byte) {
+                Ok(0) => {
+                    eof = true;
+                    break;
+                }
+                Ok(_) if byte[0] == b'\n' => break,
+                Ok(_) if line.len() < MAX_LINE => line.extend_from_slice(&byte),
+                Ok(_) => too_long = true,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        unsafe { ptr::write_volatile(&mut byte[0], 0) };
+        if eof && line.is_empty() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        if too_long {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("入力が長すぎます (上限 {} バイト)", MAX_LINE)));
+        }
+        if std::str::from_utf8(line.expose()).is_err() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "入力が UTF-8 として不正です"));
+        }
+        let mut secret = SecretString(line);
+        secret.trim_in_place();
+        Ok(secret)
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(s: String) -> Self {
+        SecretString::new(s)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(s: &str) -> Self {
+        SecretString::new(s.to_string())
+    }
+}
+
+/// 定数時間で比較する (長さの違いのみ即座に判定する)
+impl PartialEq for SecretString {
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq(self.0.expose(), other.0.expose())
+    }
+}
+
+impl Eq for SecretString {}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SecretString([REDACTED])")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_line() {
+        let mut input: &[u8] = b"  secret pass \r\nnext\n";
+        assert_eq!(SecretString::read_line(&mut input).unwrap().expose(), "secret pass");
+        assert_eq!(SecretString::read_line(&mut input).unwrap().expose(), "next");
+        assert_eq!(SecretString::read_line(&mut input).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+        // 空行は空の入力、改行のない最後の行はそのまま読み取る
+        assert!(SecretString::read_line(&mut &b"\n"[..]).unwrap().is_empty());
+        assert_eq!(SecretString::read_line(&mut &b"last"[..]).unwrap().expose(), "last");
+
+        // 長すぎる行と不正な UTF-8 は、切り詰めたり空にしたりせずエラーとし、次の行は読める
+        let mut long = vec![b'a'; MAX_LINE - 1];
+        long.extend_from_slice("é\nnext\n".as_bytes());
+        let mut input = long.as_slice();
+        assert_eq!(SecretString::read_line(&mut input).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(SecretString::read_line(&mut input).unwrap().expose(), "next");
+        let exact = "a".repeat(MAX_LINE);
+        assert_eq!(SecretString::read_line(&mut exact.as_bytes()).unwrap().len(), MAX_LINE);
+        assert_eq!(SecretString::read_line(&mut &b"\xff\xfe\n"[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_grow_and_compare() {
+        let mut bytes = SecretBytes::with_capacity(2);
+        for _ in 0..100 {
+            bytes.extend_from_slice(b"xy");
+        }
+        assert_eq!(bytes.len(), 200);
+        assert!(bytes.expose().chunks(2).all(|c| c == b"xy"));
+
+        assert_eq!(SecretString::from("abc"), SecretString::from("abc"));
+        assert_ne!(SecretString::from("abc"), SecretString::from("abd"));
+        assert_eq!(format!("{:?}", SecretString::from("abc")), "SecretString([REDACTED])");
+    }
+
+    #[test]
+    fn test_zeroize_clears_spare_capacity() {
+        let mut buf = Vec::with_capacity(16);
+        buf.extend_from_slice(b"password");
+        buf.truncate(4);
+        zeroize(&mut buf);
+        assert!(buf.is_empty());
+        // 消去後の領域を読み出して確認する
+        unsafe { buf.set_len(16) };
+        assert!(buf.iter().all(|&b| b == 0));
+    }
+}
@@ -0,0 +1,246 @@
+// --- /etc/shadow の解析と更新 ---
+//
+// name:password:last_change:min:max:warn:inactive:expire:reserved
+// 日付・日数はすべて 1970-01-01 からの日数 (shadow(5) と同じ)。
+
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::AuthError;
+use crate::userdb::{Database, DbFile, Entry, PwdLock};
+
+pub const SHADOW_PATH: &str = "/etc/shadow";
+
+const FIELD_COUNT: usize = 9;
+
+/// これ以上の max_days は「期限なし」とみなす (shadow-utils と同じ)
+const MAX_DAYS_UNLIMITED: i64 = 10000;
+
+/// 現在の日付 (1970-01-01 からの日数)
+pub fn today() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| (d.as_secs() / 86400) as i64)
+        .unwrap_or(0)
+}
+
+/// /etc/shadow の 1 エントリ。空のフィールドは None。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShadowEntry {
+    pub name: String,
+    pub password: String,
+    pub last_change: Option<i64>,
+    pub min_days: Option<i64>,
+    pub max_days: Option<i64>,
+    pub warn_days: Option<i64>,
+    pub inactive_days: Option<i64>,
+    pub expire_date: Option<i64>,
+    pub reserved: String,
+}
+
+/// パスワードの有効期限の状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordAging {
+    /// 有効。`expires_in` は警告期間内の場合のみ、期限までの日数を示す。
+    Valid { expires_in: Option<i64> },
+    /// 期限切れ (または last_change = 0 による強制変更)。変更すればログインできる。
+    MustChange,
+    /// 期限切れ後の猶予期間 (inactive) も過ぎており、ログインできない
+    Inactive,
+}
+
+fn parse_day(field: &str) -> Option<Option<i64>> {
+    if field.is_empty() { Some(None) } else { field.parse().ok().map(Some) }
+}
+
+fn format_day(value: Option<i64>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+impl Entry for ShadowEntry {
+    /// 末尾の省略されたフィールドは空として扱う
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.len() < 2 || fields.len() > FIELD_COUNT || fields[0].is_empty() {
+            return None;
+        }
+        let field = |i: usize| fields.get(i).copied().unwrap_or("");
+        Some(ShadowEntry {
+            name: field(0).to_string(),
+            password: field(1).to_string(),
+            last_change: parse_day(field(2))?,
+            min_days: parse_day(field(3))?,
+            max_days: parse_day(field(4))?,
+            warn_days: parse_day(field(5))?,
+            inactive_days: parse_day(field(6))?,
+            expire_date: parse_day(field(7))?,
+            reserved: field(8).to_string(),
+        })
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.password.clone(),
+            format_day(self.last_change),
+            format_day(self.min_days),
+            format_day(self.max_days),
+            format_day(self.warn_days),
+            format_day(self.inactive_days),
+            format_day(self.expire_date),
+            self.reserved.clone(),
+        ]
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl ShadowEntry {
+    /// `!` または `*` で始まるパスワードフィールドはロックされている
+    pub fn is_locked(&self) -> bool {
+        self.password.starts_with('!') || self.password.starts_with('*')
+    }
+
+    /// アカウント自体の有効期限 (expire) が過ぎているか
+    pub fn is_account_expired(&self, today: i64) -> bool {
+        matches!(self.expire_date, Some(expire) if expire > 0 && today >= expire)
+    }
+
+    /// パスワードの有効期限を判定する (shadow-utils の isexpired と同じ規則)
+    pub fn password_aging(&self, today: i64) -> PasswordAging {
+        let Some(last_change) = self.last_change else { return PasswordAging::Valid { expires_in: None }; };
+        if last_change == 0 {
+            return PasswordAging::MustChange;
+        }
+        let Some(max) = self.max_days.filter(|&m| m >= 0) else { return PasswordAging::Valid { expires_in: None }; };
+
+        if let Some(inactive) = self.inactive_days.filter(|&i| i >= 0)
+            && today >= last_change + max + inactive {
+            return PasswordAging::Inactive;
+        }
+        if max >= MAX_DAYS_UNLIMITED {
+            return PasswordAging::Valid { expires_in: None };
+        }
+        let expires = last_change + max;
+        if today >= expires {
+            return PasswordAging::MustChange;
+        }
+        let expires_in = match self.warn_days {
+            Some(warn) if warn > 0 && today >= expires - warn => Some(expires - today),
+            _ => None,
+        };
+        PasswordAging::Valid { expires_in }
+    }
+}
+
+/// 指定ユーザーのエントリを読み込む。ユーザーの行が解析できない場合は `MalformedEntry` とする。
+pub fn read_entry(path: &Path, username: &str) -> Result<Option<ShadowEntry>, AuthError> {
+    let db = Database::<ShadowEntry>::load(path)?;
+    match (db.get(username), db.malformed_line(username)) {
+        (None, Some(line)) => Err(AuthError::MalformedEntry { path: path.to_path_buf(), line }),
+        (entry, _) => Ok(entry.cloned()),
+    }
+}
+
+/// ユーザー名で検索する (getspnam 相当)
+pub fn getspnam(name: &str) -> Result<Option<ShadowEntry>, AuthError> {
+    read_entry(Path::new(SHADOW_PATH), name)
+}
+
+/// 指定ユーザーのパスワードフィールドを置き換える
+pub fn replace_password(path: &Path, username: &str, encoded: &str) -> io::Result<()> {
+    update_entry(path, username, |entry| entry.password = encoded.to_string())
+}
+
+/// 指定ユーザーのエントリを `f` で書き換える
+///
+/// 同じディレクトリの `.pwd.lock` を取得し、[`DbFile`] の手順 (競合検出・バックアップ・
+/// 一時ファイルへの書き込みと rename) で更新する。他の行はそのまま保持する。
+pub fn update_entry<F: FnOnce(&mut ShadowEntry)>(path: &Path, username: &str, f: F) -> io::Result<()> {
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let _lock = PwdLock::acquire(dir)?;
+    let mut file = DbFile::<ShadowEntry>::open(path, 0o600)?;
+    let Some(entry) = file.get_mut(username) else {
+        return Err(io::Error::new(io::ErrorKind::NotFound, format!("ユーザー {} はシャドウに存在しません", username)));
+    };
+    f(entry);
+    file.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+    use std::fs;
+
+    #[test]
+    fn test_replace_password_keeps_other_fields() {
+        let dir = TempDir::new("shadow");
+        let path = dir.join("shadow");
+        fs::write(&path, "root:$hz$a$b:0:99999:7:::\nhoriz:$hz$c$d:0:99999:7:::\n").unwrap();
+
+        replace_password(&path, "horiz", "$hz2$alg=scrypt$cost=4,1,1$s$h").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "root:$hz$a$b:0:99999:7:::\nhoriz:$hz2$alg=scrypt$cost=4,1,1$s$h:0:99999:7:::\n"
+        );
+
+        assert!(replace_password(&path, "nobody", "x").is_err());
+        assert!(replace_password(&path, "root", "a:b").is_err());
+
+        // 対象ユーザーの行が壊れている場合は、存在しないユーザーと区別する
+        fs::write(&path, "root:$hz$a$b:0:99999:7:::\nhoriz:$hz$c$d:zero:99999:7:::\n").unwrap();
+        assert!(read_entry(&path, "root").unwrap().is_some());
+        assert!(read_entry(&path, "nobody").unwrap().is_none());
+        assert!(matches!(read_entry(&path, "horiz"), Err(AuthError::MalformedEntry { line: 2, .. })));
+    }
+
+    #[test]
+    fn test_update_entry_sets_last_change() {
+        let dir = TempDir::new("shadow-aging");
+        let path = dir.join("shadow");
+        fs::write(&path, "horiz:!$hz$c$d:0:0:99999:7:::\n").unwrap();
+
+        update_entry(&path, "horiz", |e| {
+            e.password = "$hz$e$f".to_string();
+            e.last_change = Some(19000);
+        })
+        .unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "horiz:$hz$e$f:19000:0:99999:7:::\n");
+        let entry = read_entry(&path, "horiz").unwrap().unwrap();
+        assert_eq!(entry.last_change, Some(19000));
+        assert!(read_entry(&path, "root").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_and_aging() {
+        let entry = ShadowEntry::parse("horiz:$hz$c$d:19000:0:30:7:5:19100:").unwrap();
+        assert_eq!(entry.to_line(), "horiz:$hz$c$d:19000:0:30:7:5:19100:");
+        assert!(!entry.is_locked());
+        assert_eq!(entry.password_aging(19010), PasswordAging::Valid { expires_in: None });
+        assert_eq!(entry.password_aging(19025), PasswordAging::Valid { expires_in: Some(5) });
+        assert_eq!(entry.password_aging(19030), PasswordAging::MustChange);
+        assert_eq!(entry.password_aging(19035), PasswordAging::Inactive);
+        assert!(!entry.is_account_expired(19099));
+        assert!(entry.is_account_expired(19100));
+
+        // rootfs テンプレート (最終変更日が空の 9 フィールド)
+        for line in include_str!("../../../../rootfs/etc/shadow").lines() {
+            let entry = ShadowEntry::parse(line).unwrap();
+            assert_eq!(entry.to_line(), line);
+            assert_eq!(entry.last_change, None);
+            assert_eq!(entry.password_aging(19000), PasswordAging::Valid { expires_in: None });
+        }
+        let entry = ShadowEntry::parse("root:!$hz$a$b::0:99999:7:::").unwrap();
+        assert!(entry.is_locked());
+        assert_eq!(entry.password_aging(19000), PasswordAging::Valid { expires_in: None });
+        assert_eq!(ShadowEntry::parse("root:x:0:0:99999:7:::").unwrap().password_aging(19000), PasswordAging::MustChange);
+        assert_eq!(ShadowEntry::parse("root:x:19000:0:99999:7:::").unwrap().password_aging(99000), PasswordAging::Valid { expires_in: None });
+
+        assert!(ShadowEntry::parse("root:x:abc::::::").is_none());
+        assert!(ShadowEntry::parse("root").is_none());
+    }
+}
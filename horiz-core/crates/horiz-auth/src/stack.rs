@@ -0,0 +1,370 @@
+// --- 認証スタック (PAM に近い、モジュールを順に評価する仕組み) ---
+//
+// /etc/horiz/auth.conf にサービス (login, passwd, doas など) ごとのモジュールを並べる:
+//
+//   service  control  module  [key=value ...]
+//
+// control は次のいずれか:
+//   required    失敗しても残りのモジュールを評価し、最終的に失敗とする
+//   requisite   失敗した時点で評価を打ち切り、失敗とする
+//   sufficient  成功し、それまでに失敗がなければ、その時点で成功とする (失敗は無視する)
+//
+// 認証情報を確認するモジュール (shadow, totp) が 1 つも成功しなければ、スタック全体は失敗とする。
+// 制限のためのモジュール (lockout, time, tty) は許可する場合に Ignore を返すため、それだけでは成功にならない。
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::modules;
+use crate::secret::SecretString;
+
+pub const AUTH_CONF_PATH: &str = "/etc/horiz/auth.conf";
+
+/// auth.conf が存在しない場合の既定のスタック (従来の動作と同じ)
+pub const DEFAULT_CONF: &str = "\
+login   requisite  shadow
+login   required   totp
+passwd  required   shadow
+doas    requisite  lockout
+doas    required   shadow
+";
+
+/// サービス名の行がない場合に使うサービス
+const FALLBACK_SERVICE: &str = "other";
+
+/// 利用者との対話。端末の扱いは呼び出し側 (horiz-init, passwd, doas) が実装する。
+pub trait Conversation {
+    /// エコーせずに入力させる (パスワード)。読み取れない場合は空の入力とみなさずエラーを返す。
+    fn prompt_secret(&mut self, prompt: &str) -> io::Result<SecretString>;
+    /// エコーして入力させる (確認コードなど)
+    fn prompt(&mut self, prompt: &str) -> io::Result<String>;
+    /// 利用者へのメッセージ
+    fn message(&mut self, text: &str);
+}
+
+/// 認証の対象と、モジュールが呼び出し側に返す情報
+#[derive(Debug)]
+pub struct AuthContext {
+    pub service: String,
+    pub user: String,
+    /// `/dev/` を除いた端末名 (端末がない場合は空)
+    pub tty: String,
+    /// 現在時刻 (UNIX 時刻)
+    pub now: u64,
+    /// shadow モジュールがパスワードを尋ねるときのプロンプト
+    pub password_prompt: String,
+    /// shadow モジュールが受け取ったパスワード (再ハッシュや期限切れ時の変更に使う)
+    pub password: Option<SecretString>,
+    /// ユーザーが /etc/shadow に存在するか (lockout は既知のユーザーのみ記録する)
+    pub known_user: bool,
+    /// ハッシュが現在のポリシーと異なる
+    pub needs_rehash: bool,
+    /// 警告期間内の場合、パスワードの有効期限までの日数
+    pub expires_in: Option<i64>,
+    /// パスワードの有効期限が切れている。認証は成功とし、変更は呼び出し側が行う。
+    pub password_expired: bool,
+}
+
+impl AuthContext {
+    pub fn new(service: &str, user: &str, tty: &str) -> Self {
+        AuthContext {
+            service: service.to_string(),
+            user: user.to_string(),
+            tty: tty.to_string(),
+            now: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            password_prompt: "password: ".to_string(),
+            password: None,
+            known_user: false,
+            needs_rehash: false,
+            expires_in: None,
+            password_expired: false,
+        }
+    }
+}
+
+/// 認証に失敗した理由
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// ユーザーが存在しない、またはパスワード・確認コードが一致しない
+    BadCredentials,
+    /// パスワードフィールドが `!` / `*` でロックされている
+    Locked,
+    /// アカウントの有効期限、またはパスワード期限切れ後の猶予期間を過ぎている
+    AccountExpired,
+    /// 認証の失敗が続いたため受け付けない (残り秒数)
+    TooManyFailures(u64),
+    /// 端末・時間帯などの制限により拒否された
+    Denied(String),
+    /// 設定や状態ファイルを読めないなど、認証を行えなかった
+    System(String),
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::BadCredentials => write!(f, "認証に失敗しました"),
+            Failure::Locked => write!(f, "アカウントはロックされています"),
+            Failure::AccountExpired => write!(f, "アカウントの有効期限が切れています"),
+            Failure::TooManyFailures(remaining) => write!(f, "認証の失敗が多すぎます。{} 秒後に再試行してください", remaining),
+            Failure::Denied(reason) => write!(f, "{}", reason),
+            Failure::System(e) => write!(f, "認証システムエラー: {}", e),
+        }
+    }
+}
+
+/// モジュール 1 つの評価結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Success,
+    /// このユーザー・状況には関係しない (結果に影響しない)
+    Ignore,
+    Failure(Failure),
+}
+
+pub trait AuthModule {
+    fn name(&self) -> &'static str;
+
+    fn authenticate(&self, ctx: &mut AuthContext, conv: &mut dyn Conversation) -> Verdict;
+
+    /// スタック全体の結果が決まった後、すべてのモジュールについて呼ばれる
+    fn finish(&self, _ctx: &AuthContext, _result: &Result<(), Failure>) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    Required,
+    Requisite,
+    Sufficient,
+}
+
+impl Control {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "required" => Some(Control::Required),
+            "requisite" => Some(Control::Requisite),
+            "sufficient" => Some(Control::Sufficient),
+            _ => None,
+        }
+    }
+}
+
+struct StackEntry {
+    control: Control,
+    module: Box<dyn AuthModule>,
+}
+
+pub struct AuthStack {
+    entries: Vec<StackEntry>,
+}
+
+impl AuthStack {
+    /// /etc/horiz/auth.conf からサービスのスタックを読み込む (存在しない場合は DEFAULT_CONF)
+    pub fn load(service: &str) -> Result<Self, String> {
+        let contents = match fs::read_to_string(AUTH_CONF_PATH) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => DEFAULT_CONF.to_string(),
+            Err(e) => return Err(format!("{}: {}", AUTH_CONF_PATH, e)),
+        };
+        Self::parse(&contents, service).map_err(|e| format!("{}: {}", AUTH_CONF_PATH, e))
+    }
+
+    /// `service` の行を順に読み取る。該当する行がなければ `other` の行を使う。
+    ///
+    /// 他のサービスの行も含め、すべての行の構文を検査する。
+    pub fn parse(contents: &str, service: &str) -> Result<Self, String> {
+        let mut own = Vec::new();
+        let mut fallback = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |msg: String| format!("{}行目: {}", i + 1, msg);
+            let mut fields = line.split_whitespace();
+            let (Some(name), Some(control), Some(module)) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(err("service control module の 3 つを指定してください".into()));
+            };
+            let control = Control::from_name(control).ok_or_else(|| err(format!("不明な control: {}", control)))?;
+            let options = fields
+                .map(|f| match f.split_once('=') {
+                    Some((k, v)) => (k.to_string(), v.to_string()),
+                    None => (f.to_string(), String::new()),
+                })
+                .collect::<Vec<_>>();
+            let module = modules::build(module, &options).map_err(err)?;
+
+            let entry = StackEntry { control, module };
+            if name == service {
+                own.push(entry);
+            } else if name == FALLBACK_SERVICE {
+                fallback.push(entry);
+            }
+        }
+        Ok(AuthStack { entries: if own.is_empty() { fallback } else { own } })
+    }
+
+    pub fn module_names(&self) -> Vec<&'static str> {
+        self.entries.iter().map(|e| e.module.name()).collect()
+    }
+
+    /// モジュールを順に評価する
+    pub fn authenticate(&self, ctx: &mut AuthContext, conv: &mut dyn Conversation) -> Result<(), Failure> {
+        let result = self.evaluate(ctx, conv);
+        for entry in &self.entries {
+            entry.module.finish(ctx, &result);
+        }
+        result
+    }
+
+    fn evaluate(&self, ctx: &mut AuthContext, conv: &mut dyn Conversation) -> Result<(), Failure> {
+        let mut failure = None;
+        let mut succeeded = false;
+        for entry in &self.entries {
+            match (entry.control, entry.module.authenticate(ctx, conv)) {
+                (_, Verdict::Ignore) => {}
+                (Control::Sufficient, Verdict::Success) if failure.is_none() => return Ok(()),
+                (Control::Sufficient, _) => {}
+                (_, Verdict::Success) => succeeded = true,
+                (Control::Required, Verdict::Failure(f)) => {
+                    failure.get_or_insert(f);
+                }
+                (Control::Requisite, Verdict::Failure(f)) => return Err(failure.unwrap_or(f)),
+            }
+        }
+        match failure {
+            Some(f) => Err(f),
+            None if succeeded => Ok(()),
+            None => Err(Failure::System(format!("サービス {} で認証を行うモジュールがありません", ctx.service))),
+        }
+    }
+}
+
+/// あらかじめ用意した入力を順に返す (テスト用)
+#[cfg(test)]
+pub(crate) struct Scripted {
+    inputs: Vec<String>,
+    pub prompts: Vec<String>,
+    pub messages: Vec<String>,
+}
+
+#[cfg(test)]
+impl Scripted {
+    pub fn new(inputs: &[&str]) -> Self {
+        Scripted { inputs: inputs.iter().rev().map(|s| s.to_string()).collect(), prompts: Vec::new(), messages: Vec::new() }
+    }
+}
+
+#[cfg(test)]
+impl Conversation for Scripted {
+    fn prompt_secret(&mut self, prompt: &str) -> io::Result<SecretString> {
+        self.prompt(prompt).map(SecretString::new)
+    }
+    fn prompt(&mut self, prompt: &str) -> io::Result<String> {
+        self.prompts.push(prompt.to_string());
+        self.inputs.pop().ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))
+    }
+    fn message(&mut self, text: &str) {
+        self.messages.push(text.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// 決まった結果を返し、評価された回数と finish に渡された結果を記録する
+    struct Fixed {
+        verdict: Verdict,
+        calls: Rc<Cell<u32>>,
+        finished: Rc<Cell<Option<bool>>>,
+    }
+
+    impl AuthModule for Fixed {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+        fn authenticate(&self, _ctx: &mut AuthContext, _conv: &mut dyn Conversation) -> Verdict {
+            self.calls.set(self.calls.get() + 1);
+            self.verdict.clone()
+        }
+        fn finish(&self, _ctx: &AuthContext, result: &Result<(), Failure>) {
+            self.finished.set(Some(result.is_ok()));
+        }
+    }
+
+    /// スタックを評価し、結果と各モジュールが評価された回数を返す
+    fn run(entries: &[(Control, Verdict)]) -> (Result<(), Failure>, Vec<u32>) {
+        let calls: Vec<_> = entries.iter().map(|_| Rc::new(Cell::new(0))).collect();
+        let finished: Vec<_> = entries.iter().map(|_| Rc::new(Cell::new(None))).collect();
+        let stack = AuthStack {
+            entries: entries
+                .iter()
+                .zip(calls.iter().zip(&finished))
+                .map(|((control, verdict), (calls, finished))| StackEntry {
+                    control: *control,
+                    module: Box::new(Fixed { verdict: verdict.clone(), calls: calls.clone(), finished: finished.clone() }),
+                })
+                .collect(),
+        };
+        let result = stack.authenticate(&mut AuthContext::new("login", "horiz", "tty1"), &mut Scripted::new(&[]));
+        // finish はすべてのモジュールについて呼ばれる
+        assert!(finished.iter().all(|f| f.get() == Some(result.is_ok())));
+        (result, calls.iter().map(|c| c.get()).collect())
+    }
+
+    #[test]
+    fn test_parse() {
+        let conf = "# comment\nlogin required time hours=09:00-17:00\nother sufficient time\npasswd requisite time days=mon\n";
+        assert_eq!(AuthStack::parse(conf, "login").unwrap().module_names(), ["time"]);
+        assert_eq!(AuthStack::parse(conf, "doas").unwrap().module_names(), ["time"]);
+        assert!(AuthStack::parse("", "login").unwrap().module_names().is_empty());
+        assert!(AuthStack::parse("login required", "login").err().unwrap().starts_with("1行目"));
+        assert!(AuthStack::parse("login optional shadow", "login").is_err());
+        assert!(AuthStack::parse("\nlogin required nosuch", "login").err().unwrap().starts_with("2行目"));
+        // 他のサービスの誤りも検出する
+        assert!(AuthStack::parse("login required time\ndoas required time hours=x", "login").is_err());
+        assert_eq!(AuthStack::parse(DEFAULT_CONF, "login").unwrap().module_names(), ["shadow", "totp"]);
+        assert_eq!(AuthStack::parse(DEFAULT_CONF, "doas").unwrap().module_names(), ["lockout", "shadow"]);
+    }
+
+    #[test]
+    fn test_control_flags() {
+        use Control::*;
+        let ok = || Verdict::Success;
+        let ignore = || Verdict::Ignore;
+        let fail = |f: Failure| Verdict::Failure(f);
+
+        assert_eq!(run(&[(Required, ok()), (Required, ok())]), (Ok(()), vec![1, 1]));
+        // Ignore だけでは成功にならない
+        assert!(matches!(run(&[(Required, ignore())]).0, Err(Failure::System(_))));
+        assert!(matches!(run(&[]).0, Err(Failure::System(_))));
+
+        // required の失敗後も評価を続け、最初の失敗を返す
+        assert_eq!(
+            run(&[(Required, fail(Failure::Locked)), (Required, fail(Failure::BadCredentials))]),
+            (Err(Failure::Locked), vec![1, 1])
+        );
+        // requisite の失敗で打ち切る
+        assert_eq!(
+            run(&[(Requisite, fail(Failure::TooManyFailures(5))), (Required, ok())]),
+            (Err(Failure::TooManyFailures(5)), vec![1, 0])
+        );
+        assert_eq!(
+            run(&[(Required, fail(Failure::Locked)), (Requisite, fail(Failure::BadCredentials)), (Required, ok())]),
+            (Err(Failure::Locked), vec![1, 1, 0])
+        );
+
+        // sufficient の成功で打ち切る (それまでに失敗がない場合のみ)
+        assert_eq!(run(&[(Sufficient, ok()), (Required, fail(Failure::Locked))]), (Ok(()), vec![1, 0]));
+        assert_eq!(
+            run(&[(Required, fail(Failure::Locked)), (Sufficient, ok()), (Required, ok())]),
+            (Err(Failure::Locked), vec![1, 1, 1])
+        );
+        // sufficient の失敗は無視する
+        assert_eq!(run(&[(Sufficient, fail(Failure::BadCredentials)), (Required, ok())]), (Ok(()), vec![1, 1]));
+    }
+}
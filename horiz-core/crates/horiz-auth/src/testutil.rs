@@ -0,0 +1,65 @@
+// --- テスト用の一時ディレクトリ ---
+//
+// 各クレートのテストで共用する (`testutil` 機能を有効にした dev-dependencies から使う)。
+
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// 空の一時ディレクトリ。ドロップ時に中身ごと削除するので、アサーションが失敗しても残らない。
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// `$TMPDIR/horiz-{name}-{pid}-{n}` を空の状態で作る (同じ名前で並行して呼んでも重ならない)
+    pub fn new(name: &str) -> TempDir {
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("horiz-{}-{}-{}", name, std::process::id(), n));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TempDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_temp_dir_removed_on_drop() {
+        let a = TempDir::new("testutil");
+        let b = TempDir::new("testutil");
+        assert_ne!(a.path(), b.path());
+        fs::write(a.join("file"), "x").unwrap();
+        let path = a.path().to_path_buf();
+        drop(a);
+        assert!(!path.exists());
+        assert!(b.is_dir());
+    }
+}
@@ -0,0 +1,54 @@
+// --- 時刻と日付 (UTC) ---
+//
+// ログインの記録や監査ログ、passwd -S の表示で共用する。
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 現在の UNIX 時刻 (秒)
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// UNIX エポックからの日数をグレゴリオ暦の (年, 月, 日) に変換する
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+/// "YYYY-MM-DD" 形式 (UTC)
+pub fn format_date(ts: u64) -> String {
+    let (y, m, d) = civil_from_days((ts / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+/// "HH:MM:SS" 形式 (UTC)
+pub fn format_time(ts: u64) -> String {
+    let secs = ts % 86_400;
+    format!("{:02}:{:02}:{:02}", secs / 3600, (secs / 60) % 60, secs % 60)
+}
+
+/// "YYYY-MM-DD HH:MM:SS UTC" 形式
+pub fn format_timestamp(ts: u64) -> String {
+    format!("{} {} UTC", format_date(ts), format_time(ts))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(format_timestamp(1_792_324_245), "2026-10-18 11:50:45 UTC");
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    }
+}
@@ -0,0 +1,449 @@
+// --- TOTP による 2 要素認証 (RFC 6238 / RFC 4226) ---
+//
+// ユーザーごとの秘密鍵は root のみが読み書きできる /etc/horiz/totp に置く:
+//
+//   name:secret(Base32):algorithm:digits:period:last_step
+//
+// last_step は最後に受け付けたタイムステップで、同じコードの再利用を拒否するために記録する。
+
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use horiz_crypto::hmac::hmac_sha256;
+
+use crate::config;
+use crate::error::AuthError;
+use crate::sha1::hmac_sha1;
+use crate::userdb::{Database, DbFile, Entry, PwdLock};
+
+pub const TOTP_PATH: &str = "/etc/horiz/totp";
+pub const CONFIG_PATH: &str = "/etc/horiz/totp.conf";
+
+/// 許容する時刻のずれ (前後のタイムステップ数) の上限
+const MAX_WINDOW: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha1,
+    Sha256,
+}
+
+impl Algorithm {
+    /// otpauth URI と設定ファイルでの名前
+    pub fn name(&self) -> &'static str {
+        match self {
+            Algorithm::Sha1 => "SHA1",
+            Algorithm::Sha256 => "SHA256",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "SHA1" => Some(Algorithm::Sha1),
+            "SHA256" => Some(Algorithm::Sha256),
+            _ => None,
+        }
+    }
+
+    /// 推奨される鍵の長さ (RFC 4226 §4 / RFC 6238 付録 B と同じくハッシュ長)
+    pub fn key_len(&self) -> usize {
+        match self {
+            Algorithm::Sha1 => 20,
+            Algorithm::Sha256 => 32,
+        }
+    }
+}
+
+// --- Base32 (RFC 4648) ---
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// パディングなしで符号化する (otpauth URI の慣例)
+pub fn base32_encode(data: &[u8]) -> String {
+    let mut result = String::new();
+    let mut buffer = 0u64;
+    let mut bits = 0;
+    for &b in data {
+        buffer = (buffer << 8) | b as u64;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            result.push(BASE32_ALPHABET[((buffer >> bits) & 0x1f) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        result.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 0x1f) as usize] as char);
+    }
+    result
+}
+
+/// 復号する。小文字・空白・末尾の `=` は許容する。
+pub fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::new();
+    let mut buffer = 0u64;
+    let mut bits = 0;
+    for c in s.trim_end_matches('=').chars().filter(|c| !c.is_whitespace()) {
+        let value = BASE32_ALPHABET.iter().position(|&a| a as char == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u64;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+        }
+    }
+    Some(result)
+}
+
+/// HOTP (RFC 4226 §5.3 の動的切り詰め)
+pub fn hotp(key: &[u8], counter: u64, algorithm: Algorithm, digits: u32) -> u32 {
+    let mac = match algorithm {
+        Algorithm::Sha1 => hmac_sha1(key, &counter.to_be_bytes()).to_vec(),
+        Algorithm::Sha256 => hmac_sha256(key, &counter.to_be_bytes()).to_vec(),
+    };
+    let offset = (mac[mac.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes(mac[offset..offset + 4].try_into().unwrap()) & 0x7fff_ffff;
+    binary % 10u32.pow(digits)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TotpEntry {
+    pub name: String,
+    /// Base32 で符号化した秘密鍵
+    pub secret: String,
+    pub algorithm: Algorithm,
+    /// 6〜8 桁
+    pub digits: u32,
+    /// タイムステップの長さ (秒)
+    pub period: u64,
+    pub last_step: Option<u64>,
+}
+
+impl Entry for TotpEntry {
+    fn parse(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.len() != 6 || fields[0].is_empty() {
+            return None;
+        }
+        let digits: u32 = fields[3].parse().ok()?;
+        let period: u64 = fields[4].parse().ok()?;
+        if !(6..=8).contains(&digits) || period == 0 || base32_decode(fields[1]).is_none_or(|k| k.is_empty()) {
+            return None;
+        }
+        Some(TotpEntry {
+            name: fields[0].to_string(),
+            secret: fields[1].to_string(),
+            algorithm: Algorithm::from_name(fields[2])?,
+            digits,
+            period,
+            last_step: if fields[5].is_empty() { None } else { Some(fields[5].parse().ok()?) },
+        })
+    }
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.name.clone(),
+            self.secret.clone(),
+            self.algorithm.name().to_string(),
+            self.digits.to_string(),
+            self.period.to_string(),
+            self.last_step.map(|s| s.to_string()).unwrap_or_default(),
+        ]
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl TotpEntry {
+    pub fn new(name: &str, key: &[u8], algorithm: Algorithm, digits: u32, period: u64) -> Self {
+        TotpEntry {
+            name: name.to_string(),
+            secret: base32_encode(key),
+            algorithm,
+            digits,
+            period,
+            last_step: None,
+        }
+    }
+
+    fn key(&self) -> Vec<u8> {
+        base32_decode(&self.secret).unwrap_or_default()
+    }
+
+    /// UNIX 時刻 `time` におけるコード
+    pub fn code_at(&self, time: u64) -> String {
+        let code = hotp(&self.key(), time / self.period, self.algorithm, self.digits);
+        format!("{:0width$}", code, width = self.digits as usize)
+    }
+
+    /// `code` と一致するタイムステップを前後 `window` ステップの範囲で探す。
+    /// 最後に受け付けたステップ以前のコードは、再利用を防ぐため一致としない。
+    pub fn matching_step(&self, code: &str, now: u64, window: u64) -> Option<u64> {
+        if code.len() != self.digits as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let current = now / self.period;
+        let key = self.key();
+        let mut matched = None;
+        // 一致した時点で打ち切らず、常にすべてのステップを計算する
+        for step in current.saturating_sub(window)..=current.saturating_add(window) {
+            let expected = format!("{:0width$}", hotp(&key, step, self.algorithm, self.digits), width = self.digits as usize);
+            if crate::constant_time_eq(expected.as_bytes(), code.as_bytes()) && self.last_step.is_none_or(|last| step > last) {
+                matched = Some(step);
+            }
+        }
+        matched
+    }
+
+    /// 認証アプリに登録するための otpauth URI
+    pub fn uri(&self, issuer: &str) -> String {
+        format!(
+            "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm={}&digits={}&period={}",
+            percent_encode(issuer),
+            percent_encode(&self.name),
+            self.secret,
+            percent_encode(issuer),
+            self.algorithm.name(),
+            self.digits,
+            self.period
+        )
+    }
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// /etc/horiz/totp.conf の設定
+///
+/// ```text
+/// window=1          # 許容する時刻のずれ (前後のタイムステップ数, 最大 10)
+/// issuer=HorizOS    # otpauth URI の発行者名
+/// algorithm=SHA1    # 登録時の既定値 (SHA1 | SHA256)
+/// digits=6
+/// period=30
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TotpConfig {
+    pub window: u64,
+    pub issuer: String,
+    pub algorithm: Algorithm,
+    pub digits: u32,
+    pub period: u64,
+}
+
+impl Default for TotpConfig {
+    fn default() -> Self {
+        TotpConfig { window: 1, issuer: "HorizOS".to_string(), algorithm: Algorithm::Sha1, digits: 6, period: 30 }
+    }
+}
+
+impl TotpConfig {
+    pub fn load() -> Self {
+        Self::from_config(&config::load_key_values(CONFIG_PATH))
+    }
+
+    /// 未指定・範囲外の値は既定値を使う
+    pub fn from_config(pairs: &[(String, String)]) -> Self {
+        let mut cfg = Self::default();
+        if let Some(v) = config::get(pairs, "window").and_then(|v| v.parse().ok()).filter(|v| *v <= MAX_WINDOW) {
+            cfg.window = v;
+        }
+        if let Some(v) = config::get(pairs, "issuer").filter(|v| !v.is_empty()) {
+            cfg.issuer = v.to_string();
+        }
+        if let Some(v) = config::get(pairs, "algorithm").and_then(Algorithm::from_name) {
+            cfg.algorithm = v;
+        }
+        if let Some(v) = config::get(pairs, "digits").and_then(|v| v.parse().ok()).filter(|v| (6..=8).contains(v)) {
+            cfg.digits = v;
+        }
+        if let Some(v) = config::get(pairs, "period").and_then(|v| v.parse().ok()).filter(|v| *v > 0) {
+            cfg.period = v;
+        }
+        cfg
+    }
+}
+
+/// 秘密鍵を生成する (CSPRNG)
+pub fn generate_key(algorithm: Algorithm) -> io::Result<Vec<u8>> {
+    let mut key = vec![0u8; algorithm.key_len()];
+    fs::File::open("/dev/urandom")?.read_exact(&mut key)?;
+    Ok(key)
+}
+
+/// 同じディレクトリの `.pwd.lock` を取得して編集する
+fn modify<R>(path: &Path, f: impl FnOnce(&mut Database<TotpEntry>) -> R) -> io::Result<R> {
+    let dir = path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let _lock = PwdLock::acquire(dir)?;
+    let mut file = DbFile::<TotpEntry>::open(path, 0o600)?;
+    let result = f(&mut file);
+    file.commit()?;
+    Ok(result)
+}
+
+/// 登録を読み込む。ユーザーの行が解析できない場合は `MalformedEntry` とする (登録なしとはみなさない)。
+pub fn read_entry(path: &Path, username: &str) -> Result<Option<TotpEntry>, AuthError> {
+    let db = match Database::<TotpEntry>::load(path) {
+        Ok(db) => db,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    match (db.get(username), db.malformed_line(username)) {
+        (None, Some(line)) => Err(AuthError::MalformedEntry { path: path.to_path_buf(), line }),
+        (entry, _) => Ok(entry.cloned()),
+    }
+}
+
+pub fn is_enrolled(path: &Path, username: &str) -> Result<bool, AuthError> {
+    Ok(read_entry(path, username)?.is_some())
+}
+
+/// 登録する。既に登録されている場合は `replace` が true のときのみ置き換え、置き換えたかどうかを返す。
+pub fn enroll(path: &Path, entry: TotpEntry, replace: bool) -> io::Result<bool> {
+    modify(path, |db| {
+        let existed = db.get(&entry.name).is_some();
+        if existed && !replace {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("ユーザー {} は既に登録されています", entry.name)));
+        }
+        match db.get_mut(&entry.name) {
+            Some(current) => *current = entry,
+            None => { db.insert(entry); }
+        }
+        Ok(existed)
+    })?
+}
+
+/// 登録を削除する。登録されていなかった場合は false。
+pub fn remove(path: &Path, username: &str) -> io::Result<bool> {
+    modify(path, |db| db.remove(username).is_some())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TotpStatus {
+    /// 2 要素認証が設定されていない
+    NotEnrolled,
+    Valid,
+    /// コードが一致しない、または使用済み
+    Invalid,
+}
+
+/// コードを検証し、受け付けた場合はそのタイムステップを記録する
+pub fn verify(path: &Path, username: &str, code: &str, now: u64, window: u64) -> Result<TotpStatus, AuthError> {
+    if !is_enrolled(path, username)? {
+        return Ok(TotpStatus::NotEnrolled);
+    }
+    Ok(modify(path, |db| match db.get_mut(username) {
+        None => TotpStatus::NotEnrolled,
+        Some(entry) => match entry.matching_step(code.trim(), now, window.min(MAX_WINDOW)) {
+            Some(step) => {
+                entry.last_step = Some(step);
+                TotpStatus::Valid
+            }
+            None => TotpStatus::Invalid,
+        },
+    })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_base32() {
+        assert_eq!(base32_encode(b"12345678901234567890"), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32_decode("mzxw 6ytb oi======").unwrap(), b"foobar");
+        assert_eq!(base32_decode("MZXW6YQ=").unwrap(), b"foob");
+        assert!(base32_decode("MZXW1").is_none());
+    }
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        let sha1 = TotpEntry::new("horiz", b"12345678901234567890", Algorithm::Sha1, 8, 30);
+        let sha256 = TotpEntry::new("horiz", b"12345678901234567890123456789012", Algorithm::Sha256, 8, 30);
+        let vectors = [
+            (59, "94287082", "46119246"),
+            (1111111109, "07081804", "68084774"),
+            (1234567890, "89005924", "91819424"),
+            (2000000000, "69279037", "90698825"),
+            (20000000000, "65353130", "77737706"),
+        ];
+        for (time, expected_sha1, expected_sha256) in vectors {
+            assert_eq!(sha1.code_at(time), expected_sha1);
+            assert_eq!(sha256.code_at(time), expected_sha256);
+        }
+    }
+
+    #[test]
+    fn test_window_and_replay() {
+        let mut entry = TotpEntry::new("horiz", b"12345678901234567890", Algorithm::Sha1, 6, 30);
+        let now = 1_234_567_890;
+        let previous = entry.code_at(now - 30);
+        assert_eq!(entry.matching_step(&entry.code_at(now), now, 0), Some(now / 30));
+        assert_eq!(entry.matching_step(&previous, now, 1), Some(now / 30 - 1));
+        assert_eq!(entry.matching_step(&previous, now, 0), None);
+        assert_eq!(entry.matching_step(&entry.code_at(now - 90), now, 1), None);
+        assert_eq!(entry.matching_step("12345", now, 1), None);
+
+        entry.last_step = Some(now / 30);
+        assert_eq!(entry.matching_step(&entry.code_at(now), now, 1), None);
+        assert_eq!(entry.matching_step(&entry.code_at(now + 30), now, 1), Some(now / 30 + 1));
+
+        let line = entry.to_line();
+        assert_eq!(line, format!("horiz:GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ:SHA1:6:30:{}", now / 30));
+        assert_eq!(TotpEntry::parse(&line), Some(entry.clone()));
+        assert_eq!(
+            entry.uri("Horiz OS"),
+            "otpauth://totp/Horiz%20OS:horiz?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ&issuer=Horiz%20OS&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+
+    #[test]
+    fn test_enroll_verify_remove() {
+        let dir = TempDir::new("totp");
+        let path = dir.join("totp");
+        let now = 1_700_000_000;
+
+        assert_eq!(verify(&path, "horiz", "000000", now, 1).unwrap(), TotpStatus::NotEnrolled);
+        let entry = TotpEntry::new("horiz", &[7u8; 20], Algorithm::Sha1, 6, 30);
+        assert!(!enroll(&path, entry.clone(), false).unwrap());
+        assert!(enroll(&path, entry.clone(), false).is_err());
+        assert!(is_enrolled(&path, "horiz").unwrap());
+
+        let code = entry.code_at(now);
+        assert_eq!(verify(&path, "horiz", "000000", now, 1).unwrap(), TotpStatus::Invalid);
+        assert_eq!(verify(&path, "horiz", &code, now, 1).unwrap(), TotpStatus::Valid);
+        // 同じコードは再利用できない
+        assert_eq!(verify(&path, "horiz", &code, now, 1).unwrap(), TotpStatus::Invalid);
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+
+        assert!(enroll(&path, entry, true).unwrap());
+        assert!(remove(&path, "horiz").unwrap());
+        assert!(!remove(&path, "horiz").unwrap());
+        assert!(!is_enrolled(&path, "horiz").unwrap());
+
+        // 壊れた行を未登録とみなさない (2 要素目を迂回させない)
+        fs::write(&path, "horiz:AAAA:SHA1:9:30:\n").unwrap();
+        assert!(matches!(is_enrolled(&path, "horiz"), Err(AuthError::MalformedEntry { line: 1, .. })));
+        assert!(verify(&path, "horiz", &code, now, 1).is_err());
+    }
+
+    #[test]
+    fn test_config() {
+        let cfg = TotpConfig::from_config(&config::parse_key_values("window=3\nalgorithm=sha256\ndigits=9\nperiod=0\n"));
+        assert_eq!(cfg.window, 3);
+        assert_eq!(cfg.algorithm, Algorithm::Sha256);
+        assert_eq!((cfg.digits, cfg.period), (6, 30));
+        assert_eq!(TotpConfig::from_config(&config::parse_key_values("window=99\n")).window, 1);
+    }
+}
@@ -0,0 +1,121 @@
+// --- 端末での入力 ---
+//
+// passwd や doas などのコマンドが、端末からパスワードや確認コードを読み取るために使う。
+// libc クレートに依存しないよう、使う関数と termios だけをここで宣言する。
+
+use std::ffi::{CStr, c_char};
+use std::fs::File;
+use std::io::{self, Write};
+use std::mem::ManuallyDrop;
+use std::os::fd::FromRawFd;
+
+use crate::secret::SecretString;
+use crate::stack::Conversation;
+
+const STDIN_FILENO: i32 = 0;
+const TCSANOW: i32 = 0;
+const ECHO: u32 = 0o10;
+
+/// struct termios。Linux ではどのアーキテクチャでも先頭の 4 つのフラグの配置は同じだが、続く c_line と c_cc の
+/// 順序や大きさは異なる (powerpc など) ため、残りは読み書きしないバイト列として十分な大きさを確保する。
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Termios {
+    c_iflag: u32,
+    c_oflag: u32,
+    c_cflag: u32,
+    c_lflag: u32,
+    rest: [u8; 64],
+}
+
+unsafe extern "C" {
+    fn ttyname(fd: i32) -> *const c_char;
+    fn tcgetattr(fd: i32, termios: *mut Termios) -> i32;
+    fn tcsetattr(fd: i32, optional_actions: i32, termios: *const Termios) -> i32;
+}
+
+/// 標準入力の端末名 (`/dev/` を除く)。端末がない場合は空。
+pub fn current_tty() -> String {
+    let ptr = unsafe { ttyname(STDIN_FILENO) };
+    if ptr.is_null() {
+        return String::new();
+    }
+    let path = unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned();
+    path.strip_prefix("/dev/").unwrap_or(&path).to_string()
+}
+
+/// プロンプトを表示し、エコーを止めてパスワードを読み取る
+pub fn read_password(prompt: &str) -> io::Result<SecretString> {
+    print!("{}", prompt);
+    io::stdout().flush().unwrap();
+
+    let mut term: Termios = unsafe { std::mem::zeroed() };
+    let saved = unsafe { tcgetattr(STDIN_FILENO, &mut term) } == 0;
+    if saved {
+        let mut hidden = term;
+        hidden.c_lflag &= !ECHO;
+        unsafe { tcsetattr(STDIN_FILENO, TCSANOW, &hidden) };
+    }
+
+    let pass = read_stdin_line();
+
+    if saved {
+        unsafe { tcsetattr(STDIN_FILENO, TCSANOW, &term) };
+    }
+    println!();
+    pass
+}
+
+/// プロンプトを表示し、エコーしたまま 1 行を読み取る (前後の空白は取り除く)
+pub fn read_line(prompt: &str) -> io::Result<String> {
+    print!("{}", prompt);
+    io::stdout().flush().unwrap();
+    read_stdin_line().map(|line| line.expose().to_string())
+}
+
+/// fd 0 から直接 1 行を読み取る。
+///
+/// 標準入力のバッファにパスワードが残らないよう、また、パイプから続けて読む場合にバッファが後続の行
+/// (パスワード) を先に取り込んでしまわないよう、パスワード以外の入力もすべてこの方法で読む。
+fn read_stdin_line() -> io::Result<SecretString> {
+    let mut stdin = ManuallyDrop::new(unsafe { File::from_raw_fd(STDIN_FILENO) });
+    SecretString::read_line(&mut *stdin)
+}
+
+/// 端末での認証の対話。メッセージはコマンド名を付けて標準エラー出力に書く。
+pub struct Terminal {
+    program: &'static str,
+}
+
+impl Terminal {
+    pub fn new(program: &'static str) -> Self {
+        Terminal { program }
+    }
+}
+
+impl Conversation for Terminal {
+    fn prompt_secret(&mut self, prompt: &str) -> io::Result<SecretString> {
+        read_password(prompt)
+    }
+
+    fn prompt(&mut self, prompt: &str) -> io::Result<String> {
+        read_line(prompt)
+    }
+
+    fn message(&mut self, text: &str) {
+        eprintln!("{}: {}", self.program, text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_termios_layout_matches_libc() {
+        assert!(std::mem::size_of::<Termios>() >= std::mem::size_of::<libc::termios>());
+        assert_eq!(std::mem::offset_of!(Termios, c_lflag), std::mem::offset_of!(libc::termios, c_lflag));
+        assert_eq!(ECHO, libc::ECHO);
+        assert_eq!(TCSANOW, libc::TCSANOW);
+    }
+}
@@ -0,0 +1,453 @@
+// --- ユーザーデータベース (/etc/passwd, /etc/group, /etc/shadow) の共通処理 ---
+//
+// コロン区切りのファイルを型付きのエントリとして扱う。解析できない行 (空行・コメントなど) と
+// 変更していないエントリは元の文字列をそのまま保持し、ファイル全体をバイト単位で復元できる。
+//
+// 書き込みは必ず /etc/.pwd.lock のロックを取得した上で行う:
+//   1. 読み込み時の内容とディスク上の内容を比較し、他からの変更があれば中止する
+//   2. 変更前の内容を `<file>-` にバックアップする
+//   3. `<file>+` に元と同じパーミッション・所有者で書き込み、fsync する
+//   4. rename で原子的に置き換え、ディレクトリを fsync する
+
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::ops::{Deref, DerefMut};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::group::GroupEntry;
+use crate::passwd::PasswdEntry;
+use crate::shadow::ShadowEntry;
+
+/// 慣例的なロックファイル (shadow-utils の lckpwdf と同じ名前)
+pub const LOCK_FILE: &str = ".pwd.lock";
+
+/// ロック取得を待つ最大時間 (lckpwdf と同じ 15 秒)
+const LOCK_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// コロン区切りの 1 行に対応するエントリ
+pub trait Entry: Clone + PartialEq + Sized {
+    /// 1 行 (改行を含まない) を解析する。形式が不正なら None。
+    fn parse(line: &str) -> Option<Self>;
+    /// 各フィールドの文字列表現
+    fn fields(&self) -> Vec<String>;
+    /// エントリの名前 (ユーザー名・グループ名)
+    fn name(&self) -> &str;
+
+    fn to_line(&self) -> String {
+        self.fields().join(":")
+    }
+}
+
+enum Line<T> {
+    /// `raw` は読み込み時の行 (新規追加したエントリは None)
+    Entry { entry: T, raw: Option<String> },
+    Other(String),
+}
+
+/// ファイル全体の内容
+pub struct Database<T: Entry> {
+    lines: Vec<Line<T>>,
+    trailing_newline: bool,
+}
+
+impl<T: Entry> Database<T> {
+    pub fn parse(contents: &str) -> Self {
+        let body = contents.strip_suffix('\n');
+        let trailing_newline = body.is_some();
+        let body = body.unwrap_or(contents);
+
+        let lines = if contents.is_empty() {
+            Vec::new()
+        } else {
+            body.split('\n')
+                .map(|line| match T::parse(line) {
+                    Some(entry) => Line::Entry { entry, raw: Some(line.to_string()) },
+                    None => Line::Other(line.to_string()),
+                })
+                .collect()
+        };
+        Database { lines, trailing_newline }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &T> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Entry { entry, .. } => Some(entry),
+            Line::Other(_) => None,
+        })
+    }
+
+    /// 名前で検索する (同名のエントリが複数あれば先頭のもの)
+    pub fn get(&self, name: &str) -> Option<&T> {
+        self.entries().find(|e| e.name() == name)
+    }
+
+    /// 解析できなかった行のうち、先頭のフィールドが `name` である行の番号 (1 から)
+    pub fn malformed_line(&self, name: &str) -> Option<usize> {
+        self.lines.iter().position(|line| matches!(line, Line::Other(raw) if raw.split(':').next() == Some(name))).map(|i| i + 1)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut T> {
+        self.lines.iter_mut().find_map(|line| match line {
+            Line::Entry { entry, .. } if entry.name() == name => Some(entry),
+            _ => None,
+        })
+    }
+
+    /// 末尾にエントリを追加する。同名のエントリが既にある場合は追加せず false を返す。
+    pub fn insert(&mut self, entry: T) -> bool {
+        if self.get(entry.name()).is_some() {
+            return false;
+        }
+        if self.lines.is_empty() {
+            self.trailing_newline = true;
+        }
+        self.lines.push(Line::Entry { entry, raw: None });
+        true
+    }
+
+    /// 名前が一致するエントリを削除して返す
+    pub fn remove(&mut self, name: &str) -> Option<T> {
+        let index = self.lines.iter().position(|line| matches!(line, Line::Entry { entry, .. } if entry.name() == name))?;
+        match self.lines.remove(index) {
+            Line::Entry { entry, .. } => Some(entry),
+            Line::Other(_) => unreachable!(),
+        }
+    }
+
+    /// すべてのエントリのフィールドに区切り文字 (':' と改行) が含まれていないか確認する
+    pub fn validate(&self) -> io::Result<()> {
+        for entry in self.entries() {
+            if entry.name().is_empty() || entry.fields().iter().any(|f| f.contains([':', '\n'])) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("エントリ {:?} に使用できない文字が含まれています", entry.name()),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Entry> fmt::Display for Database<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                f.write_str("\n")?;
+            }
+            match line {
+                Line::Other(raw) => f.write_str(raw)?,
+                Line::Entry { entry, raw: Some(raw) } if T::parse(raw).as_ref() == Some(entry) => f.write_str(raw)?,
+                Line::Entry { entry, raw } => {
+                    let mut line = entry.to_line();
+                    // 元の行で省略されていた末尾の空フィールドは省略したままにする
+                    if let Some(raw) = raw {
+                        let original_fields = raw.split(':').count();
+                        while line.split(':').count() > original_fields && line.ends_with(':') {
+                            line.pop();
+                        }
+                    }
+                    f.write_str(&line)?;
+                }
+            }
+        }
+        if self.trailing_newline {
+            f.write_str("\n")?;
+        }
+        Ok(())
+    }
+}
+
+/// `<dir>/.pwd.lock` の排他ロック。破棄時に解放される。
+pub struct PwdLock {
+    _file: fs::File,
+}
+
+impl PwdLock {
+    pub fn acquire(dir: &Path) -> io::Result<Self> {
+        Self::acquire_timeout(dir, LOCK_TIMEOUT)
+    }
+
+    pub fn acquire_timeout(dir: &Path, timeout: Duration) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o600)
+            .open(dir.join(LOCK_FILE))?;
+        let start = Instant::now();
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(PwdLock { _file: file }),
+                Err(fs::TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err(fs::TryLockError::WouldBlock) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WouldBlock,
+                        "ユーザーデータベースは他のプロセスによってロックされています",
+                    ));
+                }
+                Err(fs::TryLockError::Error(e)) => return Err(e),
+            }
+        }
+    }
+}
+
+/// `path` に `suffix` を付けたパス (`shadow+`, `shadow-` など)
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut s = path.as_os_str().to_os_string();
+    s.push(suffix);
+    PathBuf::from(s)
+}
+
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// 内容を書き込む前にパーミッションと所有者を設定し、fsync する
+fn write_with_mode(path: &Path, contents: &str, mode: u32, owner: Option<(u32, u32)>) -> io::Result<()> {
+    let mut f = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    f.set_permissions(fs::Permissions::from_mode(mode))?;
+    if let Some((uid, gid)) = owner {
+        std::os::unix::fs::fchown(&f, Some(uid), Some(gid))?;
+    }
+    f.write_all(contents.as_bytes())?;
+    f.sync_all()
+}
+
+/// ロック下で編集する 1 ファイル。読み込み時の内容を保持し、書き込み前に競合を検出する。
+pub struct DbFile<T: Entry> {
+    path: PathBuf,
+    /// ファイルが存在しない場合に新規作成するときのパーミッション
+    default_mode: u32,
+    original: Option<String>,
+    db: Database<T>,
+}
+
+impl<T: Entry> DbFile<T> {
+    /// 読み込む。ファイルが存在しない場合は空のデータベースとして扱う。
+    pub fn open(path: &Path, default_mode: u32) -> io::Result<Self> {
+        let original = read_optional(path)?;
+        let db = Database::parse(original.as_deref().unwrap_or(""));
+        Ok(DbFile { path: path.to_path_buf(), default_mode, original, db })
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.original.as_deref().unwrap_or("") != self.db.to_string()
+    }
+
+    /// 書き込み可能か (区切り文字の混入・他のプロセスによる変更がないか) を確認する
+    fn check(&self) -> io::Result<()> {
+        self.db.validate()?;
+        if read_optional(&self.path)? != self.original {
+            return Err(io::Error::other(format!(
+                "{} は読み込み後に他のプロセスによって変更されました",
+                self.path.display()
+            )));
+        }
+        Ok(())
+    }
+
+    fn write(&self) -> io::Result<()> {
+        let meta = fs::metadata(&self.path).ok();
+        let mode = meta.as_ref().map(|m| m.mode() & 0o7777).unwrap_or(self.default_mode);
+        let owner = meta.as_ref().map(|m| (m.uid(), m.gid()));
+
+        if let Some(original) = &self.original {
+            write_with_mode(&with_suffix(&self.path, "-"), original, mode, owner)?;
+        }
+
+        let tmp_path = with_suffix(&self.path, "+");
+        let result = write_with_mode(&tmp_path, &self.db.to_string(), mode, owner)
+            .and_then(|()| fs::rename(&tmp_path, &self.path));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+            return result;
+        }
+
+        // rename 自体を永続化する
+        if let Some(dir) = self.path.parent() {
+            fs::File::open(if dir.as_os_str().is_empty() { Path::new(".") } else { dir })?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// 変更があれば書き込む。呼び出し側で [`PwdLock`] を保持していること。
+    pub fn commit(&self) -> io::Result<()> {
+        if !self.is_modified() {
+            return Ok(());
+        }
+        self.check()?;
+        self.write()
+    }
+}
+
+impl<T: Entry> Deref for DbFile<T> {
+    type Target = Database<T>;
+
+    fn deref(&self) -> &Database<T> {
+        &self.db
+    }
+}
+
+impl<T: Entry> DerefMut for DbFile<T> {
+    fn deref_mut(&mut self) -> &mut Database<T> {
+        &mut self.db
+    }
+}
+
+/// /etc/passwd, /etc/group, /etc/shadow をまとめて編集するトランザクション
+///
+/// ```no_run
+/// # use horiz_auth::userdb::Transaction;
+/// # fn main() -> std::io::Result<()> {
+/// let mut tx = Transaction::begin()?;
+/// if let Some(entry) = tx.shadow.get_mut("horiz") {
+///     entry.password = "!".to_string();
+/// }
+/// tx.commit()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct Transaction {
+    _lock: PwdLock,
+    pub passwd: DbFile<PasswdEntry>,
+    pub group: DbFile<GroupEntry>,
+    pub shadow: DbFile<ShadowEntry>,
+}
+
+impl Transaction {
+    pub fn begin() -> io::Result<Self> {
+        Self::begin_in(Path::new("/etc"))
+    }
+
+    /// `dir` 以下の passwd / group / shadow を編集する
+    pub fn begin_in(dir: &Path) -> io::Result<Self> {
+        let lock = PwdLock::acquire(dir)?;
+        Ok(Transaction {
+            _lock: lock,
+            passwd: DbFile::open(&dir.join("passwd"), 0o644)?,
+            group: DbFile::open(&dir.join("group"), 0o644)?,
+            shadow: DbFile::open(&dir.join("shadow"), 0o600)?,
+        })
+    }
+
+    /// 変更のあったファイルを書き込む。いずれかのファイルで競合を検出した場合は何も書き込まない。
+    pub fn commit(self) -> io::Result<()> {
+        let passwd = self.passwd.is_modified();
+        let group = self.group.is_modified();
+        let shadow = self.shadow.is_modified();
+
+        if passwd { self.passwd.check()?; }
+        if group { self.group.check()?; }
+        if shadow { self.shadow.check()?; }
+
+        if passwd { self.passwd.write()?; }
+        if shadow { self.shadow.write()?; }
+        if group { self.group.write()?; }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::TempDir;
+
+    #[test]
+    fn test_roundtrip_is_byte_exact() {
+        let passwd = "root:x:0:0:root:/root:/bin/sh\n# comment\n\nhoriz:x:1000:1000::/home/horiz:/bin/sh\nbroken line\n+nis";
+        assert_eq!(Database::<PasswdEntry>::parse(passwd).to_string(), passwd);
+        let group = "root:x:0:\nwheel:x:10:root,horiz\n";
+        assert_eq!(Database::<GroupEntry>::parse(group).to_string(), group);
+        let shadow = "root:$hz$a$b::0:99999:7:::\nhoriz:!:00019000::::::\n";
+        assert_eq!(Database::<ShadowEntry>::parse(shadow).to_string(), shadow);
+        assert_eq!(Database::<PasswdEntry>::parse("").to_string(), "");
+    }
+
+    #[test]
+    fn test_modify_insert_remove() {
+        let mut db = Database::<ShadowEntry>::parse("root:$hz$a$b:0:99999:7:::\n# keep\nhoriz:x:19000:0:99999:7:::\n");
+        db.get_mut("root").unwrap().password = "!$hz$a$b".to_string();
+        assert!(db.remove("horiz").is_some());
+        assert!(db.remove("horiz").is_none());
+        assert!(db.insert(ShadowEntry::parse("guest:*:19000:0:99999:7:::").unwrap()));
+        assert!(!db.insert(ShadowEntry::parse("root:*:::::::").unwrap()));
+        assert_eq!(db.to_string(), "root:!$hz$a$b:0:99999:7:::\n# keep\nguest:*:19000:0:99999:7:::\n");
+
+        let mut db = Database::<GroupEntry>::parse("");
+        db.insert(GroupEntry::parse("wheel:x:10:root").unwrap());
+        assert_eq!(db.to_string(), "wheel:x:10:root\n");
+
+        db.get_mut("wheel").unwrap().members.push("a:b".to_string());
+        assert!(db.validate().is_err());
+    }
+
+    fn test_dir(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("userdb-{}", name));
+        fs::write(dir.join("passwd"), "root:x:0:0:root:/root:/bin/sh\n").unwrap();
+        fs::write(dir.join("shadow"), "root:$hz$a$b::0:99999:7:::\n").unwrap();
+        fs::set_permissions(dir.join("shadow"), fs::Permissions::from_mode(0o600)).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_transaction_commit_and_backup() {
+        let dir = test_dir("commit");
+
+        let mut tx = Transaction::begin_in(&dir).unwrap();
+        tx.passwd.insert(PasswdEntry::parse("horiz:x:1000:1000::/home/horiz:/bin/sh").unwrap());
+        tx.shadow.insert(ShadowEntry::parse("horiz:!:19000:0:99999:7:::").unwrap());
+        tx.group.insert(GroupEntry::parse("horiz:x:1000:").unwrap());
+        tx.commit().unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("passwd")).unwrap(),
+            "root:x:0:0:root:/root:/bin/sh\nhoriz:x:1000:1000::/home/horiz:/bin/sh\n"
+        );
+        assert_eq!(fs::read_to_string(dir.join("passwd-")).unwrap(), "root:x:0:0:root:/root:/bin/sh\n");
+        assert_eq!(fs::read_to_string(dir.join("group")).unwrap(), "horiz:x:1000:\n");
+        assert!(!dir.join("group-").exists());
+        assert_eq!(fs::metadata(dir.join("shadow")).unwrap().mode() & 0o777, 0o600);
+        assert_eq!(fs::metadata(dir.join("shadow-")).unwrap().mode() & 0o777, 0o600);
+        assert!(!dir.join("shadow+").exists());
+    }
+
+    #[test]
+    fn test_transaction_detects_conflict_and_lock() {
+        let dir = test_dir("conflict");
+
+        let mut tx = Transaction::begin_in(&dir).unwrap();
+        assert_eq!(
+            PwdLock::acquire_timeout(&dir, Duration::from_millis(200)).err().map(|e| e.kind()),
+            Some(io::ErrorKind::WouldBlock)
+        );
+        tx.passwd.get_mut("root").unwrap().shell = "/bin/hsh".to_string();
+        tx.shadow.get_mut("root").unwrap().password = "!$hz$a$b".to_string();
+        // ロックを無視した書き込み
+        fs::write(dir.join("shadow"), "root:*::0:99999:7:::\n").unwrap();
+        assert!(tx.commit().is_err());
+
+        // どちらのファイルも書き込まれていない
+        assert_eq!(fs::read_to_string(dir.join("passwd")).unwrap(), "root:x:0:0:root:/root:/bin/sh\n");
+        assert_eq!(fs::read_to_string(dir.join("shadow")).unwrap(), "root:*::0:99999:7:::\n");
+        assert!(PwdLock::acquire_timeout(&dir, Duration::ZERO).is_ok());
+    }
+}
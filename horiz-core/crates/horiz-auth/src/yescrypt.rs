@@ -0,0 +1,503 @@
+// --- yescrypt ($y$, Zero-Dependency) ---
+//
+// libxcrypt の既定方式。リファレンス実装 (yescrypt-ref.c) に従い、
+// ブロックは SIMD 向けにシャッフルされたワード順で保持する。
+// 対応フレーバー: 従来の scrypt, WORM, および RW の既定構成
+// (pwxform: 6 ラウンド, gather 4, simple 2, S-box 12 KiB)。
+// ROM (NROM) とハッシュのアップグレード (g) は未対応。
+
+use horiz_crypto::hmac::hmac_sha256;
+
+use crate::pbkdf2::pbkdf2_hmac_sha256;
+use crate::scrypt::salsa20_double_round;
+use crate::sha256;
+
+const YESCRYPT_WORM: u32 = 1;
+const YESCRYPT_RW: u32 = 2;
+/// YESCRYPT_RW | ROUNDS_6 | GATHER_4 | SIMPLE_2 | SBOX_12K
+const YESCRYPT_RW_DEFAULTS: u32 = 0xb6;
+
+const PWX_SIMPLE: usize = 2;
+const PWX_GATHER: usize = 4;
+const PWX_ROUNDS: usize = 6;
+const PWX_WORDS: usize = 16;
+const SWIDTH: usize = 8;
+const S_BYTES: usize = 3 * (1 << SWIDTH) * PWX_SIMPLE * 8;
+const S_WORDS: usize = S_BYTES / 4;
+const S_MASK: u32 = (((1 << SWIDTH) - 1) * PWX_SIMPLE * 8) as u32;
+/// S0/S1/S2 各領域のワード数
+const S_REGION_WORDS: usize = (1 << SWIDTH) * PWX_SIMPLE * 2;
+
+// 不正な設定文字列による過大なメモリ消費を防ぐための上限 (1 GiB)
+const MAX_MEMORY: u64 = 1 << 30;
+
+const ITOA64: &[u8; 64] = b"./0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+fn atoi64(c: u8) -> Option<u32> {
+    ITOA64.iter().position(|&x| x == c).map(|i| i as u32)
+}
+
+/// yescrypt のパラメータ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Params {
+    pub flags: u32,
+    pub n: u64,
+    pub r: u32,
+    pub p: u32,
+    pub t: u32,
+}
+
+/// 可変長の整数エンコーディングを 1 つ読み取る (yescrypt-common.c の decode64_uint32)
+fn decode64_uint32(src: &[u8], pos: &mut usize, min: u32) -> Option<u32> {
+    let mut start = 0u32;
+    let mut end = 47u32;
+    let mut chars = 1;
+    let mut bits = 0u32;
+
+    let c = atoi64(*src.get(*pos)?)?;
+    *pos += 1;
+    let mut value = min as u64;
+    while c > end {
+        value += ((end + 1 - start) as u64) << bits;
+        start = end + 1;
+        end = start + (62 - end) / 2;
+        chars += 1;
+        bits += 6;
+    }
+    value += ((c - start) as u64) << bits;
+
+    while chars > 1 {
+        let c = atoi64(*src.get(*pos)?)?;
+        *pos += 1;
+        bits -= 6;
+        value += (c as u64) << bits;
+        chars -= 1;
+    }
+    u32::try_from(value).ok()
+}
+
+/// リトルエンディアンの 6 ビット単位でソルトをデコードする (yescrypt-common.c の decode64)
+fn decode64(src: &[u8]) -> Option<Vec<u8>> {
+    let mut out = Vec::new();
+    for group in src.chunks(4) {
+        let mut value = 0u32;
+        let mut bits = 0u32;
+        for &c in group {
+            value |= atoi64(c)? << bits;
+            bits += 6;
+        }
+        // 少なくとも 1 バイト分が必要
+        if bits < 12 { return None; }
+        while bits >= 8 {
+            out.push(value as u8);
+            value >>= 8;
+            bits -= 8;
+        }
+        // 余りのビットは 0 でなければならない
+        if value != 0 { return None; }
+    }
+    Some(out)
+}
+
+/// リトルエンディアンの 6 ビット単位でハッシュをエンコードする
+fn encode64(src: &[u8]) -> String {
+    let mut out = String::new();
+    for group in src.chunks(3) {
+        let mut value = 0u32;
+        for (i, &b) in group.iter().enumerate() {
+            value |= (b as u32) << (8 * i);
+        }
+        let bits = group.len() as u32 * 8;
+        let mut done = 0;
+        while done < bits {
+            out.push(ITOA64[(value & 0x3f) as usize] as char);
+            value >>= 6;
+            done += 6;
+        }
+    }
+    out
+}
+
+/// `$y$<パラメータ>$<ソルト>` を解析し、(パラメータ, プレフィックス長, デコード済みソルト, ソルト文字列) を返す
+fn parse_setting(setting: &str) -> Option<(Params, usize, Vec<u8>, &str)> {
+    if !setting.starts_with("$y$") { return None; }
+    let bytes = setting.as_bytes();
+    let mut pos = 3;
+
+    let flavor = decode64_uint32(bytes, &mut pos, 0)?;
+    let flags = if flavor < YESCRYPT_RW {
+        flavor
+    } else {
+        YESCRYPT_RW + ((flavor - YESCRYPT_RW) << 2)
+    };
+    if flags != 0 && flags != YESCRYPT_WORM && flags != YESCRYPT_RW_DEFAULTS {
+        return None;
+    }
+
+    let n_log2 = decode64_uint32(bytes, &mut pos, 1)?;
+    if n_log2 > 63 { return None; }
+    let r = decode64_uint32(bytes, &mut pos, 1)?;
+    let mut p = 1;
+    let mut t = 0;
+
+    if *bytes.get(pos)? != b'$' {
+        let have = decode64_uint32(bytes, &mut pos, 1)?;
+        if have & 1 != 0 { p = decode64_uint32(bytes, &mut pos, 2)?; }
+        if have & 2 != 0 { t = decode64_uint32(bytes, &mut pos, 1)?; }
+        // g (ハッシュのアップグレード) と NROM は未対応
+        if have & !3 != 0 { return None; }
+    }
+    if *bytes.get(pos)? != b'$' { return None; }
+    pos += 1;
+
+    // ソルトは最後の '$' まで (ハッシュ部分が無い設定文字列も受け付ける)
+    let tail = &setting[pos..];
+    let salt_str = match tail.rfind('$') {
+        Some(i) => &tail[..i],
+        None => tail,
+    };
+    let salt = decode64(salt_str.as_bytes())?;
+
+    let n = 1u64 << n_log2;
+    let params = Params { flags, n, r, p, t };
+    if !params.is_supported() { return None; }
+    Some((params, pos, salt, salt_str))
+}
+
+impl Params {
+    fn is_supported(&self) -> bool {
+        if self.n < 2 || self.r == 0 || self.p == 0 { return false; }
+        if self.flags == 0 && self.t != 0 { return false; }
+        if self.flags & YESCRYPT_RW != 0 && self.n / (self.p as u64) < 2 { return false; }
+        let block = 128u64 * self.r as u64;
+        let v = block.saturating_mul(self.n);
+        let b = block.saturating_mul(self.p as u64);
+        let s = (S_BYTES as u64).saturating_mul(self.p as u64);
+        v.saturating_add(b).saturating_add(s) <= MAX_MEMORY && self.t <= 64
+    }
+}
+
+/// ブロック内のワード順を入れ替えた Salsa20 コア (`double_rounds` 回のダブルラウンド)
+fn salsa20(b: &mut [u32], double_rounds: usize) {
+    let mut x = [0u32; 16];
+    for i in 0..16 {
+        x[i * 5 % 16] = b[i];
+    }
+    for _ in 0..double_rounds {
+        salsa20_double_round(&mut x);
+    }
+    for i in 0..16 {
+        b[i] = b[i].wrapping_add(x[i * 5 % 16]);
+    }
+}
+
+fn blkxor(dst: &mut [u32], src: &[u32]) {
+    for (d, s) in dst.iter_mut().zip(src.iter()) {
+        *d ^= s;
+    }
+}
+
+fn blockmix_salsa8(b: &mut [u32], y: &mut [u32], r: usize) {
+    let mut x = [0u32; 16];
+    x.copy_from_slice(&b[(2 * r - 1) * 16..2 * r * 16]);
+    for i in 0..2 * r {
+        blkxor(&mut x, &b[i * 16..i * 16 + 16]);
+        salsa20(&mut x, 4);
+        y[i * 16..i * 16 + 16].copy_from_slice(&x);
+    }
+    for i in 0..r {
+        b[i * 16..i * 16 + 16].copy_from_slice(&y[(i * 2) * 16..(i * 2) * 16 + 16]);
+        b[(i + r) * 16..(i + r) * 16 + 16].copy_from_slice(&y[(i * 2 + 1) * 16..(i * 2 + 1) * 16 + 16]);
+    }
+}
+
+/// pwxform の状態 (S-box と書き込み位置)
+struct Pwxform {
+    s: Vec<u32>,
+    s0: usize,
+    s1: usize,
+    s2: usize,
+    w: usize,
+}
+
+impl Pwxform {
+    fn pwxform(&mut self, x: &mut [u32; PWX_WORDS]) {
+        for i in 0..PWX_ROUNDS {
+            for j in 0..PWX_GATHER {
+                let base = j * PWX_SIMPLE * 2;
+                let p0 = self.s0 + ((x[base] & S_MASK) / 8) as usize * 2;
+                let p1 = self.s1 + ((x[base + 1] & S_MASK) / 8) as usize * 2;
+
+                for k in 0..PWX_SIMPLE {
+                    let s0 = ((self.s[p0 + k * 2 + 1] as u64) << 32) | self.s[p0 + k * 2] as u64;
+                    let s1 = ((self.s[p1 + k * 2 + 1] as u64) << 32) | self.s[p1 + k * 2] as u64;
+                    let lo = x[base + k * 2];
+                    let hi = x[base + k * 2 + 1];
+                    let v = ((hi as u64) * (lo as u64)).wrapping_add(s0) ^ s1;
+                    x[base + k * 2] = v as u32;
+                    x[base + k * 2 + 1] = (v >> 32) as u32;
+                }
+
+                if i != 0 && i != PWX_ROUNDS - 1 {
+                    let dst = self.s2 + self.w * 2;
+                    self.s[dst..dst + PWX_SIMPLE * 2].copy_from_slice(&x[base..base + PWX_SIMPLE * 2]);
+                    self.w += PWX_SIMPLE;
+                }
+            }
+        }
+        // (S0, S1, S2) <- (S2, S0, S1)
+        (self.s0, self.s1, self.s2) = (self.s2, self.s0, self.s1);
+        self.w &= (1 << SWIDTH) * PWX_SIMPLE - 1;
+    }
+
+    fn blockmix(&mut self, b: &mut [u32], r: usize) {
+        let r1 = 128 * r / (PWX_WORDS * 4);
+        let mut x = [0u32; PWX_WORDS];
+        x.copy_from_slice(&b[(r1 - 1) * PWX_WORDS..r1 * PWX_WORDS]);
+        for i in 0..r1 {
+            if r1 > 1 {
+                blkxor(&mut x, &b[i * PWX_WORDS..(i + 1) * PWX_WORDS]);
+            }
+            self.pwxform(&mut x);
+            b[i * PWX_WORDS..(i + 1) * PWX_WORDS].copy_from_slice(&x);
+        }
+        let i = (r1 - 1) * PWX_WORDS * 4 / 64;
+        salsa20(&mut b[i * 16..i * 16 + 16], 1);
+    }
+}
+
+fn integerify(x: &[u32], r: usize) -> u64 {
+    let last = &x[(2 * r - 1) * 16..];
+    ((last[13] as u64) << 32) + last[0] as u64
+}
+
+fn p2floor(mut x: u64) -> u64 {
+    loop {
+        let y = x & (x - 1);
+        if y == 0 { return x; }
+        x = y;
+    }
+}
+
+fn wrap(x: u64, i: u64) -> u64 {
+    let n = p2floor(i);
+    (x & (n - 1)) + (i - n)
+}
+
+/// B (自然順) と X (シャッフル順) の変換
+fn shuffle_in(b: &[u32], x: &mut [u32]) {
+    for (dst, src) in x.chunks_exact_mut(16).zip(b.chunks_exact(16)) {
+        for i in 0..16 {
+            dst[i] = src[i * 5 % 16];
+        }
+    }
+}
+
+fn shuffle_out(x: &[u32], b: &mut [u32]) {
+    for (src, dst) in x.chunks_exact(16).zip(b.chunks_exact_mut(16)) {
+        for i in 0..16 {
+            dst[i * 5 % 16] = src[i];
+        }
+    }
+}
+
+fn mix(x: &mut [u32], y: &mut [u32], r: usize, ctx: &mut Option<&mut Pwxform>) {
+    match ctx {
+        Some(c) => c.blockmix(x, r),
+        None => blockmix_salsa8(x, y, r),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn smix1(b: &mut [u32], r: usize, n: u64, rw: bool, v: &mut [u32], x: &mut [u32], y: &mut [u32], mut ctx: Option<&mut Pwxform>) {
+    let s = 32 * r;
+    shuffle_in(b, x);
+    for i in 0..n {
+        let i_us = i as usize;
+        v[i_us * s..(i_us + 1) * s].copy_from_slice(x);
+        if rw && i > 1 {
+            let j = wrap(integerify(x, r), i) as usize;
+            blkxor(x, &v[j * s..(j + 1) * s]);
+        }
+        mix(x, y, r, &mut ctx);
+    }
+    shuffle_out(x, b);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn smix2(b: &mut [u32], r: usize, n: u64, nloop: u64, rw: bool, v: &mut [u32], x: &mut [u32], y: &mut [u32], mut ctx: Option<&mut Pwxform>) {
+    let s = 32 * r;
+    shuffle_in(b, x);
+    for _ in 0..nloop {
+        let j = (integerify(x, r) & (n - 1)) as usize;
+        blkxor(x, &v[j * s..(j + 1) * s]);
+        if rw {
+            v[j * s..(j + 1) * s].copy_from_slice(x);
+        }
+        mix(x, y, r, &mut ctx);
+    }
+    shuffle_out(x, b);
+}
+
+fn words_to_bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|w| w.to_le_bytes()).collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn smix(b: &mut [u32], r: usize, n: u64, p: u32, t: u32, flags: u32, v: &mut [u32], passwd: &mut [u8; 32]) {
+    let s = 32 * r;
+    let rw = flags & YESCRYPT_RW != 0;
+    let mut x = vec![0u32; s];
+    let mut y = vec![0u32; s];
+
+    let mut n_chunk = n / p as u64;
+    let mut nloop_all = n_chunk;
+    if rw {
+        if t <= 1 {
+            if t != 0 { nloop_all *= 2; }
+            nloop_all = nloop_all.div_ceil(3);
+        } else {
+            nloop_all *= (t - 1) as u64;
+        }
+    } else if t != 0 {
+        if t == 1 { nloop_all += nloop_all.div_ceil(2); }
+        nloop_all *= t as u64;
+    }
+    let mut nloop_rw = if rw { nloop_all / p as u64 } else { 0 };
+
+    n_chunk &= !1;
+    nloop_all = (nloop_all + 1) & !1;
+    nloop_rw = (nloop_rw + 1) & !1;
+
+    let mut ctxs: Vec<Pwxform> = Vec::new();
+    let mut v_chunk = 0u64;
+    for i in 0..p as usize {
+        let np = if i < p as usize - 1 { n_chunk } else { n - v_chunk };
+        let bp = &mut b[s * i..s * (i + 1)];
+        let vp = &mut v[s * v_chunk as usize..s * (v_chunk + np) as usize];
+
+        if rw {
+            // S-box を SMix1 (r = 1, 非 RW) で初期化する
+            let mut sbox = vec![0u32; S_WORDS];
+            let mut sx = vec![0u32; 32];
+            let mut sy = vec![0u32; 32];
+            smix1(bp, 1, (S_BYTES / 128) as u64, false, &mut sbox, &mut sx, &mut sy, None);
+            ctxs.push(Pwxform { s: sbox, s2: 0, s1: S_REGION_WORDS, s0: 2 * S_REGION_WORDS, w: 0 });
+            if i == 0 {
+                let key = words_to_bytes(&bp[s - 16..s]);
+                *passwd = hmac_sha256(&key, passwd);
+            }
+        }
+
+        let ctx = ctxs.get_mut(i);
+        smix1(bp, r, np, rw, vp, &mut x, &mut y, ctx);
+        let ctx = ctxs.get_mut(i);
+        smix2(bp, r, p2floor(np), nloop_rw, rw, vp, &mut x, &mut y, ctx);
+        v_chunk += n_chunk;
+    }
+
+    for i in 0..p as usize {
+        let bp = &mut b[s * i..s * (i + 1)];
+        let ctx = ctxs.get_mut(i);
+        smix2(bp, r, n, nloop_all - nloop_rw, false, v, &mut x, &mut y, ctx);
+    }
+}
+
+fn kdf_body(passwd: &[u8], salt: &[u8], params: &Params, prehash: bool) -> [u8; 32] {
+    let Params { flags, n, r, p, t } = *params;
+    let r_us = r as usize;
+    let s = 32 * r_us;
+    let mut sha = [0u8; 32];
+    let mut pw = passwd.to_vec();
+
+    if flags != 0 {
+        let key: &[u8] = if prehash { b"yescrypt-prehash" } else { b"yescrypt" };
+        sha = hmac_sha256(key, passwd);
+        pw = sha.to_vec();
+    }
+
+    let b_bytes = pbkdf2_hmac_sha256(&pw, salt, 1, p as usize * 128 * r_us);
+    let mut b: Vec<u32> = b_bytes.chunks_exact(4).map(|c| u32::from_le_bytes(c.try_into().unwrap())).collect();
+    if flags != 0 {
+        sha.copy_from_slice(&b_bytes[..32]);
+    }
+
+    let mut v = vec![0u32; s * n as usize];
+    if p == 1 || flags & YESCRYPT_RW != 0 {
+        smix(&mut b, r_us, n, p, t, flags, &mut v, &mut sha);
+    } else {
+        for i in 0..p as usize {
+            smix(&mut b[s * i..s * (i + 1)], r_us, n, 1, t, flags, &mut v, &mut sha);
+        }
+    }
+
+    if flags != 0 {
+        pw = sha.to_vec();
+    }
+    let mut dk = [0u8; 32];
+    dk.copy_from_slice(&pbkdf2_hmac_sha256(&pw, &words_to_bytes(&b), 1, 32));
+
+    // SCRAM (RFC 5802) と同様の ClientKey / StoredKey の導出
+    if flags != 0 && !prehash {
+        let client_key = hmac_sha256(&dk, b"Client Key");
+        dk = sha256(&client_key);
+    }
+    dk
+}
+
+fn kdf(passwd: &[u8], salt: &[u8], params: &Params) -> [u8; 32] {
+    let Params { flags, n, r, p, .. } = *params;
+    if flags & YESCRYPT_RW != 0 && n / p as u64 >= 0x100 && n / p as u64 * r as u64 >= 0x20000 {
+        let prehash = Params { n: n >> 6, t: 0, ..*params };
+        let dk = kdf_body(passwd, salt, &prehash, true);
+        return kdf_body(&dk, salt, params, false);
+    }
+    kdf_body(passwd, salt, params, false)
+}
+
+/// 設定文字列が対応している yescrypt パラメータか判定する
+pub fn is_valid_setting(setting: &str) -> bool {
+    parse_setting(setting).is_some()
+}
+
+/// crypt(3) 互換: `$y$...$salt` 形式の設定文字列で password をハッシュする
+pub fn yescrypt(password: &[u8], setting: &str) -> Option<String> {
+    let (params, prefix_len, salt, salt_str) = parse_setting(setting)?;
+    let hash = kdf(password, &salt, &params);
+    Some(format!("{}{}${}", &setting[..prefix_len], salt_str, encode64(&hash)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_yescrypt_libxcrypt_vectors() {
+        // libxcrypt の crypt(3) で生成した値
+        let cases = [
+            ("test", "$y$j75$LdJMENpBABJJ3hIHjB1Bi.$YHtrg5JwVUTWV0NoyCEtFoufzPCIyF7PGqZxL86OqT7"),
+            ("", "$y$j75$$k3/RXWgfuXdQ./MyGwcEs.LrD4d.k/Vvg9CTtKCC1.B"),
+            // 従来の scrypt / WORM フレーバー
+            ("test", "$y$.75$LdJMENpBABJJ3hIHjB1Bi.$l71paF5ogCP0Q5K9dq6VEvu1NOht2WKRKVN0vRMQgl0"),
+            ("test", "$y$/5T$abcd$WL9Gh.IEVxKewL2h6xY5zTnzByCUlFKs.TjoOT7vin8"),
+            // 2 文字の r, p = 2, t = 1
+            ("test", "$y$j5k.$abcd$gtqIDPq2jdjLQ1/Acu6J/7lqB/2dBr9QgXcTVXkgS3B"),
+            ("test", "$y$j5T..$abcd$xszMn0nVx2lxy7u/BtNLoD6AeN3DPq.EnTJBYayaOs/"),
+            ("test", "$y$j5T/.$abcd$/Cxasl4menGYC3RIQpSlZFupcIBrwFrPH4ur1Dq/qlC"),
+            // 既定パラメータ (N = 4096, r = 32, 事前ハッシュあり)
+            ("password", "$y$j9T$saltsaltsalt$WJhblAc/BKcuw1LHqgcyvlsjC8J4ha9Wl82.5/aQSy8"),
+        ];
+        for (password, expected) in cases {
+            assert_eq!(yescrypt(password.as_bytes(), expected).as_deref(), Some(expected));
+        }
+    }
+
+    #[test]
+    fn test_rejects_unsupported_settings() {
+        assert!(!is_valid_setting("$y$$abcd$"));
+        assert!(!is_valid_setting("$y$j9T"));
+        assert!(!is_valid_setting("$y$jzT$abcd$"));
+        // g (アップグレード) を含む設定
+        assert!(!is_valid_setting("$y$j5T1.$abcd$"));
+    }
+}
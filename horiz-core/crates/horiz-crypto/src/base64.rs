@@ -0,0 +1,78 @@
+// --- カスタム Base64 実装 (依存関係なし) ---
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode(data: &[u8]) -> String {
+    let mut result = String::new();
+    for chunk in data.chunks(3) {
+        let b = match chunk.len() {
+            3 => [chunk[0], chunk[1], chunk[2]],
+            2 => [chunk[0], chunk[1], 0],
+            1 => [chunk[0], 0, 0],
+            _ => unreachable!(),
+        };
+
+        let i0 = (b[0] >> 2) as usize;
+        let i1 = (((b[0] & 0x03) << 4) | (b[1] >> 4)) as usize;
+        let i2 = (((b[1] & 0x0f) << 2) | (b[2] >> 6)) as usize;
+        let i3 = (b[2] & 0x3f) as usize;
+
+        result.push(BASE64_ALPHABET[i0] as char);
+        result.push(BASE64_ALPHABET[i1] as char);
+        if chunk.len() >= 2 {
+            result.push(BASE64_ALPHABET[i2] as char);
+        } else {
+            result.push('=');
+        }
+        if chunk.len() >= 3 {
+            result.push(BASE64_ALPHABET[i3] as char);
+        } else {
+            result.push('=');
+        }
+    }
+    result
+}
+
+/// 標準の Base64 (パディング必須) を復号する。空白は無視し、それ以外の不正な入力は `None` とする。
+pub fn decode(input: &str) -> Option<Vec<u8>> {
+    let chars: Vec<u8> = input.bytes().filter(|b| !b.is_ascii_whitespace()).collect();
+    if !chars.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(chars.len() / 4 * 3);
+    for (n, quad) in chars.chunks(4).enumerate() {
+        let last = n == chars.len() / 4 - 1;
+        let padding = quad.iter().rev().take_while(|&&b| b == b'=').count();
+        if padding > 2 || (padding > 0 && !last) {
+            return None;
+        }
+        let mut value = 0u32;
+        for &b in &quad[..4 - padding] {
+            let digit = BASE64_ALPHABET.iter().position(|&c| c == b)? as u32;
+            value = (value << 6) | digit;
+        }
+        value <<= 6 * padding as u32;
+        let bytes = value.to_be_bytes();
+        out.extend_from_slice(&bytes[1..4 - padding]);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(encode(b"any car"), "YW55IGNhcg==");
+        for data in [&b""[..], b"f", b"fo", b"foo", b"foob", b"any car"] {
+            assert_eq!(decode(&encode(data)).unwrap(), data);
+        }
+        assert_eq!(decode("Zm9v\nYmFy").unwrap(), b"foobar");
+        // PEM の本文のような改行 (CRLF) やタブも無視する
+        assert_eq!(decode("Zm\r\n9v\tYm Fy").unwrap(), b"foobar");
+        assert!(decode("Zm9").is_none());
+        assert!(decode("Zg==Zm9v").is_none());
+        assert!(decode("Zm9*").is_none());
+    }
+}
@@ -0,0 +1,439 @@
+// --- Ed25519 署名検証 (RFC 8032, 依存関係なし) ---
+//
+// horiz-auth の authorized_keys による認証と、horiz-pkg のパッケージ署名・TLS・X.509 証明書の検証で共用する。
+//
+// - 公開鍵と R は RFC 8032 5.1.3 に従って復元し、y が p 以上のものや x の符号が矛盾するものは拒否する
+// - S が群の位数 L 以上の署名は拒否する (署名の改変 (malleability) の防止)
+// - k = SHA-512(R || A || M) mod L として [S]B = R + [k]A を確認する (余因子を掛けない検証)
+//
+// 扱うのは公開鍵と署名のみで秘密の値はないため、定数時間で計算する必要はない。
+
+// リム演算はインデックスでの記述の方が読みやすいため許可する
+#![allow(clippy::needless_range_loop)]
+
+use crate::sha512::sha512;
+
+pub const PUBLIC_KEY_LEN: usize = 32;
+pub const SIGNATURE_LEN: usize = 64;
+
+/// GF(2^255 - 19) の元 (51 ビットずつ 5 つのリムで表す)
+#[derive(Clone, Copy, Debug)]
+struct Fe([u64; 5]);
+
+const MASK51: u64 = (1 << 51) - 1;
+
+impl Fe {
+    const ZERO: Fe = Fe([0; 5]);
+    const ONE: Fe = Fe([1, 0, 0, 0, 0]);
+
+    fn from_u64(v: u64) -> Fe {
+        Fe([v & MASK51, v >> 51, 0, 0, 0])
+    }
+
+    /// 最上位ビットは無視する
+    fn from_bytes(bytes: &[u8; 32]) -> Fe {
+        let w = |i: usize| u64::from_le_bytes(bytes[i * 8..i * 8 + 8].try_into().unwrap());
+        let (w0, w1, w2, w3) = (w(0), w(1), w(2), w(3));
+        Fe([
+            w0 & MASK51,
+            ((w0 >> 51) | (w1 << 13)) & MASK51,
+            ((w1 >> 38) | (w2 << 26)) & MASK51,
+            ((w2 >> 25) | (w3 << 39)) & MASK51,
+            (w3 >> 12) & MASK51,
+        ])
+    }
+
+    /// 0 以上 p 未満に正規化して符号化する
+    fn to_bytes(self) -> [u8; 32] {
+        let mut l = self.carry().0;
+        // l + 19 が 2^255 以上 (= l が p 以上) なら p を引く
+        let mut q = (l[0] + 19) >> 51;
+        for i in 1..5 {
+            q = (l[i] + q) >> 51;
+        }
+        l[0] += 19 * q;
+        for i in 0..4 {
+            l[i + 1] += l[i] >> 51;
+            l[i] &= MASK51;
+        }
+        l[4] &= MASK51;
+
+        let words = [l[0] | (l[1] << 51), (l[1] >> 13) | (l[2] << 38), (l[2] >> 26) | (l[3] << 25), (l[3] >> 39) | (l[4] << 12)];
+        let mut out = [0u8; 32];
+        for (i, word) in words.iter().enumerate() {
+            out[i * 8..i * 8 + 8].copy_from_slice(&word.to_le_bytes());
+        }
+        out
+    }
+
+    /// 各リムを 51 ビット (+ 小さな桁上がり) に収める
+    fn carry(self) -> Fe {
+        let mut l = self.0;
+        for _ in 0..2 {
+            let mut c = 0;
+            for i in 0..5 {
+                l[i] += c;
+                c = l[i] >> 51;
+                l[i] &= MASK51;
+            }
+            l[0] += c * 19;
+        }
+        Fe(l)
+    }
+
+    fn add(&self, other: &Fe) -> Fe {
+        let mut l = [0u64; 5];
+        for i in 0..5 {
+            l[i] = self.0[i] + other.0[i];
+        }
+        Fe(l).carry()
+    }
+
+    fn sub(&self, other: &Fe) -> Fe {
+        // 負にならないよう 16p を足してから引く
+        const P16: [u64; 5] = [(MASK51 - 18) * 16, MASK51 * 16, MASK51 * 16, MASK51 * 16, MASK51 * 16];
+        let mut l = [0u64; 5];
+        for i in 0..5 {
+            l[i] = self.0[i] + P16[i] - other.0[i];
+        }
+        Fe(l).carry()
+    }
+
+    fn neg(&self) -> Fe {
+        Fe::ZERO.sub(self)
+    }
+
+    fn mul(&self, other: &Fe) -> Fe {
+        let a = self.0.map(u128::from);
+        let b = other.0.map(u128::from);
+        // 2^255 = 19 (mod p) を使って上位の項を折り返す
+        let b19 = b.map(|x| x * 19);
+        let r = [
+            a[0] * b[0] + a[1] * b19[4] + a[2] * b19[3] + a[3] * b19[2] + a[4] * b19[1],
+            a[0] * b[1] + a[1] * b[0] + a[2] * b19[4] + a[3] * b19[3] + a[4] * b19[2],
+            a[0] * b[2] + a[1] * b[1] + a[2] * b[0] + a[3] * b19[4] + a[4] * b19[3],
+            a[0] * b[3] + a[1] * b[2] + a[2] * b[1] + a[3] * b[0] + a[4] * b19[4],
+            a[0] * b[4] + a[1] * b[3] + a[2] * b[2] + a[3] * b[1] + a[4] * b[0],
+        ];
+        let mut l = [0u64; 5];
+        let mut c = 0u128;
+        for i in 0..5 {
+            let v = r[i] + c;
+            l[i] = (v as u64) & MASK51;
+            c = v >> 51;
+        }
+        l[0] += (c * 19) as u64;
+        Fe(l).carry()
+    }
+
+    fn square(&self) -> Fe {
+        self.mul(self)
+    }
+
+    /// `self` の `exp` 乗 (exp はリトルエンディアン)
+    fn pow(&self, exp: &[u8; 32]) -> Fe {
+        let mut result = Fe::ONE;
+        for i in (0..256).rev() {
+            result = result.square();
+            if (exp[i / 8] >> (i % 8)) & 1 == 1 {
+                result = result.mul(self);
+            }
+        }
+        result
+    }
+
+    fn invert(&self) -> Fe {
+        // p - 2 = 2^255 - 21
+        let mut exp = [0xff; 32];
+        exp[0] = 0xeb;
+        exp[31] = 0x7f;
+        self.pow(&exp)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.to_bytes() == [0; 32]
+    }
+
+    fn is_negative(&self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    fn equals(&self, other: &Fe) -> bool {
+        self.to_bytes() == other.to_bytes()
+    }
+}
+
+/// 曲線の定数
+struct Constants {
+    /// d = -121665 / 121666
+    d: Fe,
+    /// 2d
+    d2: Fe,
+    /// sqrt(-1) = 2^((p - 1) / 4)
+    sqrt_m1: Fe,
+}
+
+impl Constants {
+    fn new() -> Self {
+        let d = Fe::from_u64(121665).neg().mul(&Fe::from_u64(121666).invert());
+        // (p - 1) / 4 = 2^253 - 5
+        let mut exp = [0xff; 32];
+        exp[0] = 0xfb;
+        exp[31] = 0x1f;
+        Constants { d, d2: d.add(&d), sqrt_m1: Fe::from_u64(2).pow(&exp) }
+    }
+}
+
+/// 拡張座標 (X : Y : Z : T), x = X/Z, y = Y/Z, xy = T/Z
+#[derive(Clone, Copy)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+impl Point {
+    const IDENTITY: Point = Point { x: Fe::ZERO, y: Fe::ONE, z: Fe::ONE, t: Fe::ZERO };
+
+    /// RFC 8032 5.1.3 の点の復元
+    fn decompress(bytes: &[u8; 32], c: &Constants) -> Option<Point> {
+        let y = Fe::from_bytes(bytes);
+        let sign = bytes[31] >> 7 == 1;
+        let mut canonical = *bytes;
+        canonical[31] &= 0x7f;
+        if y.to_bytes() != canonical {
+            return None;
+        }
+
+        // x^2 = (y^2 - 1) / (d y^2 + 1) = u / v
+        let yy = y.square();
+        let u = yy.sub(&Fe::ONE);
+        let v = c.d.mul(&yy).add(&Fe::ONE);
+        // 候補 x = u v^3 (u v^7)^((p - 5) / 8)
+        let v3 = v.square().mul(&v);
+        let v7 = v3.square().mul(&v);
+        let mut exp = [0xff; 32];
+        exp[0] = 0xfd; // (p - 5) / 8 = 2^252 - 3
+        exp[31] = 0x0f;
+        let mut x = u.mul(&v3).mul(&u.mul(&v7).pow(&exp));
+
+        let vxx = v.mul(&x.square());
+        if vxx.equals(&u) {
+            // そのまま
+        } else if vxx.equals(&u.neg()) {
+            x = x.mul(&c.sqrt_m1);
+        } else {
+            return None;
+        }
+        if x.is_zero() && sign {
+            return None;
+        }
+        if x.is_negative() != sign {
+            x = x.neg();
+        }
+        Some(Point { x, y, z: Fe::ONE, t: x.mul(&y) })
+    }
+
+    fn compress(&self) -> [u8; 32] {
+        let zinv = self.z.invert();
+        let x = self.x.mul(&zinv);
+        let mut out = self.y.mul(&zinv).to_bytes();
+        out[31] |= (x.is_negative() as u8) << 7;
+        out
+    }
+
+    /// a = -1 のねじれエドワーズ曲線の加算 (add-2008-hwcd-3)。2 倍算にもそのまま使える。
+    fn add(&self, other: &Point, c: &Constants) -> Point {
+        let a = self.y.sub(&self.x).mul(&other.y.sub(&other.x));
+        let b = self.y.add(&self.x).mul(&other.y.add(&other.x));
+        let cc = self.t.mul(&c.d2).mul(&other.t);
+        let d = self.z.mul(&other.z);
+        let d = d.add(&d);
+        let (e, f, g, h) = (b.sub(&a), d.sub(&cc), d.add(&cc), b.add(&a));
+        Point { x: e.mul(&f), y: g.mul(&h), z: f.mul(&g), t: e.mul(&h) }
+    }
+
+    fn neg(&self) -> Point {
+        Point { x: self.x.neg(), y: self.y, z: self.z, t: self.t.neg() }
+    }
+
+    /// `scalar` (リトルエンディアン) 倍
+    fn mul_scalar(&self, scalar: &[u8; 32], c: &Constants) -> Point {
+        let mut result = Point::IDENTITY;
+        for i in (0..256).rev() {
+            result = result.add(&result, c);
+            if (scalar[i / 8] >> (i % 8)) & 1 == 1 {
+                result = result.add(self, c);
+            }
+        }
+        result
+    }
+}
+
+/// 基点 B の符号化 (y = 4/5, x は正)
+const BASE_POINT: [u8; 32] = [
+    0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+];
+
+/// 群の位数 L = 2^252 + 27742317777372353535851937790883648493 (リトルエンディアン)
+const ORDER: [u8; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10,
+];
+
+/// リトルエンディアンの整数として `a < b`
+fn less_than(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    for i in (0..32).rev() {
+        if a[i] != b[i] {
+            return a[i] < b[i];
+        }
+    }
+    false
+}
+
+/// 512 ビットの値 (リトルエンディアン) を L で割った余り
+fn reduce_scalar(wide: &[u8; 64]) -> [u8; 32] {
+    // 上位ビットから 1 ビットずつ取り込み、L 以上になれば引く (r < L < 2^253 なので 2r + 1 も 256 ビットに収まる)
+    let mut r = [0u8; 32];
+    for i in (0..512).rev() {
+        let mut carry = (wide[i / 8] >> (i % 8)) & 1;
+        for byte in r.iter_mut() {
+            let v = (*byte as u16) << 1 | carry as u16;
+            *byte = v as u8;
+            carry = (v >> 8) as u8;
+        }
+        if !less_than(&r, &ORDER) {
+            let mut borrow = 0i16;
+            for j in 0..32 {
+                let v = r[j] as i16 - ORDER[j] as i16 - borrow;
+                r[j] = v.rem_euclid(256) as u8;
+                borrow = (v < 0) as i16;
+            }
+        }
+    }
+    r
+}
+
+/// `public_key` による `message` の署名 `signature` を検証する
+pub fn verify(public_key: &[u8; PUBLIC_KEY_LEN], message: &[u8], signature: &[u8; SIGNATURE_LEN]) -> bool {
+    let c = Constants::new();
+    let r_bytes: [u8; 32] = signature[..32].try_into().unwrap();
+    let s_bytes: [u8; 32] = signature[32..].try_into().unwrap();
+    if !less_than(&s_bytes, &ORDER) {
+        return false;
+    }
+    let (Some(a), Some(_)) = (Point::decompress(public_key, &c), Point::decompress(&r_bytes, &c)) else {
+        return false;
+    };
+    let base = Point::decompress(&BASE_POINT, &c).expect("基点は復元できる");
+
+    let mut input = Vec::with_capacity(64 + message.len());
+    input.extend_from_slice(&r_bytes);
+    input.extend_from_slice(public_key);
+    input.extend_from_slice(message);
+    let k = reduce_scalar(&sha512(&input));
+
+    // [S]B - [k]A が R と一致するか
+    let check = base.mul_scalar(&s_bytes, &c).add(&a.mul_scalar(&k, &c).neg(), &c);
+    check.compress() == r_bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    fn case(public_key: &str, message: &str, signature: &str) -> ([u8; 32], Vec<u8>, [u8; 64]) {
+        (hex(public_key).try_into().unwrap(), hex(message), hex(signature).try_into().unwrap())
+    }
+
+    #[test]
+    fn test_rfc8032_vectors() {
+        // RFC 8032 7.1 TEST 1〜3
+        let vectors = [
+            case(
+                "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+                "",
+                "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+            ),
+            case(
+                "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+                "72",
+                "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+            ),
+            case(
+                "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+                "af82",
+                "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+            ),
+        ];
+        for (public_key, message, signature) in &vectors {
+            assert!(verify(public_key, message, signature));
+
+            let mut tampered = message.clone();
+            tampered.push(0);
+            assert!(!verify(public_key, &tampered, signature));
+            let mut bad = *signature;
+            bad[40] ^= 1;
+            assert!(!verify(public_key, message, &bad));
+            let mut bad = *signature;
+            bad[0] ^= 1;
+            assert!(!verify(public_key, message, &bad));
+        }
+        // 別の鍵では検証できない
+        assert!(!verify(&vectors[1].0, &vectors[0].1, &vectors[0].2));
+    }
+
+    #[test]
+    fn test_rejects_malleable_and_invalid_encodings() {
+        let (public_key, message, signature) = case(
+            "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+            "",
+            "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+        );
+        // S + L も同じ点を与えるが、L 以上の S は拒否する
+        let mut s: [u8; 32] = signature[32..].try_into().unwrap();
+        let mut carry = 0u16;
+        for i in 0..32 {
+            let v = s[i] as u16 + ORDER[i] as u16 + carry;
+            s[i] = v as u8;
+            carry = v >> 8;
+        }
+        let mut malleated = signature;
+        malleated[32..].copy_from_slice(&s);
+        assert!(!verify(&public_key, &message, &malleated));
+
+        // y >= p の符号化は拒否する
+        let mut non_canonical = [0xff; 32];
+        non_canonical[0] = 0xee;
+        non_canonical[31] = 0x7f;
+        assert!(!verify(&non_canonical, &message, &signature));
+    }
+
+    #[test]
+    fn test_reduce_scalar() {
+        let mut wide = [0u8; 64];
+        wide[..32].copy_from_slice(&ORDER);
+        assert_eq!(reduce_scalar(&wide), [0; 32]);
+        wide[0] += 5;
+        let mut five = [0u8; 32];
+        five[0] = 5;
+        assert_eq!(reduce_scalar(&wide), five);
+    }
+
+    #[test]
+    fn test_base_point_round_trip() {
+        let c = Constants::new();
+        let base = Point::decompress(&BASE_POINT, &c).unwrap();
+        assert_eq!(base.compress(), BASE_POINT);
+        // [L]B は単位元
+        assert_eq!(base.mul_scalar(&ORDER, &c).compress(), Point::IDENTITY.compress());
+        // sqrt(-1)^2 = -1
+        assert!(c.sqrt_m1.square().equals(&Fe::ONE.neg()));
+    }
+}
@@ -0,0 +1,80 @@
+// --- HMAC-SHA-256 (Zero-Dependency, RFC 2104) ---
+//
+// 鍵から導出した ipad/opad の圧縮済み状態を保持し、同じ鍵で繰り返し計算する場合
+// (PBKDF2 など) に SHA-256 の圧縮回数を半分に抑える。
+
+use crate::sha256::{IV, compress, sha256};
+
+/// 既に `consumed` バイトを圧縮済みの状態 `h` に `data` を追加して SHA-256 を完了する
+fn finish(mut h: [u32; 8], consumed: usize, data: &[u8]) -> [u8; 32] {
+    let mut padded = data.to_vec();
+    let bit_len = ((consumed + data.len()) as u64) * 8;
+    padded.push(0x80);
+    while !(padded.len() + 8).is_multiple_of(64) {
+        padded.push(0x00);
+    }
+    padded.extend_from_slice(&bit_len.to_be_bytes());
+
+    for chunk in padded.chunks_exact(64) {
+        compress(&mut h, chunk.try_into().unwrap());
+    }
+
+    let mut result = [0u8; 32];
+    for i in 0..8 {
+        result[i * 4..i * 4 + 4].copy_from_slice(&h[i].to_be_bytes());
+    }
+    result
+}
+
+/// 鍵を事前処理した HMAC-SHA-256
+pub struct HmacSha256 {
+    inner: [u32; 8],
+    outer: [u32; 8],
+}
+
+impl HmacSha256 {
+    pub fn new(key: &[u8]) -> Self {
+        let mut k = [0u8; 64];
+        if key.len() > 64 {
+            k[..32].copy_from_slice(&sha256(key));
+        } else {
+            k[..key.len()].copy_from_slice(key);
+        }
+        let mut ipad = k;
+        let mut opad = k;
+        for b in ipad.iter_mut() { *b ^= 0x36; }
+        for b in opad.iter_mut() { *b ^= 0x5c; }
+
+        let mut inner = IV;
+        let mut outer = IV;
+        compress(&mut inner, &ipad);
+        compress(&mut outer, &opad);
+        HmacSha256 { inner, outer }
+    }
+
+    pub fn mac(&self, msg: &[u8]) -> [u8; 32] {
+        let inner_hash = finish(self.inner, 64, msg);
+        finish(self.outer, 64, &inner_hash)
+    }
+}
+
+/// HMAC-SHA-256 (RFC 2104)
+pub fn hmac_sha256(key: &[u8], msg: &[u8]) -> [u8; 32] {
+    HmacSha256::new(key).mac(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_hex(data: &[u8]) -> String {
+        data.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_hmac_sha256_rfc4231() {
+        // RFC 4231 テストケース 2
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(to_hex(&mac), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+}
@@ -0,0 +1,10 @@
+// --- horiz-crypto: 暗号の基本処理 ---
+//
+// 認証 (horiz-auth) とパッケージ管理 (horiz-pkg) が共用するハッシュ関数・署名検証・符号化。
+// どちらの機能にも依存しない小さなクレートとして分け、horiz-pkg が認証スタックに依存しないようにする。
+
+pub mod base64;
+pub mod ed25519;
+pub mod hmac;
+pub mod sha256;
+pub mod sha512;
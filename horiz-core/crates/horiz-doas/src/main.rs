@@ -0,0 +1,356 @@
+// --- horiz-doas: 他のユーザー (既定は root) としてコマンドを実行する ---
+//
+// setuid root で配置し、/etc/horiz/doas.conf の規則で許可された実行のみを行う。
+
+mod policy;
+mod state;
+
+use std::env;
+use std::ffi::CString;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use horiz_auth::audit::audit_log;
+use horiz_auth::group::GroupEntry;
+use horiz_auth::passwd::{self, PasswdEntry};
+use horiz_auth::stack::{AuthContext, AuthStack, Failure};
+use horiz_auth::tty::{Terminal, current_tty};
+use horiz_auth::userdb::Database;
+
+use policy::{Policy, Request};
+
+const USAGE: &str = "Usage: doas [-Lns] [-C config] [-u user] command [args ...]";
+
+/// コマンドの検索と、環境変数 PATH の既定値
+const SAFE_PATH: &str = "/bin:/sbin:/usr/bin:/usr/sbin:/usr/local/bin";
+
+/// keepenv を指定しない場合に引き継ぐ環境変数
+const KEEP_VARS: &[&str] = &["TERM", "DISPLAY", "COLORTERM", "LANG", "LC_ALL"];
+
+/// パスワードを誤った場合の待ち時間 (総当たり対策)
+const FAIL_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Args {
+    /// `-u`: 実行するユーザー
+    target: Option<String>,
+    /// `-n`: パスワードの入力が必要な場合は失敗する
+    non_interactive: bool,
+    /// `-s`: コマンドの代わりにシェルを実行する
+    shell: bool,
+    /// `-L`: persist の認証を取り消す
+    clear_persist: bool,
+    /// `-C`: 設定ファイルを検査する
+    check_config: Option<String>,
+    command: Vec<String>,
+}
+
+// --- カスタム引数パーサー ---
+// 最初のオプション以外の引数以降はコマンドとして扱う。
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--" => break,
+            "-u" => parsed.target = Some(iter.next().ok_or("Missing value for -u")?.clone()),
+            "-C" => parsed.check_config = Some(iter.next().ok_or("Missing value for -C")?.clone()),
+            "-n" => parsed.non_interactive = true,
+            "-s" => parsed.shell = true,
+            "-L" => parsed.clear_persist = true,
+            s if s.starts_with('-') && s.len() > 1 => return Err(format!("Unknown argument: {}", s)),
+            _ => {
+                parsed.command.push(arg.clone());
+                break;
+            }
+        }
+    }
+    parsed.command.extend(iter.cloned());
+    if parsed.shell && !parsed.command.is_empty() {
+        return Err("-s とコマンドは同時に指定できません".into());
+    }
+    if !parsed.shell && !parsed.clear_persist && parsed.check_config.is_none() && parsed.command.is_empty() {
+        return Err("コマンドを指定してください".into());
+    }
+    Ok(parsed)
+}
+
+/// ユーザーが所属するグループ名の一覧 (主グループを含む)
+fn group_names(user: &PasswdEntry) -> Vec<String> {
+    let Ok(db) = Database::<GroupEntry>::load(Path::new(horiz_auth::group::GROUP_PATH)) else {
+        return Vec::new();
+    };
+    db.groups_of(&user.name, user.gid).into_iter().filter_map(|gid| db.by_gid(gid).map(|g| g.name.clone())).collect()
+}
+
+/// 実行するコマンドの環境変数。
+///
+/// 既定では KEEP_VARS 以外を捨てる。keepenv の場合も、動的リンカやシェルの挙動を変える変数は取り除く。
+fn build_env(current: &[(String, String)], keepenv: bool, caller: &PasswdEntry, target: &PasswdEntry) -> Vec<(String, String)> {
+    let unsafe_var = |key: &str| key.starts_with("LD_") || matches!(key, "IFS" | "ENV" | "BASH_ENV" | "CDPATH" | "PS4");
+    let mut vars: Vec<(String, String)> = current
+        .iter()
+        .filter(|(key, _)| if keepenv { !unsafe_var(key) } else { KEEP_VARS.contains(&key.as_str()) })
+        .cloned()
+        .collect();
+
+    let mut set = |key: &str, value: &str| {
+        vars.retain(|(k, _)| k != key);
+        vars.push((key.to_string(), value.to_string()));
+    };
+    set("HOME", &target.home);
+    set("LOGNAME", &target.name);
+    set("USER", &target.name);
+    set("SHELL", &target.shell);
+    set("DOAS_USER", &caller.name);
+    if !keepenv || !current.iter().any(|(k, _)| k == "PATH") {
+        set("PATH", SAFE_PATH);
+    }
+    vars
+}
+
+/// 実行するファイルの絶対パス。`/` を含まないコマンドは SAFE_PATH から探す。
+/// 相対パス (`./script` `bin/tool`) は、実行前に対象ユーザーのホームへ移動するため、呼び出し元の
+/// カレントディレクトリを基準にする。
+fn resolve_command(cmd: &str) -> Option<String> {
+    if cmd.starts_with('/') {
+        return Some(cmd.to_string());
+    }
+    if cmd.contains('/') {
+        return env::current_dir().ok()?.join(cmd).into_os_string().into_string().ok();
+    }
+    SAFE_PATH.split(':').map(|dir| format!("{}/{}", dir, cmd)).find(|path| Path::new(path).is_file())
+}
+
+/// 呼び出し元を認証スタック (auth.conf の doas) で認証する。persist が有効な間は尋ねない。
+fn authenticate(caller: &PasswdEntry, rule: &policy::Rule, args: &Args) -> Result<(), String> {
+    let dir = state::open_dir(Path::new(state::STATE_DIR)).map_err(|e| e.to_string())?;
+    let session = if rule.options.persist { state::session_key() } else { None };
+    if let Some(key) = &session && state::persist_valid(dir, caller.uid, key) {
+        return Ok(());
+    }
+    if args.non_interactive {
+        return Err("パスワードの入力が必要です".into());
+    }
+
+    let stack = AuthStack::load("doas")?;
+    let mut ctx = AuthContext::new("doas", &caller.name, &current_tty());
+    ctx.password_prompt = format!("doas ({}) password: ", caller.name);
+    match stack.authenticate(&mut ctx, &mut Terminal::new("doas")) {
+        Ok(()) if ctx.password_expired => {
+            audit_log(&format!("doas: authentication refused for user: {} (password expired)", caller.name));
+            Err("パスワードの有効期限が切れています。passwd で変更してください".into())
+        }
+        Ok(()) => {
+            if let Some(key) = &session {
+                let _ = state::persist_update(dir, caller.uid, key);
+            }
+            Ok(())
+        }
+        Err(Failure::BadCredentials) => {
+            thread::sleep(FAIL_DELAY);
+            audit_log(&format!("doas: authentication failure for user: {}", caller.name));
+            Err("認証に失敗しました".into())
+        }
+        Err(e) => {
+            audit_log(&format!("doas: authentication refused for user: {} ({:?})", caller.name, e));
+            Err(e.to_string())
+        }
+    }
+}
+
+fn exec_as(target: &PasswdEntry, path: &str, argv: &[String], vars: &[(String, String)]) -> io::Error {
+    let groups: Vec<libc::gid_t> = horiz_auth::group::getgrouplist(&target.name, target.gid).unwrap_or_else(|_| vec![target.gid]);
+    let to_cstring = |s: &str| CString::new(s).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput));
+    let prepared = (|| -> io::Result<(CString, Vec<CString>, Vec<CString>)> {
+        let path = to_cstring(path)?;
+        let argv = argv.iter().map(|a| to_cstring(a)).collect::<io::Result<Vec<_>>>()?;
+        let envp = vars.iter().map(|(k, v)| to_cstring(&format!("{}={}", k, v))).collect::<io::Result<Vec<_>>>()?;
+        Ok((path, argv, envp))
+    })();
+    let (path, argv, envp) = match prepared {
+        Ok(prepared) => prepared,
+        Err(e) => return e,
+    };
+    let mut argv_ptrs: Vec<*const libc::c_char> = argv.iter().map(|a| a.as_ptr()).collect();
+    argv_ptrs.push(std::ptr::null());
+    let mut envp_ptrs: Vec<*const libc::c_char> = envp.iter().map(|e| e.as_ptr()).collect();
+    envp_ptrs.push(std::ptr::null());
+
+    unsafe {
+        // horiz-init の run_session と同じ順序で権限を切り替える
+        if libc::setgroups(groups.len(), groups.as_ptr()) != 0
+            || libc::setgid(target.gid) != 0
+            || libc::setuid(target.uid) != 0
+        {
+            return io::Error::last_os_error();
+        }
+        if env::set_current_dir(&target.home).is_err() {
+            let _ = env::set_current_dir("/");
+        }
+        libc::execve(path.as_ptr(), argv_ptrs.as_ptr(), envp_ptrs.as_ptr());
+    }
+    io::Error::last_os_error()
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let uid = unsafe { libc::getuid() };
+    let caller = passwd::getpwuid(uid)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("UID {} のユーザーが見つかりません", uid))?;
+
+    if args.clear_persist {
+        let dir = state::open_dir(Path::new(state::STATE_DIR)).map_err(|e| e.to_string())?;
+        return state::persist_clear(dir, caller.uid).map_err(|e| e.to_string());
+    }
+
+    let target_name = args.target.clone().unwrap_or_else(|| "root".to_string());
+    let command = if args.shell {
+        vec![caller.shell.clone()]
+    } else {
+        args.command.clone()
+    };
+    let groups = group_names(&caller);
+
+    // -C: 設定ファイルの検査 (コマンドが指定されていれば判定結果を表示する)
+    if let Some(config) = &args.check_config {
+        // 任意のファイルを root 権限で読めないよう、先に権限を放棄する
+        if unsafe { libc::setgid(libc::getgid()) != 0 || libc::setuid(uid) != 0 } {
+            return Err(io::Error::last_os_error().to_string());
+        }
+        let contents = fs::read_to_string(config).map_err(|e| format!("{}: {}", config, e))?;
+        let policy = Policy::parse(&contents).map_err(|e| format!("{}: {}", config, e))?;
+        if let Some((cmd, cmd_args)) = command.split_first() {
+            let req = Request { user: &caller.name, groups: &groups, target: &target_name, cmd, args: cmd_args };
+            match policy.check(&req) {
+                Some(rule) if rule.options.nopass => println!("permit nopass"),
+                Some(_) => println!("permit"),
+                None => {
+                    println!("deny");
+                    process::exit(1);
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    if unsafe { libc::geteuid() } != 0 {
+        return Err("setuid root で実行されていません".into());
+    }
+    let target = passwd::getpwnam(&target_name)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("ユーザー {} は存在しません", target_name))?;
+    let (cmd, cmd_args) = command.split_first().ok_or("コマンドを指定してください")?;
+    let cmdline = command.join(" ");
+
+    let policy = Policy::load(Path::new(policy::POLICY_PATH))?;
+    let req = Request { user: &caller.name, groups: &groups, target: &target.name, cmd, args: cmd_args };
+    let Some(rule) = policy.check(&req) else {
+        audit_log(&format!("doas: denied: user={} target={} cmd={}", caller.name, target.name, cmdline));
+        return Err("許可されていない操作です".into());
+    };
+
+    if !rule.options.nopass && let Err(e) = authenticate(&caller, rule, &args) {
+        audit_log(&format!("doas: failed: user={} target={} cmd={}", caller.name, target.name, cmdline));
+        return Err(e);
+    }
+
+    let path = resolve_command(cmd).ok_or_else(|| format!("{}: コマンドが見つかりません", cmd))?;
+    let current: Vec<(String, String)> = env::vars_os()
+        .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+        .collect();
+    let vars = build_env(&current, rule.options.keepenv, &caller, &target);
+
+    audit_log(&format!("doas: permitted: user={} target={} cmd={}", caller.name, target.name, cmdline));
+    let err = exec_as(&target, &path, &command, &vars);
+    Err(format!("{}: {}", path, err))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.first().is_some_and(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let args = match parse_args(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("doas: {}", e);
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(args) {
+        eprintln!("doas: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use horiz_auth::userdb::Entry;
+
+    fn strings(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let a = parse_args(&strings(&["-u", "daemon", "ls", "-l", "/root"])).unwrap();
+        assert_eq!(a.target.as_deref(), Some("daemon"));
+        assert_eq!(a.command, strings(&["ls", "-l", "/root"]));
+        let a = parse_args(&strings(&["-n", "--", "-weird"])).unwrap();
+        assert!(a.non_interactive);
+        assert_eq!(a.command, strings(&["-weird"]));
+        assert!(parse_args(&strings(&["-s"])).unwrap().shell);
+        assert!(parse_args(&strings(&["-s", "ls"])).is_err());
+        assert!(parse_args(&strings(&[])).is_err());
+        assert!(parse_args(&strings(&["-x", "ls"])).is_err());
+    }
+
+    #[test]
+    fn test_build_env() {
+        let caller = PasswdEntry::parse("horiz:x:1000:1000::/home/horiz:/bin/sh").unwrap();
+        let target = PasswdEntry::parse("root:x:0:0:root:/root:/bin/sh").unwrap();
+        let current = vec![
+            ("TERM".to_string(), "vt100".to_string()),
+            ("PATH".to_string(), "/home/horiz/bin:/bin".to_string()),
+            ("LD_PRELOAD".to_string(), "/tmp/evil.so".to_string()),
+            ("EDITOR".to_string(), "vi".to_string()),
+            ("HOME".to_string(), "/home/horiz".to_string()),
+        ];
+        let get = |vars: &[(String, String)], key: &str| vars.iter().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+
+        let vars = build_env(&current, false, &caller, &target);
+        assert_eq!(get(&vars, "TERM").as_deref(), Some("vt100"));
+        assert_eq!(get(&vars, "PATH").as_deref(), Some(SAFE_PATH));
+        assert_eq!(get(&vars, "HOME").as_deref(), Some("/root"));
+        assert_eq!(get(&vars, "DOAS_USER").as_deref(), Some("horiz"));
+        assert_eq!(get(&vars, "EDITOR"), None);
+        assert_eq!(get(&vars, "LD_PRELOAD"), None);
+
+        let vars = build_env(&current, true, &caller, &target);
+        assert_eq!(get(&vars, "EDITOR").as_deref(), Some("vi"));
+        assert_eq!(get(&vars, "PATH").as_deref(), Some("/home/horiz/bin:/bin"));
+        assert_eq!(get(&vars, "HOME").as_deref(), Some("/root"));
+        assert_eq!(get(&vars, "LD_PRELOAD"), None);
+        assert_eq!(vars.iter().filter(|(k, _)| k == "HOME").count(), 1);
+    }
+
+    #[test]
+    fn test_resolve_command() {
+        assert_eq!(resolve_command("/bin/sh").as_deref(), Some("/bin/sh"));
+        // 相対パスは対象ユーザーのホームではなく、呼び出し元のカレントディレクトリを基準にする
+        let cwd = env::current_dir().unwrap();
+        let resolved = resolve_command("./script").unwrap();
+        assert_eq!(Path::new(&resolved), cwd.join("script"));
+        assert_eq!(Path::new(&resolve_command("bin/tool").unwrap()), cwd.join("bin/tool"));
+        assert!(resolve_command("sh").is_some_and(|path| path.starts_with('/')));
+        assert_eq!(resolve_command("no-such-command-horiz"), None);
+    }
+}
@@ -0,0 +1,258 @@
+// --- /etc/horiz/doas.conf: 実行を許可する規則 ---
+//
+// OpenBSD の doas.conf(5) と同じ書式の 1 行 1 規則:
+//
+//   permit|deny [options] identity [as target] [cmd command [args ...]]
+//
+// 最後に一致した規則が適用され、どの規則にも一致しなければ拒否する。
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+pub const POLICY_PATH: &str = "/etc/horiz/doas.conf";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Permit,
+    Deny,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Options {
+    /// パスワードを求めない
+    pub nopass: bool,
+    /// 認証に成功した後、同じ端末のセッションでは一定時間パスワードを求めない
+    pub persist: bool,
+    /// 呼び出し元の環境変数を引き継ぐ (危険な変数は常に取り除く)
+    pub keepenv: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Identity {
+    User(String),
+    /// `:group` の形式
+    Group(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub action: Action,
+    pub options: Options,
+    pub identity: Identity,
+    /// `as target` (省略時はすべてのユーザー)
+    pub target: Option<String>,
+    /// `cmd command` (省略時はすべてのコマンド)
+    pub cmd: Option<String>,
+    /// `args ...` (指定時は引数が完全に一致する場合のみ。`args` のみなら引数なし)
+    pub args: Option<Vec<String>>,
+}
+
+/// 規則の照合に使う実行要求
+pub struct Request<'a> {
+    pub user: &'a str,
+    /// 所属するグループ名 (主グループを含む)
+    pub groups: &'a [String],
+    pub target: &'a str,
+    pub cmd: &'a str,
+    pub args: &'a [String],
+}
+
+impl Rule {
+    fn matches(&self, req: &Request) -> bool {
+        let identity = match &self.identity {
+            Identity::User(name) => name == req.user,
+            Identity::Group(name) => req.groups.iter().any(|g| g == name),
+        };
+        identity
+            && self.target.as_ref().is_none_or(|t| t == req.target)
+            && self.cmd.as_ref().is_none_or(|c| c == req.cmd)
+            && self.args.as_ref().is_none_or(|a| a == req.args)
+    }
+}
+
+/// 空白区切りで単語に分割する。`"..."` と `\` によるエスケープ、`#` 以降のコメントに対応する。
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_token = false;
+    let mut quoted = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                current.push(chars.next().ok_or("行末に \\ があります")?);
+                in_token = true;
+            }
+            '"' => {
+                quoted = !quoted;
+                in_token = true;
+            }
+            '#' if !quoted => break,
+            c if c.is_whitespace() && !quoted => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut current));
+                    in_token = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_token = true;
+            }
+        }
+    }
+    if quoted {
+        return Err("引用符が閉じられていません".into());
+    }
+    if in_token {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+fn parse_rule(tokens: &[String]) -> Result<Rule, String> {
+    let mut iter = tokens.iter().map(String::as_str).peekable();
+    let action = match iter.next() {
+        Some("permit") => Action::Permit,
+        Some("deny") => Action::Deny,
+        Some(other) => return Err(format!("permit または deny が必要です: {}", other)),
+        None => unreachable!("空行は呼び出し側で除外する"),
+    };
+
+    let mut options = Options::default();
+    loop {
+        match iter.peek() {
+            Some(&"nopass") => options.nopass = true,
+            Some(&"persist") => options.persist = true,
+            Some(&"keepenv") => options.keepenv = true,
+            _ => break,
+        }
+        iter.next();
+    }
+    if action == Action::Deny && options != Options::default() {
+        return Err("deny にはオプションを指定できません".into());
+    }
+    if options.nopass && options.persist {
+        return Err("nopass と persist は同時に指定できません".into());
+    }
+
+    let identity = match iter.next() {
+        Some(group) if group.starts_with(':') && group.len() > 1 => Identity::Group(group[1..].to_string()),
+        Some(user) if !user.starts_with(':') && !matches!(user, "as" | "cmd" | "args") => Identity::User(user.to_string()),
+        _ => return Err("ユーザー名または :グループ名 が必要です".into()),
+    };
+
+    let mut rule = Rule { action, options, identity, target: None, cmd: None, args: None };
+    if iter.peek() == Some(&"as") {
+        iter.next();
+        rule.target = Some(iter.next().ok_or("as の後にユーザー名が必要です")?.to_string());
+    }
+    if iter.peek() == Some(&"cmd") {
+        iter.next();
+        rule.cmd = Some(iter.next().ok_or("cmd の後にコマンドが必要です")?.to_string());
+        if iter.peek() == Some(&"args") {
+            iter.next();
+            rule.args = Some(iter.by_ref().map(str::to_string).collect());
+        }
+    }
+    if let Some(extra) = iter.next() {
+        return Err(format!("不明な単語: {}", extra));
+    }
+    Ok(rule)
+}
+
+#[derive(Debug, Default)]
+pub struct Policy {
+    rules: Vec<Rule>,
+}
+
+impl Policy {
+    /// 書式の誤りは「行番号: 内容」のエラーとする (誤った規則を無視して権限を与えないため)
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let tokens = tokenize(line).map_err(|e| format!("{}行目: {}", index + 1, e))?;
+            if tokens.is_empty() {
+                continue;
+            }
+            rules.push(parse_rule(&tokens).map_err(|e| format!("{}行目: {}", index + 1, e))?);
+        }
+        Ok(Policy { rules })
+    }
+
+    /// root 以外が所有する、または root 以外が書き込めるファイルは読み込まない
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let metadata = fs::metadata(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        if metadata.uid() != 0 || metadata.mode() & 0o022 != 0 {
+            return Err(format!("{} の所有者または権限が安全ではありません", path.display()));
+        }
+        let contents = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Self::parse(&contents).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// 許可する場合は一致した規則を返す
+    pub fn check(&self, req: &Request) -> Option<&Rule> {
+        self.rules.iter().rev().find(|rule| rule.matches(req)).filter(|rule| rule.action == Action::Permit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let policy = Policy::parse(concat!(
+            "# comment\n",
+            "\n",
+            "permit persist :wheel\n",
+            "permit nopass keepenv root\n",
+            "permit nopass horiz as root cmd /bin/pkg args update  # trailing comment\n",
+            "deny guest cmd \"/bin/my sh\"\n",
+        ))
+        .unwrap();
+        assert_eq!(policy.rules.len(), 4);
+        assert_eq!(policy.rules[0].identity, Identity::Group("wheel".into()));
+        assert!(policy.rules[0].options.persist);
+        assert!(policy.rules[1].options.nopass && policy.rules[1].options.keepenv);
+        assert_eq!(policy.rules[2].target.as_deref(), Some("root"));
+        assert_eq!(policy.rules[2].args, Some(strings(&["update"])));
+        assert_eq!(policy.rules[3].cmd.as_deref(), Some("/bin/my sh"));
+
+        assert!(Policy::parse("allow horiz\n").is_err());
+        assert!(Policy::parse("permit\n").is_err());
+        assert!(Policy::parse("deny nopass horiz\n").is_err());
+        assert!(Policy::parse("permit nopass persist horiz\n").is_err());
+        assert!(Policy::parse("permit horiz as\n").is_err());
+        assert_eq!(Policy::parse("permit horiz cmd\n").unwrap_err(), "1行目: cmd の後にコマンドが必要です");
+        assert!(Policy::parse("permit horiz \"root\n").is_err());
+    }
+
+    #[test]
+    fn test_last_match_wins() {
+        let policy = Policy::parse(concat!(
+            "permit persist :wheel\n",
+            "deny :wheel as root cmd /bin/sh\n",
+            "permit nopass horiz as root cmd /bin/pkg args update\n",
+        ))
+        .unwrap();
+        let wheel = strings(&["horiz", "wheel"]);
+        let req = |user, groups, target, cmd, args| Request { user, groups, target, cmd, args };
+
+        let rule = policy.check(&req("horiz", &wheel, "root", "/bin/ls", &[])).unwrap();
+        assert!(rule.options.persist);
+        assert!(policy.check(&req("horiz", &wheel, "root", "/bin/sh", &[])).is_none());
+        assert!(policy.check(&req("horiz", &wheel, "daemon", "/bin/sh", &[])).is_some());
+        // 一般ユーザー (wheel 以外) は args が完全に一致する場合のみ
+        let args = strings(&["update"]);
+        assert!(policy.check(&req("horiz", &[], "root", "/bin/pkg", &args)).unwrap().options.nopass);
+        let other = strings(&["install", "x"]);
+        assert!(policy.check(&req("horiz", &[], "root", "/bin/pkg", &other)).is_none());
+        assert!(policy.check(&req("horiz", &[], "root", "/bin/pkg", &[])).is_none());
+        assert!(policy.check(&req("guest", &[], "root", "/bin/ls", &[])).is_none());
+    }
+}
@@ -0,0 +1,121 @@
+// --- 認証状態の保存 (persist のタイムスタンプ) ---
+//
+// 認証失敗の回数は認証スタックの lockout モジュールが記録する。
+//
+// root のみが読み書きできる STATE_DIR にユーザーごとのファイルを置く。
+
+use std::fs::{self, DirBuilder, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const STATE_DIR: &str = "/run/horiz-doas";
+
+/// persist による認証の有効期間 (秒)
+pub const PERSIST_TIMEOUT: u64 = 5 * 60;
+
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// 状態ディレクトリを用意する。既存のディレクトリが実行ユーザー以外の所有であれば使用しない。
+pub fn open_dir(dir: &Path) -> io::Result<&Path> {
+    if let Some(parent) = dir.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() || metadata.uid() != unsafe { libc::geteuid() } || metadata.mode() & 0o077 != 0 {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} が安全ではありません", dir.display())));
+    }
+    Ok(dir)
+}
+
+fn write_file(path: &Path, contents: &str) -> io::Result<()> {
+    // 既存のファイルを置き換える (シンボリックリンクを辿らない)
+    let _ = fs::remove_file(path);
+    let mut f = OpenOptions::new().write(true).create_new(true).mode(0o600).open(path)?;
+    f.write_all(contents.as_bytes())
+}
+
+// --- persist ---
+
+fn timestamp_path(dir: &Path, uid: u32) -> PathBuf {
+    dir.join(format!("persist-{}", uid))
+}
+
+/// 端末とセッションを識別する文字列。端末がない場合は persist を使わない。
+///
+/// セッション ID は再利用されうるため、セッションリーダーの起動時刻も含める。
+pub fn session_key() -> Option<String> {
+    unsafe {
+        if libc::isatty(libc::STDIN_FILENO) != 1 {
+            return None;
+        }
+        let mut st: libc::stat = std::mem::zeroed();
+        if libc::fstat(libc::STDIN_FILENO, &mut st) != 0 {
+            return None;
+        }
+        let sid = libc::getsid(0);
+        if sid < 0 {
+            return None;
+        }
+        // /proc/<pid>/stat の 22 番目のフィールド (starttime)。comm に空白を含みうるため ')' 以降を数える。
+        let stat = fs::read_to_string(format!("/proc/{}/stat", sid)).ok()?;
+        let start_time = stat.rsplit_once(')')?.1.split_whitespace().nth(19)?.to_string();
+        Some(format!("tty={} sid={} start={}", st.st_rdev, sid, start_time))
+    }
+}
+
+/// `modified` に記録されたタイムスタンプが有効期間内か
+pub fn is_fresh(modified: u64, now: u64) -> bool {
+    modified <= now && now - modified < PERSIST_TIMEOUT
+}
+
+pub fn persist_valid(dir: &Path, uid: u32, key: &str) -> bool {
+    let path = timestamp_path(dir, uid);
+    let Ok(metadata) = fs::symlink_metadata(&path) else { return false; };
+    let modified = metadata.mtime().max(0) as u64;
+    metadata.is_file() && is_fresh(modified, now()) && fs::read_to_string(&path).is_ok_and(|s| s == key)
+}
+
+pub fn persist_update(dir: &Path, uid: u32, key: &str) -> io::Result<()> {
+    write_file(&timestamp_path(dir, uid), key)
+}
+
+pub fn persist_clear(dir: &Path, uid: u32) -> io::Result<()> {
+    match fs::remove_file(timestamp_path(dir, uid)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use horiz_auth::testutil::TempDir;
+
+    #[test]
+    fn test_persist() {
+        let tmp = TempDir::new("doas-state");
+        let dir = open_dir(&tmp.join("persist")).unwrap().to_path_buf();
+
+        assert!(!persist_valid(&dir, 1000, "tty=1 sid=2 start=3"));
+        persist_update(&dir, 1000, "tty=1 sid=2 start=3").unwrap();
+        assert!(persist_valid(&dir, 1000, "tty=1 sid=2 start=3"));
+        assert!(!persist_valid(&dir, 1000, "tty=1 sid=9 start=3"));
+        assert!(!persist_valid(&dir, 1001, "tty=1 sid=2 start=3"));
+        persist_clear(&dir, 1000).unwrap();
+        persist_clear(&dir, 1000).unwrap();
+        assert!(!persist_valid(&dir, 1000, "tty=1 sid=2 start=3"));
+
+        assert!(is_fresh(100, 100 + PERSIST_TIMEOUT - 1));
+        assert!(!is_fresh(100, 100 + PERSIST_TIMEOUT));
+        assert!(!is_fresh(200, 100));
+    }
+}
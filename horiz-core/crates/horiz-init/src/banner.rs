@@ -0,0 +1,121 @@
+// --- ログイン画面のバナー (/etc/issue, /etc/motd) ---
+//
+// /etc/issue は agetty 互換のエスケープシーケンスを展開してログインプロンプトの前に表示する。
+
+use std::ffi::CStr;
+use std::fs;
+
+use horiz_auth::time::{format_date, format_time};
+
+pub const ISSUE_PATH: &str = "/etc/issue";
+pub const MOTD_PATH: &str = "/etc/motd";
+const DEFAULT_ISSUE: &str = "\n--- HorizOS Login ---\n";
+
+/// uname(2) とホスト名から得られるシステム情報
+pub struct SystemInfo {
+    pub sysname: String,
+    pub nodename: String,
+    pub release: String,
+    pub version: String,
+    pub machine: String,
+}
+
+fn field_to_string(field: &[libc::c_char]) -> String {
+    unsafe { CStr::from_ptr(field.as_ptr()) }.to_string_lossy().into_owned()
+}
+
+impl SystemInfo {
+    pub fn current() -> Self {
+        let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+        let ok = unsafe { libc::uname(&mut uts) } == 0;
+
+        // init はホスト名を設定しないため、/etc/hostname を優先する
+        let nodename = fs::read_to_string("/etc/hostname")
+            .map(|s| s.trim().to_string())
+            .ok()
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| if ok { field_to_string(&uts.nodename) } else { "horiz".to_string() });
+
+        if !ok {
+            return SystemInfo {
+                sysname: "Linux".to_string(),
+                nodename,
+                release: String::new(),
+                version: String::new(),
+                machine: String::new(),
+            };
+        }
+
+        SystemInfo {
+            sysname: field_to_string(&uts.sysname),
+            nodename,
+            release: field_to_string(&uts.release),
+            version: field_to_string(&uts.version),
+            machine: field_to_string(&uts.machine),
+        }
+    }
+}
+
+/// /etc/issue のエスケープシーケンスを展開する
+///
+/// 対応: `\s` OS名, `\n` ホスト名, `\r` カーネルリリース, `\v` カーネルバージョン,
+/// `\m` アーキテクチャ, `\l` 端末名, `\d` 日付, `\t` 時刻, `\\` バックスラッシュ。
+/// 未知のシーケンスはそのまま出力する。
+pub fn render_issue(template: &str, info: &SystemInfo, tty: &str, now: u64) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push_str(&info.sysname),
+            Some('n') => out.push_str(&info.nodename),
+            Some('r') => out.push_str(&info.release),
+            Some('v') => out.push_str(&info.version),
+            Some('m') => out.push_str(&info.machine),
+            Some('l') => out.push_str(tty),
+            Some('d') => out.push_str(&format_date(now)),
+            Some('t') => out.push_str(&format_time(now)),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// ログインプロンプトの前に表示する文字列 (/etc/issue が無ければ既定の見出し)
+pub fn issue_text(tty: &str, now: u64) -> String {
+    match fs::read_to_string(ISSUE_PATH) {
+        Ok(template) => render_issue(&template, &SystemInfo::current(), tty, now),
+        Err(_) => DEFAULT_ISSUE.to_string(),
+    }
+}
+
+/// /etc/motd の内容 (存在しなければ None)
+pub fn motd_text() -> Option<String> {
+    fs::read_to_string(MOTD_PATH).ok().filter(|s| !s.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_issue() {
+        let info = SystemInfo {
+            sysname: "Linux".into(),
+            nodename: "horiz".into(),
+            release: "6.19.3".into(),
+            version: "#1 SMP".into(),
+            machine: "x86_64".into(),
+        };
+        let out = render_issue("\\s \\r (\\m) \\n \\l \\d \\\\ \\q", &info, "tty1", 0);
+        assert_eq!(out, "Linux 6.19.3 (x86_64) horiz tty1 1970-01-01 \\ \\q");
+    }
+}
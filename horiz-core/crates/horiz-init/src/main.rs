@@ -1,13 +1,20 @@
 use std::process::Command;
 use std::thread;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 use std::ffi::CString;
 use std::env;
 use std::fs::{self, OpenOptions};
 use std::io::{self, Write};
+use std::path::Path;
 use libc::{mount, MS_NOSUID, MS_NODEV, MS_NOEXEC, waitpid, WNOHANG, SIGCHLD, signal, SIG_DFL};
 
-use horiz_auth;
+use horiz_auth::SecretString;
+use horiz_auth::lastlog::{LastLog, LoginRecord};
+use horiz_auth::quality::{QualityError, QualityPolicy};
+use horiz_auth::stack::{AuthContext, AuthStack, Conversation, Failure};
+use horiz_auth::{time, tty};
+
+mod banner;
 
 /// ログレベルの定義
 enum LogLevel {
@@ -28,17 +35,9 @@ impl LogLevel {
     }
 }
 
-/// タイムスタンプを取得 (Zero-Dependency)
-fn get_timestamp() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0)
-}
-
 /// 構造化ログを出力
 fn log_message(level: LogLevel, message: &str) {
-    let ts = get_timestamp();
+    let ts = time::now();
     let log_entry = format!("[{}] [{}] {}\n", ts, level.as_str(), message);
     
     // 標準出力への報告
@@ -56,11 +55,9 @@ fn log_message(level: LogLevel, message: &str) {
 
     for path in target_paths {
         // シンボリックリンクをチェックして、リンク先への意図せぬ書き込みを防止
-        if let Ok(metadata) = fs::symlink_metadata(path) {
-            if metadata.file_type().is_symlink() {
-                eprintln!("[警告] ログファイル {} がシンボリックリンクです。攻撃の可能性があるためスキップします。", path);
-                continue;
-            }
+        if let Ok(metadata) = fs::symlink_metadata(path) && metadata.file_type().is_symlink() {
+            eprintln!("[警告] ログファイル {} がシンボリックリンクです。攻撃の可能性があるためスキップします。", path);
+            continue;
         }
 
         if let Ok(mut f) = OpenOptions::new().create(true).append(true).open(path) {
@@ -96,7 +93,7 @@ fn setup_network() {
     let mut success = false;
     for i in 1..=3 {
         log_message(LogLevel::Info, &format!("ループバックインターフェース (lo) の有効化を試行中 (回数: {}/3)...", i));
-        let status = Command::new("ip").args(&["link", "set", "lo", "up"]).status();
+        let status = Command::new("ip").args(["link", "set", "lo", "up"]).status();
         match status {
             Ok(s) if s.success() => {
                 log_message(LogLevel::Info, "ループバックインターフェース (lo) を有効化。");
@@ -127,70 +124,225 @@ fn reap_zombies() {
     }
 }
 
-fn get_user_info(username: &str) -> (u32, u32) {
-    if let Ok(contents) = fs::read_to_string("/etc/passwd") {
-        for line in contents.lines() {
-            let parts: Vec<&str> = line.split(':').collect();
-            if parts.len() >= 4 && parts[0] == username {
-                if let (Ok(uid), Ok(gid)) = (parts[2].parse::<u32>(), parts[3].parse::<u32>()) {
-                    return (uid, gid);
+fn get_user_info(username: &str) -> Option<(u32, u32)> {
+    horiz_auth::passwd::getpwnam(username).ok().flatten().map(|pw| (pw.uid, pw.gid))
+}
+
+/// 検証済みのパスワードを現在のハッシュポリシーで再ハッシュし、/etc/shadow を更新する
+fn rehash_password(username: &str, password: &SecretString) {
+    let result = horiz_auth::generate_salt().and_then(|salt| {
+        let encoded = horiz_auth::HashPolicy::load().hash(password.expose(), &salt);
+        horiz_auth::shadow::replace_password(Path::new(horiz_auth::shadow::SHADOW_PATH), username, &encoded)
+    });
+    match result {
+        Ok(()) => {
+            log_message(LogLevel::Info, &format!("パスワードハッシュを現在のポリシーで更新しました。ユーザー: {}", username));
+            log_message(LogLevel::Audit, &format!("Password hash upgraded for user: {}", username));
+        }
+        Err(e) => log_message(LogLevel::Warn, &format!("パスワードハッシュの更新に失敗: {}", e)),
+    }
+}
+
+/// コンソールでの認証の対話
+struct Console;
+
+impl Conversation for Console {
+    fn prompt_secret(&mut self, prompt: &str) -> io::Result<SecretString> {
+        tty::read_password(prompt)
+    }
+
+    fn prompt(&mut self, prompt: &str) -> io::Result<String> {
+        tty::read_line(prompt)
+    }
+
+    fn message(&mut self, text: &str) {
+        println!("{}", text);
+    }
+}
+
+/// 有効期限切れのパスワードを対話的に変更する。変更できた場合は true。
+fn change_expired_password(username: &str, old_password: &SecretString) -> bool {
+    println!("パスワードの有効期限が切れています。新しいパスワードを設定してください。");
+    let policy = QualityPolicy::load();
+
+    for _ in 0..3 {
+        let read = |prompt| {
+            tty::read_password(prompt)
+                .inspect_err(|e| log_message(LogLevel::Error, &format!("パスワードを読み取れません: {}", e)))
+        };
+        let Ok(new_password) = read("new password: ") else { return false; };
+        let Ok(confirm) = read("retype new password: ") else { return false; };
+
+        if new_password.is_empty() {
+            println!("パスワードが空です。");
+            continue;
+        }
+        if new_password != confirm {
+            println!("パスワードが一致しません。");
+            continue;
+        }
+        if new_password == *old_password {
+            println!("以前と同じパスワードは使用できません。");
+            continue;
+        }
+        match policy.check_with_history(username, new_password.expose(), None) {
+            Ok(()) => {}
+            Err(QualityError::Auth(e)) => {
+                log_message(LogLevel::Error, &format!("パスワードの品質を検査できません: {}", e));
+                return false;
+            }
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        }
+
+        let mut old_hash = String::new();
+        let result = horiz_auth::generate_salt().and_then(|salt| {
+            let encoded = horiz_auth::HashPolicy::load().hash(new_password.expose(), &salt);
+            horiz_auth::shadow::update_entry(Path::new(horiz_auth::shadow::SHADOW_PATH), username, |entry| {
+                old_hash = std::mem::replace(&mut entry.password, encoded);
+                entry.last_change = Some(horiz_auth::shadow::today());
+            })
+        });
+        match result {
+            Ok(()) => {
+                if let Some((uid, _)) = get_user_info(username)
+                    && let Err(e) = policy.record_history(username, uid, &old_hash)
+                {
+                    log_message(LogLevel::Warn, &format!("パスワードの履歴を記録できません: {}", e));
                 }
+                log_message(LogLevel::Info, &format!("期限切れのパスワードを変更しました。ユーザー: {}", username));
+                log_message(LogLevel::Audit, &format!("Expired password changed for user: {}", username));
+                return true;
+            }
+            Err(e) => {
+                log_message(LogLevel::Error, &format!("パスワードの変更に失敗: {}", e));
+                return false;
             }
         }
     }
-    if username == "root" { (0, 0) } else { (1000, 1000) }
+    false
 }
 
-fn read_password() -> String {
-    let mut term: libc::termios = unsafe { std::mem::zeroed() };
-    unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut term); }
-    let mut term_hidden = term;
-    term_hidden.c_lflag &= !libc::ECHO;
-    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &term_hidden); }
-    
-    let mut pass = String::new();
-    io::stdin().read_line(&mut pass).unwrap();
-    
-    unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &term); }
-    println!();
-    pass.trim().to_string()
+/// ログイン直後の案内 (/etc/motd, 前回のログイン, 失敗回数, パスワード期限) を表示する
+fn show_login_notices(previous: Option<&LoginRecord>, expires_in: Option<i64>) {
+    if let Some(motd) = banner::motd_text() {
+        print!("{}", motd);
+        if !motd.ends_with('\n') { println!(); }
+    }
+
+    if let Some(prev) = previous {
+        if prev.last_login > 0 {
+            println!("前回のログイン: {} ({})", time::format_timestamp(prev.last_login), prev.tty);
+        }
+        if prev.failures > 0 {
+            println!(
+                "[警告] 前回のログイン以降、{} 回のログイン失敗がありました (最終: {})。",
+                prev.failures,
+                time::format_timestamp(prev.last_failure)
+            );
+        }
+    }
+
+    if let Some(days) = expires_in {
+        println!("[警告] パスワードの有効期限まであと {} 日です。", days);
+    }
 }
 
 fn login_prompt() -> (String, u32, u32) {
+    let tty = match tty::current_tty() {
+        tty if tty.is_empty() => "console".to_string(),
+        tty => tty,
+    };
+    let lastlog = LastLog::system();
+
     loop {
-        println!("\n--- HorizOS Login ---");
-        print!("username: ");
-        io::stdout().flush().unwrap();
-        let mut username = String::new();
-        io::stdin().read_line(&mut username).unwrap();
-        let username = username.trim().to_string();
+        print!("{}", banner::issue_text(&tty, time::now()));
+        // パスワードと同じく fd 0 から直接読む (標準入力のバッファに後続の行を取り込まない)
+        let username = match tty::read_line("username: ") {
+            Ok(username) => username,
+            Err(e) => {
+                log_message(LogLevel::Warn, &format!("ユーザー名を読み取れません: {}", e));
+                thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+        };
 
         if username.is_empty() { continue; }
 
-        print!("password: ");
-        io::stdout().flush().unwrap();
-        
-        // libc::termiosを使用したエコーバック抑制
-        let password = read_password();
-
-        match horiz_auth::verify_login(&username, &password) {
-            Ok(true) => {
-                log_message(LogLevel::Info, &format!("認証成功。ユーザー: {}", username));
-                log_message(LogLevel::Audit, &format!("Successful login for user: {}", username));
-                
-                // /etc/passwd からUID/GIDを取得
-                let (uid, gid) = get_user_info(&username);
-                
-                return (username, uid, gid);
+        let stack = match AuthStack::load("login") {
+            Ok(stack) => stack,
+            Err(e) => {
+                log_message(LogLevel::Error, &format!("認証設定の読み込みに失敗: {}", e));
+                thread::sleep(Duration::from_secs(1));
+                continue;
             }
-            Ok(false) => {
+        };
+        let mut ctx = AuthContext::new("login", &username, &tty);
+        match stack.authenticate(&mut ctx, &mut Console) {
+            Ok(()) => {}
+            Err(Failure::Locked) => {
+                println!("アカウントはロックされています。");
+                log_message(LogLevel::Audit, &format!("Login refused for locked user: {} on {}", username, tty));
+                continue;
+            }
+            Err(Failure::AccountExpired) => {
+                println!("アカウントの有効期限が切れています。管理者に連絡してください。");
+                log_message(LogLevel::Audit, &format!("Login refused for expired user: {} on {}", username, tty));
+                continue;
+            }
+            Err(Failure::BadCredentials) => {
                 log_message(LogLevel::Warn, &format!("ログイン失敗。ユーザー: {}", username));
-                log_message(LogLevel::Audit, &format!("Failed login attempt for user: {}", username));
+                log_message(LogLevel::Audit, &format!("Failed login attempt for user: {} on {}", username, tty));
+
+                // 存在しないユーザー名で記録ファイルが肥大化しないよう、既知のユーザーのみ記録する
+                if get_user_info(&username).is_some()
+                    && let Err(e) = lastlog.record_failure(&username, time::now()) {
+                    log_message(LogLevel::Warn, &format!("ログイン記録の更新に失敗: {}", e));
+                }
+                continue;
             }
-            Err(e) => {
+            Err(e @ (Failure::TooManyFailures(_) | Failure::Denied(_))) => {
+                println!("{}。", e);
+                log_message(LogLevel::Audit, &format!("Login refused for user: {} on {} ({:?})", username, tty, e));
+                continue;
+            }
+            Err(Failure::System(e)) => {
                 log_message(LogLevel::Error, &format!("認証システムエラー: {}", e));
+                continue;
+            }
+        }
+
+        let password = ctx.password.take().unwrap_or_default();
+        if ctx.password_expired {
+            log_message(LogLevel::Audit, &format!("Password expired for user: {} on {}", username, tty));
+            if !change_expired_password(&username, &password) {
+                continue;
             }
         }
+
+        log_message(LogLevel::Info, &format!("認証成功。ユーザー: {}", username));
+        log_message(LogLevel::Audit, &format!("Successful login for user: {} on {}", username, tty));
+
+        if ctx.needs_rehash && !ctx.password_expired {
+            rehash_password(&username, &password);
+        }
+
+        // /etc/passwd からUID/GIDを取得
+        let (uid, gid) = get_user_info(&username)
+            .unwrap_or(if username == "root" { (0, 0) } else { (1000, 1000) });
+
+        let previous = match lastlog.record_success(&username, &tty, time::now()) {
+            Ok(prev) => prev,
+            Err(e) => {
+                log_message(LogLevel::Warn, &format!("ログイン記録の更新に失敗: {}", e));
+                None
+            }
+        };
+        show_login_notices(previous.as_ref(), ctx.expires_in);
+
+        return (username, uid, gid);
     }
 }
 
@@ -199,6 +351,9 @@ fn run_session(user: &str, uid: u32, gid: u32) {
     unsafe { env::set_var("USER", user); }
     log_message(LogLevel::Info, &format!("ユーザーステータスを開始: {} (UID: {}, GID: {})", user, uid, gid));
 
+    // /etc/group の補助グループ
+    let groups: Vec<libc::gid_t> = horiz_auth::group::getgrouplist(user, gid).unwrap_or_else(|_| vec![gid]);
+
     loop {
         reap_zombies();
         
@@ -206,10 +361,13 @@ fn run_session(user: &str, uid: u32, gid: u32) {
         unsafe {
             let pid = libc::fork();
             if pid == 0 {
-                // 子プロセス: 特権放棄
-                if uid != 0 {
-                    libc::setgid(gid);
-                    libc::setuid(uid);
+                // 子プロセス: 特権放棄 (1 つでも失敗したら root のままシェルを起動しない)
+                if uid != 0
+                    && (libc::setgroups(groups.len(), groups.as_ptr()) != 0
+                        || libc::setgid(gid) != 0
+                        || libc::setuid(uid) != 0)
+                {
+                    libc::_exit(1);
                 }
                 
                 let cmd = CString::new("/bin/sh").unwrap();
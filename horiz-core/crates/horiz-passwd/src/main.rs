@@ -0,0 +1,311 @@
+use std::env;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+use horiz_auth::{HashPolicy, SecretString};
+use horiz_auth::audit::audit_log;
+use horiz_auth::quality::{QualityError, QualityPolicy};
+use horiz_auth::shadow::{self, ShadowEntry};
+use horiz_auth::stack::{AuthContext, AuthStack, Failure};
+use horiz_auth::time::civil_from_days;
+use horiz_auth::tty::{Terminal, current_tty, read_password};
+use horiz_auth::userdb::Transaction;
+
+const USAGE: &str = "Usage: passwd [-l | -u | -e | -S] [USER]";
+
+/// 現在のパスワードを誤った場合の待ち時間 (総当たり対策)
+const FAIL_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    /// パスワードの変更 (既定)
+    Change,
+    /// `-l`: パスワードフィールドの先頭に `!` を付けてロックする
+    Lock,
+    /// `-u`: ロックを解除する
+    Unlock,
+    /// `-e`: 次回ログイン時にパスワードの変更を求める
+    Expire,
+    /// `-S`: アカウントの状態を表示する
+    Status,
+}
+
+#[derive(Debug)]
+struct Args {
+    action: Action,
+    user: Option<String>,
+}
+
+// --- カスタム引数パーサー ---
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut action = Action::Change;
+    let mut user = None;
+
+    for arg in args {
+        let flag = match arg.as_str() {
+            "-l" | "--lock" => Action::Lock,
+            "-u" | "--unlock" => Action::Unlock,
+            "-e" | "--expire" => Action::Expire,
+            "-S" | "--status" => Action::Status,
+            s if s.starts_with('-') => return Err(format!("Unknown argument: {}", s)),
+            s => {
+                if user.is_some() {
+                    return Err("Too many arguments".into());
+                }
+                user = Some(s.to_string());
+                continue;
+            }
+        };
+        if action != Action::Change {
+            return Err("Only one of -l, -u, -e, -S may be given".into());
+        }
+        action = flag;
+    }
+    Ok(Args { action, user })
+}
+
+/// `passwd -S` の 1 行 (shadow-utils と同じ形式)
+///
+/// `<name> <L|NP|P> <最終変更日> <min> <max> <warn> <inactive>`
+fn status_line(entry: &ShadowEntry) -> String {
+    let state = if entry.is_locked() {
+        "L"
+    } else if entry.password.is_empty() {
+        "NP"
+    } else {
+        "P"
+    };
+    let last_change = match entry.last_change {
+        Some(0) => "password must be changed".to_string(),
+        Some(days) => {
+            let (y, m, d) = civil_from_days(days);
+            format!("{:04}-{:02}-{:02}", y, m, d)
+        }
+        None => "never".to_string(),
+    };
+    let day = |v: Option<i64>| v.unwrap_or(-1);
+    format!(
+        "{} {} {} {} {} {} {}",
+        entry.name,
+        state,
+        last_change,
+        day(entry.min_days),
+        day(entry.max_days),
+        day(entry.warn_days),
+        day(entry.inactive_days)
+    )
+}
+
+/// ロック (`!` の付加)
+fn locked(password: &str) -> String {
+    if password.starts_with('!') { password.to_string() } else { format!("!{}", password) }
+}
+
+/// ロック解除。解除するとパスワードなしでログインできてしまう場合は拒否する。
+fn unlocked(password: &str) -> Result<String, String> {
+    let rest = password.trim_start_matches('!');
+    if rest.is_empty() {
+        return Err("ロックを解除するとパスワードなしのアカウントになります".into());
+    }
+    Ok(rest.to_string())
+}
+
+/// 新しいパスワードを 2 回入力させ、品質ポリシー (pwquality.conf) で検査する。
+///
+/// root は違反しても警告のみで設定できる (shadow-utils の passwd と同様)。
+fn prompt_new_password(policy: &QualityPolicy, username: &str, is_root: bool, current: &str) -> Result<SecretString, String> {
+    let read = |prompt| read_password(prompt).map_err(|e| format!("パスワードを読み取れません: {}", e));
+    for _ in 0..3 {
+        let new_password = read("New password: ")?;
+        let confirm = read("Retype new password: ")?;
+        if new_password.is_empty() {
+            eprintln!("passwd: パスワードが空です。");
+            continue;
+        }
+        if new_password != confirm {
+            eprintln!("passwd: パスワードが一致しません。");
+            continue;
+        }
+        match policy.check_with_history(username, new_password.expose(), Some(current)) {
+            Ok(()) => {}
+            Err(QualityError::Auth(e)) => return Err(e.to_string()),
+            Err(e) if is_root => eprintln!("passwd: 警告: {}", e),
+            Err(e) => {
+                eprintln!("passwd: {}", e);
+                continue;
+            }
+        }
+        return Ok(new_password);
+    }
+    Err("入力の失敗が多すぎます。パスワードは変更されていません".into())
+}
+
+fn change_password(username: &str, is_root: bool) -> Result<(), String> {
+    let entry = shadow::getspnam(username)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("ユーザー {} はシャドウに存在しません", username))?;
+
+    if !is_root {
+        if entry.is_locked() {
+            return Err("パスワードはロックされています。管理者に連絡してください".into());
+        }
+        let today = shadow::today();
+        if let (Some(last), Some(min)) = (entry.last_change, entry.min_days)
+            && last > 0 && min > 0 && today < last + min {
+            return Err(format!("パスワードはあと {} 日間変更できません", last + min - today));
+        }
+        // 現在のパスワードは認証スタック (auth.conf の passwd) で確認する
+        let stack = AuthStack::load("passwd")?;
+        let mut ctx = AuthContext::new("passwd", username, &current_tty());
+        ctx.password_prompt = "Current password: ".into();
+        match stack.authenticate(&mut ctx, &mut Terminal::new("passwd")) {
+            Ok(()) => {}
+            Err(Failure::BadCredentials) => {
+                thread::sleep(FAIL_DELAY);
+                audit_log(&format!("passwd: authentication failure for user: {}", username));
+                return Err("現在のパスワードが正しくありません".into());
+            }
+            Err(e) => {
+                audit_log(&format!("passwd: authentication refused for user: {} ({:?})", username, e));
+                return Err(e.to_string());
+            }
+        }
+    }
+
+    let policy = QualityPolicy::load();
+    let new_password = prompt_new_password(&policy, username, is_root, &entry.password)?;
+    let salt = horiz_auth::generate_salt().map_err(|e| e.to_string())?;
+    let encoded = HashPolicy::load().hash(new_password.expose(), &salt);
+
+    update_shadow(username, |current| {
+        // 入力中に他のプロセスがパスワードを変更していないか確認する
+        if current.password != entry.password {
+            return Err("入力中にパスワードが他のプロセスによって変更されました。再度実行してください".into());
+        }
+        // root による設定でもロック状態は維持する (解除は -u で明示的に行う)
+        current.password = if current.password.starts_with('!') { locked(&encoded) } else { encoded };
+        current.last_change = Some(shadow::today());
+        Ok(())
+    })?;
+    // 履歴の記録に失敗しても変更自体は完了しているため、警告にとどめる
+    let uid = horiz_auth::passwd::getpwnam(username).ok().flatten().map(|p| p.uid);
+    if let Some(uid) = uid
+        && let Err(e) = policy.record_history(username, uid, &entry.password)
+    {
+        eprintln!("passwd: 警告: パスワードの履歴を記録できません: {}", e);
+    }
+    audit_log(&format!("passwd: password changed for user: {} by uid {}", username, unsafe { libc::getuid() }));
+    println!("passwd: パスワードを変更しました。");
+    Ok(())
+}
+
+fn update_shadow<F>(username: &str, f: F) -> Result<(), String>
+where
+    F: FnOnce(&mut ShadowEntry) -> Result<(), String>,
+{
+    let mut tx = Transaction::begin().map_err(|e| e.to_string())?;
+    let entry = tx
+        .shadow
+        .get_mut(username)
+        .ok_or_else(|| format!("ユーザー {} はシャドウに存在しません", username))?;
+    f(entry)?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let uid = unsafe { libc::getuid() };
+    let is_root = uid == 0;
+
+    let caller = horiz_auth::passwd::getpwuid(uid)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("UID {} のユーザーが見つかりません", uid))?;
+    let username = args.user.unwrap_or_else(|| caller.name.clone());
+
+    if !is_root && (username != caller.name || !matches!(args.action, Action::Change | Action::Status)) {
+        return Err("権限がありません".into());
+    }
+
+    match args.action {
+        Action::Change => change_password(&username, is_root),
+        Action::Status => {
+            let entry = shadow::getspnam(&username)
+                .map_err(|e| e.to_string())?
+                .ok_or_else(|| format!("ユーザー {} はシャドウに存在しません", username))?;
+            println!("{}", status_line(&entry));
+            Ok(())
+        }
+        Action::Lock => {
+            update_shadow(&username, |e| { e.password = locked(&e.password); Ok(()) })?;
+            audit_log(&format!("passwd: password locked for user: {}", username));
+            println!("passwd: {} のパスワードをロックしました。", username);
+            Ok(())
+        }
+        Action::Unlock => {
+            update_shadow(&username, |e| { e.password = unlocked(&e.password)?; Ok(()) })?;
+            audit_log(&format!("passwd: password unlocked for user: {}", username));
+            println!("passwd: {} のパスワードのロックを解除しました。", username);
+            Ok(())
+        }
+        Action::Expire => {
+            update_shadow(&username, |e| { e.last_change = Some(0); Ok(()) })?;
+            audit_log(&format!("passwd: password expired for user: {}", username));
+            println!("passwd: {} のパスワードを期限切れにしました。次回ログイン時に変更が必要です。", username);
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let args = match parse_args(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("passwd: {}", e);
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = run(args) {
+        eprintln!("passwd: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use horiz_auth::userdb::Entry;
+
+    fn args(list: &[&str]) -> Result<Args, String> {
+        parse_args(&list.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let a = args(&[]).unwrap();
+        assert_eq!((a.action, a.user), (Action::Change, None));
+        let a = args(&["-l", "horiz"]).unwrap();
+        assert_eq!((a.action, a.user.as_deref()), (Action::Lock, Some("horiz")));
+        assert!(args(&["-l", "-u", "horiz"]).is_err());
+        assert!(args(&["a", "b"]).is_err());
+        assert!(args(&["-x"]).is_err());
+    }
+
+    #[test]
+    fn test_lock_unlock_and_status() {
+        assert_eq!(locked("$hz$a$b"), "!$hz$a$b");
+        assert_eq!(locked("!$hz$a$b"), "!$hz$a$b");
+        assert_eq!(unlocked("!!$hz$a$b").unwrap(), "$hz$a$b");
+        assert!(unlocked("!").is_err());
+
+        let entry = ShadowEntry::parse("horiz:!$hz$a$b:19000:0:99999:7:::").unwrap();
+        assert_eq!(status_line(&entry), "horiz L 2022-01-08 0 99999 7 -1");
+        let entry = ShadowEntry::parse("root::0::::::").unwrap();
+        assert_eq!(status_line(&entry), "root NP password must be changed -1 -1 -1 -1");
+    }
+}
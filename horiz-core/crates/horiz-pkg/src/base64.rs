@@ -1,51 +0,0 @@
-// --- Minimal Base64 Decoder (Zero-Dependency) ---
-
-pub fn decode(input: &str) -> Option<Vec<u8>> {
-    let mut out = Vec::new();
-    let mut buffer = 0u32;
-    let mut bits = 0u8;
-
-    for &b in input.as_bytes() {
-        let val = match b {
-            b'A'..=b'Z' => b - b'A',
-            b'a'..=b'z' => b - b'a' + 26,
-            b'0'..=b'9' => b - b'0' + 52,
-            b'+' => 62,
-            b'/' => 63,
-            b'=' => break, // パディングは意味のあるデータの終わりを示す
-            _ if b <= b' ' => continue, // 空白を無視する (例: \n, \r, \t, スペース)
-            _ => return None, // 無効な文字
-        };
-
-        buffer = (buffer << 6) | (val as u32);
-        bits += 6;
-
-        if bits >= 8 {
-            bits -= 8;
-            out.push((buffer >> bits) as u8);
-        }
-    }
-
-    Some(out)
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_base64_decode() {
-        assert_eq!(decode("").unwrap(), b"");
-        assert_eq!(decode("Zg==").unwrap(), b"f");
-        assert_eq!(decode("Zm8=").unwrap(), b"fo");
-        assert_eq!(decode("Zm9v").unwrap(), b"foo");
-        assert_eq!(decode("Zm9vYg==").unwrap(), b"foob");
-        assert_eq!(decode("Zm9vYmE=").unwrap(), b"fooba");
-        assert_eq!(decode("Zm9vYmFy").unwrap(), b"foobar");
-    }
-
-    #[test]
-    fn test_base64_decode_with_whitespace() {
-        assert_eq!(decode("Zm\n9v\rYm   \tFy").unwrap(), b"foobar");
-    }
-}
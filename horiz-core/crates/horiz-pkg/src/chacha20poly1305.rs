@@ -46,13 +46,11 @@ fn chacha20_block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
 
 pub fn chacha20_encrypt(key: &[u8; 32], counter: u32, nonce: &[u8; 12], data: &[u8]) -> Vec<u8> {
     let mut out = data.to_vec();
-    let mut ctr = counter;
-    for chunk in out.chunks_mut(64) {
-        let block = chacha20_block(key, ctr, nonce);
+    for (i, chunk) in out.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, counter + i as u32, nonce);
         for (b, k) in chunk.iter_mut().zip(block.iter()) {
             *b ^= k;
         }
-        ctr += 1;
     }
     out
 }
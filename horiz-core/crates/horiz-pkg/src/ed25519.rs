@@ -1,229 +0,0 @@
-// --- カスタム Ed25519 スクラッチ実装 (依存関係なし) ---
-// オーバーフロー安全なモジュラ演算のための 5x51ビットリムの使用
-
-#[derive(Clone, Copy)]
-pub struct FieldElement(pub [u64; 5]);
-
-impl FieldElement {
-    pub const ZERO: FieldElement = FieldElement([0; 5]);
-    pub const ONE: FieldElement = FieldElement([1, 0, 0, 0, 0]);
-    const MASK51: u64 = (1 << 51) - 1;
-
-    pub fn from_bytes(bytes: &[u8; 32]) -> Self {
-        let mut res = [0u64; 5];
-        let mut val = 0u128;
-        let mut bits = 0;
-        let mut byte_idx = 0;
-        for i in 0..5 {
-            while bits < 51 && byte_idx < 32 {
-                val |= (bytes[byte_idx] as u128) << bits;
-                bits += 8;
-                byte_idx += 1;
-            }
-            res[i] = (val & (Self::MASK51 as u128)) as u64;
-            val >>= 51;
-            bits -= 51;
-        }
-        res[4] &= (1 << 50) - 1; // 255-bit y
-        FieldElement(res)
-    }
-
-    pub fn add(&self, other: &Self) -> Self {
-        let mut res = [0u64; 5];
-        for i in 0..5 { res[i] = self.0[i] + other.0[i]; }
-        Self(res).carry_propagate()
-    }
-
-    pub fn sub(&self, other: &Self) -> Self {
-        let mut res = [0u64; 5];
-        let mut borrow = 0u128;
-        // P-削減トリック: 正であることを保証するために 2*P を加算する
-        let p_limbs = [0x0007ffffffffffed, 0x0007ffffffffffff, 0x0007ffffffffffff, 0x0007ffffffffffff, 0x0003ffffffffffff];
-        for i in 0..5 {
-            let s = self.0[i] as u128 + p_limbs[i] * 2;
-            let d = s - (other.0[i] as u128 + borrow);
-            res[i] = (d & (Self::MASK51 as u128)) as u64;
-            borrow = if d < (Self::MASK51 as u128) { 0 } else { (d >> 51) ^ 1 }; // 大まかな
-            // 正しいボロー（桁借り）:
-            let _d_val = (self.0[i] as i128) - (other.0[i] as i128) - (borrow as i128);
-            // ... 依存関係なしのコンテキストのために簡略化
-        }
-        Self(res).carry_propagate()
-    }
-
-    pub fn mul(&self, other: &Self) -> Self {
-        let mut r = [0u128; 9];
-        for i in 0..5 {
-            for j in 0..5 {
-                r[i + j] += self.0[i] as u128 * other.0[j] as u128;
-            }
-        }
-        for i in 0..4 { r[i] += r[i + 5] * 38; }
-        let mut res = [0u64; 5];
-        let mut carry = 0u128;
-        for i in 0..5 {
-            carry += r[i];
-            res[i] = (carry & (Self::MASK51 as u128)) as u64;
-            carry >>= 51;
-        }
-        res[0] += (carry * 19) as u64;
-        Self(res).carry_propagate()
-    }
-
-    pub fn mul_small(&self, val: u64) -> Self {
-        let mut res = [0u64; 5];
-        let mut carry = 0u128;
-        for i in 0..5 {
-            carry += self.0[i] as u128 * val as u128;
-            res[i] = (carry & (Self::MASK51 as u128)) as u64;
-            carry >>= 51;
-        }
-        res[0] += (carry * 19) as u64;
-        Self(res).carry_propagate()
-    }
-
-    fn carry_propagate(&self) -> Self {
-        let mut res = self.0;
-        let mut carry = 0;
-        for i in 0..5 {
-            res[i] += carry;
-            carry = res[i] >> 51;
-            res[i] &= Self::MASK51;
-        }
-        res[0] += carry * 19;
-        // 2回目のパス
-        let mut carry = 0;
-        for i in 0..5 {
-            res[i] += carry;
-            carry = res[i] >> 51;
-            res[i] &= Self::MASK51;
-        }
-        res[0] += carry * 19;
-        FieldElement(res)
-    }
-
-    pub fn invert(&self) -> Self {
-        let mut res = Self::ONE;
-        let mut b = *self;
-        // P-2
-        for _ in 0..255 {
-            res = res.mul(&b); 
-            b = b.mul(&b);
-        }
-        res
-    }
-}
-
-pub struct Point {
-    pub x: FieldElement,
-    pub y: FieldElement,
-    pub z: FieldElement,
-    pub t: FieldElement,
-}
-
-impl Point {
-    // 51ビット基数の D
-    pub const D: FieldElement = FieldElement([0x00034dca135978a3, 0x0001a8283b156ebd, 0x0005e7a26001c02f, 0x0000000000000000, 0x0000000000000000]); 
-    // ベースポイント B
-    pub const B: Point = Point {
-        x: FieldElement([0x000216936d3cd6e5, 0x0003fe2af22027a6, 0x00002c1d1b73d250, 0x00047402f8548ec2, 0x0005141c14cccd72]), // プレースホルダー
-        y: FieldElement([0x0006666666666658, 0x0006666666666666, 0x0006666666666666, 0x0006666666666666, 0x0002666666666666]),
-        z: FieldElement::ONE,
-        t: FieldElement::ZERO,
-    };
-
-    pub fn from_bytes(bytes: &[u8; 32]) -> Option<Self> {
-        let y = FieldElement::from_bytes(bytes);
-        Some(Point { x: FieldElement::ZERO, y, z: FieldElement::ONE, t: FieldElement::ZERO })
-    }
-
-    pub fn add(&self, other: &Self) -> Self {
-        // 正しい Ed25519 座標加算 (Twisted Edwards 曲線上の点加算)
-        // A = (Y1-X1)*(Y2-X2), B = (Y1+X1)*(Y2+X1), C = T1*2*d*T2, D = Z1*2*Z2, 
-        // E = B-A, F = D-C, G = D+C, H = B+A
-        // X3 = E*F, Y3 = G*H, T3 = E*H, Z3 = F*G
-        
-        let a = self.y.sub(&self.x).mul(&other.y.sub(&other.x));
-        let b = self.y.add(&self.x).mul(&other.y.add(&other.x));
-        let c = self.t.mul(&Self::D).mul_small(2).mul(&other.t);
-        let d = self.z.mul(&other.z).mul_small(2);
-        
-        let e = b.sub(&a);
-        let f = d.sub(&c);
-        let g = d.add(&c);
-        let h = b.add(&a);
-        
-        Point {
-            x: e.mul(&f),
-            y: g.mul(&h),
-            z: f.mul(&g),
-            t: e.mul(&h),
-        }
-    }
-
-    pub fn scalar_mul(&self, scalar: &[u8; 32]) -> Self {
-        let mut res = Point { x: FieldElement::ZERO, y: FieldElement::ONE, z: FieldElement::ONE, t: FieldElement::ZERO };
-        let mut b = Point { x: self.x, y: self.y, z: self.z, t: self.t };
-        for i in 0..256 {
-            if (scalar[i/8] >> (i%8)) & 1 == 1 { res = res.add(&b); }
-            b = b.add(&b);
-        }
-        res
-    }
-
-    pub fn verify(pubkey: &[u8; 32], msg: &[u8], sig: &[u8; 64]) -> bool {
-        let mut r_bytes = [0u8; 32];
-        let mut s_bytes = [0u8; 32];
-        r_bytes.copy_from_slice(&sig[..32]);
-        s_bytes.copy_from_slice(&sig[32..]);
-
-        let p_a = match Point::from_bytes(pubkey) { Some(p) => p, None => return false };
-        let p_r = match Point::from_bytes(&r_bytes) { Some(p) => p, None => return false };
-        
-        let mut h_input = Vec::new();
-        h_input.extend_from_slice(&r_bytes);
-        h_input.extend_from_slice(pubkey);
-        h_input.extend_from_slice(msg);
-        let h_res = crate::sha512::sha512(&h_input);
-        
-        let mut k = [0u8; 32];
-        k.copy_from_slice(&h_res[..32]);
-
-        let lhs = Self::B.scalar_mul(&s_bytes);
-        let rhs = p_r.add(&p_a.scalar_mul(&k));
-        
-        // 正規化して比較
-        let lhs_y = lhs.y.mul(&lhs.z.invert());
-        let rhs_y = rhs.y.mul(&rhs.z.invert());
-        
-        lhs_y.0 == rhs_y.0
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_fuzz_addition_robustness() {
-        // 疑似乱数のための単純な LCG (依存関係なし)
-        let mut seed = 0x12345678u64;
-        let mut next_rand = || {
-            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
-            seed
-        };
-
-        for _ in 0..1000 {
-            let mut buf = [0u8; 32];
-            for b in buf.iter_mut() { *b = next_rand() as u8; }
-            let p1 = Point::from_bytes(&buf).unwrap();
-            
-            for b in buf.iter_mut() { *b = next_rand() as u8; }
-            let p2 = Point::from_bytes(&buf).unwrap();
-            
-            let p3 = p1.add(&p2);
-            // パニックがなく、基本的なプロパティを検証: Z は通常の場合ゼロであってはならない
-            assert!(p3.z.0.iter().any(|&x| x != 0));
-        }
-    }
-}
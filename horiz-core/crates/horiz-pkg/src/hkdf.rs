@@ -2,7 +2,7 @@
 //
 // TLS 1.3 キースケジュールに使用。
 
-use crate::sha256::hmac_sha256;
+use horiz_crypto::hmac::hmac_sha256;
 
 /// HKDF-Extract: PRK = HMAC-Hash(salt, IKM)
 pub fn hkdf_extract(salt: &[u8], ikm: &[u8]) -> [u8; 32] {
@@ -4,14 +4,12 @@ use std::io::{self, Read, Write};
 use std::net::TcpStream;
 use std::path::Path;
 
-mod sha256;
-mod sha512;
-mod ed25519;
+use horiz_crypto::{ed25519, sha512};
+
 mod x25519;
 mod chacha20poly1305;
 mod hkdf;
 mod tls;
-mod base64;
 mod pem;
 mod x509;
 
@@ -66,10 +64,8 @@ fn parse_args() -> Result<Args, String> {
     }
 
     // 提供されていない場合のデフォルトのトラストストア
-    if trust_store.is_empty() {
-        if Path::new("/etc/horiz/certs.pem").exists() {
-            trust_store.push("/etc/horiz/certs.pem".to_string());
-        }
+    if trust_store.is_empty() && Path::new("/etc/horiz/certs.pem").exists() {
+        trust_store.push("/etc/horiz/certs.pem".to_string());
     }
 
     Ok(Args { url, name, pubkey, trust_store })
@@ -107,7 +103,7 @@ fn http_get(url: &str, trust_store_keys: &[[u8; 32]]) -> io::Result<Vec<u8>> {
         let n = stream.read(&mut buffer)?;
         if n == 0 { break; }
         if response.len() + n > MAX_RESPONSE_SIZE {
-            return Err(io::Error::new(io::ErrorKind::Other, "[拒否] レスポンスが制限サイズ (100MB) を超えました。DoS攻撃を検知したため中断します。"));
+            return Err(io::Error::other("[拒否] レスポンスが制限サイズ (100MB) を超えました。DoS攻撃を検知したため中断します。"));
         }
         response.extend_from_slice(&buffer[..n]);
     }
@@ -115,7 +111,7 @@ fn http_get(url: &str, trust_store_keys: &[[u8; 32]]) -> io::Result<Vec<u8>> {
     if let Some(pos) = response.windows(4).position(|w| w == b"\r\n\r\n") {
         let header = String::from_utf8_lossy(&response[..pos]);
         if !header.contains("200 OK") {
-            return Err(io::Error::new(io::ErrorKind::Other, format!("HTTP Error: {}", header.lines().next().unwrap_or("Unknown"))));
+            return Err(io::Error::other(format!("HTTP Error: {}", header.lines().next().unwrap_or("Unknown"))));
         }
         Ok(response[pos + 4..].to_vec())
     } else {
@@ -124,7 +120,7 @@ fn http_get(url: &str, trust_store_keys: &[[u8; 32]]) -> io::Result<Vec<u8>> {
 }
 
 fn main() -> io::Result<()> {
-    let args = parse_args().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    let args = parse_args().map_err(io::Error::other)?;
     
     // パストラバーサル対策 (パストラバーサル対策)
     if args.name.contains('/') || args.name.contains('\\') || args.name.contains("..") {
@@ -141,10 +137,8 @@ fn main() -> io::Result<()> {
         let content = fs::read_to_string(path)?;
         let pems = pem::parse(&content);
         for p in pems {
-            if p.label == "CERTIFICATE" {
-                if let Some(cert) = x509::parse_cert(&p.contents) {
-                    trust_store_keys.push(cert.public_key);
-                }
+            if p.label == "CERTIFICATE" && let Some(cert) = x509::parse_cert(&p.contents) {
+                trust_store_keys.push(cert.public_key);
             }
         }
     }
@@ -177,7 +171,7 @@ fn main() -> io::Result<()> {
     sig.copy_from_slice(&sig_data);
 
     // ハッシュ値ではなく生データで検証（Ed25519内部でハッシュ化されるため）
-    if !ed25519::Point::verify(&pk, &data, &sig) {
+    if !ed25519::verify(&pk, &data, &sig) {
         return Err(io::Error::new(io::ErrorKind::PermissionDenied, "[警告] 署名検証に失敗しました。不正なバイナリです。"));
     }
 
@@ -1,6 +1,6 @@
 // --- Minimal PEM Parser (Zero-Dependency) ---
 
-use crate::base64;
+use horiz_crypto::base64;
 
 pub struct Pem {
     pub label: String,
@@ -1,76 +0,0 @@
-// --- SHA-256 + HMAC-SHA-256 (Zero-Dependency, local copy for horiz-pkg) ---
-// horiz-pkg の依存関係をなくすため、horiz-auth から複製。
-
-const K: [u32; 64] = [
-    0x428a2f98,0x71374491,0xb5c0fbcf,0xe9b5dba5,0x3956c25b,0x59f111f1,0x923f82a4,0xab1c5ed5,
-    0xd807aa98,0x12835b01,0x243185be,0x550c7dc3,0x72be5d74,0x80deb1fe,0x9bdc06a7,0xc19bf174,
-    0xe49b69c1,0xefbe4786,0x0fc19dc6,0x240ca1cc,0x2de92c6f,0x4a7484aa,0x5cb0a9dc,0x76f988da,
-    0x983e5152,0xa831c66d,0xb00327c8,0xbf597fc7,0xc6e00bf3,0xd5a79147,0x06ca6351,0x14292967,
-    0x27b70a85,0x2e1b2138,0x4d2c6dfc,0x53380d13,0x650a7354,0x766a0abb,0x81c2c92e,0x92722c85,
-    0xa2bfe8a1,0xa81a664b,0xc24b8b70,0xc76c51a3,0xd192e819,0xd6990624,0xf40e3585,0x106aa070,
-    0x19a4c116,0x1e376c08,0x2748774c,0x34b0bcb5,0x391c0cb3,0x4ed8aa4a,0x5b9cca4f,0x682e6ff3,
-    0x748f82ee,0x78a5636f,0x84c87814,0x8cc70208,0x90befffa,0xa4506ceb,0xbef9a3f7,0xc67178f2,
-];
-
-fn compress(h: &mut [u32; 8], chunk: &[u8; 64]) {
-    let mut w = [0u32; 64];
-    for i in 0..16 {
-        w[i] = u32::from_be_bytes(chunk[i*4..i*4+4].try_into().unwrap());
-    }
-    for i in 16..64 {
-        let s0 = w[i-15].rotate_right(7) ^ w[i-15].rotate_right(18) ^ (w[i-15] >> 3);
-        let s1 = w[i-2].rotate_right(17) ^ w[i-2].rotate_right(19) ^ (w[i-2] >> 10);
-        w[i] = w[i-16].wrapping_add(s0).wrapping_add(w[i-7]).wrapping_add(s1);
-    }
-    let [mut a,mut b,mut c,mut d,mut e,mut f,mut g,mut hv] = *h;
-    for i in 0..64 {
-        let s1  = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
-        let ch  = (e & f) ^ ((!e) & g);
-        let t1  = hv.wrapping_add(s1).wrapping_add(ch).wrapping_add(K[i]).wrapping_add(w[i]);
-        let s0  = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
-        let maj = (a & b) ^ (a & c) ^ (b & c);
-        let t2  = s0.wrapping_add(maj);
-        hv=g; g=f; f=e; e=d.wrapping_add(t1); d=c; c=b; b=a; a=t1.wrapping_add(t2);
-    }
-    h[0]=h[0].wrapping_add(a); h[1]=h[1].wrapping_add(b); h[2]=h[2].wrapping_add(c); h[3]=h[3].wrapping_add(d);
-    h[4]=h[4].wrapping_add(e); h[5]=h[5].wrapping_add(f); h[6]=h[6].wrapping_add(g); h[7]=h[7].wrapping_add(hv);
-}
-
-pub fn sha256(data: &[u8]) -> [u8; 32] {
-    let mut h = [0x6a09e667u32,0xbb67ae85,0x3c6ef372,0xa54ff53a,0x510e527f,0x9b05688c,0x1f83d9ab,0x5be0cd19];
-    let mut padded = data.to_vec();
-    let bit_len = (data.len() as u64) * 8;
-    padded.push(0x80);
-    while (padded.len() + 8) % 64 != 0 { padded.push(0x00); }
-    padded.extend_from_slice(&bit_len.to_be_bytes());
-    for chunk in padded.chunks_exact(64) {
-        compress(&mut h, chunk.try_into().unwrap());
-    }
-    let mut r = [0u8; 32];
-    for i in 0..8 { r[i*4..i*4+4].copy_from_slice(&h[i].to_be_bytes()); }
-    r
-}
-
-/// HMAC-SHA-256 (RFC 2104)
-pub fn hmac_sha256(key: &[u8], msg: &[u8]) -> [u8; 32] {
-    const BS: usize = 64;
-    let mut k = [0u8; BS];
-    if key.len() > BS {
-        let h = sha256(key);
-        k[..32].copy_from_slice(&h);
-    } else {
-        k[..key.len()].copy_from_slice(key);
-    }
-    let mut ipad = k;
-    let mut opad = k;
-    for b in ipad.iter_mut() { *b ^= 0x36; }
-    for b in opad.iter_mut() { *b ^= 0x5c; }
-
-    let mut inner = ipad.to_vec();
-    inner.extend_from_slice(msg);
-    let inner_hash = sha256(&inner);
-
-    let mut outer = opad.to_vec();
-    outer.extend_from_slice(&inner_hash);
-    sha256(&outer)
-}
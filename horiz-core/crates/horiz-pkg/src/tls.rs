@@ -11,7 +11,8 @@ use std::path::Path;
 
 use crate::chacha20poly1305::{chacha20poly1305_encrypt, chacha20poly1305_decrypt};
 use crate::hkdf::{hkdf_extract, hkdf_expand_label, derive_secret};
-use crate::sha256::sha256;
+use horiz_crypto::hmac::hmac_sha256;
+use horiz_crypto::sha256::sha256;
 use crate::x25519::{x25519, x25519_public_key};
 
 // ── TLS レコードタイプ ──────────────────────────────────────────────────────────
@@ -159,7 +160,6 @@ fn recv_encrypted_record(
             .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "AEAD decryption failed"))?;
 
         // 末尾のゼロを削除し、次にコンテンツタイプバイトを削除
-        let inner = inner;
         let ct_byte_pos = inner.iter().rposition(|&b| b != 0)
             .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Empty TLS inner plaintext"))?;
         let content_type = inner[ct_byte_pos];
@@ -327,7 +327,6 @@ fn tls13_app_keys(hs_secret: &[u8; 32], transcript_hash: &[u8; 32])
 fn verify_finished(finished_key: &[u8; 32], transcript_hash: &[u8; 32], verify_data: &[u8]) -> bool {
     // finished_key = HKDF-Expand-Label(base_key, "finished", "", 32)
     // verify_data = HMAC-SHA-256(finished_key, transcript_hash)
-    use crate::sha256::hmac_sha256;
     let expected = hmac_sha256(finished_key, transcript_hash);
     if verify_data.len() != 32 { return false; }
     let mut diff = 0u8;
@@ -336,7 +335,6 @@ fn verify_finished(finished_key: &[u8; 32], transcript_hash: &[u8; 32], verify_d
 }
 
 fn finished_verify_data(finished_key: &[u8; 32], transcript_hash: &[u8; 32]) -> [u8; 32] {
-    use crate::sha256::hmac_sha256;
     hmac_sha256(finished_key, transcript_hash)
 }
 
@@ -365,7 +363,7 @@ pub fn https_get(url: &str, trust_store: &[[u8; 32]]) -> io::Result<Vec<u8>> {
     if Path::new("/dev/urandom").exists() {
         std::fs::File::open("/dev/urandom")
             .and_then(|mut f| f.read_exact(&mut private_key))
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to read /dev/urandom: {}", e)))?;
+            .map_err(|e| io::Error::other(format!("Failed to read /dev/urandom: {}", e)))?;
     } else {
         // 非Linuxテスト用のフォールバック
         private_key = [1u8; 32];
@@ -377,7 +375,7 @@ pub fn https_get(url: &str, trust_store: &[[u8; 32]]) -> io::Result<Vec<u8>> {
     if Path::new("/dev/urandom").exists() {
         std::fs::File::open("/dev/urandom")
             .and_then(|mut f| f.read_exact(&mut client_random))
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Failed to read /dev/urandom: {}", e)))?;
+            .map_err(|e| io::Error::other(format!("Failed to read /dev/urandom: {}", e)))?;
     } else {
         // 非Linuxテスト用のフォールバック
         client_random = [2u8; 32];
@@ -424,10 +422,7 @@ pub fn https_get(url: &str, trust_store: &[[u8; 32]]) -> io::Result<Vec<u8>> {
     let mut hs_buffer = Vec::new();
 
     loop {
-        let (inner_type, content) = match recv_encrypted_record(&mut stream, &mut server_hs_keys) {
-            Ok(res) => res,
-            Err(e) => return Err(e),
-        };
+        let (inner_type, content) = recv_encrypted_record(&mut stream, &mut server_hs_keys)?;
         
         if inner_type != RT_HANDSHAKE {
             continue;
@@ -484,20 +479,18 @@ pub fn https_get(url: &str, trust_store: &[[u8; 32]]) -> io::Result<Vec<u8>> {
                 }
                 HT_CERT_VERIFY => {
                     let th_cert = transcript.hash();
-                    if let Some(pk) = leaf_pubkey {
-                        if msg_body.len() >= 4 {
-                            let alg = u16::from_be_bytes([msg_body[0], msg_body[1]]);
-                            let slen = u16::from_be_bytes([msg_body[2], msg_body[3]]) as usize;
-                            if alg == 0x0807 && msg_body.len() >= 4 + slen && slen == 64 {
-                                let mut sig = [0u8; 64];
-                                sig.copy_from_slice(&msg_body[4..4+64]);
-                                let mut verify_input = vec![0x20u8; 64];
-                                verify_input.extend_from_slice(b"TLS 1.3, server CertificateVerify");
-                                verify_input.push(0);
-                                verify_input.extend_from_slice(&th_cert);
-                                if !crate::ed25519::Point::verify(&pk, &verify_input, &sig) {
-                                    return Err(io::Error::new(io::ErrorKind::PermissionDenied, "TLS Handshake Signature Verification Failed"));
-                                }
+                    if let Some(pk) = leaf_pubkey && msg_body.len() >= 4 {
+                        let alg = u16::from_be_bytes([msg_body[0], msg_body[1]]);
+                        let slen = u16::from_be_bytes([msg_body[2], msg_body[3]]) as usize;
+                        if alg == 0x0807 && msg_body.len() >= 4 + slen && slen == 64 {
+                            let mut sig = [0u8; 64];
+                            sig.copy_from_slice(&msg_body[4..4+64]);
+                            let mut verify_input = vec![0x20u8; 64];
+                            verify_input.extend_from_slice(b"TLS 1.3, server CertificateVerify");
+                            verify_input.push(0);
+                            verify_input.extend_from_slice(&th_cert);
+                            if !horiz_crypto::ed25519::verify(&pk, &verify_input, &sig) {
+                                return Err(io::Error::new(io::ErrorKind::PermissionDenied, "TLS Handshake Signature Verification Failed"));
                             }
                         }
                     }
@@ -527,12 +520,10 @@ pub fn https_get(url: &str, trust_store: &[[u8; 32]]) -> io::Result<Vec<u8>> {
     let mut s_finished_key = [0u8; 32];
     s_finished_key.copy_from_slice(&s_finished_key_v);
 
-    if let Some(vd) = server_finished_data {
-        if !verify_finished(&s_finished_key, &th_before_server_finished, &vd) {
-            // この最小実装では致命的ではない — ログに記録して続行
-            // (本番環境ではこれは致命的でなければならない)
-            eprintln!("[警告] TLS: Server Finished 検証失敗 (証明書なし検証モード)");
-        }
+    if let Some(vd) = server_finished_data && !verify_finished(&s_finished_key, &th_before_server_finished, &vd) {
+        // この最小実装では致命的ではない — ログに記録して続行
+        // (本番環境ではこれは致命的でなければならない)
+        eprintln!("[警告] TLS: Server Finished 検証失敗 (証明書なし検証モード)");
     }
 
     // ── アプリケーショントラフィックキーの導出 ───────────────────────────────────────
@@ -570,7 +561,7 @@ pub fn https_get(url: &str, trust_store: &[[u8; 32]]) -> io::Result<Vec<u8>> {
         match recv_encrypted_record(&mut stream, &mut server_app_keys) {
             Ok((RT_APPLICATION_DATA, data)) => {
                 if response_body.len() + data.len() > MAX_RESPONSE_SIZE {
-                    return Err(io::Error::new(io::ErrorKind::Other, "レスポンスが100MB制限を超えました"));
+                    return Err(io::Error::other("レスポンスが100MB制限を超えました"));
                 }
                 response_body.extend_from_slice(&data);
             }
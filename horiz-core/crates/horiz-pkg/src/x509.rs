@@ -75,7 +75,7 @@ pub fn parse_cert(der: &[u8]) -> Option<X509Cert> {
     let alg_len = parse_length(der, &mut pos)?;
     let alg_data = &der[pos..pos+alg_len];
     // Ed25519 OID is 1.3.101.112 -> DER: 06 03 2b 65 70
-    if alg_data != &[0x06, 0x03, 0x2b, 0x65, 0x70] { return None; }
+    if alg_data != [0x06, 0x03, 0x2b, 0x65, 0x70] { return None; }
     pos += alg_len;
 
     // subjectPublicKey (BIT STRING)
@@ -105,7 +105,7 @@ pub fn parse_cert(der: &[u8]) -> Option<X509Cert> {
 
 impl X509Cert {
     pub fn verify(&self, issuer_pubkey: &[u8; 32]) -> bool {
-        crate::ed25519::Point::verify(issuer_pubkey, &self.tbs_der, &self.signature)
+        horiz_crypto::ed25519::verify(issuer_pubkey, &self.tbs_der, &self.signature)
     }
 }
 
@@ -126,8 +126,8 @@ pub fn verify_chain(certs: &[Vec<u8>], trust_store: &[[u8; 32]]) -> Result<[u8;
     }
 
     if !verified && certs.len() > 1 {
-        for i in 1..certs.len() {
-            let issuer = parse_cert(&certs[i]).ok_or("Failed to parse issuer certificate")?;
+        for cert in certs.iter().skip(1) {
+            let issuer = parse_cert(cert).ok_or("Failed to parse issuer certificate")?;
             if current_cert.verify(&issuer.public_key) {
                 current_cert = issuer;
                 // この発行者が信頼されているか確認する
@@ -150,3 +150,27 @@ pub fn verify_chain(certs: &[Vec<u8>], trust_store: &[[u8; 32]]) -> Result<[u8;
         Err("Certificate is not trusted (no path to trust store)".to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    #[test]
+    fn test_verify_rfc8032_signature() {
+        // RFC 8032 7.1 TEST 2 (署名対象を tbs_der とした証明書として検証する)
+        let public_key: [u8; 32] = hex("3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c").try_into().unwrap();
+        let signature: [u8; 64] = hex(
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+        )
+        .try_into()
+        .unwrap();
+        let cert = X509Cert { tbs_der: vec![0x72], public_key: [0; 32], signature };
+        assert!(cert.verify(&public_key));
+        let tampered = X509Cert { tbs_der: vec![0x73], ..cert };
+        assert!(!tampered.verify(&public_key));
+    }
+}
@@ -0,0 +1,346 @@
+// --- 算術展開 (`$((式))`) ---
+//
+// パラメータ展開とコマンド置換を済ませた式を、符号付き 64 ビット整数で評価する (オーバーフローは折り返す)。
+// 演算子の優先順位と結合規則は C と同じ (優先順位の低い順):
+//
+//   代入     := `=` `*=` `/=` `%=` `+=` `-=` `<<=` `>>=` `&=` `^=` `|=` (右結合)
+//   条件     := `?:` (右結合)
+//   二項     := `||` < `&&` < `|` < `^` < `&` < `==` `!=` < `<` `<=` `>` `>=` < `<<` `>>` < `+` `-` < `*` `/` `%`
+//   単項     := `+` `-` `!` `~`
+//   一次式   := 定数 | 変数名 | `(` 式 `)`
+//
+// 定数は 10 進数、`0` で始まる 8 進数、`0x` で始まる 16 進数。変数名はその値に置き換える (未設定と空は 0、
+// 整数でない値はエラー)。`&&` `||` `?:` の評価しない側では代入と 0 除算のエラーを行わない。
+// インクリメント・デクリメント (`++` `--`) には対応しない (`--x` は `-(-x)` となる)。
+
+use crate::vars::Variables;
+
+/// 長いものから順に照合する
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=", "/=", "%=", "+=", "-=", "&=", "^=", "|=", "*", "/",
+    "%", "+", "-", "<", ">", "&", "^", "|", "!", "~", "?", ":", "=", "(", ")",
+];
+
+/// 二項演算子と優先順位 (大きいほど強く結合する)
+const BINARY: &[(&str, u8)] = &[
+    ("||", 1),
+    ("&&", 2),
+    ("|", 3),
+    ("^", 4),
+    ("&", 5),
+    ("==", 6),
+    ("!=", 6),
+    ("<", 7),
+    ("<=", 7),
+    (">", 7),
+    (">=", 7),
+    ("<<", 8),
+    (">>", 8),
+    ("+", 9),
+    ("-", 9),
+    ("*", 10),
+    ("/", 10),
+    ("%", 10),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+impl Token {
+    fn text(&self) -> String {
+        match self {
+            Token::Number(n) => n.to_string(),
+            Token::Name(name) => name.clone(),
+            Token::Op(op) => op.to_string(),
+        }
+    }
+}
+
+/// 式を評価する。`nounset` (`set -u`) では設定されていない変数の参照をエラーとする。
+pub fn evaluate(expr: &str, vars: &mut Variables, nounset: bool) -> Result<i64, String> {
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = Parser { tokens, pos: 0, vars, nounset, skip: false };
+    let value = parser.assignment()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(value),
+        Some(token) => Err(format!("予期しないトークン `{}` があります", token.text())),
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expr.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(if c.is_ascii_digit() { Token::Number(constant(&text)?) } else { Token::Name(text) });
+            continue;
+        }
+        let rest: String = chars[i..chars.len().min(i + 3)].iter().collect();
+        let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) else {
+            return Err(format!("予期しない文字 `{}` があります", c));
+        };
+        i += op.len();
+        tokens.push(Token::Op(op));
+    }
+    Ok(tokens)
+}
+
+/// 整数定数 (10 進数・`0` で始まる 8 進数・`0x` で始まる 16 進数)
+fn constant(text: &str) -> Result<i64, String> {
+    let (digits, radix) = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => (hex, 16),
+        None if text.len() > 1 && text.starts_with('0') => (&text[1..], 8),
+        None => (text, 10),
+    };
+    i64::from_str_radix(digits, radix)
+        .ok()
+        .filter(|_| !digits.starts_with(['+', '-']))
+        .ok_or_else(|| format!("{}: 不正な数値です", text))
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    vars: &'a mut Variables,
+    nounset: bool,
+    /// 評価しない側 (`0 && x=1` の右辺など) を読んでいる
+    skip: bool,
+}
+
+impl Parser<'_> {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) if *op == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(token) => Err(format!("予期しないトークン `{}` があります (`{}` が必要です)", token.text(), expected)),
+            None => Err(format!("式が途中で終わっています (`{}` が必要です)", expected)),
+        }
+    }
+
+    fn assignment(&mut self) -> Result<i64, String> {
+        if let Some(Token::Name(name)) = self.tokens.get(self.pos)
+            && let Some(Token::Op(op)) = self.tokens.get(self.pos + 1)
+            && op.ends_with('=')
+            && !["==", "!=", "<=", ">="].contains(op)
+        {
+            let (name, op) = (name.clone(), *op);
+            self.pos += 2;
+            let right = self.assignment()?;
+            let value = match op {
+                "=" => right,
+                _ => {
+                    let left = self.var(&name)?;
+                    self.apply(&op[..op.len() - 1], left, right)?
+                }
+            };
+            if !self.skip {
+                self.vars.set(&name, &value.to_string()).map_err(|e| e.to_string())?;
+            }
+            return Ok(value);
+        }
+        self.conditional()
+    }
+
+    fn conditional(&mut self) -> Result<i64, String> {
+        let condition = self.binary(1)?;
+        if self.peek_op() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let skip = self.skip;
+        self.skip = skip || condition == 0;
+        let then = self.assignment()?;
+        self.expect(":")?;
+        self.skip = skip || condition != 0;
+        let otherwise = self.conditional()?;
+        self.skip = skip;
+        Ok(if condition != 0 { then } else { otherwise })
+    }
+
+    /// 優先順位が `min` 以上の二項演算子の並び (左結合)
+    fn binary(&mut self, min: u8) -> Result<i64, String> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.peek_op()
+            && let Some(&(_, precedence)) = BINARY.iter().find(|(o, p)| *o == op && *p >= min)
+        {
+            self.pos += 1;
+            let skip = self.skip;
+            self.skip = skip || (op == "&&" && left == 0) || (op == "||" && left != 0);
+            let right = self.binary(precedence + 1)?;
+            self.skip = skip;
+            left = self.apply(op, left, right)?;
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        let op = self.peek_op();
+        if let Some(op @ ("+" | "-" | "!" | "~")) = op {
+            self.pos += 1;
+            let value = self.unary()?;
+            return Ok(match op {
+                "+" => value,
+                "-" => value.wrapping_neg(),
+                "!" => i64::from(value == 0),
+                _ => !value,
+            });
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<i64, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or_else(|| "式が途中で終わっています".to_string())?;
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(n),
+            Token::Name(name) => self.var(&name),
+            Token::Op("(") => {
+                let value = self.assignment()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            token => Err(format!("予期しないトークン `{}` があります", token.text())),
+        }
+    }
+
+    /// 変数の値 (先頭に符号の付いた整数定数)
+    fn var(&self, name: &str) -> Result<i64, String> {
+        if self.skip {
+            return Ok(0);
+        }
+        let value = match self.vars.get(name) {
+            None if self.nounset => return Err(format!("{}: パラメータが設定されていません", name)),
+            None => return Ok(0),
+            Some(value) => value.trim(),
+        };
+        if value.is_empty() {
+            return Ok(0);
+        }
+        let (negative, digits) = match value.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, value.strip_prefix('+').unwrap_or(value)),
+        };
+        let n = constant(digits).map_err(|_| format!("{}: 値 `{}` は整数ではありません", name, value))?;
+        Ok(if negative { n.wrapping_neg() } else { n })
+    }
+
+    fn apply(&self, op: &str, left: i64, right: i64) -> Result<i64, String> {
+        if matches!(op, "/" | "%") && right == 0 {
+            return if self.skip { Ok(0) } else { Err("0 で除算しました".to_string()) };
+        }
+        Ok(match op {
+            "*" => left.wrapping_mul(right),
+            "/" => left.wrapping_div(right),
+            "%" => left.wrapping_rem(right),
+            "+" => left.wrapping_add(right),
+            "-" => left.wrapping_sub(right),
+            "<<" => left.wrapping_shl(right as u32),
+            ">>" => left.wrapping_shr(right as u32),
+            "<" => i64::from(left < right),
+            "<=" => i64::from(left <= right),
+            ">" => i64::from(left > right),
+            ">=" => i64::from(left >= right),
+            "==" => i64::from(left == right),
+            "!=" => i64::from(left != right),
+            "&" => left & right,
+            "^" => left ^ right,
+            "|" => left | right,
+            "&&" => i64::from(left != 0 && right != 0),
+            _ => i64::from(left != 0 || right != 0),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> Result<i64, String> {
+        evaluate(expr, &mut Variables::default(), false)
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(eval(""), Ok(0));
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("7 / 2 + 7 % 2 - -1"), Ok(5));
+        assert_eq!(eval("-7 / 2"), Ok(-3));
+        assert_eq!(eval("010 + 0x1f + 0XA"), Ok(49));
+        assert_eq!(eval("1 << 4 >> 2"), Ok(4));
+        assert_eq!(eval("!0 + !5 + ~0"), Ok(0));
+        assert_eq!(eval("6 & 3 | 8 ^ 1"), Ok(11));
+        assert_eq!(eval("1 < 2 == 2 > 1"), Ok(1));
+        assert_eq!(eval("3 <= 2 || 2 >= 2 && 0 != 0"), Ok(0));
+        assert_eq!(eval("0 ? 1 : 2 ? 3 : 4"), Ok(3));
+        assert_eq!(eval("9223372036854775807 + 1"), Ok(i64::MIN));
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut vars = Variables::default();
+        vars.set("i", "4").unwrap();
+        vars.set("n", " -2 ").unwrap();
+        vars.set("e", "").unwrap();
+        assert_eq!(evaluate("i * n + e + unset", &mut vars, false), Ok(-8));
+        assert_eq!(evaluate("i = i + 1", &mut vars, false), Ok(5));
+        assert_eq!(vars.get("i"), Some("5"));
+        assert_eq!(evaluate("a = b = 2", &mut vars, false), Ok(2));
+        assert_eq!((vars.get("a"), vars.get("b")), (Some("2"), Some("2")));
+        assert_eq!(evaluate("(i *= 2) + (i <<= 1) + (i -= 4)", &mut vars, false), Ok(46));
+        assert_eq!(vars.get("i"), Some("16"));
+        // 評価しない側の代入と 0 除算は行わない
+        assert_eq!(evaluate("0 && (i = 1 / 0)", &mut vars, false), Ok(0));
+        assert_eq!(evaluate("1 || (i = 1)", &mut vars, false), Ok(1));
+        assert_eq!(evaluate("i ? 7 : (i = 0)", &mut vars, false), Ok(7));
+        assert_eq!(vars.get("i"), Some("16"));
+
+        vars.set("s", "abc").unwrap();
+        assert_eq!(evaluate("s + 1", &mut vars, false), Err("s: 値 `abc` は整数ではありません".into()));
+        assert_eq!(evaluate("unset", &mut vars, true), Err("unset: パラメータが設定されていません".into()));
+        vars.set_readonly("r");
+        assert_eq!(evaluate("r = 1", &mut vars, false), Err("r: 読み取り専用の変数です".into()));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(eval("1 / 0"), Err("0 で除算しました".into()));
+        assert_eq!(eval("1 % (2 - 2)"), Err("0 で除算しました".into()));
+        assert_eq!(eval("1 +"), Err("式が途中で終わっています".into()));
+        assert_eq!(eval("(1 + 2"), Err("式が途中で終わっています (`)` が必要です)".into()));
+        assert_eq!(eval("1 2"), Err("予期しないトークン `2` があります".into()));
+        assert_eq!(eval("1 ? 2"), Err("式が途中で終わっています (`:` が必要です)".into()));
+        assert_eq!(eval("08"), Err("08: 不正な数値です".into()));
+        assert_eq!(eval("0x"), Err("0x: 不正な数値です".into()));
+        assert_eq!(eval("1 = 2"), Err("予期しないトークン `=` があります".into()));
+        assert_eq!(eval("1, 2"), Err("予期しない文字 `,` があります".into()));
+        assert_eq!(eval("\"1\""), Err("予期しない文字 `\"` があります".into()));
+    }
+}
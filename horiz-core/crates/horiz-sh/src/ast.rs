@@ -0,0 +1,112 @@
+// --- 構文木 ---
+
+use std::rc::Rc;
+
+use crate::lexer::{HereDoc, Word};
+
+/// リダイレクトの種類と対象
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RedirectKind {
+    /// `<`
+    Input(Word),
+    /// `>` / `>|`
+    Output(Word),
+    /// `>>`
+    Append(Word),
+    /// `<>`
+    ReadWrite(Word),
+    /// `<&` (記述子番号または `-`)
+    DupInput(Word),
+    /// `>&` (記述子番号または `-`)
+    DupOutput(Word),
+    /// `&>`: 標準出力と標準エラー出力の両方
+    OutputBoth(Word),
+    /// `<<` / `<<-`
+    HereDoc(HereDoc),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    /// 対象のファイル記述子 (`2>` の 2。省略時は入力系が 0、出力系が 1)
+    pub fd: i32,
+    pub kind: RedirectKind,
+}
+
+/// 変数への代入 (`NAME=value`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+}
+
+/// 単純コマンド (`NAME=value cmd arg... > file`)。リダイレクトは書かれた順に適用する。
+/// コマンド名がある場合、代入はそのコマンドの実行中だけ有効になる (特殊組み込みコマンドを除く)。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimpleCommand {
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    pub redirects: Vec<Redirect>,
+}
+
+/// 複合コマンド
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompoundCommand {
+    /// `{ list; }`: シェル自身で実行する
+    Group(List),
+    /// `( list )`: 子プロセス (サブシェル) で実行する
+    Subshell(List),
+    /// `if list; then list; [elif list; then list;]... [else list;] fi`
+    If { branches: Vec<(List, List)>, otherwise: Option<List> },
+    /// `while list; do list; done` / `until list; do list; done`
+    Loop { until: bool, condition: List, body: List },
+    /// `for name [in word...]; do list; done` (`in` を省略した場合は位置パラメータ)
+    For { name: String, words: Option<Vec<Word>>, body: List },
+    /// `case word in pattern [| pattern]...) list;; ... esac`
+    Case { word: Word, items: Vec<CaseItem> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseItem {
+    pub patterns: Vec<Word>,
+    pub body: List,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Simple(SimpleCommand),
+    /// 複合コマンドと、その全体に適用するリダイレクト
+    Compound(CompoundCommand, Vec<Redirect>),
+    /// 関数定義 (`name() compound-command`)。本体は定義後も関数の表から参照する。
+    FunctionDef { name: String, body: Rc<Command> },
+}
+
+/// `|` でつないだコマンドの並び。対話モードではすべて 1 つのプロセスグループで実行する。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pipeline {
+    /// `!`: 終了ステータスを反転する
+    pub negate: bool,
+    pub commands: Vec<Command>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connector {
+    /// `&&`: 直前のステータスが 0 の場合に実行する
+    And,
+    /// `||`: 直前のステータスが 0 以外の場合に実行する
+    Or,
+}
+
+/// `&&` と `||` でつないだパイプラインの並び (左から順に評価する)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AndOr {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    /// `&` で終わる (終了を待たずにバックグラウンドで実行する)
+    pub background: bool,
+}
+
+/// `;` または改行で区切った並び
+pub type List = Vec<AndOr>;
+
+/// 入力全体
+pub type Program = List;
@@ -0,0 +1,577 @@
+// --- 組み込みコマンド ---
+//
+// 引数 (argv[0] はコマンド名) を受け取り、終了ステータスを返す。
+
+use std::env;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cond;
+use crate::exec::{self, ExecResult, Interrupt, Shell};
+use crate::jobs;
+use crate::lexer;
+use crate::source::{self, Input};
+use crate::vars::{self, Var, Variables};
+
+/// `.` の入れ子の深さの上限 (ファイルが自分自身を読み込み続けないようにする)
+const MAX_SOURCE_DEPTH: usize = 100;
+
+pub type Builtin = fn(&mut Shell, &[String]) -> ExecResult;
+
+const BUILTINS: &[(&str, Builtin)] = &[
+    (".", dot),
+    (":", colon),
+    ("[", test),
+    ("bg", bg),
+    ("break", break_),
+    ("cd", cd),
+    ("continue", continue_),
+    ("echo", echo),
+    ("exit", exit),
+    ("export", export),
+    ("false", false_),
+    ("fg", fg),
+    ("jobs", jobs),
+    ("kill", kill),
+    ("readonly", readonly),
+    ("return", return_),
+    ("set", set),
+    ("shift", shift),
+    ("source", dot),
+    ("test", test),
+    ("true", true_),
+    ("unset", unset),
+    ("version", version),
+    ("wait", wait),
+    ("whoami", whoami),
+];
+
+/// POSIX の特殊組み込みコマンド (コマンド名の前の代入が実行後も残る。関数より先に探す)
+const SPECIAL: &[&str] = &[".", ":", "break", "continue", "exit", "export", "readonly", "return", "set", "shift", "unset"];
+
+pub fn lookup(name: &str) -> Option<Builtin> {
+    BUILTINS.iter().find(|(n, _)| *n == name).map(|(_, f)| *f)
+}
+
+/// 組み込みコマンドの名前 (補完に使う)
+pub fn names() -> impl Iterator<Item = &'static str> {
+    BUILTINS.iter().map(|(name, _)| *name)
+}
+
+pub fn is_special(name: &str) -> bool {
+    SPECIAL.contains(&name)
+}
+
+/// `:`: 何もせずに成功する (引数は展開だけされる)
+fn colon(_shell: &mut Shell, _argv: &[String]) -> ExecResult {
+    Ok(0)
+}
+
+fn true_(_shell: &mut Shell, _argv: &[String]) -> ExecResult {
+    Ok(0)
+}
+
+fn false_(_shell: &mut Shell, _argv: &[String]) -> ExecResult {
+    Ok(1)
+}
+
+/// `break [n]`: n 段 (省略時は 1 段) 外側までのループを抜ける
+fn break_(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    loop_levels(shell, argv).map_or(Ok(1), |n| Err(Interrupt::Break(n)))
+}
+
+/// `continue [n]`: n 段外側のループの次の繰り返しへ進む
+fn continue_(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    loop_levels(shell, argv).map_or(Ok(1), |n| Err(Interrupt::Continue(n)))
+}
+
+/// break / continue の段数 (実行中のループの数を超える場合はいちばん外側のループ)。エラーの場合は None。
+fn loop_levels(shell: &Shell, argv: &[String]) -> Option<usize> {
+    if shell.loop_depth == 0 {
+        eprintln!("{}: ループの中ではありません", argv[0]);
+        return None;
+    }
+    match argv.get(1).map(|n| n.parse::<usize>()) {
+        None => Some(1),
+        Some(Ok(n)) if n > 0 => Some(n.min(shell.loop_depth)),
+        Some(_) => {
+            eprintln!("{}: {}: 1 以上の数値を指定してください", argv[0], argv[1]);
+            None
+        }
+    }
+}
+
+/// `return [n]`: 関数 (または `.` で読み込んだファイル) を終了する。省略時は直前の終了ステータスを返す。
+fn return_(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    if shell.function_depth == 0 && shell.source_depth == 0 {
+        eprintln!("return: 関数の中ではありません");
+        return Ok(1);
+    }
+    match argv.get(1).map(|n| n.parse::<i32>()) {
+        None => Err(Interrupt::Return(shell.last_status)),
+        Some(Ok(n)) => Err(Interrupt::Return(n & 0xff)),
+        Some(Err(_)) => {
+            eprintln!("return: {}: 数値を指定してください", argv[1]);
+            Err(Interrupt::Return(2))
+        }
+    }
+}
+
+/// `test 式` / `[ 式 ]`: 式を評価し、真なら 0、偽なら 1、式が正しくなければ 2 を返す
+fn test(_shell: &mut Shell, argv: &[String]) -> ExecResult {
+    let mut args: Vec<&str> = argv[1..].iter().map(String::as_str).collect();
+    if argv[0] == "[" {
+        if args.last() != Some(&"]") {
+            eprintln!("[: `]' がありません");
+            return Ok(2);
+        }
+        args.pop();
+    }
+    match cond::evaluate(&args) {
+        Ok(true) => Ok(0),
+        Ok(false) => Ok(1),
+        Err(e) => {
+            eprintln!("{}: {}", argv[0], e);
+            Ok(2)
+        }
+    }
+}
+
+/// `. file [引数...]` / `source file [引数...]`: ファイルの内容を現在のシェルで実行する。
+/// 引数を指定した場合は、実行中だけ位置パラメータを置き換える。
+fn dot(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    let Some(name) = argv.get(1) else {
+        eprintln!("{}: ファイル名を指定してください", argv[0]);
+        return Ok(2);
+    };
+    if shell.source_depth >= MAX_SOURCE_DEPTH {
+        eprintln!("{}: {}: 読み込みの入れ子が深すぎます", argv[0], name);
+        return Ok(1);
+    }
+    let Some(path) = find_source(name, shell.vars.get("PATH")) else {
+        eprintln!("{}: {}: ファイルが見つかりません", argv[0], name);
+        return Ok(1);
+    };
+    let mut input = match Input::file(&path) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("{}: {}: {}", argv[0], name, e);
+            return Ok(1);
+        }
+    };
+    let positional = (argv.len() > 2).then(|| std::mem::replace(&mut shell.positional, argv[2..].to_vec()));
+    shell.source_depth += 1;
+    let result = source::run(shell, &mut input);
+    shell.source_depth -= 1;
+    if let Some(positional) = positional {
+        shell.positional = positional;
+    }
+    match result {
+        Err(Interrupt::Return(status)) => Ok(status),
+        result => result,
+    }
+}
+
+/// `.` で読み込むファイルを探す。`/` を含まない場合は PATH の後にカレントディレクトリを探す。
+fn find_source(name: &str, path: Option<&str>) -> Option<PathBuf> {
+    if name.contains('/') {
+        return Some(PathBuf::from(name));
+    }
+    path.unwrap_or_default()
+        .split(':')
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| Path::new(dir).join(name))
+        .chain([PathBuf::from(name)])
+        .find(|candidate| candidate.is_file())
+}
+
+fn cd(_shell: &mut Shell, argv: &[String]) -> ExecResult {
+    let new_dir = argv.get(1).map(String::as_str).unwrap_or("/");
+    if let Err(e) = env::set_current_dir(Path::new(new_dir)) {
+        eprintln!("cd: {}: {}", new_dir, e);
+        return Ok(1);
+    }
+    Ok(0)
+}
+
+/// `echo [-n] [文字列...]`: `-n` は末尾の改行を出力しない
+fn echo(_shell: &mut Shell, argv: &[String]) -> ExecResult {
+    let (newline, args) = match argv.get(1).map(String::as_str) {
+        Some("-n") => (false, &argv[2..]),
+        _ => (true, &argv[1..]),
+    };
+    let mut line = args.join(" ");
+    if newline {
+        line.push('\n');
+    }
+    let mut stdout = io::stdout().lock();
+    if let Err(e) = stdout.write_all(line.as_bytes()).and_then(|_| stdout.flush()) {
+        eprintln!("echo: 書き込みエラー: {}", e);
+        return Ok(1);
+    }
+    Ok(0)
+}
+
+/// `exit [n]`: 省略時は直前の終了ステータスで終了する
+fn exit(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    match argv.get(1).map(|n| n.parse::<i32>()) {
+        None => Err(Interrupt::Exit(shell.last_status)),
+        Some(Ok(n)) => Err(Interrupt::Exit(n & 0xff)),
+        Some(Err(_)) => {
+            eprintln!("exit: {}: 数値を指定してください", argv[1]);
+            Err(Interrupt::Exit(2))
+        }
+    }
+}
+
+/// `set [-efnux] [+efnux] [-o 名前] [+o 名前] [--] [引数...]`。
+/// 引数がなければすべての変数を、`-o` / `+o` だけならオプションの設定を表示する。
+/// オプション以外の引数 (または `--` の後の引数) は位置パラメータに設定する。
+fn set(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    if argv.len() == 1 {
+        for (name, var) in shell.vars.iter() {
+            if let Some(value) = &var.value {
+                println!("{}={}", name, vars::quote(value));
+            }
+        }
+        return Ok(0);
+    }
+    let mut args = argv[1..].iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            shell.positional = args.cloned().collect();
+            return Ok(0);
+        }
+        let Some(flags) = arg.strip_prefix('-').or_else(|| arg.strip_prefix('+')).filter(|flags| !flags.is_empty()) else {
+            shell.positional = argv[argv.len() - args.len() - 1..].to_vec();
+            return Ok(0);
+        };
+        let enable = arg.starts_with('-');
+        if flags == "o" {
+            match args.next() {
+                Some(name) => match shell.option_mut(name) {
+                    Some(option) => *option = enable,
+                    None => {
+                        eprintln!("set: {}: 不明なオプションです", name);
+                        return Ok(2);
+                    }
+                },
+                None => print_options(shell, enable),
+            }
+            continue;
+        }
+        for flag in flags.chars() {
+            match shell.flag_mut(flag) {
+                Some(option) => *option = enable,
+                None => {
+                    eprintln!("set: {}{}: 不明なオプションです", &arg[..1], flag);
+                    return Ok(2);
+                }
+            }
+        }
+    }
+    Ok(0)
+}
+
+/// `set -o` は一覧で、`set +o` はシェルの入力として読み直せる形で表示する
+fn print_options(shell: &mut Shell, table: bool) {
+    for (name, _) in exec::OPTIONS {
+        let on = shell.option_mut(name).is_some_and(|option| *option);
+        match table {
+            true => println!("{}\t{}", name, if on { "on" } else { "off" }),
+            false => println!("set {}o {}", if on { '-' } else { '+' }, name),
+        }
+    }
+}
+
+/// `shift [n]`: 位置パラメータを n 個 (省略時は 1 個) 取り除く
+fn shift(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    let n = match argv.get(1).map(|n| n.parse::<usize>()) {
+        None => 1,
+        Some(Ok(n)) if n <= shell.positional.len() => n,
+        Some(Ok(_)) => {
+            eprintln!("shift: 位置パラメータの数 ({}) を超えています", shell.positional.len());
+            return Ok(1);
+        }
+        Some(Err(_)) => {
+            eprintln!("shift: {}: 数値を指定してください", argv[1]);
+            return Ok(2);
+        }
+    };
+    shell.positional.drain(..n.min(shell.positional.len()));
+    Ok(0)
+}
+
+/// `export [-p] [NAME[=value]...]`: 変数をエクスポートする。引数がなければエクスポートされた変数を表示する。
+fn export(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    declare(shell, argv, |var| var.exported, Variables::export)
+}
+
+/// `readonly [-p] [NAME[=value]...]`: 変数を読み取り専用にする。引数がなければ読み取り専用の変数を表示する。
+fn readonly(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    declare(shell, argv, |var| var.readonly, Variables::set_readonly)
+}
+
+/// export と readonly の共通部分。`is_marked` で表示する変数を選び、`mark` で属性を付ける。
+fn declare(shell: &mut Shell, argv: &[String], is_marked: fn(&Var) -> bool, mark: fn(&mut Variables, &str)) -> ExecResult {
+    let command = &argv[0];
+    let args: Vec<&String> = argv[1..].iter().filter(|arg| *arg != "-p").collect();
+    if args.is_empty() {
+        for (name, var) in shell.vars.iter().filter(|(_, var)| is_marked(var)) {
+            match &var.value {
+                Some(value) => println!("{} {}={}", command, name, vars::quote(value)),
+                None => println!("{} {}", command, name),
+            }
+        }
+        return Ok(0);
+    }
+    let mut status = 0;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !lexer::is_name(name) {
+            eprintln!("{}: {}: 変数名として正しくありません", command, arg);
+            status = 1;
+            continue;
+        }
+        if let Some(value) = value
+            && let Err(e) = shell.vars.set(name, value)
+        {
+            eprintln!("{}: {}", command, e);
+            status = 1;
+            continue;
+        }
+        mark(&mut shell.vars, name);
+    }
+    Ok(status)
+}
+
+/// `unset [-v|-f] NAME...`: 変数 (`-f` なら関数) を削除する
+fn unset(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    let functions = argv.get(1).is_some_and(|arg| arg == "-f");
+    let mut status = 0;
+    for name in argv[1..].iter().filter(|arg| *arg != "-v" && *arg != "-f") {
+        if functions {
+            shell.functions.remove(name);
+        } else if !lexer::is_name(name) {
+            eprintln!("unset: {}: 変数名として正しくありません", name);
+            status = 1;
+        } else if let Err(e) = shell.vars.unset(name) {
+            eprintln!("unset: {}", e);
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+/// `jobs [-l|-p] [%n...]`: ジョブの状態を表示する (`-l` はプロセス ID も、`-p` はプロセス ID だけを表示する)
+fn jobs(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    let (mut long, mut pids_only) = (false, false);
+    let mut specs = Vec::new();
+    for arg in &argv[1..] {
+        match arg.as_str() {
+            "-l" => long = true,
+            "-p" => pids_only = true,
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                eprintln!("jobs: {}: 不明なオプションです", arg);
+                return Ok(2);
+            }
+            _ => specs.push(arg.as_str()),
+        }
+    }
+    shell.jobs.poll();
+    let mut status = 0;
+    let ids = match specs.is_empty() {
+        true => shell.jobs.ids(),
+        false => specs.iter().filter_map(|spec| find_job(shell, "jobs", Some(spec)).inspect_err(|_| status = 1).ok()).collect(),
+    };
+    let mut out = String::new();
+    for &id in &ids {
+        match pids_only {
+            true => out += &format!("{}\n", shell.jobs.get(id).and_then(|job| job.processes.first()).map_or(0, |p| p.pid)),
+            false => out += &format!("{}\n", shell.jobs.format(id, long)),
+        }
+    }
+    // 終了を表示したジョブは表から取り除く
+    for id in ids {
+        shell.jobs.mark_reported(id);
+    }
+    let mut stdout = io::stdout().lock();
+    if let Err(e) = stdout.write_all(out.as_bytes()).and_then(|_| stdout.flush()) {
+        eprintln!("jobs: 書き込みエラー: {}", e);
+        return Ok(1);
+    }
+    Ok(status)
+}
+
+/// ジョブの指定をジョブ番号にする。見つからなければエラーを表示する。
+fn find_job(shell: &Shell, command: &str, spec: Option<&str>) -> Result<usize, ()> {
+    shell.jobs.find(spec).map_err(|e| eprintln!("{}: {}", command, e))
+}
+
+/// `fg [%n]`: ジョブ (省略時はカレントジョブ) をフォアグラウンドで再開し、終了か停止を待つ
+fn fg(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    if !shell.job_control() {
+        eprintln!("fg: ジョブ制御が無効です");
+        return Ok(1);
+    }
+    match find_job(shell, "fg", argv.get(1).map(String::as_str)) {
+        Ok(id) => Ok(shell.foreground(id)),
+        Err(()) => Ok(1),
+    }
+}
+
+/// `bg [%n...]`: 停止したジョブ (省略時はカレントジョブ) をバックグラウンドで再開する
+fn bg(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    if !shell.job_control() {
+        eprintln!("bg: ジョブ制御が無効です");
+        return Ok(1);
+    }
+    let specs: Vec<Option<&str>> = match argv.len() {
+        1 => vec![None],
+        _ => argv[1..].iter().map(|spec| Some(spec.as_str())).collect(),
+    };
+    let mut status = 0;
+    for spec in specs {
+        let resumed = find_job(shell, "bg", spec).and_then(|id| shell.continue_background(id).map_err(|e| eprintln!("bg: {}", e)));
+        if resumed.is_err() {
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+/// `wait [%n|pid...]`: ジョブの終了を待つ。引数がなければすべてのジョブを待って 0 を返す。
+/// 引数があれば最後のジョブの終了ステータスを返す (不明なジョブは 127)。
+fn wait(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    if argv.len() == 1 {
+        for id in shell.jobs.ids() {
+            shell.wait_job(id);
+        }
+        return Ok(0);
+    }
+    let mut status = 0;
+    for arg in &argv[1..] {
+        let id = match arg.parse::<i32>() {
+            _ if arg.starts_with('%') => shell.jobs.find(Some(arg)).ok(),
+            Ok(pid) => shell.jobs.find_pid(pid),
+            Err(_) => {
+                eprintln!("wait: {}: プロセス ID かジョブを指定してください", arg);
+                status = 2;
+                continue;
+            }
+        };
+        status = match id {
+            Some(id) => shell.wait_job(id),
+            None => {
+                eprintln!("wait: {}: 子プロセスではありません", arg);
+                127
+            }
+        };
+    }
+    Ok(status)
+}
+
+/// `kill [-s シグナル | -シグナル] pid|%n...`: プロセスかジョブにシグナル (省略時は TERM) を送る。
+/// `kill -l [終了ステータス...]` はシグナルの名前を表示する。
+fn kill(shell: &mut Shell, argv: &[String]) -> ExecResult {
+    let mut args = &argv[1..];
+    let mut sig = libc::SIGTERM;
+    match args.first().map(String::as_str) {
+        Some("-l") => return list_signals(&args[1..]),
+        Some("-s") => {
+            let Some(name) = args.get(1) else {
+                eprintln!("kill: -s にはシグナルを指定してください");
+                return Ok(2);
+            };
+            match jobs::parse_signal(name) {
+                Some(s) => sig = s,
+                None => {
+                    eprintln!("kill: {}: 不明なシグナルです", name);
+                    return Ok(1);
+                }
+            }
+            args = &args[2..];
+        }
+        Some("--") => args = &args[1..],
+        Some(arg) if arg.starts_with('-') && arg.len() > 1 => {
+            match jobs::parse_signal(&arg[1..]) {
+                Some(s) => sig = s,
+                None => {
+                    eprintln!("kill: {}: 不明なシグナルです", &arg[1..]);
+                    return Ok(1);
+                }
+            }
+            args = &args[1..];
+        }
+        _ => {}
+    }
+    if args.is_empty() {
+        eprintln!("kill: プロセス ID かジョブを指定してください");
+        return Ok(2);
+    }
+    let mut status = 0;
+    for target in args {
+        let sent = match target.parse::<i32>() {
+            _ if target.starts_with('%') => shell.jobs.find(Some(target)).and_then(|id| {
+                let job = shell.jobs.get(id).ok_or_else(|| format!("{}: ジョブがありません", target))?;
+                signal_job(job, sig).map_err(|e| format!("{}: {}", target, e))
+            }),
+            Ok(pid) if unsafe { libc::kill(pid, sig) } == 0 => Ok(()),
+            Ok(_) => Err(format!("{}: {}", target, io::Error::last_os_error())),
+            Err(_) => Err(format!("{}: プロセス ID かジョブを指定してください", target)),
+        };
+        if let Err(e) = sent {
+            eprintln!("kill: {}", e);
+            status = 1;
+        }
+    }
+    Ok(status)
+}
+
+/// ジョブにシグナルを送る。停止したジョブは、そのままではシグナルを処理できないので再開させる。
+fn signal_job(job: &jobs::Job, sig: i32) -> io::Result<()> {
+    job.kill(sig)?;
+    if matches!(job.state(), jobs::State::Stopped(_)) && matches!(sig, libc::SIGTERM | libc::SIGHUP) {
+        job.kill(libc::SIGCONT)?;
+    }
+    Ok(())
+}
+
+/// `kill -l`: シグナルの名前の一覧、または終了ステータス (128 + 番号) や番号に対応する名前を表示する
+fn list_signals(args: &[String]) -> ExecResult {
+    let mut out = String::new();
+    let mut status = 0;
+    if args.is_empty() {
+        let names: Vec<&str> = jobs::SIGNALS.iter().map(|(name, _)| *name).collect();
+        out = names.join(" ") + "\n";
+    }
+    for arg in args {
+        let name = arg.parse::<i32>().ok().map(|n| if n > 128 { n - 128 } else { n }).and_then(jobs::signal_name);
+        match name {
+            Some(name) => out += &format!("{}\n", name),
+            None => {
+                eprintln!("kill: {}: 不明なシグナルです", arg);
+                status = 1;
+            }
+        }
+    }
+    let mut stdout = io::stdout().lock();
+    if let Err(e) = stdout.write_all(out.as_bytes()).and_then(|_| stdout.flush()) {
+        eprintln!("kill: 書き込みエラー: {}", e);
+        return Ok(1);
+    }
+    Ok(status)
+}
+
+fn version(_shell: &mut Shell, _argv: &[String]) -> ExecResult {
+    println!("HorizOS Shell v1.2.1 (Custom Ownership Edition)");
+    Ok(0)
+}
+
+fn whoami(shell: &mut Shell, _argv: &[String]) -> ExecResult {
+    println!("{}", shell.vars.get("USER").unwrap_or("root"));
+    Ok(0)
+}
@@ -0,0 +1,445 @@
+// --- Tab 補完 ---
+//
+// カーソルより前の文字列から補完する単語を取り出し、その位置と形で候補の種類を決める。
+//
+// - `$name` / `${name`: 変数名
+// - `/` を含まない `~user`: /etc/passwd のユーザー名 (`~user/` の形にする)
+// - コマンドの位置 (行頭、`;` `|` `&` `(` の後、`if` などの予約語や代入の後) の `/` を含まない単語:
+//   組み込みコマンド、関数、PATH (と /bin) の実行ファイルの名前
+// - それ以外 (リダイレクトの対象を含む): ファイル名。ディレクトリには `/` を付ける
+//
+// 候補は単語全体を置き換える文字列として返す。クォートが閉じていない単語はそのクォートで、それ以外は
+// `\` で特殊文字をエスケープする。先頭の `~user/` はチルダ展開されるようにそのまま残す。
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use horiz_auth::passwd::{PASSWD_PATH, PasswdEntry};
+use horiz_auth::userdb::Database;
+
+use crate::builtins;
+use crate::editor::str_width;
+use crate::exec::{self, Shell};
+use crate::expand;
+use crate::lexer;
+
+/// 後ろに続く単語もコマンドの位置にある予約語
+const COMMAND_PREFIXES: &[&str] = &["!", "{", "do", "elif", "else", "if", "then", "until", "while"];
+
+/// クォートの外で `\` を付ける文字
+const SPECIAL_CHARS: &str = " \t\n'\"\\$`;&|<>()*?[]";
+
+/// 補完に使うシェルの状態
+#[derive(Debug, Default)]
+pub struct Context {
+    /// 組み込みコマンドと関数の名前
+    pub commands: Vec<String>,
+    /// 変数の名前
+    pub variables: Vec<String>,
+    /// 変数 PATH の値
+    pub path: Option<String>,
+    /// 変数 HOME の値
+    pub home: Option<String>,
+}
+
+impl Context {
+    pub fn new(shell: &Shell) -> Self {
+        Context {
+            commands: builtins::names().map(String::from).chain(shell.functions.keys().cloned()).collect(),
+            variables: shell.vars.iter().map(|(name, _)| name.to_string()).collect(),
+            path: shell.vars.get("PATH").map(String::from),
+            home: shell.vars.get("HOME").map(String::from),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// 単語を置き換える文字列 (エスケープ済み)
+    pub replacement: String,
+    /// 候補の一覧に表示する名前
+    pub display: String,
+    /// 単語が完結しているか (候補が 1 つなら後ろに空白を付ける)。ディレクトリなどは false。
+    pub complete: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// 置き換える単語の開始位置 (バイト単位)
+    pub start: usize,
+    /// 名前順の候補
+    pub candidates: Vec<Candidate>,
+}
+
+/// カーソルより前の文字列 `line` の最後の単語を補完する
+pub fn complete(line: &str, context: &Context) -> Completion {
+    let scan = scan(line);
+    let raw = &line[scan.start..];
+    let mut candidates = match variable(raw, scan.quote) {
+        Some((prefix, name, brace)) => variables(prefix, name, brace, scan.quote, context),
+        None => {
+            let text = unquote(raw);
+            if let Some(user) = text.strip_prefix('~').filter(|user| !user.contains('/') && raw.starts_with('~')) {
+                users(user)
+            } else if scan.command && !text.contains('/') {
+                commands(&text, scan.quote, context)
+            } else {
+                files(&text, raw.starts_with('~'), scan.quote, context)
+            }
+        }
+    };
+    candidates.sort_by(|a, b| (&a.display, &a.replacement).cmp(&(&b.display, &b.replacement)));
+    candidates.dedup();
+    Completion { start: scan.start, candidates }
+}
+
+/// 最後の単語の位置と状態
+#[derive(Debug, PartialEq, Eq)]
+struct Scan {
+    /// 単語の開始位置 (単語がなければ line の長さ)
+    start: usize,
+    /// 単語がコマンドの位置にあるか
+    command: bool,
+    /// 単語の中で閉じていないクォート
+    quote: Option<char>,
+}
+
+fn scan(line: &str) -> Scan {
+    let mut scan = Scan { start: line.len(), command: true, quote: None };
+    // 単語の途中か、直前がリダイレクトの演算子か
+    let (mut in_word, mut redirect) = (false, false);
+    let mut chars = line.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match (scan.quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => scan.quote = None,
+            (Some('"'), '\\') => {
+                chars.next();
+            }
+            (Some(_), _) => {}
+            (None, ' ' | '\t' | '\n' | ';' | '|' | '&' | '(' | ')' | '<' | '>') => {
+                if in_word {
+                    let word = &line[scan.start..i];
+                    // リダイレクトの対象はコマンドの位置を変えない
+                    if !redirect && !COMMAND_PREFIXES.contains(&word) && !is_assignment(word) {
+                        scan.command = false;
+                    }
+                    redirect = false;
+                    in_word = false;
+                }
+                match c {
+                    '<' | '>' => redirect = true,
+                    ';' | '|' | '&' | '(' | ')' => (scan.command, redirect) = (true, false),
+                    _ => {}
+                }
+            }
+            (None, _) => {
+                if !in_word {
+                    (in_word, scan.start) = (true, i);
+                }
+                match c {
+                    '\\' => {
+                        chars.next();
+                    }
+                    '\'' | '"' => scan.quote = Some(c),
+                    _ => {}
+                }
+            }
+        }
+    }
+    if !in_word {
+        scan.start = line.len();
+    }
+    scan
+}
+
+fn is_assignment(word: &str) -> bool {
+    word.split_once('=').is_some_and(|(name, _)| lexer::is_name(name))
+}
+
+/// クォートを取り除く (閉じていないクォートは単語の終わりまで続くものとする)
+fn unquote(raw: &str) -> String {
+    let mut out = String::new();
+    let mut quote = None;
+    let mut chars = raw.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some('\''), _) => out.push(c),
+            (Some(_), '\\') => match chars.next() {
+                Some(next) if "$`\"\\".contains(next) => out.push(next),
+                Some(next) => out.extend(['\\', next]),
+                None => out.push('\\'),
+            },
+            (None, '\\') => out.extend(chars.next()),
+            (None, '\'' | '"') => quote = Some(c),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// 文字列を単語の一部としてクォートする。`quote` が閉じていないクォートなら、そのクォートで囲む
+/// (`close` なら閉じる)。`start` なら単語の先頭で特別な意味を持つ `#` `~` もエスケープする。
+fn quote(text: &str, quote: Option<char>, close: bool, start: bool) -> String {
+    let mut out = String::new();
+    match quote {
+        Some('\'') => {
+            out.push('\'');
+            out.push_str(&text.replace('\'', "'\\''"));
+        }
+        Some(_) => {
+            out.push('"');
+            for c in text.chars() {
+                if "$`\"\\".contains(c) {
+                    out.push('\\');
+                }
+                out.push(c);
+            }
+        }
+        None => {
+            for (i, c) in text.chars().enumerate() {
+                if SPECIAL_CHARS.contains(c) || (start && i == 0 && (c == '#' || c == '~')) {
+                    out.push('\\');
+                }
+                out.push(c);
+            }
+            return out;
+        }
+    }
+    if close {
+        out.extend(quote);
+    }
+    out
+}
+
+/// 単語の末尾の `$name` / `${name` (シングルクォートの内側を除く)。`$` までの部分、名前の途中まで、
+/// `{` の有無を返す。
+fn variable(raw: &str, quote: Option<char>) -> Option<(&str, &str, bool)> {
+    if quote == Some('\'') {
+        return None;
+    }
+    let dollar = raw.rfind('$')?;
+    let (prefix, rest) = raw.split_at(dollar + 1);
+    let (name, brace) = match rest.strip_prefix('{') {
+        Some(name) => (name, true),
+        None => (rest, false),
+    };
+    name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_').then_some((prefix, name, brace))
+}
+
+fn variables(prefix: &str, name: &str, brace: bool, quote: Option<char>, context: &Context) -> Vec<Candidate> {
+    context
+        .variables
+        .iter()
+        .filter(|var| var.starts_with(name))
+        .map(|var| Candidate {
+            replacement: if brace { format!("{}{{{}}}", prefix, var) } else { format!("{}{}", prefix, var) },
+            display: var.clone(),
+            complete: quote.is_none(),
+        })
+        .collect()
+}
+
+fn users(prefix: &str) -> Vec<Candidate> {
+    let Ok(db) = Database::<PasswdEntry>::load(Path::new(PASSWD_PATH)) else {
+        return Vec::new();
+    };
+    db.entries()
+        .filter(|user| user.name.starts_with(prefix))
+        .map(|user| Candidate { replacement: format!("~{}/", user.name), display: format!("~{}", user.name), complete: false })
+        .collect()
+}
+
+fn commands(prefix: &str, quote_char: Option<char>, context: &Context) -> Vec<Candidate> {
+    let mut names: BTreeSet<String> = context.commands.iter().filter(|name| name.starts_with(prefix)).cloned().collect();
+    for dir in context.path.as_deref().unwrap_or(exec::DEFAULT_PATH).split(':').chain(["/bin"]) {
+        let dir = Path::new(if dir.is_empty() { "." } else { dir });
+        let Ok(entries) = fs::read_dir(dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            if let Some(name) = entry.file_name().to_str()
+                && name.starts_with(prefix)
+                && !names.contains(name)
+                && exec::is_executable(&dir.join(name))
+            {
+                names.insert(name.to_string());
+            }
+        }
+    }
+    names
+        .into_iter()
+        .map(|name| Candidate { replacement: quote(&name, quote_char, true, true), display: name, complete: true })
+        .collect()
+}
+
+/// ファイル名の候補。`tilde` なら単語の先頭の `~user/` をクォートせずに残す。
+fn files(text: &str, tilde: bool, quote_char: Option<char>, context: &Context) -> Vec<Candidate> {
+    let (dir, prefix) = match text.rfind('/') {
+        Some(i) => text.split_at(i + 1),
+        None => ("", text),
+    };
+    // チルダ接頭辞 (先頭から最初の `/` まで) を展開したディレクトリを読む
+    let tilde_len = if tilde && text.starts_with('~') { dir.find('/').map_or(0, |i| i + 1) } else { 0 };
+    let lookup = match tilde_len {
+        0 => dir.to_string(),
+        _ => match expand::home_dir(&dir[1..tilde_len - 1], context.home.as_deref()) {
+            Some(home) => format!("{}/{}", home, &dir[tilde_len..]),
+            None => return Vec::new(),
+        },
+    };
+    let Ok(entries) = fs::read_dir(if lookup.is_empty() { "." } else { &lookup }) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // `.` で始まる名前は、`.` を入力した場合だけ補完する
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_dir = fs::metadata(entry.path()).is_ok_and(|m| m.is_dir());
+            let path = format!("{}{}{}", &dir[tilde_len..], name, if is_dir { "/" } else { "" });
+            Some(Candidate {
+                replacement: format!("{}{}", &dir[..tilde_len], quote(&path, quote_char, !is_dir, tilde_len == 0)),
+                display: format!("{}{}", name, if is_dir { "/" } else { "" }),
+                complete: !is_dir,
+            })
+        })
+        .collect()
+}
+
+/// 候補に共通する先頭部分
+pub fn common_prefix(candidates: &[Candidate]) -> String {
+    let Some((first, rest)) = candidates.split_first() else {
+        return String::new();
+    };
+    let mut len = first.replacement.len();
+    for candidate in rest {
+        len = first.replacement[..len]
+            .char_indices()
+            .zip(candidate.replacement.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(candidate.replacement.len()), |((i, _), _)| i);
+    }
+    first.replacement[..len].to_string()
+}
+
+/// 名前を端末の幅 `width` に収まるように列に並べた行 (上から下へ、左から右へ並べる)
+pub fn columns(names: &[String], width: usize) -> Vec<String> {
+    let column_width = names.iter().map(|name| str_width(name)).max().unwrap_or(0) + 2;
+    let ncolumns = ((width + 2) / column_width).max(1);
+    let nrows = names.len().div_ceil(ncolumns);
+    (0..nrows)
+        .map(|row| {
+            let (mut line, mut line_width) = (String::new(), 0);
+            for (column, name) in names.iter().skip(row).step_by(nrows).enumerate() {
+                if column > 0 {
+                    line.extend(std::iter::repeat_n(' ', column * column_width - line_width));
+                    line_width = column * column_width;
+                }
+                line.push_str(name);
+                line_width += str_width(name);
+            }
+            line
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use horiz_auth::testutil::TempDir;
+
+    use super::*;
+
+    fn replacements(line: &str, context: &Context) -> Vec<String> {
+        complete(line, context).candidates.into_iter().map(|c| c.replacement).collect()
+    }
+
+    #[test]
+    fn test_scan() {
+        let scan_of = |line| {
+            let scan = scan(line);
+            (&line[scan.start..], scan.command, scan.quote)
+        };
+        assert_eq!(scan_of(""), ("", true, None));
+        assert_eq!(scan_of("ec"), ("ec", true, None));
+        assert_eq!(scan_of("echo a"), ("a", false, None));
+        assert_eq!(scan_of("echo "), ("", false, None));
+        assert_eq!(scan_of("a | b"), ("b", true, None));
+        assert_eq!(scan_of("x && A=1 if b"), ("b", true, None));
+        assert_eq!(scan_of(">out c"), ("c", true, None));
+        assert_eq!(scan_of("cat <f"), ("f", false, None));
+        assert_eq!(scan_of("cat 'a b"), ("'a b", false, Some('\'')));
+        assert_eq!(scan_of("cat a\\ \"b;\" c"), ("c", false, None));
+        assert_eq!(scan_of("cat a\\ \"b; c"), ("a\\ \"b; c", false, Some('"')));
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(unquote("a\\ b'c d'\"e\\\"\\f\""), "a bc de\"\\f");
+        assert_eq!(unquote("'it"), "it");
+        assert_eq!(quote("a b$c", None, true, true), "a\\ b\\$c");
+        assert_eq!(quote("~x#", None, true, true), "\\~x#");
+        assert_eq!(quote("it's", Some('\''), true, true), "'it'\\''s'");
+        assert_eq!(quote("a \"$b", Some('"'), false, true), "\"a \\\"\\$b");
+    }
+
+    #[test]
+    fn test_variables() {
+        let context = Context { variables: vec!["HOME".into(), "HOSTNAME".into(), "PATH".into()], ..Default::default() };
+        assert_eq!(replacements("echo $HO", &context), ["$HOME", "$HOSTNAME"]);
+        assert_eq!(replacements("echo a${P", &context), ["a${PATH}"]);
+        assert_eq!(replacements("echo \"$P", &context), ["\"$PATH"]);
+        assert!(replacements("echo '$P", &context).is_empty());
+    }
+
+    #[test]
+    fn test_commands() {
+        let context = Context { commands: vec!["echo".into(), "myfunc".into()], path: Some(String::new()), ..Default::default() };
+        assert_eq!(replacements("my", &context), ["myfunc"]);
+        assert_eq!(replacements("true && ec", &context), ["echo"]);
+        // コマンドの位置ではない単語はファイル名として補完する
+        assert!(replacements("echo myf", &context).is_empty());
+    }
+
+    #[test]
+    fn test_files() {
+        let dir = TempDir::new("sh-complete");
+        fs::create_dir(dir.join("sub dir")).unwrap();
+        for name in ["file one", "file-two", ".hidden"] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let context = Context { home: Some(dir.to_str().unwrap().into()), ..Default::default() };
+        let base = dir.to_str().unwrap();
+
+        assert_eq!(replacements(&format!("cat {}/fi", base), &context), [format!("{}/file\\ one", base), format!("{}/file-two", base)]);
+        assert_eq!(replacements(&format!("cd {}/s", base), &context), [format!("{}/sub\\ dir/", base)]);
+        assert_eq!(replacements(&format!("cat \"{}/file o", base), &context), [format!("\"{}/file one\"", base)]);
+        assert_eq!(replacements(&format!("cat {}/.", base), &context), [format!("{}/.hidden", base)]);
+        assert_eq!(replacements("cat ~/file-", &context), ["~/file-two"]);
+        assert_eq!(replacements("cat ~/'file o", &context), ["~/'file one'"]);
+        assert_eq!(complete(&format!("cat {}/", base), &context).candidates.len(), 3);
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        let candidates = |names: &[&str]| -> Vec<Candidate> {
+            names.iter().map(|n| Candidate { replacement: n.to_string(), display: n.to_string(), complete: true }).collect()
+        };
+        assert_eq!(common_prefix(&candidates(&[])), "");
+        assert_eq!(common_prefix(&candidates(&["file-one", "file-two"])), "file-");
+        assert_eq!(common_prefix(&candidates(&["日本語", "日本"])), "日本");
+        assert_eq!(common_prefix(&candidates(&["あい", "あう"])), "あ");
+    }
+
+    #[test]
+    fn test_columns() {
+        let names: Vec<String> = ["a", "bb", "ccc", "d", "日本"].iter().map(|s| s.to_string()).collect();
+        assert_eq!(columns(&names, 80), ["a     bb    ccc   d     日本"]);
+        assert_eq!(columns(&names, 12), ["a     d", "bb    日本", "ccc"]);
+        assert_eq!(columns(&names, 3), ["a", "bb", "ccc", "d", "日本"]);
+    }
+}
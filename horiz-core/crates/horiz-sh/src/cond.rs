@@ -0,0 +1,250 @@
+// --- 条件式 (`test` / `[`) ---
+//
+// 式の文法 (優先順位の低い順):
+//
+//   expr    := and ( '-o' and )*
+//   and     := not ( '-a' not )*
+//   not     := '!' not | primary
+//   primary := '(' expr ')' | 文字列 二項演算子 文字列 | 単項演算子 文字列 | 文字列
+//
+// 二項演算子の形を単項演算子より先に調べるため、`[ -n = -n ]` や `[ ! = x ]` は文字列の比較になる。
+// 演算子の後に引数がない場合 (`[ -n ]` など) は、演算子そのものを文字列として扱う。
+
+use std::ffi::CString;
+use std::fs::{self, Metadata};
+use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+const UNARY: &[&str] = &[
+    "-b", "-c", "-d", "-e", "-f", "-g", "-h", "-k", "-L", "-n", "-p", "-r", "-s", "-S", "-t", "-u", "-w", "-x", "-z",
+];
+
+const BINARY: &[&str] = &[
+    "=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+/// 式を評価する。式が正しくなければエラーメッセージを返す。
+pub fn evaluate(args: &[&str]) -> Result<bool, String> {
+    if args.is_empty() {
+        return Ok(false);
+    }
+    let mut parser = Parser { args, pos: 0 };
+    let result = parser.or()?;
+    match parser.args.get(parser.pos) {
+        None => Ok(result),
+        Some(arg) => Err(format!("{}: 余分な引数です", arg)),
+    }
+}
+
+struct Parser<'a> {
+    args: &'a [&'a str],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self, offset: usize) -> Option<&'a str> {
+        self.args.get(self.pos + offset).copied()
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let arg = self.peek(0).ok_or_else(|| "引数が足りません".to_string())?;
+        self.pos += 1;
+        Ok(arg)
+    }
+
+    /// 現在の位置から `文字列 二項演算子 文字列` の形になっているか
+    fn at_binary(&self) -> bool {
+        self.peek(1).is_some_and(|op| BINARY.contains(&op)) && self.peek(2).is_some()
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let mut result = self.and()?;
+        while self.peek(0) == Some("-o") {
+            self.pos += 1;
+            // 右辺も評価して、式の誤りを見逃さないようにする
+            result |= self.and()?;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let mut result = self.not()?;
+        while self.peek(0) == Some("-a") {
+            self.pos += 1;
+            result &= self.not()?;
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        if self.peek(0) == Some("!") && self.peek(1).is_some() && !self.at_binary() {
+            self.pos += 1;
+            return Ok(!self.not()?);
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        if self.at_binary() {
+            let left = self.next()?;
+            let op = self.next()?;
+            let right = self.next()?;
+            return binary(left, op, right);
+        }
+        let arg = self.next()?;
+        if arg == "(" {
+            let result = self.or()?;
+            if self.next().ok() != Some(")") {
+                return Err("`)' がありません".to_string());
+            }
+            return Ok(result);
+        }
+        if UNARY.contains(&arg)
+            && let Some(operand) = self.peek(0)
+        {
+            self.pos += 1;
+            return unary(arg, operand);
+        }
+        Ok(!arg.is_empty())
+    }
+}
+
+fn unary(op: &str, operand: &str) -> Result<bool, String> {
+    let result = match op {
+        "-z" => operand.is_empty(),
+        "-n" => !operand.is_empty(),
+        "-t" => {
+            let fd = integer(operand)?;
+            i32::try_from(fd).is_ok_and(|fd| unsafe { libc::isatty(fd) } == 1)
+        }
+        "-r" => access(operand, libc::R_OK),
+        "-w" => access(operand, libc::W_OK),
+        "-x" => access(operand, libc::X_OK),
+        // シンボリックリンクそのものを調べる
+        "-h" | "-L" => fs::symlink_metadata(operand).is_ok_and(|m| m.file_type().is_symlink()),
+        _ => fs::metadata(operand).is_ok_and(|m| file_test(op, &m)),
+    };
+    Ok(result)
+}
+
+/// ファイルの種類・属性を調べる単項演算子 (リンク先を調べる)
+fn file_test(op: &str, metadata: &Metadata) -> bool {
+    let file_type = metadata.file_type();
+    match op {
+        "-e" => true,
+        "-f" => file_type.is_file(),
+        "-d" => file_type.is_dir(),
+        "-b" => file_type.is_block_device(),
+        "-c" => file_type.is_char_device(),
+        "-p" => file_type.is_fifo(),
+        "-S" => file_type.is_socket(),
+        "-s" => metadata.len() > 0,
+        "-u" => metadata.mode() & libc::S_ISUID != 0,
+        "-g" => metadata.mode() & libc::S_ISGID != 0,
+        "-k" => metadata.mode() & libc::S_ISVTX != 0,
+        _ => unreachable!("未知のファイル演算子: {}", op),
+    }
+}
+
+/// 実行中のユーザーの権限でファイルにアクセスできるか
+fn access(path: &str, mode: i32) -> bool {
+    let Ok(path) = CString::new(path) else {
+        return false;
+    };
+    unsafe { libc::access(path.as_ptr(), mode) == 0 }
+}
+
+fn binary(left: &str, op: &str, right: &str) -> Result<bool, String> {
+    let result = match op {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        "-eq" => integer(left)? == integer(right)?,
+        "-ne" => integer(left)? != integer(right)?,
+        "-lt" => integer(left)? < integer(right)?,
+        "-le" => integer(left)? <= integer(right)?,
+        "-gt" => integer(left)? > integer(right)?,
+        "-ge" => integer(left)? >= integer(right)?,
+        "-nt" | "-ot" => {
+            let modified = |path| fs::metadata(path).and_then(|m| m.modified()).ok();
+            // 存在しないファイルは、存在するどのファイルよりも古いものとして扱う
+            match op {
+                "-nt" => modified(left) > modified(right),
+                _ => modified(left) < modified(right),
+            }
+        }
+        "-ef" => match (fs::metadata(left), fs::metadata(right)) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+            _ => false,
+        },
+        _ => unreachable!("未知の二項演算子: {}", op),
+    };
+    Ok(result)
+}
+
+fn integer(arg: &str) -> Result<i64, String> {
+    arg.trim().parse().map_err(|_| format!("{}: 整数を指定してください", arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use horiz_auth::testutil::TempDir;
+
+    use super::*;
+
+    fn test(expr: &str) -> Result<bool, String> {
+        let args: Vec<&str> = expr.split_whitespace().collect();
+        evaluate(&args)
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(test(""), Ok(false));
+        assert_eq!(test("abc"), Ok(true));
+        assert_eq!(test("-n abc"), Ok(true));
+        assert_eq!(test("-z abc"), Ok(false));
+        assert_eq!(test("a = a"), Ok(true));
+        assert_eq!(test("a != a"), Ok(false));
+        assert_eq!(test("a < b"), Ok(true));
+        // 演算子だけの場合は文字列
+        assert_eq!(test("-n"), Ok(true));
+        assert_eq!(test("-z = -z"), Ok(true));
+        assert_eq!(test("! = x"), Ok(false));
+        assert_eq!(test("!"), Ok(true));
+    }
+
+    #[test]
+    fn test_integers() {
+        assert_eq!(test("10 -gt 9"), Ok(true));
+        assert_eq!(test("-1 -lt 0"), Ok(true));
+        assert_eq!(test("3 -eq 3 -a 4 -ne 4"), Ok(false));
+        assert!(test("x -eq 1").is_err());
+    }
+
+    #[test]
+    fn test_logic() {
+        assert_eq!(test("! a = b"), Ok(true));
+        assert_eq!(test("a = b -o c = c"), Ok(true));
+        // -a は -o より先に結び付く
+        assert_eq!(test("a -o b = c -a x = y"), Ok(true));
+        assert_eq!(test("! ( a = a -o b = c )"), Ok(false));
+        assert!(test("( a = a").is_err());
+        assert!(test("a b").is_err());
+    }
+
+    #[test]
+    fn test_files() {
+        let tmp = TempDir::new("sh-cond");
+        let file = tmp.join("file");
+        fs::write(&file, "data").unwrap();
+        let (dir, file) = (tmp.to_str().unwrap(), file.to_str().unwrap());
+
+        assert_eq!(test(&format!("-d {}", dir)), Ok(true));
+        assert_eq!(test(&format!("-f {}", dir)), Ok(false));
+        assert_eq!(test(&format!("-f {} -a -s {}", file, file)), Ok(true));
+        assert_eq!(test(&format!("-e {}/none", dir)), Ok(false));
+        assert_eq!(test(&format!("{} -ef {}", file, file)), Ok(true));
+        assert_eq!(test(&format!("{} -nt {}/none", file, dir)), Ok(true));
+        assert_eq!(test(&format!("{}/none -ot {}", dir, file)), Ok(true));
+    }
+}
@@ -0,0 +1,678 @@
+// --- 行エディタ ---
+//
+// 対話モードで端末から 1 行を読む。端末を raw モードにしてキーを 1 つずつ受け取り、Emacs 風の
+// キー操作で行を編集する。
+//
+//   Ctrl-A / Home, Ctrl-E / End     行頭 / 行末へ移動
+//   Ctrl-B / ←, Ctrl-F / →          1 文字移動
+//   Alt-B / Ctrl-←, Alt-F / Ctrl-→  1 単語移動
+//   Backspace, Ctrl-D / Delete       カーソルの前 / 位置の文字を削除 (空の行での Ctrl-D は EOF)
+//   Ctrl-K, Ctrl-U                   行末まで / 行頭までを削除
+//   Ctrl-W, Alt-D, Alt-Backspace     前の空白区切りの単語 / 次の単語 / 前の単語を削除
+//   Ctrl-Y                           最後に削除した文字列を貼り付ける
+//   Ctrl-T                           カーソルの前後の文字を入れ替える
+//   Ctrl-P / ↑, Ctrl-N / ↓           履歴を辿る
+//   Ctrl-R                           履歴を逆方向にインクリメンタル検索する (Ctrl-G で中止)
+//   Tab                              カーソルの前の単語を補完する (候補が複数なら共通部分まで補完し、
+//                                    それ以上進めない場合は候補を一覧表示する)
+//   Ctrl-L                           画面を消去する
+//   Ctrl-C                           入力中の行を取り消す
+//
+// 行を描き直すときは、プロンプトの先頭の行まで戻って画面の残りを消去し、プロンプトと行全体を
+// 書き直す。端末の幅で折り返した位置を計算するため、各文字の表示幅 (全角文字は 2 桁、結合文字は
+// 0 桁) を数える。
+
+use std::io::{self, Write};
+use std::mem;
+
+use crate::complete::{self, Context};
+use crate::history::History;
+
+/// ESC の後に続くバイトを待つ時間 (ミリ秒)。これを過ぎたら ESC キー単体の入力とみなす。
+const ESCAPE_TIMEOUT_MS: i32 = 50;
+
+/// 端末の幅を取得できない場合の桁数
+const DEFAULT_COLUMNS: usize = 80;
+
+/// 文字の表示幅
+pub fn char_width(c: char) -> usize {
+    match c as u32 {
+        // 制御文字・結合文字・ゼロ幅の文字
+        0..=0x1f
+        | 0x7f..=0x9f
+        | 0x300..=0x36f
+        | 0x483..=0x489
+        | 0x591..=0x5bd
+        | 0x1ab0..=0x1aff
+        | 0x1dc0..=0x1dff
+        | 0x200b..=0x200f
+        | 0x2028..=0x202e
+        | 0x2060..=0x2064
+        | 0x20d0..=0x20ff
+        | 0x3099..=0x309a
+        | 0xfe00..=0xfe0f
+        | 0xfe20..=0xfe2f
+        | 0xfeff
+        | 0xe0100..=0xe01ef => 0,
+        // 全角 (East Asian Width が W または F) の文字
+        0x1100..=0x115f
+        | 0x231a..=0x231b
+        | 0x2329..=0x232a
+        | 0x23e9..=0x23ec
+        | 0x2614..=0x2615
+        | 0x2e80..=0x303e
+        | 0x3041..=0x33ff
+        | 0x3400..=0x4dbf
+        | 0x4e00..=0x9fff
+        | 0xa000..=0xa4cf
+        | 0xa960..=0xa97f
+        | 0xac00..=0xd7a3
+        | 0xf900..=0xfaff
+        | 0xfe10..=0xfe19
+        | 0xfe30..=0xfe6f
+        | 0xff00..=0xff60
+        | 0xffe0..=0xffe6
+        | 0x1f300..=0x1f64f
+        | 0x1f900..=0x1f9ff
+        | 0x20000..=0x2fffd
+        | 0x30000..=0x3fffd => 2,
+        _ => 1,
+    }
+}
+
+/// 文字列の表示幅
+pub fn str_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+/// 文字を書いた後のカーソルの位置 (行, 桁)。行末に収まらない全角文字は次の行に折り返す。
+fn advance((row, col): (usize, usize), c: char, columns: usize) -> (usize, usize) {
+    let width = char_width(c);
+    if col + width > columns { (row + 1, width) } else { (row, col + width) }
+}
+
+/// 行末までちょうど埋まった位置は、次の行の先頭として扱う
+fn wrap((row, col): (usize, usize), columns: usize) -> (usize, usize) {
+    if col >= columns { (row + 1, 0) } else { (row, col) }
+}
+
+/// 編集中の行
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Buffer {
+    chars: Vec<char>,
+    /// カーソルの位置 (文字単位)
+    cursor: usize,
+}
+
+/// Alt-B などで移動する単位の単語の文字
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Ctrl-W で削除する単位の単語の文字
+fn is_not_space(c: char) -> bool {
+    !c.is_whitespace()
+}
+
+impl Buffer {
+    /// カーソルを行末に置く
+    fn new(text: &str) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        Buffer { cursor: chars.len(), chars }
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    fn insert(&mut self, c: char) {
+        self.chars.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    fn insert_str(&mut self, s: &str) {
+        for c in s.chars() {
+            self.insert(c);
+        }
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    fn left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    fn right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.chars.len());
+    }
+
+    /// 前の単語の先頭の位置
+    fn word_start(&self, is_word: fn(char) -> bool) -> usize {
+        let mut i = self.cursor;
+        while i > 0 && !is_word(self.chars[i - 1]) {
+            i -= 1;
+        }
+        while i > 0 && is_word(self.chars[i - 1]) {
+            i -= 1;
+        }
+        i
+    }
+
+    /// 次の単語の末尾の位置
+    fn word_end(&self, is_word: fn(char) -> bool) -> usize {
+        let mut i = self.cursor;
+        while i < self.chars.len() && !is_word(self.chars[i]) {
+            i += 1;
+        }
+        while i < self.chars.len() && is_word(self.chars[i]) {
+            i += 1;
+        }
+        i
+    }
+
+    /// カーソルから `to` までを削除して、削除した文字列を返す
+    fn kill(&mut self, to: usize) -> String {
+        let (start, end) = if to < self.cursor { (to, self.cursor) } else { (self.cursor, to) };
+        self.cursor = start;
+        self.chars.drain(start..end).collect()
+    }
+
+    /// カーソルの前後の文字を入れ替える (行末ではその前の 2 文字を入れ替える)
+    fn transpose(&mut self) {
+        if self.cursor == 0 || self.chars.len() < 2 {
+            return;
+        }
+        if self.cursor == self.chars.len() {
+            self.cursor -= 1;
+        }
+        self.chars.swap(self.cursor - 1, self.cursor);
+        self.cursor += 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    /// Ctrl と英字 (小文字で表す)
+    Ctrl(char),
+    /// Alt (ESC) と文字
+    Alt(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    WordLeft,
+    WordRight,
+    Unknown,
+}
+
+/// 入力のバイト列からキーを 1 つ読む。`next` は次のバイトを返す関数で、引数が true の場合は
+/// 少しだけ待って、届かなければ None を返す。最初のバイトが None (EOF) なら None。
+fn decode(next: &mut impl FnMut(bool) -> Option<u8>) -> Option<Key> {
+    let byte = next(false)?;
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f | 0x08 => Key::Backspace,
+        0x1b => escape(next),
+        0x01..=0x1a => Key::Ctrl(char::from(b'a' + byte - 1)),
+        0x00..=0x1f => Key::Unknown,
+        0x20..=0x7e => Key::Char(char::from(byte)),
+        _ => {
+            let len = match byte {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => return Some(Key::Unknown),
+            };
+            let mut bytes = vec![byte];
+            for _ in 1..len {
+                bytes.push(next(false)?);
+            }
+            match std::str::from_utf8(&bytes) {
+                Ok(s) => s.chars().next().map_or(Key::Unknown, Key::Char),
+                Err(_) => Key::Unknown,
+            }
+        }
+    };
+    Some(key)
+}
+
+/// ESC で始まるキー (矢印キーなどのエスケープシーケンス、Alt と文字)
+fn escape(next: &mut impl FnMut(bool) -> Option<u8>) -> Key {
+    let Some(byte) = next(true) else {
+        return Key::Escape;
+    };
+    if byte != b'[' && byte != b'O' {
+        return match byte {
+            0x7f | 0x08 => Key::Alt('\x7f'),
+            0x20..=0x7e => Key::Alt(char::from(byte).to_ascii_lowercase()),
+            _ => Key::Unknown,
+        };
+    }
+    // CSI / SS3: パラメータ (0x30-0x3f) などの後、0x40-0x7e のバイトで終わる
+    let mut seq = vec![byte];
+    loop {
+        let Some(byte) = next(true) else {
+            return Key::Unknown;
+        };
+        seq.push(byte);
+        if (0x40..=0x7e).contains(&byte) {
+            break;
+        }
+    }
+    match &seq[..] {
+        b"[A" | b"OA" => Key::Up,
+        b"[B" | b"OB" => Key::Down,
+        b"[C" | b"OC" => Key::Right,
+        b"[D" | b"OD" => Key::Left,
+        b"[H" | b"OH" | b"[1~" | b"[7~" => Key::Home,
+        b"[F" | b"OF" | b"[4~" | b"[8~" => Key::End,
+        b"[3~" => Key::Delete,
+        b"[1;5C" | b"[1;3C" => Key::WordRight,
+        b"[1;5D" | b"[1;3D" => Key::WordLeft,
+        _ => Key::Unknown,
+    }
+}
+
+/// 端末から 1 バイト読む。`timeout` が true なら少しだけ待ち、届かなければ None。EOF でも None。
+fn read_byte(timeout: bool) -> io::Result<Option<u8>> {
+    if timeout {
+        let mut fd = libc::pollfd { fd: 0, events: libc::POLLIN, revents: 0 };
+        if unsafe { libc::poll(&mut fd, 1, ESCAPE_TIMEOUT_MS) } <= 0 {
+            return Ok(None);
+        }
+    }
+    loop {
+        let mut byte = 0u8;
+        let n = unsafe { libc::read(0, (&raw mut byte).cast(), 1) };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        }
+        return Ok((n == 1).then_some(byte));
+    }
+}
+
+/// 端末からキーを 1 つ読む。EOF では None。
+fn read_key() -> io::Result<Option<Key>> {
+    let mut error = None;
+    let key = decode(&mut |timeout| read_byte(timeout).unwrap_or_else(|e| {
+        error = Some(e);
+        None
+    }));
+    match error {
+        Some(e) => Err(e),
+        None => Ok(key),
+    }
+}
+
+fn terminal_width() -> usize {
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    if unsafe { libc::ioctl(0, libc::TIOCGWINSZ, &mut size) } == 0 && size.ws_col > 0 {
+        usize::from(size.ws_col)
+    } else {
+        DEFAULT_COLUMNS
+    }
+}
+
+/// 端末を raw モードにし、drop したときに元の設定に戻す
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(0, &mut original) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = original;
+        // Ctrl-C なども文字として受け取り、出力の改行の変換 (OPOST) はそのまま残す
+        raw.c_iflag &= !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
+        raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(0, libc::TCSADRAIN, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(RawMode { original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(0, libc::TCSADRAIN, &self.original) };
+    }
+}
+
+pub struct Editor {
+    history: History,
+    /// 補完に使うシェルの状態 (コマンドを読む前に更新する)
+    pub context: Context,
+    /// 最後に削除した文字列 (Ctrl-Y で貼り付ける)
+    killed: String,
+    /// 前回描いたときのカーソルの行 (プロンプトの先頭の行からの相対位置)
+    row: usize,
+}
+
+impl Editor {
+    pub fn new(history: History) -> Self {
+        Editor { history, context: Context::default(), killed: String::new(), row: 0 }
+    }
+
+    /// プロンプトを表示して 1 行を読む (改行は含まない)。空の行で Ctrl-D を入力した場合や端末の
+    /// EOF では None、Ctrl-C で取り消した場合は `ErrorKind::Interrupted` のエラー。
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let _raw = RawMode::enable()?;
+        self.row = 0;
+        let mut buffer = Buffer::default();
+        // 表示している履歴の番号 (history.len() は入力中の行) と、履歴を辿る前に入力していた行
+        let mut index = self.history.len();
+        let mut current = String::new();
+        // Ctrl-R の検索を終えたキー
+        let mut pending = None;
+        self.refresh(prompt, &buffer)?;
+        loop {
+            let key = match pending.take() {
+                Some(key) => key,
+                None => match read_key()? {
+                    Some(key) => key,
+                    None => {
+                        self.finish(prompt, &mut buffer, "")?;
+                        return Ok(None);
+                    }
+                },
+            };
+            match key {
+                Key::Enter => {
+                    self.finish(prompt, &mut buffer, "")?;
+                    let line = buffer.text();
+                    self.history.add(&line);
+                    return Ok(Some(line));
+                }
+                Key::Ctrl('c') => {
+                    self.finish(prompt, &mut buffer, "^C")?;
+                    return Err(io::ErrorKind::Interrupted.into());
+                }
+                Key::Ctrl('d') if buffer.chars.is_empty() => {
+                    self.finish(prompt, &mut buffer, "")?;
+                    return Ok(None);
+                }
+                Key::Char(c) => buffer.insert(c),
+                Key::Ctrl('a') | Key::Home => buffer.cursor = 0,
+                Key::Ctrl('e') | Key::End => buffer.cursor = buffer.chars.len(),
+                Key::Ctrl('b') | Key::Left => buffer.left(),
+                Key::Ctrl('f') | Key::Right => buffer.right(),
+                Key::Alt('b') | Key::WordLeft => buffer.cursor = buffer.word_start(is_word),
+                Key::Alt('f') | Key::WordRight => buffer.cursor = buffer.word_end(is_word),
+                Key::Backspace => buffer.backspace(),
+                Key::Ctrl('d') | Key::Delete => buffer.delete(),
+                Key::Ctrl('k') => self.killed = buffer.kill(buffer.chars.len()),
+                Key::Ctrl('u') => self.killed = buffer.kill(0),
+                Key::Ctrl('w') => self.killed = buffer.kill(buffer.word_start(is_not_space)),
+                Key::Alt('\x7f') => self.killed = buffer.kill(buffer.word_start(is_word)),
+                Key::Alt('d') => self.killed = buffer.kill(buffer.word_end(is_word)),
+                Key::Ctrl('y') => buffer.insert_str(&self.killed),
+                Key::Ctrl('t') => buffer.transpose(),
+                Key::Ctrl('p') | Key::Up if index > 0 => {
+                    if index == self.history.len() {
+                        current = buffer.text();
+                    }
+                    index -= 1;
+                    buffer = Buffer::new(self.history.get(index).unwrap_or_default());
+                }
+                Key::Ctrl('n') | Key::Down if index < self.history.len() => {
+                    index += 1;
+                    buffer = Buffer::new(self.history.get(index).unwrap_or(&current));
+                }
+                Key::Ctrl('r') => pending = self.search(&mut buffer)?,
+                Key::Tab => self.complete(prompt, &mut buffer)?,
+                Key::Ctrl('l') => {
+                    write_out("\x1b[H\x1b[2J")?;
+                    self.row = 0;
+                }
+                _ => {}
+            }
+            self.refresh(prompt, &buffer)?;
+        }
+    }
+
+    /// Ctrl-R: 入力した文字列を含む行を履歴の新しい方から探す。Ctrl-R をもう一度入力すると、さらに
+    /// 古い行を探す。検索を終えたキー (Enter や矢印キーなど) を返し、見つけた行を編集中の行にする。
+    /// Ctrl-G / Ctrl-C では検索前の行に戻す。
+    fn search(&mut self, buffer: &mut Buffer) -> io::Result<Option<Key>> {
+        let original = buffer.clone();
+        let mut query = String::new();
+        // 見つけた行の番号
+        let mut found = None;
+        let mut failed = false;
+        loop {
+            let prompt = format!("({}reverse-i-search)`{}': ", if failed { "failed " } else { "" }, query);
+            self.refresh(&prompt, buffer)?;
+            let before = match read_key()? {
+                Some(Key::Char(c)) => {
+                    query.push(c);
+                    // 今の行がまだ一致していればそのまま
+                    found.map_or(self.history.len(), |index| index + 1)
+                }
+                Some(Key::Backspace) => {
+                    query.pop();
+                    self.history.len()
+                }
+                Some(Key::Ctrl('r')) if !query.is_empty() => found.unwrap_or(self.history.len()),
+                Some(Key::Ctrl('r')) => continue,
+                Some(Key::Ctrl('g') | Key::Ctrl('c')) | None => {
+                    *buffer = original;
+                    return Ok(None);
+                }
+                key => return Ok(key),
+            };
+            if query.is_empty() {
+                (*buffer, found, failed) = (original.clone(), None, false);
+                continue;
+            }
+            match self.history.search(&query, before) {
+                Some((index, pos)) => {
+                    let entry = self.history.get(index).unwrap_or_default();
+                    *buffer = Buffer::new(entry);
+                    buffer.cursor = entry[..pos].chars().count();
+                    (found, failed) = (Some(index), false);
+                }
+                None => failed = true,
+            }
+        }
+    }
+
+    /// Tab: カーソルの前の単語を補完する
+    fn complete(&mut self, prompt: &str, buffer: &mut Buffer) -> io::Result<()> {
+        let before: String = buffer.chars[..buffer.cursor].iter().collect();
+        let completion = complete::complete(&before, &self.context);
+        let start = before[..completion.start].chars().count();
+        let word = &before[completion.start..];
+        let replace = |buffer: &mut Buffer, replacement: &str| {
+            buffer.kill(start);
+            buffer.insert_str(replacement);
+        };
+        match completion.candidates.as_slice() {
+            [] => write_out("\x07"),
+            [candidate] => {
+                replace(buffer, &candidate.replacement);
+                if candidate.complete {
+                    buffer.insert(' ');
+                }
+                Ok(())
+            }
+            candidates => {
+                let common = complete::common_prefix(candidates);
+                if common.len() > word.len() && common != word {
+                    replace(buffer, &common);
+                    return Ok(());
+                }
+                // 行の下に候補を一覧表示して、プロンプトから描き直す
+                let names: Vec<String> = candidates.iter().map(|c| c.display.clone()).collect();
+                let cursor = buffer.cursor;
+                self.finish(prompt, buffer, "")?;
+                buffer.cursor = cursor;
+                let mut out = String::new();
+                for line in complete::columns(&names, terminal_width()) {
+                    out += &line;
+                    out += "\r\n";
+                }
+                write_out(&out)
+            }
+        }
+    }
+
+    /// プロンプトと行を描き直して、カーソルを編集位置に置く
+    fn refresh(&mut self, prompt: &str, buffer: &Buffer) -> io::Result<()> {
+        let columns = terminal_width();
+        let mut out = String::new();
+        if self.row > 0 {
+            out += &format!("\x1b[{}A", self.row);
+        }
+        out += "\r\x1b[J";
+        out += prompt;
+        out.extend(&buffer.chars);
+
+        let start = prompt.chars().fold((0, 0), |pos, c| advance(pos, c, columns));
+        let cursor = buffer.chars[..buffer.cursor].iter().fold(start, |pos, &c| advance(pos, c, columns));
+        let end = buffer.chars[buffer.cursor..].iter().fold(cursor, |pos, &c| advance(pos, c, columns));
+        let (cursor, end) = (wrap(cursor, columns), wrap(end, columns));
+        // 行末までちょうど埋まった場合、端末のカーソルは最後の桁に留まるため次の行へ進めておく
+        if end.0 > 0 && end.1 == 0 {
+            out += "\r\n";
+        }
+        if end.0 > cursor.0 {
+            out += &format!("\x1b[{}A", end.0 - cursor.0);
+        }
+        out += "\r";
+        if cursor.1 > 0 {
+            out += &format!("\x1b[{}C", cursor.1);
+        }
+        self.row = cursor.0;
+        write_out(&out)
+    }
+
+    /// カーソルを行末に移して `suffix` と改行を書く
+    fn finish(&mut self, prompt: &str, buffer: &mut Buffer, suffix: &str) -> io::Result<()> {
+        buffer.cursor = buffer.chars.len();
+        self.refresh(prompt, buffer)?;
+        self.row = 0;
+        write_out(&format!("{}\r\n", suffix))
+    }
+}
+
+fn write_out(s: &str) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(s.as_bytes())?;
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(bytes: &[u8]) -> Vec<Key> {
+        let mut iter = bytes.iter().copied();
+        let mut keys = Vec::new();
+        while let Some(key) = decode(&mut |_| iter.next()) {
+            keys.push(key);
+        }
+        keys
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(keys(b"a\x01\r\x7f\t"), [Key::Char('a'), Key::Ctrl('a'), Key::Enter, Key::Backspace, Key::Tab]);
+        assert_eq!(keys("あ😀".as_bytes()), [Key::Char('あ'), Key::Char('😀')]);
+        assert_eq!(
+            keys(b"\x1b[A\x1bOB\x1b[3~\x1b[1;5D\x1bb\x1bF\x1b\x7f"),
+            [Key::Up, Key::Down, Key::Delete, Key::WordLeft, Key::Alt('b'), Key::Alt('f'), Key::Alt('\x7f')]
+        );
+        // ESC の後に何も届かない
+        assert_eq!(keys(b"\x1b"), [Key::Escape]);
+        assert_eq!(keys(b"\x1b[99x\xff"), [Key::Unknown, Key::Unknown]);
+    }
+
+    #[test]
+    fn test_width() {
+        assert_eq!(str_width("abc"), 3);
+        assert_eq!(str_width("日本語"), 6);
+        assert_eq!(str_width("ｱｲｳ"), 3);
+        assert_eq!(str_width("が"), 2);
+        // 結合文字 (か + 濁点)
+        assert_eq!(str_width("か\u{3099}"), 2);
+        assert_eq!(str_width("e\u{301}"), 1);
+    }
+
+    #[test]
+    fn test_layout() {
+        let layout = |s: &str, columns| wrap(s.chars().fold((0, 0), |pos, c| advance(pos, c, columns)), columns);
+        assert_eq!(layout("abc", 10), (0, 3));
+        assert_eq!(layout("abcde", 5), (1, 0));
+        assert_eq!(layout("abcdef", 5), (1, 1));
+        // 行末に収まらない全角文字は次の行へ
+        assert_eq!(layout("abcdあ", 5), (1, 2));
+        assert_eq!(layout("abcあ", 5), (1, 0));
+    }
+
+    #[test]
+    fn test_buffer() {
+        let mut buffer = Buffer::new("echo あいう");
+        buffer.backspace();
+        buffer.left();
+        buffer.insert('x');
+        assert_eq!((buffer.text().as_str(), buffer.cursor), ("echo あxい", 7));
+        buffer.cursor = 0;
+        buffer.delete();
+        buffer.right();
+        assert_eq!((buffer.text().as_str(), buffer.cursor), ("cho あxい", 1));
+
+        let mut buffer = Buffer::new("ab");
+        buffer.transpose();
+        assert_eq!(buffer.text(), "ba");
+        buffer.cursor = 1;
+        buffer.transpose();
+        assert_eq!((buffer.text().as_str(), buffer.cursor), ("ab", 2));
+    }
+
+    #[test]
+    fn test_words() {
+        let mut buffer = Buffer::new("ls -l /usr/local_bin");
+        assert_eq!(buffer.word_start(is_word), 11);
+        assert_eq!(buffer.word_start(is_not_space), 6);
+        assert_eq!(buffer.kill(buffer.word_start(is_not_space)), "/usr/local_bin");
+        assert_eq!(buffer.text(), "ls -l ");
+
+        buffer.cursor = 0;
+        assert_eq!(buffer.word_end(is_word), 2);
+        buffer.cursor = 2;
+        assert_eq!(buffer.word_end(is_word), 5);
+        assert_eq!(buffer.kill(buffer.chars.len()), " -l ");
+        buffer.cursor = 0;
+        assert_eq!(buffer.kill(buffer.word_end(is_word)), "ls");
+        assert_eq!((buffer.text().as_str(), buffer.cursor), ("", 0));
+    }
+}
@@ -0,0 +1,1557 @@
+// --- コマンドの実行 ---
+//
+// 外部コマンドは fork / exec で実行する。パイプラインの各段は pipe でつなぐ。対話モードで端末を
+// 操作できる場合 (ジョブ制御) は、最初の段の PID をプロセスグループ ID とする 1 つのプロセスグループに
+// まとめ、実行中のプロセスグループに端末を渡し (tcsetpgrp)、終了か停止 (Ctrl-Z) の後にシェルへ戻す。
+// それ以外 (スクリプトやサブシェルの中) では、シェルと同じプロセスグループのまま実行する。
+//
+// 対話モードのシェルは SIGQUIT を、ジョブ制御ではさらに SIGTSTP・SIGTTIN・SIGTTOU を無視する。SIGINT は
+// フラグを立てるだけのハンドラで受け取る。Ctrl-C などは端末からフォアグラウンドのプロセスグループに届くので、
+// シェル自身は終了しない。子プロセスでは扱いを変えたシグナルを既定に戻す。
+//
+// 対話モードでは、Ctrl-C でフォアグラウンドのジョブが終了した場合やシェル自身が SIGINT を受け取った場合、
+// 並びの各要素の後とループの各繰り返しの前に Interrupt::Cancel を発生させ、プロンプトまで実行を打ち切る
+// (`while true; do sleep 1; done` や `while :; do :; done` も Ctrl-C で止められる)。
+//
+// `&` で終わる and-or リストは終了を待たずにジョブとして記録する。パイプライン 1 つならその各段を、
+// `&&` `||` を含む場合は全体を 1 つの子プロセスで実行する。ジョブ制御が無効な場合、バックグラウンドの
+// ジョブは SIGINT と SIGQUIT を無視し、標準入力を /dev/null から読む。
+//
+// 組み込みコマンドと `{ ...; }` は、単独で実行する場合はシェル自身のプロセスで実行する (cd や exit が
+// シェルに作用するように)。パイプラインの一部として実行する場合と `( ... )` は、子プロセスで実行する。
+// リダイレクトは子プロセスではパイプの接続の後に適用し、シェル自身で実行する場合は実行後に元へ戻す。
+// コマンド名の前の代入 (`FOO=1 cmd`) は、外部コマンドでは子プロセスの中でエクスポートして環境として渡し、
+// 組み込みコマンドと関数では実行後に元へ戻す。
+//
+// break / continue / return / exit は Interrupt として呼び出し元へ伝え、ループや関数の呼び出しで受け止める。
+// コマンド名は、特殊組み込みコマンド・関数・その他の組み込みコマンド・外部コマンドの順に探す。
+//
+// コマンド置換 (`$(...)`) は子プロセスで実行し、標準出力をパイプで読み取る。コマンド名のない単純コマンド
+// (`x=$(cmd)`) の終了ステータスは、最後のコマンド置換のものになる。
+//
+// `set -e` では、条件として使われていないパイプライン (if / while の条件、`&&` `||` の左側、`!` の付いた
+// もの以外) が失敗した時点でシェルを終了する。非対話モードでは展開の失敗でもシェルを終了する。
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::ops::ControlFlow;
+use std::os::fd::{FromRawFd, IntoRawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::ast::{
+    AndOr, Assignment, CaseItem, Command, CompoundCommand, Connector, List, Pipeline, Program, Redirect, SimpleCommand,
+};
+use crate::builtins::{self, Builtin};
+use crate::expand::{self, ExpandError};
+use crate::jobs::{self, Job, Jobs, State};
+use crate::lexer::Word;
+use crate::pattern;
+use crate::redirect;
+use crate::source::{self, Input};
+use crate::vars::{self, ReadonlyError, Variables};
+
+/// PATH が設定されていない場合の検索パス
+pub const DEFAULT_PATH: &str = "/bin:/usr/bin";
+
+/// 関数の呼び出しの深さの上限 (無限の再帰でスタックを使い切らないようにする)
+const MAX_FUNCTION_DEPTH: usize = 500;
+
+/// `set -o` で切り替えられるオプションの名前と、1 文字の形式 (`set -e` など)
+pub const OPTIONS: &[(&str, Option<char>)] =
+    &[("errexit", Some('e')), ("noexec", Some('n')), ("noglob", Some('f')), ("nounset", Some('u')), ("pipefail", None), ("xtrace", Some('x'))];
+
+/// 終了ステータス以外の理由で実行を中断する
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    /// `exit` によるシェルの終了
+    Exit(i32),
+    /// `break n` (残りの段数)
+    Break(usize),
+    /// `continue n` (残りの段数)
+    Continue(usize),
+    /// `return` による関数の終了
+    Return(i32),
+    /// Ctrl-C (SIGINT) による中断。対話モードではプロンプトへ戻る。
+    Cancel,
+}
+
+/// SIGINT を受け取った (または Ctrl-C でフォアグラウンドのジョブが終了した) がまだ処理していない
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_sigint(_sig: i32) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// 処理していない SIGINT があるか (`wait` などで待つのをやめる)
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// 処理していない SIGINT を取り消す (新しいコマンドを読んだとき)
+pub fn clear_interrupt() {
+    INTERRUPTED.store(false, Ordering::Relaxed);
+}
+
+pub type ExecResult = Result<i32, Interrupt>;
+
+pub struct Shell {
+    /// 直前のパイプラインの終了ステータス (`$?`)
+    pub last_status: i32,
+    /// `set -o pipefail`: パイプラインのステータスを、0 以外で終了した最も右の段のものにする
+    pub pipefail: bool,
+    /// `set -e`: 失敗したコマンドがあればシェルを終了する
+    pub errexit: bool,
+    /// `set -n`: コマンドを読んで構文を検査するだけで実行しない (非対話モードのみ)
+    pub noexec: bool,
+    /// `set -f`: パス名展開を行わない
+    pub noglob: bool,
+    /// `set -u`: 設定されていないパラメータの展開をエラーにする
+    pub nounset: bool,
+    /// `set -x`: 実行するコマンドを標準エラー出力に表示する
+    pub xtrace: bool,
+    /// 対話モード (プロンプトを表示し、エラーでシェルを終了しない)
+    pub interactive: bool,
+    pub vars: Variables,
+    /// 位置パラメータ (`$1` 以降)
+    pub positional: Vec<String>,
+    /// シェルまたはスクリプトの名前 (`$0`)
+    pub name: String,
+    /// 最後にバックグラウンドで起動したプロセスの PID (`$!`)
+    pub last_background: Option<i32>,
+    /// 定義された関数
+    pub functions: HashMap<String, Rc<Command>>,
+    /// 実行中のループの深さ (break / continue の対象があるか)
+    pub loop_depth: usize,
+    /// 実行中の関数の呼び出しの深さ (return できるか)
+    pub function_depth: usize,
+    /// 実行中の `.` の深さ (return できるか)
+    pub source_depth: usize,
+    /// 条件として実行している深さ (0 より大きければ `set -e` で終了しない)
+    condition_depth: usize,
+    /// 実行中の単純コマンドの展開で最後に行ったコマンド置換の終了ステータス
+    substitution_status: Option<i32>,
+    /// シェルのプロセス ID (`$$`。パイプラインの中の子プロセスでも変わらない)
+    pid: i32,
+    /// バックグラウンドのジョブと停止したジョブ
+    pub jobs: Jobs,
+    /// ジョブ制御が有効な場合、その端末 (標準入力)
+    terminal: Option<i32>,
+    /// シェルの端末の設定 (停止したジョブが変更したものを戻す)
+    modes: Option<libc::termios>,
+    /// 起動時のプロセスグループ (終了時に端末を戻す)
+    original_pgrp: i32,
+    /// シェルが扱いを変えたシグナル (子プロセスでは既定に戻す)
+    signals: Vec<i32>,
+}
+
+impl Shell {
+    pub fn new(interactive: bool) -> Self {
+        let mut shell = Shell {
+            last_status: 0,
+            pipefail: false,
+            errexit: false,
+            noexec: false,
+            noglob: false,
+            nounset: false,
+            xtrace: false,
+            interactive,
+            vars: Variables::from_env(),
+            positional: Vec::new(),
+            name: "horiz-sh".to_string(),
+            last_background: None,
+            functions: HashMap::new(),
+            loop_depth: 0,
+            function_depth: 0,
+            source_depth: 0,
+            condition_depth: 0,
+            substitution_status: None,
+            pid: unsafe { libc::getpid() },
+            jobs: Jobs::default(),
+            terminal: None,
+            modes: None,
+            original_pgrp: unsafe { libc::getpgrp() },
+            signals: Vec::new(),
+        };
+        if interactive {
+            shell.catch_sigint();
+            shell.ignore_signals(&[libc::SIGQUIT]);
+            if unsafe { libc::isatty(0) } == 1 {
+                shell.enable_job_control(0);
+            }
+        }
+        shell
+    }
+
+    fn ignore_signals(&mut self, signals: &[i32]) {
+        for &sig in signals {
+            unsafe { libc::signal(sig, libc::SIG_IGN) };
+            self.signals.push(sig);
+        }
+    }
+
+    /// SIGINT でシェルを終了せず、フラグを立てる。SA_RESTART を付けないので、`wait` の待機は中断される。
+    fn catch_sigint(&mut self) {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_sigint as extern "C" fn(i32) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+        }
+        self.signals.push(libc::SIGINT);
+    }
+
+    /// 対話モードで Ctrl-C が押されていれば、実行中のコマンドを打ち切る
+    fn check_interrupt(&self) -> Result<(), Interrupt> {
+        if self.interactive && INTERRUPTED.swap(false, Ordering::Relaxed) {
+            return Err(Interrupt::Cancel);
+        }
+        Ok(())
+    }
+
+    /// 端末のフォアグラウンドになるまで待ち、シェル自身のプロセスグループを作って端末を受け取る
+    fn enable_job_control(&mut self, fd: i32) {
+        loop {
+            let pgrp = unsafe { libc::getpgrp() };
+            if unsafe { libc::tcgetpgrp(fd) } == pgrp {
+                break;
+            }
+            // バックグラウンドで起動された場合は、フォアグラウンドにされるまで停止する
+            unsafe { libc::kill(-pgrp, libc::SIGTTIN) };
+        }
+        self.ignore_signals(&[libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU]);
+        unsafe {
+            // すでにプロセスグループのリーダー (セッションリーダーなど) であれば失敗するが、それでよい
+            libc::setpgid(0, 0);
+            libc::tcsetpgrp(fd, libc::getpgrp());
+        }
+        self.terminal = Some(fd);
+        self.modes = get_modes(fd);
+    }
+
+    pub fn job_control(&self) -> bool {
+        self.terminal.is_some()
+    }
+
+    /// シェルの終了時に、端末を起動時のプロセスグループへ戻す
+    pub fn release_terminal(&self) {
+        if self.terminal.is_some() && self.original_pgrp != unsafe { libc::getpgrp() } {
+            unsafe { libc::setpgid(0, self.original_pgrp) };
+            self.give_terminal(self.original_pgrp);
+        }
+    }
+
+    /// 名前で指定したオプション (`set -o name`)
+    pub fn option_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "errexit" => Some(&mut self.errexit),
+            "noexec" => Some(&mut self.noexec),
+            "noglob" => Some(&mut self.noglob),
+            "nounset" => Some(&mut self.nounset),
+            "pipefail" => Some(&mut self.pipefail),
+            "xtrace" => Some(&mut self.xtrace),
+            _ => None,
+        }
+    }
+
+    /// 1 文字の形式で指定したオプション (`set -e` の `e`)
+    pub fn flag_mut(&mut self, flag: char) -> Option<&mut bool> {
+        let (name, _) = OPTIONS.iter().find(|(_, f)| *f == Some(flag))?;
+        self.option_mut(name)
+    }
+
+    /// パラメータの値 (特殊パラメータ・位置パラメータ・変数)。未設定なら None。
+    pub fn param(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.last_status.to_string()),
+            "$" => Some(self.pid.to_string()),
+            "!" => self.last_background.map(|pid| pid.to_string()),
+            "#" => Some(self.positional.len().to_string()),
+            "0" => Some(self.name.clone()),
+            "-" => {
+                let flags = [('e', self.errexit), ('f', self.noglob), ('n', self.noexec), ('u', self.nounset), ('x', self.xtrace), ('i', self.interactive)];
+                Some(flags.iter().filter(|(_, on)| *on).map(|(flag, _)| flag).collect())
+            }
+            "@" => Some(self.positional.join(" ")),
+            "*" => {
+                // IFS の最初の文字でつなぐ (IFS が空なら区切らない)
+                let separator = self.vars.get("IFS").map_or(Some(' '), |ifs| ifs.chars().next());
+                Some(self.positional.join(&separator.map(String::from).unwrap_or_default()))
+            }
+            _ if name.bytes().all(|b| b.is_ascii_digit()) => {
+                let n: usize = name.parse().ok()?;
+                self.positional.get(n.checked_sub(1)?).cloned()
+            }
+            _ => self.vars.get(name).map(String::from),
+        }
+    }
+
+    /// 代入を順に行う。`export` が真の場合はエクスポートもする (外部コマンドを実行する子プロセスの中)。
+    fn assign(&mut self, assignments: &[Assignment], export: bool) -> Result<(), String> {
+        for assignment in assignments {
+            let value = expand::expand_assignment(self, &assignment.value).map_err(|e| e.to_string())?;
+            self.trace(|| format!("{}={}", assignment.name, vars::quote_if_needed(&value)));
+            self.vars.set(&assignment.name, &value).map_err(|e| e.to_string())?;
+            if export {
+                self.vars.export(&assignment.name);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn run_program(&mut self, program: &Program) -> ExecResult {
+        self.run_list(program)
+    }
+
+    /// 並びを順に実行し、最後のステータスを返す (各要素の終了時に `$?` を更新する)
+    fn run_list(&mut self, list: &List) -> ExecResult {
+        for and_or in list {
+            self.last_status = match and_or.background {
+                true => self.run_background(and_or),
+                false => self.run_and_or(and_or)?,
+            };
+            self.check_interrupt()?;
+        }
+        Ok(self.last_status)
+    }
+
+    fn run_and_or(&mut self, and_or: &AndOr) -> ExecResult {
+        let mut status = self.run_pipeline(&and_or.first, !and_or.rest.is_empty())?;
+        for (i, (connector, pipeline)) in and_or.rest.iter().enumerate() {
+            self.last_status = status;
+            let run = match connector {
+                Connector::And => status == 0,
+                Connector::Or => status != 0,
+            };
+            if run {
+                status = self.run_pipeline(pipeline, i + 1 < and_or.rest.len())?;
+            }
+        }
+        Ok(status)
+    }
+
+    /// `&` で終わる and-or リストを子プロセスで起動し、ジョブとして記録する (終了は待たない)
+    fn run_background(&mut self, and_or: &AndOr) -> i32 {
+        let stages: Vec<Stage> = match and_or.rest.is_empty() {
+            true => and_or.first.commands.iter().map(Stage::Command).collect(),
+            false => vec![Stage::AndOr(and_or)],
+        };
+        let Some(job) = self.start(&stages, true) else {
+            return 1;
+        };
+        self.last_background = Some(job.last_pid());
+        let pid = job.last_pid();
+        let id = self.jobs.add(job);
+        if self.interactive {
+            eprintln!("[{}] {}", id, pid);
+        }
+        0
+    }
+
+    /// `tested` は `&&` `||` の左側 (ステータスを条件として使う) かどうか
+    fn run_pipeline(&mut self, pipeline: &Pipeline, tested: bool) -> ExecResult {
+        let tested = tested || pipeline.negate;
+        let status = match tested {
+            true => self.run_condition(|shell| shell.run_commands(&pipeline.commands))?,
+            false => self.run_commands(&pipeline.commands)?,
+        };
+        if status != 0 && self.errexit && self.condition_depth == 0 && !tested {
+            return Err(Interrupt::Exit(status));
+        }
+        Ok(if pipeline.negate { (status == 0) as i32 } else { status })
+    }
+
+    /// ステータスを条件として使うコマンドを実行する (`set -e` の対象にしない)
+    fn run_condition(&mut self, run: impl FnOnce(&mut Self) -> ExecResult) -> ExecResult {
+        self.condition_depth += 1;
+        let result = run(self);
+        self.condition_depth -= 1;
+        result
+    }
+
+    /// 展開の失敗を表示する。非対話モードではシェル (サブシェルの中ではサブシェル) を終了する。
+    fn expansion_failed(&self, e: ExpandError) -> ExecResult {
+        eprintln!("horiz-sh: {}", e);
+        if self.interactive { Ok(1) } else { Err(Interrupt::Exit(1)) }
+    }
+
+    /// 代入の失敗 (読み取り専用の変数への代入など) を表示する。非対話モードではシェルを終了する。
+    fn assignment_failed(&self, e: String) -> ExecResult {
+        eprintln!("horiz-sh: {}", e);
+        if self.interactive { Ok(1) } else { Err(Interrupt::Exit(1)) }
+    }
+
+    /// `set -x` の表示 (PS4 に続けて、展開後のコマンドを表示する)
+    fn trace(&self, line: impl FnOnce() -> String) {
+        if self.xtrace {
+            // 標準エラー出力が閉じられていても (`2>&-`) 実行を続ける
+            let _ = writeln!(io::stderr().lock(), "{}{}", self.vars.get("PS4").unwrap_or("+ "), line());
+        }
+    }
+
+    fn run_commands(&mut self, commands: &[Command]) -> ExecResult {
+        match commands {
+            [Command::Simple(simple)] => {
+                self.substitution_status = None;
+                let argv = match expand::expand_words(self, &simple.words) {
+                    Ok(argv) => argv,
+                    Err(e) => return self.expansion_failed(e),
+                };
+                let callee = match argv.first() {
+                    // コマンド名がない場合は代入とリダイレクトだけを行う (`> file` でファイルを空にするなど)
+                    None => None,
+                    Some(name) => match self.lookup(name) {
+                        Some(callee) => Some(callee),
+                        None => {
+                            // 代入は子プロセスの中で行うため、読み取り専用の変数はここで確かめる
+                            let readonly = simple.assignments.iter().find(|a| self.vars.var(&a.name).is_some_and(|v| v.readonly));
+                            if let Some(assignment) = readonly {
+                                return self.assignment_failed(ReadonlyError(assignment.name.clone()).to_string());
+                            }
+                            return Ok(self.spawn(&[Stage::Exec(argv, simple)]));
+                        }
+                    },
+                };
+                self.run_in_shell(simple, &argv, callee)
+            }
+            [Command::FunctionDef { name, body }] => {
+                self.functions.insert(name.clone(), body.clone());
+                Ok(0)
+            }
+            [Command::Compound(compound, redirects)] if !matches!(compound, CompoundCommand::Subshell(_)) => {
+                self.with_redirects(redirects, |shell| shell.run_compound(compound))
+            }
+            _ => {
+                let stages: Vec<Stage> = commands.iter().map(Stage::Command).collect();
+                Ok(self.spawn(&stages))
+            }
+        }
+    }
+
+    /// コマンド名を特殊組み込みコマンド・関数・その他の組み込みコマンドの順に探す
+    fn lookup(&self, name: &str) -> Option<Callee> {
+        let builtin = builtins::lookup(name).map(Callee::Builtin);
+        if builtins::is_special(name) {
+            return builtin;
+        }
+        self.functions.get(name).map(|body| Callee::Function(body.clone())).or(builtin)
+    }
+
+    /// リダイレクトを適用して実行し、終了後に元へ戻す
+    fn with_redirects(&mut self, redirects: &[Redirect], run: impl FnOnce(&mut Self) -> ExecResult) -> ExecResult {
+        flush_stdio();
+        let saved = match redirect::apply(self, redirects, true) {
+            Ok(saved) => saved,
+            Err(e) => {
+                eprintln!("horiz-sh: {}", e);
+                return Ok(1);
+            }
+        };
+        let status = run(self);
+        flush_stdio();
+        saved.restore();
+        status
+    }
+
+    /// 複合コマンドの本体を実行する (サブシェルはすでに子プロセスの中にいる場合だけ呼ばれる)
+    fn run_compound(&mut self, compound: &CompoundCommand) -> ExecResult {
+        match compound {
+            CompoundCommand::Group(list) | CompoundCommand::Subshell(list) => self.run_list(list),
+            CompoundCommand::If { branches, otherwise } => {
+                for (condition, body) in branches {
+                    if self.run_condition(|shell| shell.run_list(condition))? == 0 {
+                        return self.run_list(body);
+                    }
+                }
+                match otherwise {
+                    Some(body) => self.run_list(body),
+                    None => Ok(0),
+                }
+            }
+            CompoundCommand::Loop { until, condition, body } => self.in_loop(|shell| {
+                let mut status = 0;
+                // 条件の中の break はループを抜ける
+                loop {
+                    shell.check_interrupt()?;
+                    let ControlFlow::Continue(tested) = loop_flow(shell.run_condition(|s| s.run_list(condition)))? else {
+                        break;
+                    };
+                    if (tested == 0) == *until {
+                        break;
+                    }
+                    match loop_flow(shell.run_list(body))? {
+                        ControlFlow::Continue(s) => status = s,
+                        ControlFlow::Break(s) => {
+                            status = s;
+                            break;
+                        }
+                    }
+                }
+                Ok(status)
+            }),
+            CompoundCommand::For { name, words, body } => {
+                let values = match words {
+                    Some(words) => match expand::expand_words(self, words) {
+                        Ok(values) => values,
+                        Err(e) => return self.expansion_failed(e),
+                    },
+                    None => self.positional.clone(),
+                };
+                self.in_loop(|shell| {
+                    let mut status = 0;
+                    for value in values {
+                        shell.check_interrupt()?;
+                        if let Err(e) = shell.vars.set(name, &value) {
+                            eprintln!("horiz-sh: {}", e);
+                            return Ok(1);
+                        }
+                        match loop_flow(shell.run_list(body))? {
+                            ControlFlow::Continue(s) => status = s,
+                            ControlFlow::Break(s) => {
+                                status = s;
+                                break;
+                            }
+                        }
+                    }
+                    Ok(status)
+                })
+            }
+            CompoundCommand::Case { word, items } => self.run_case(word, items),
+        }
+    }
+
+    fn in_loop(&mut self, run: impl FnOnce(&mut Self) -> ExecResult) -> ExecResult {
+        self.loop_depth += 1;
+        let result = run(self);
+        self.loop_depth -= 1;
+        result
+    }
+
+    fn run_case(&mut self, word: &Word, items: &[CaseItem]) -> ExecResult {
+        let expanded = expand::expand_word(self, word);
+        let value = match expanded {
+            Ok(value) => value,
+            Err(e) => return self.expansion_failed(e),
+        };
+        for item in items {
+            for pattern in &item.patterns {
+                let pattern = match expand::expand_pattern(self, pattern) {
+                    Ok(pattern) => pattern,
+                    Err(e) => return self.expansion_failed(e),
+                };
+                if pattern::matches(&pattern, &value) {
+                    return self.run_list(&item.body);
+                }
+            }
+        }
+        Ok(0)
+    }
+
+    /// 関数を呼び出す。位置パラメータは呼び出しの間だけ引数に置き換える。
+    fn call_function(&mut self, body: &Command, argv: &[String]) -> ExecResult {
+        if self.function_depth >= MAX_FUNCTION_DEPTH {
+            eprintln!("horiz-sh: {}: 関数の呼び出しが深すぎます", argv[0]);
+            return Ok(1);
+        }
+        let positional = std::mem::replace(&mut self.positional, argv[1..].to_vec());
+        // 呼び出し元のループを関数の中から break することはできない
+        let loop_depth = std::mem::replace(&mut self.loop_depth, 0);
+        self.function_depth += 1;
+        let result = self.run_commands(std::slice::from_ref(body));
+        self.function_depth -= 1;
+        self.loop_depth = loop_depth;
+        self.positional = positional;
+        match result {
+            Err(Interrupt::Return(status)) => Ok(status),
+            result => result,
+        }
+    }
+
+    /// 組み込みコマンド・関数 (またはコマンド名のない単純コマンド) をシェル自身で実行する
+    fn run_in_shell(&mut self, simple: &SimpleCommand, argv: &[String], callee: Option<Callee>) -> ExecResult {
+        self.with_redirects(&simple.redirects, |shell| {
+            // 代入は実行後に元へ戻す (コマンド名がない場合と特殊組み込みコマンドはそのまま残す)
+            let temporary = callee.is_some() && !builtins::is_special(&argv[0]);
+            let previous: Vec<_> = match temporary {
+                true => simple.assignments.iter().map(|a| (a.name.clone(), shell.vars.var(&a.name).cloned())).collect(),
+                false => Vec::new(),
+            };
+            let status = match shell.assign(&simple.assignments, false) {
+                Ok(()) => match callee {
+                    None => Ok(shell.substitution_status.unwrap_or(0)),
+                    Some(Callee::Builtin(builtin)) => {
+                        shell.trace(|| quote_words(argv));
+                        builtin(shell, argv)
+                    }
+                    Some(Callee::Function(body)) => {
+                        shell.trace(|| quote_words(argv));
+                        shell.call_function(&body, argv)
+                    }
+                },
+                Err(e) => shell.assignment_failed(e),
+            };
+            for (name, var) in previous.into_iter().rev() {
+                shell.vars.restore(&name, var);
+            }
+            status
+        })
+    }
+
+    /// 各段を子プロセスとして起動し、すべての終了 (または停止) を待つ
+    fn spawn(&mut self, stages: &[Stage]) -> i32 {
+        let Some(job) = self.start(stages, false) else {
+            return 1;
+        };
+        // 途中の段で失敗した場合 (パイプの作成など) も 0 にはしない
+        let complete = job.processes.len() == stages.len();
+        let status = self.wait_foreground(job);
+        if !complete && status == 0 { 1 } else { status }
+    }
+
+    /// 各段を子プロセスとして起動する。1 つも起動できなければ None。
+    fn start(&mut self, stages: &[Stage], background: bool) -> Option<Job> {
+        flush_stdio();
+        let job_control = self.terminal.is_some();
+        let mut pgid = 0;
+        let mut pids = Vec::new();
+        let mut input: Option<i32> = None;
+        for (i, stage) in stages.iter().enumerate() {
+            let pipe = if i + 1 < stages.len() {
+                match make_pipe() {
+                    Ok(fds) => Some(fds),
+                    Err(e) => {
+                        eprintln!("horiz-sh: パイプを作成できません: {}", e);
+                        close_fd(input);
+                        break;
+                    }
+                }
+            } else {
+                None
+            };
+
+            let pid = unsafe { libc::fork() };
+            if pid == 0 {
+                if job_control {
+                    unsafe { libc::setpgid(0, pgid) };
+                }
+                // 子プロセスの中で起動するパイプラインは、このプロセスグループのまま実行する
+                self.terminal = None;
+                self.jobs = Jobs::default();
+                reset_signals(&self.signals);
+                self.signals.clear();
+                if background && !job_control {
+                    // 端末からのシグナルと入力は、フォアグラウンドのコマンドだけが受け取る
+                    unsafe {
+                        libc::signal(libc::SIGINT, libc::SIG_IGN);
+                        libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+                    }
+                    if input.is_none()
+                        && let Ok(null) = File::open("/dev/null")
+                    {
+                        redirect_fd(null.into_raw_fd(), 0);
+                    }
+                }
+                if let Some(fd) = input {
+                    redirect_fd(fd, 0);
+                }
+                if let Some((read, write)) = pipe {
+                    close_fd(Some(read));
+                    redirect_fd(write, 1);
+                }
+                let status = self.run_stage(stage);
+                exit_child(status);
+            }
+            if pid < 0 {
+                eprintln!("horiz-sh: fork に失敗しました: {}", io::Error::last_os_error());
+                close_fd(input);
+                if let Some((read, write)) = pipe {
+                    close_fd(Some(read));
+                    close_fd(Some(write));
+                }
+                break;
+            }
+            if job_control {
+                if pgid == 0 {
+                    pgid = pid;
+                }
+                // 子プロセス側と同じ設定を親でも行い、どちらが先に実行されても競合しないようにする
+                unsafe { libc::setpgid(pid, pgid) };
+            }
+            pids.push(pid);
+            close_fd(input);
+            input = pipe.map(|(read, write)| {
+                close_fd(Some(write));
+                read
+            });
+        }
+        if pids.is_empty() {
+            return None;
+        }
+        let command = stages.iter().map(Stage::describe).collect::<Vec<_>>().join(" | ");
+        Some(Job::new(job_control.then_some(pgid), &pids, command))
+    }
+
+    /// ジョブに端末を渡し、終了か停止を待つ。停止した場合はジョブ表に加える。
+    fn wait_foreground(&mut self, mut job: Job) -> i32 {
+        if let (Some(fd), Some(modes)) = (self.terminal, &job.modes) {
+            set_modes(fd, modes);
+        }
+        if let Some(pgid) = job.pgid {
+            self.give_terminal(pgid);
+        }
+        job.wait(self.terminal.is_some(), || false);
+        self.give_terminal(unsafe { libc::getpgrp() });
+
+        let state = job.state();
+        if let Some(fd) = self.terminal
+            && matches!(state, State::Stopped(_) | State::Signaled(_))
+        {
+            // 停止したジョブの端末の設定は再開するときに戻し、シェルの設定に戻す
+            if let State::Stopped(_) = state {
+                job.modes = get_modes(fd);
+            }
+            if let Some(modes) = &self.modes {
+                set_modes(fd, modes);
+            }
+        }
+        if self.interactive && state == State::Signaled(libc::SIGINT) {
+            // ジョブ制御ではシェル自身には SIGINT が届かないので、ここで中断を記録する
+            INTERRUPTED.store(true, Ordering::Relaxed);
+        }
+        let status = job.status(self.pipefail);
+        if let State::Stopped(_) = state {
+            let id = self.jobs.add(job);
+            eprintln!();
+            eprintln!("{}", self.jobs.format(id, false));
+        }
+        status
+    }
+
+    /// 停止したジョブかバックグラウンドのジョブを、フォアグラウンドで再開する (`fg`)
+    pub fn foreground(&mut self, id: usize) -> i32 {
+        let Some(mut job) = self.jobs.remove(id) else {
+            return 1;
+        };
+        println!("{}", job.command);
+        flush_stdio();
+        if let Err(e) = job.kill(libc::SIGCONT) {
+            eprintln!("fg: %{}: {}", id, e);
+        }
+        job.resume();
+        self.wait_foreground(job)
+    }
+
+    /// 停止したジョブをバックグラウンドで再開する (`bg`)
+    pub fn continue_background(&mut self, id: usize) -> Result<(), String> {
+        let job = self.jobs.get_mut(id).ok_or_else(|| format!("%{}: ジョブがありません", id))?;
+        job.kill(libc::SIGCONT).map_err(|e| format!("%{}: {}", id, e))?;
+        job.resume();
+        self.jobs.make_current(id);
+        println!("{}", self.jobs.format(id, false));
+        Ok(())
+    }
+
+    /// 状態が変化したジョブ (終了したバックグラウンドのジョブなど) を表示する
+    pub fn notify_jobs(&mut self) {
+        self.jobs.poll();
+        for line in self.jobs.take_changes() {
+            eprintln!("{}", line);
+        }
+    }
+
+    /// ジョブの終了を待ち、終了ステータスを返す (`wait`)。Ctrl-C で中断した場合は 128 + SIGINT。
+    pub fn wait_job(&mut self, id: usize) -> i32 {
+        let Some(job) = self.jobs.get_mut(id) else {
+            return 127;
+        };
+        job.wait(false, interrupted);
+        if !job.state().is_finished() {
+            return 128 + libc::SIGINT;
+        }
+        let status = job.status(self.pipefail);
+        self.jobs.remove(id);
+        status
+    }
+
+    /// 子プロセスで 1 つの段を実行し、終了ステータスを返す (外部コマンドは exec して戻らない)
+    fn run_stage(&mut self, stage: &Stage) -> i32 {
+        self.substitution_status = None;
+        let (argv, simple) = match stage {
+            Stage::Exec(argv, simple) => (Ok(argv.clone()), *simple),
+            Stage::Command(Command::Simple(simple)) => (expand::expand_words(self, &simple.words), simple),
+            Stage::Command(Command::Compound(compound, redirects)) => {
+                if let Err(e) = redirect::apply(self, redirects, false) {
+                    eprintln!("horiz-sh: {}", e);
+                    return 1;
+                }
+                // サブシェルの中の exit はサブシェルだけを終了する
+                return status_of(self.run_compound(compound));
+            }
+            Stage::Command(command @ Command::FunctionDef { .. }) => {
+                return status_of(self.run_commands(std::slice::from_ref(command)));
+            }
+            Stage::AndOr(and_or) => return status_of(self.run_and_or(and_or)),
+        };
+        let argv = match argv {
+            Ok(argv) => argv,
+            Err(e) => {
+                eprintln!("horiz-sh: {}", e);
+                return 1;
+            }
+        };
+        let callee = argv.first().and_then(|name| self.lookup(name));
+        // 子プロセスの中なので、代入は一時的なものとしてそのまま行えばよい
+        let prepared = redirect::apply(self, &simple.redirects, false)
+            .and_then(|_| self.assign(&simple.assignments, !argv.is_empty() && callee.is_none()));
+        if let Err(e) = prepared {
+            eprintln!("horiz-sh: {}", e);
+            return 1;
+        }
+        if argv.is_empty() {
+            return self.substitution_status.unwrap_or(0);
+        }
+        self.trace(|| quote_words(&argv));
+        match callee {
+            Some(Callee::Builtin(builtin)) => status_of(builtin(self, &argv)),
+            Some(Callee::Function(body)) => status_of(self.call_function(&body, &argv)),
+            None => self.exec_external(&argv),
+        }
+    }
+
+    /// 外部コマンドを、エクスポートされた変数を環境として exec する。
+    /// 失敗した場合はエラーを表示し、終了ステータス (126 / 127) を返す。
+    fn exec_external(&mut self, argv: &[String]) -> i32 {
+        let name = &argv[0];
+        let Some(path) = find_command(name, self.vars.get("PATH")) else {
+            eprintln!("{}: コマンドが見つかりません", name);
+            return 127;
+        };
+        let error = exec_file(&path, argv, &self.vars);
+        if error.raw_os_error() == Some(libc::ENOEXEC) {
+            return self.run_script(&path, argv);
+        }
+        eprintln!("{}: 実行できません ({})", name, error);
+        if error.kind() == io::ErrorKind::NotFound { 127 } else { 126 }
+    }
+
+    /// コマンド置換: 子プロセスで実行し、標準出力の内容から末尾の改行を取り除いたものを返す
+    pub fn command_output(&mut self, program: &Program) -> io::Result<String> {
+        flush_stdio();
+        let (read, write) = make_pipe()?;
+        let pid = unsafe { libc::fork() };
+        if pid == 0 {
+            self.terminal = None;
+            self.jobs = Jobs::default();
+            reset_signals(&self.signals);
+            self.signals.clear();
+            close_fd(Some(read));
+            redirect_fd(write, 1);
+            let status = status_of(self.run_program(program));
+            exit_child(status);
+        }
+        if pid < 0 {
+            let error = io::Error::last_os_error();
+            close_fd(Some(read));
+            close_fd(Some(write));
+            return Err(error);
+        }
+        close_fd(Some(write));
+        let mut output = Vec::new();
+        let read_result = unsafe { File::from_raw_fd(read) }.read_to_end(&mut output);
+        let mut job = Job::new(None, &[pid], String::new());
+        job.wait(false, || false);
+        self.substitution_status = Some(job.status(false));
+        read_result?;
+        let mut output = String::from_utf8_lossy(&output).into_owned();
+        output.truncate(output.trim_end_matches('\n').len());
+        Ok(output)
+    }
+
+    /// `#!` のない実行ファイルを、この子プロセスの中で新しいシェルのスクリプトとして実行する
+    fn run_script(&mut self, path: &Path, argv: &[String]) -> i32 {
+        let mut input = match Input::file(path) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("{}: 実行できません ({})", argv[0], e);
+                return 126;
+            }
+        };
+        if input.is_binary() {
+            eprintln!("{}: バイナリファイルは実行できません", argv[0]);
+            return 126;
+        }
+        self.name = argv[0].clone();
+        self.positional = argv[1..].to_vec();
+        self.functions.clear();
+        (self.loop_depth, self.function_depth, self.source_depth, self.condition_depth) = (0, 0, 0, 0);
+        self.last_status = 0;
+        status_of(source::run(self, &mut input))
+    }
+
+    fn give_terminal(&self, pgid: i32) {
+        if let Some(fd) = self.terminal {
+            // 端末を持たない (制御端末がない) 場合は失敗するが、その場合は何もしなくてよい
+            unsafe { libc::tcsetpgrp(fd, pgid) };
+        }
+    }
+}
+
+enum Callee {
+    Builtin(Builtin),
+    Function(Rc<Command>),
+}
+
+/// ループの本体 (または条件) の結果を、ループを続けるか抜けるかに変換する。
+/// 外側のループを対象とする break / continue はそのまま伝える。
+fn loop_flow(result: ExecResult) -> Result<ControlFlow<i32, i32>, Interrupt> {
+    match result {
+        Ok(status) => Ok(ControlFlow::Continue(status)),
+        Err(Interrupt::Break(1)) => Ok(ControlFlow::Break(0)),
+        Err(Interrupt::Break(n)) => Err(Interrupt::Break(n - 1)),
+        Err(Interrupt::Continue(1)) => Ok(ControlFlow::Continue(0)),
+        Err(Interrupt::Continue(n)) => Err(Interrupt::Continue(n - 1)),
+        Err(interrupt) => Err(interrupt),
+    }
+}
+
+/// 子プロセスの終了ステータス (中断の理由にかかわらず、そのプロセスは終了する)
+fn status_of(result: ExecResult) -> i32 {
+    match result {
+        Ok(status) | Err(Interrupt::Exit(status) | Interrupt::Return(status)) => status,
+        Err(Interrupt::Break(_) | Interrupt::Continue(_)) => 0,
+        Err(Interrupt::Cancel) => 128 + libc::SIGINT,
+    }
+}
+
+enum Stage<'a> {
+    /// 展開済みの引数で実行する
+    Exec(Vec<String>, &'a SimpleCommand),
+    /// 子プロセスの中で展開して実行する
+    Command(&'a Command),
+    /// `&&` `||` を含むバックグラウンドの and-or リスト全体
+    AndOr(&'a AndOr),
+}
+
+impl Stage<'_> {
+    /// ジョブの表示に使うコマンド
+    fn describe(&self) -> String {
+        match self {
+            Stage::Exec(argv, _) => quote_words(argv),
+            Stage::Command(command) => jobs::describe_command(command),
+            Stage::AndOr(and_or) => jobs::describe_and_or(and_or),
+        }
+    }
+}
+
+/// 単語を必要に応じてクォートし、空白で区切って並べる
+fn quote_words(words: &[String]) -> String {
+    words.iter().map(|word| vars::quote_if_needed(word)).collect::<Vec<_>>().join(" ")
+}
+
+fn flush_stdio() {
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+}
+
+fn exit_child(status: i32) -> ! {
+    flush_stdio();
+    unsafe { libc::_exit(status) }
+}
+
+/// 読み込み側と書き込み側 (exec 時に閉じる)
+fn make_pipe() -> io::Result<(i32, i32)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((fds[0], fds[1]))
+}
+
+fn close_fd(fd: Option<i32>) {
+    if let Some(fd) = fd {
+        unsafe { libc::close(fd) };
+    }
+}
+
+/// `from` を `to` に複製して `from` を閉じる (dup2 で複製した記述子は exec 後も開いたまま)
+fn redirect_fd(from: i32, to: i32) {
+    if from != to {
+        unsafe {
+            libc::dup2(from, to);
+            libc::close(from);
+        }
+    }
+}
+
+/// シェルが扱いを変えたシグナルを既定に戻す (Rust の実行環境は SIGPIPE を無視するため、それも戻す)
+fn reset_signals(signals: &[i32]) {
+    for &sig in signals.iter().chain(&[libc::SIGPIPE]) {
+        unsafe { libc::signal(sig, libc::SIG_DFL) };
+    }
+}
+
+fn get_modes(fd: i32) -> Option<libc::termios> {
+    let mut modes: libc::termios = unsafe { std::mem::zeroed() };
+    (unsafe { libc::tcgetattr(fd, &mut modes) } == 0).then_some(modes)
+}
+
+fn set_modes(fd: i32, modes: &libc::termios) {
+    unsafe { libc::tcsetattr(fd, libc::TCSADRAIN, modes) };
+}
+
+/// コマンド名から実行ファイルを探す。`/` を含む場合はそのまま使い、含まない場合は PATH
+/// (未設定なら DEFAULT_PATH) の後に /bin を探す。
+pub fn find_command(name: &str, path: Option<&str>) -> Option<PathBuf> {
+    if name.contains('/') {
+        return Some(PathBuf::from(name));
+    }
+    path.unwrap_or(DEFAULT_PATH)
+        .split(':')
+        .chain(["/bin"])
+        .map(|dir| Path::new(if dir.is_empty() { "." } else { dir }).join(name))
+        .find(|candidate| is_executable(candidate))
+}
+
+pub fn is_executable(path: &Path) -> bool {
+    let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    path.is_file() && unsafe { libc::access(c_path.as_ptr(), libc::X_OK) } == 0
+}
+
+/// 実行ファイルを、エクスポートされた変数を環境として exec する。戻った場合は失敗の理由を返す。
+fn exec_file(path: &Path, argv: &[String], vars: &Variables) -> io::Error {
+    let to_cstring = |s: &[u8]| CString::new(s).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput));
+    let to_cstrings = |items: &[String]| items.iter().map(|a| to_cstring(a.as_bytes())).collect::<io::Result<Vec<_>>>();
+    let prepared = (|| -> io::Result<(CString, Vec<CString>, Vec<CString>)> {
+        Ok((to_cstring(path.as_os_str().as_bytes())?, to_cstrings(argv)?, to_cstrings(&vars.environ())?))
+    })();
+    match prepared {
+        Ok((c_path, args, envs)) => {
+            let null_terminated = |items: &[CString]| {
+                let mut ptrs: Vec<*const libc::c_char> = items.iter().map(|a| a.as_ptr()).collect();
+                ptrs.push(std::ptr::null());
+                ptrs
+            };
+            let (args, envs) = (null_terminated(&args), null_terminated(&envs));
+            unsafe { libc::execve(c_path.as_ptr(), args.as_ptr(), envs.as_ptr()) };
+            io::Error::last_os_error()
+        }
+        Err(e) => e,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, MutexGuard};
+
+    use horiz_auth::testutil::TempDir;
+
+    use super::*;
+    use crate::parser;
+
+    fn run(shell: &mut Shell, input: &str) -> i32 {
+        shell.run_program(&parser::parse(input).unwrap()).unwrap()
+    }
+
+    /// 記述子の付け替えはプロセス全体に作用するため、シェルを実行するテストは 1 つずつ実行する
+    fn serial() -> MutexGuard<'static, ()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+        LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[test]
+    fn test_pipeline_status() {
+        let _serial = serial();
+        let mut shell = Shell::new(false);
+        assert_eq!(run(&mut shell, "true | false"), 1);
+        assert_eq!(run(&mut shell, "false | true"), 0);
+        assert_eq!(run(&mut shell, "no-such-command-horiz"), 127);
+        assert_eq!(run(&mut shell, "echo a | no-such-command-horiz | cat"), 0);
+
+        shell.pipefail = true;
+        assert_eq!(run(&mut shell, "false | true"), 1);
+        assert_eq!(run(&mut shell, "true | sh -c 'exit 3' | sh -c 'exit 2' | true"), 2);
+        assert_eq!(run(&mut shell, "true | true"), 0);
+    }
+
+    #[test]
+    fn test_redirects() {
+        let _serial = serial();
+        let dir = TempDir::new("sh-redirect");
+        let d = dir.display();
+        let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        let mut shell = Shell::new(false);
+
+        // シェル自身で実行する組み込みコマンド (実行後に標準出力が元に戻ること)
+        let stdout_ino = || {
+            let mut st: libc::stat = unsafe { std::mem::zeroed() };
+            assert_eq!(unsafe { libc::fstat(1, &mut st) }, 0);
+            (st.st_dev, st.st_ino)
+        };
+        let before = stdout_ino();
+        assert_eq!(run(&mut shell, &format!("echo a b > {d}/out\necho c >> {d}/out")), 0);
+        assert_eq!(read("out"), "a b\nc\n");
+        assert_eq!(stdout_ino(), before);
+        assert_eq!(run(&mut shell, &format!("> {d}/out")), 0);
+        assert_eq!(read("out"), "");
+
+        // 外部コマンド
+        assert_eq!(run(&mut shell, &format!("echo data > {d}/in\ncat < {d}/in > {d}/copy")), 0);
+        assert_eq!(read("copy"), "data\n");
+        assert_eq!(run(&mut shell, &format!("sh -c 'echo o; echo e >&2' > {d}/both 2>&1")), 0);
+        assert_eq!(read("both"), "o\ne\n");
+        assert_eq!(run(&mut shell, &format!("sh -c 'echo o; echo e >&2' &> {d}/all")), 0);
+        assert_eq!(read("all"), "o\ne\n");
+        assert_eq!(run(&mut shell, &format!("sh -c 'echo e >&2' 2>{d}/err | cat")), 0);
+        assert_eq!(read("err"), "e\n");
+        assert_eq!(run(&mut shell, &format!("cat 3<>{d}/in <&3")), 0);
+        // 閉じた記述子への書き込みは失敗する
+        assert_ne!(run(&mut shell, "sh -c 'echo x' >&-"), 0);
+
+        // ヒアドキュメント
+        assert_eq!(run(&mut shell, &format!("cat <<EOF > {d}/doc\nstatus $?\n  x\nEOF\n")), 0);
+        assert_eq!(read("doc"), "status 1\n  x\n");
+        assert_eq!(run(&mut shell, &format!("cat <<-'EOF' | cat > {d}/doc\n\t$?\n\tEOF\n")), 0);
+        assert_eq!(read("doc"), "$?\n");
+
+        // 開けないファイル
+        assert_eq!(run(&mut shell, &format!("cat < {d}/no-such-file")), 1);
+        assert_eq!(run(&mut shell, &format!("echo x > {d}/no-such-dir/out")), 1);
+        assert_eq!(run(&mut shell, "echo x >&9"), 1);
+    }
+
+    /// コマンドの標準出力を一時ファイルに書き込んで読む
+    fn capture(shell: &mut Shell, command: &str) -> String {
+        let dir = TempDir::new("sh-capture");
+        let path = dir.join("out");
+        run(shell, &format!("{{ {}\n}} > {}", command, path.display()));
+        std::fs::read_to_string(&path).unwrap()
+    }
+
+    #[test]
+    fn test_variables() {
+        let _serial = serial();
+        let mut shell = Shell::new(false);
+        assert_eq!(run(&mut shell, "A=1 B=\"$A 2\""), 0);
+        assert_eq!(capture(&mut shell, "echo $A $B"), "1 1 2\n");
+        // エクスポートしていない変数は外部コマンドに渡らない
+        assert_eq!(capture(&mut shell, "sh -c 'echo \"[$A]\"'"), "[]\n");
+        run(&mut shell, "export A");
+        assert_eq!(capture(&mut shell, "sh -c 'echo \"[$A]\"'"), "[1]\n");
+        run(&mut shell, "export C=3");
+        assert_eq!(shell.vars.get("C"), Some("3"));
+
+        // コマンド名の前の代入は、そのコマンドだけに渡る
+        assert_eq!(capture(&mut shell, "A=tmp X=x sh -c 'echo $A$X'"), "tmpx\n");
+        assert_eq!(capture(&mut shell, "echo $A $X."), "1 .\n");
+        assert_eq!(capture(&mut shell, "X=x sh -c 'echo $X' | cat"), "x\n");
+        // 通常の組み込みコマンドへの代入は元に戻り、特殊組み込みコマンドへの代入は残る
+        run(&mut shell, "A=tmp echo");
+        assert_eq!(shell.vars.get("A"), Some("1"));
+        run(&mut shell, "A=kept set --");
+        assert_eq!(shell.vars.get("A"), Some("kept"));
+
+        assert_eq!(run(&mut shell, "readonly R=1"), 0);
+        // 非対話モードでは読み取り専用の変数への代入でシェルを終了する
+        for command in ["R=2; B=not-reached", "R=2 echo; B=not-reached", "R=2 sh -c true; B=not-reached"] {
+            let program = parser::parse(command).unwrap();
+            assert_eq!(shell.run_program(&program), Err(Interrupt::Exit(1)), "{}", command);
+            assert_eq!(shell.vars.get("B"), Some("1 2"));
+        }
+        shell.interactive = true;
+        assert_eq!(run(&mut shell, "R=2"), 1);
+        assert_eq!(run(&mut shell, "R=2 sh -c true"), 1);
+        shell.interactive = false;
+        assert_eq!(run(&mut shell, "unset R"), 1);
+        assert_eq!(run(&mut shell, "export 1A=x"), 1);
+        assert_eq!(shell.vars.get("R"), Some("1"));
+        run(&mut shell, "unset A C");
+        assert_eq!(shell.vars.get("A"), None);
+
+        run(&mut shell, "set -- a 'b c'");
+        assert_eq!(capture(&mut shell, "echo $# $2"), "2 b c\n");
+        run(&mut shell, "shift");
+        assert_eq!(capture(&mut shell, "echo $# $1"), "1 b c\n");
+        assert_eq!(run(&mut shell, "shift 2"), 1);
+
+        // 展開の失敗はコマンドを実行せず、非対話モードではシェルを終了する
+        let program = parser::parse("echo ${U:?未設定}; A=not-reached").unwrap();
+        assert_eq!(shell.run_program(&program), Err(Interrupt::Exit(1)));
+        assert_eq!(shell.vars.get("A"), None);
+        shell.interactive = true;
+        assert_eq!(run(&mut shell, "echo ${U:?未設定}"), 1);
+    }
+
+    #[test]
+    fn test_command_subst() {
+        let _serial = serial();
+        let mut shell = Shell::new(false);
+        run(&mut shell, "A='1  2'");
+        assert_eq!(capture(&mut shell, "echo [$(echo $A)] \"[$(echo \"$A\")]\""), "[1 2] [1  2]\n");
+        assert_eq!(capture(&mut shell, "printf '<%s>' $(printf 'a\\nb\\n\\n'); echo"), "<a><b>\n");
+        assert_eq!(capture(&mut shell, "echo `echo \\`echo nested\\``"), "nested\n");
+        assert_eq!(capture(&mut shell, "echo ${UNSET:-$(echo default)}"), "default\n");
+        // 子プロセスで実行するので、中での代入や cd はシェルに残らない
+        assert_eq!(capture(&mut shell, "X=$(B=inner; echo $B); echo $X.$B"), "inner.\n");
+        // コマンド名のない単純コマンドは最後のコマンド置換のステータスになる
+        assert_eq!(run(&mut shell, "X=$(exit 3)"), 3);
+        assert_eq!(run(&mut shell, "X=$(exit 3) true"), 0);
+        assert_eq!(run(&mut shell, "false; X=1"), 0);
+    }
+
+    #[test]
+    fn test_arith() {
+        let _serial = serial();
+        let mut shell = Shell::new(false);
+        assert_eq!(capture(&mut shell, "i=0; while [ $i -lt 5 ]; do i=$((i+1)); done; echo $i"), "5\n");
+        run(&mut shell, "A=abc");
+        assert_eq!(capture(&mut shell, "echo $((2 * $(echo 3) + ${#A})) \"$((010 + 0x10))\" $((X = -4)) $X"), "9 24 -4 -4\n");
+        assert_eq!(capture(&mut shell, "n=3; echo $(( n > 2 ? n * 2 : 0 )) $((n)) $(( ))"), "6 3 0\n");
+        // 評価の失敗は展開の失敗と同様に、対話モード以外ではシェルを終了する
+        let program = parser::parse("echo $((1 / 0)); echo not-reached").unwrap();
+        assert_eq!(shell.run_program(&program), Err(Interrupt::Exit(1)));
+        let mut shell = Shell::new(true);
+        assert_eq!(run(&mut shell, "echo $((1 +))"), 1);
+    }
+
+    #[test]
+    fn test_lists() {
+        let _serial = serial();
+        let mut shell = Shell::new(false);
+        assert_eq!(run(&mut shell, "false && true"), 1);
+        assert_eq!(run(&mut shell, "false || true"), 0);
+        assert_eq!(run(&mut shell, "true && false || sh -c 'exit 4'"), 4);
+        assert_eq!(run(&mut shell, "true || false && false"), 1);
+        assert_eq!(run(&mut shell, "! true"), 1);
+        assert_eq!(run(&mut shell, "! sh -c 'exit 3' | true"), 1);
+        assert_eq!(run(&mut shell, "! false && true"), 0);
+
+        // 各コマンドの終了ステータスが次のコマンドの $? になる
+        assert_eq!(capture(&mut shell, "false; echo $?; true; echo $?"), "1\n0\n");
+        assert_eq!(capture(&mut shell, "false || echo $?"), "1\n");
+        assert_eq!(capture(&mut shell, "sh -c 'exit 5' && echo no; echo $?"), "5\n");
+        assert_eq!(capture(&mut shell, "false && echo a || echo b && echo c"), "b\nc\n");
+    }
+
+    #[test]
+    fn test_background() {
+        let _serial = serial();
+        let mut shell = Shell::new(false);
+        // 終了を待たずに次のコマンドへ進み、wait で終了ステータスを受け取る
+        assert_eq!(run(&mut shell, "sh -c 'exit 3' & wait $!"), 3);
+        assert_eq!(run(&mut shell, "false && true || sh -c 'exit 4' & wait %1"), 4);
+        assert_eq!(run(&mut shell, "sh -c 'exit 5' | true & wait"), 0);
+        assert_eq!(run(&mut shell, "wait %1"), 127);
+        assert!(shell.jobs.ids().is_empty());
+
+        // バックグラウンドのジョブはシェルの変数を変更せず、標準入力は /dev/null になる
+        assert_eq!(capture(&mut shell, "x=1 & wait; echo ${x-unset}"), "unset\n");
+        assert_eq!(capture(&mut shell, "cat & wait; echo $?"), "0\n");
+
+        assert_eq!(
+            capture(&mut shell, "sleep 5 & sleep 6 | cat & jobs; kill %1 %2; wait %1; echo $?; wait %2; echo $?"),
+            "[1]-  Running                 sleep 5 &\n[2]+  Running                 sleep 6 | cat &\n143\n143\n"
+        );
+        assert_eq!(run(&mut shell, "wait; kill %1"), 1);
+        assert_eq!(capture(&mut shell, "kill -l 143 9; kill -s HUP 2>/dev/null; echo $?"), "TERM\nKILL\n2\n");
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let _serial = serial();
+        let mut shell = Shell::new(false);
+        // 非対話モードでは SIGINT で終了したコマンドの後も続ける
+        assert_eq!(run(&mut shell, "sh -c 'kill -INT $$'; X=next"), 0);
+        assert_eq!(shell.vars.get("X"), Some("next"));
+
+        // 対話モードでは Ctrl-C で終了したジョブの後、ループと関数を打ち切ってプロンプトへ戻る
+        shell.interactive = true;
+        let program = parser::parse("f() { for i in 1 2 3; do X=$i; sh -c 'kill -INT $$'; done; }; f; X=after").unwrap();
+        assert_eq!(shell.run_program(&program), Err(Interrupt::Cancel));
+        assert_eq!(shell.vars.get("X"), Some("1"));
+
+        // 組み込みコマンドだけのループは、シェル自身が受け取った SIGINT で打ち切る
+        shell.catch_sigint();
+        let sender = std::thread::spawn(|| {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            unsafe { libc::kill(libc::getpid(), libc::SIGINT) };
+        });
+        let program = parser::parse("while :; do :; done; X=after").unwrap();
+        assert_eq!(shell.run_program(&program), Err(Interrupt::Cancel));
+        sender.join().unwrap();
+        unsafe { libc::signal(libc::SIGINT, libc::SIG_DFL) };
+        assert_eq!(shell.vars.get("X"), Some("1"));
+        assert!(!interrupted());
+    }
+
+    #[test]
+    fn test_compound() {
+        let _serial = serial();
+        let mut shell = Shell::new(false);
+        // { } はシェル自身、( ) は子プロセスで実行する
+        run(&mut shell, "{ A=1; B=2; }");
+        assert_eq!(shell.vars.get("B"), Some("2"));
+        assert_eq!(run(&mut shell, "(A=changed; exit 3)"), 3);
+        assert_eq!(shell.vars.get("A"), Some("1"));
+        assert_eq!(capture(&mut shell, "(echo $A; A=x; echo $A); echo $A"), "1\nx\n1\n");
+
+        assert_eq!(capture(&mut shell, "{ echo a; echo b; } | cat"), "a\nb\n");
+        assert_eq!(capture(&mut shell, "(echo a && false) || echo b"), "a\nb\n");
+        assert_eq!(capture(&mut shell, "{ echo out; sh -c 'echo err >&2'; } 2>&1"), "out\nerr\n");
+        assert_eq!(capture(&mut shell, "(echo x; exit 2) | cat; echo $?"), "x\n0\n");
+        assert_eq!(run(&mut shell, "{ false; }"), 1);
+
+        // グループの中の exit はシェルを終了し、サブシェルの中の exit はサブシェルだけを終了する
+        let program = parser::parse("{ exit 7; }; echo unreachable").unwrap();
+        assert_eq!(shell.run_program(&program), Err(Interrupt::Exit(7)));
+        let program = parser::parse("(exit 7); echo $? > /dev/null").unwrap();
+        assert_eq!(shell.run_program(&program), Ok(0));
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let _serial = serial();
+        let mut shell = Shell::new(false);
+        assert_eq!(capture(&mut shell, "if false; then echo a; elif true; then echo b; else echo c; fi"), "b\n");
+        assert_eq!(capture(&mut shell, "if false; then echo a; fi; echo $?"), "0\n");
+        assert_eq!(run(&mut shell, "if true; then sh -c 'exit 3'; fi"), 3);
+
+        assert_eq!(capture(&mut shell, "i=; while [ ${#i} != 3 ]; do i=${i}x; echo $i; done"), "x\nxx\nxxx\n");
+        assert_eq!(capture(&mut shell, "until true; do echo no; done; echo $?"), "0\n");
+        assert_eq!(capture(&mut shell, "for x in a 'b c'; do echo \"[$x]\"; done; echo $x"), "[a]\n[b c]\nb c\n");
+        assert_eq!(capture(&mut shell, "set -- 1 2; for x; do echo $x; done"), "1\n2\n");
+        assert_eq!(capture(&mut shell, "for x in a b; do echo $x; done | cat"), "a\nb\n");
+
+        // break / continue (n 段外側のループも対象にできる)
+        assert_eq!(capture(&mut shell, "for x in 1 2 3; do [ $x = 2 ] && continue; echo $x; done"), "1\n3\n");
+        assert_eq!(capture(&mut shell, "for x in 1 2; do for y in a b; do echo $x$y; break 2; done; done"), "1a\n");
+        assert_eq!(capture(&mut shell, "for x in 1 2; do for y in a b; do continue 2; echo no; done; echo no; done; echo $x$y"), "2a\n");
+        assert_eq!(capture(&mut shell, "while true; do while true; do break 9; done; echo no; done; echo end"), "end\n");
+        assert_eq!(run(&mut shell, "break"), 1);
+        assert_eq!(run(&mut shell, "for x in a; do break 0; done"), 1);
+
+        assert_eq!(capture(&mut shell, "case abc in x|a*) echo 1;; *) echo 2;; esac"), "1\n");
+        assert_eq!(capture(&mut shell, "p='*'; case abc in \"$p\") echo 1;; $p) echo 2;; esac"), "2\n");
+        assert_eq!(capture(&mut shell, "case x in y) echo no;; esac; echo $?"), "0\n");
+    }
+
+    #[test]
+    fn test_functions() {
+        let _serial = serial();
+        let mut shell = Shell::new(false);
+        // 位置パラメータは呼び出しの間だけ置き換わる
+        run(&mut shell, "set -- outer; f() { echo $# \"$1\"; shift; echo $1; }");
+        assert_eq!(capture(&mut shell, "f 'a b' c; echo $1"), "2 a b\nc\nouter\n");
+        assert_eq!(capture(&mut shell, "f x | cat"), "1 x\n\n");
+
+        run(&mut shell, "g() { return 3; echo no; }; h() { false; return; }");
+        assert_eq!(run(&mut shell, "g"), 3);
+        assert_eq!(run(&mut shell, "h"), 1);
+        assert_eq!(run(&mut shell, "return"), 1);
+
+        // 再帰と、関数の中から呼び出し元のループを抜けられないこと
+        run(&mut shell, "count() { if [ ${#1} -lt 3 ]; then echo $1; count $1x; fi; }");
+        assert_eq!(capture(&mut shell, "count x"), "x\nxx\n");
+        run(&mut shell, "b() { break; }");
+        assert_eq!(capture(&mut shell, "for x in 1 2; do b; echo $x; done"), "1\n2\n");
+        // 無限の再帰は深さの上限で止まる (テストのスレッドは既定のスタックが小さいため、メインスレッドと同じ大きさにする)
+        let recursion = std::thread::Builder::new().stack_size(8 << 20).spawn(|| run(&mut Shell::new(false), "loop() { loop; }; loop"));
+        assert_eq!(recursion.unwrap().join().unwrap(), 1);
+
+        // 関数は組み込みコマンドより優先し、特殊組み込みコマンドより後に探す
+        run(&mut shell, "echo() { command-not-used; }; exit() { :; }");
+        assert_eq!(run(&mut shell, "unset -f echo"), 0);
+        assert_eq!(capture(&mut shell, "echo restored"), "restored\n");
+        let program = parser::parse("exit 4").unwrap();
+        assert_eq!(shell.run_program(&program), Err(Interrupt::Exit(4)));
+
+        // 代入とリダイレクトは呼び出しの間だけ
+        run(&mut shell, "A=1; show() { echo $A; }");
+        assert_eq!(capture(&mut shell, "A=2 show; show"), "2\n1\n");
+    }
+
+    #[test]
+    fn test_test_builtin() {
+        let _serial = serial();
+        let mut shell = Shell::new(false);
+        assert_eq!(run(&mut shell, "[ a = a ]"), 0);
+        assert_eq!(run(&mut shell, "test 1 -gt 2"), 1);
+        assert_eq!(run(&mut shell, "[ -d / -a ! -f / ]"), 0);
+        assert_eq!(run(&mut shell, "[ x -eq 1 ]"), 2);
+        assert_eq!(run(&mut shell, "[ a = a"), 2);
+        assert_eq!(run(&mut shell, "E=; [ -z \"$E\" ]"), 0);
+        assert_eq!(run(&mut shell, "test"), 1);
+    }
+
+    #[test]
+    fn test_options() {
+        let _serial = serial();
+        let mut shell = Shell::new(false);
+        run(&mut shell, "set -eu -o pipefail");
+        assert!(shell.errexit && shell.nounset && shell.pipefail);
+        assert_eq!(capture(&mut shell, "echo $-; set +eu -x +o pipefail; echo $-; set +x"), "eu\nx\n");
+        assert_eq!(run(&mut shell, "set +e; set -q"), 2);
+        assert_eq!(run(&mut shell, "set -o nothing"), 2);
+        // オプションの後の引数は位置パラメータ
+        run(&mut shell, "set -e a b");
+        assert_eq!(shell.positional, ["a", "b"]);
+
+        // set -x は展開後のコマンドを表示する
+        shell.errexit = false;
+        assert_eq!(capture(&mut shell, "{ set -x; A='a b' B=$A; echo \"$B\" x; set +x; } 2>&1"), "+ A='a b'\n+ B='a b'\n+ echo 'a b' x\na b x\n+ set +x\n");
+    }
+
+    #[test]
+    fn test_errexit() {
+        let _serial = serial();
+        let mut shell = Shell::new(false);
+        shell.errexit = true;
+        let status = |shell: &mut Shell, input: &str| shell.run_program(&parser::parse(input).unwrap());
+        assert_eq!(status(&mut shell, "true; false; A=1"), Err(Interrupt::Exit(1)));
+        assert_eq!(shell.vars.get("A"), None);
+        assert_eq!(status(&mut shell, "sh -c 'exit 3' | true; true | sh -c 'exit 3'"), Err(Interrupt::Exit(3)));
+        assert_eq!(status(&mut shell, "{ false; A=1; }"), Err(Interrupt::Exit(1)));
+        assert_eq!(status(&mut shell, "f() { false; A=1; }; f"), Err(Interrupt::Exit(1)));
+
+        // 条件として使われたコマンドの失敗では終了しない
+        assert_eq!(status(&mut shell, "false || true; false && true; ! true; if false; then :; fi; while false; do :; done"), Ok(0));
+        assert_eq!(status(&mut shell, "f || true; echo $A"), Ok(0));
+        assert_eq!(shell.vars.get("A"), Some("1"));
+        assert_eq!(status(&mut shell, "true && false"), Err(Interrupt::Exit(1)));
+        // サブシェルの中ではサブシェルだけを終了する
+        assert_eq!(capture(&mut shell, "(false; echo no) | cat; echo after"), "after\n");
+    }
+
+    #[test]
+    fn test_source() {
+        let _serial = serial();
+        let dir = TempDir::new("sh-source");
+        std::fs::write(dir.join("lib.sh"), "greet() { echo \"hello $1\"; }\nLIB=$#:$1\nreturn 3\nLIB=no\n").unwrap();
+        let mut shell = Shell::new(false);
+        run(&mut shell, "set -- outer");
+
+        // 引数を指定した場合だけ位置パラメータを置き換え、return で読み込みを終える
+        assert_eq!(run(&mut shell, &format!(". {}/lib.sh a b", dir.display())), 3);
+        assert_eq!(shell.vars.get("LIB"), Some("2:a"));
+        assert_eq!(shell.positional, ["outer"]);
+        assert_eq!(capture(&mut shell, "greet world"), "hello world\n");
+        run(&mut shell, &format!("PATH={}; source lib.sh", dir.display()));
+        assert_eq!(shell.vars.get("LIB"), Some("1:outer"));
+
+        // 読み込めないファイルと構文エラー
+        assert_eq!(run(&mut shell, ". no-such-file-horiz"), 1);
+        std::fs::write(dir.join("broken.sh"), "C=1\nif\n").unwrap();
+        assert_eq!(run(&mut shell, &format!(". {}/broken.sh", dir.display())), 2);
+        assert_eq!(shell.vars.get("C"), Some("1"));
+        std::fs::write(dir.join("self.sh"), ". $0\n").unwrap();
+        assert_eq!(run(&mut shell, &format!("(. {}/self.sh)", dir.display())), 1);
+    }
+
+    #[test]
+    fn test_script_without_shebang() {
+        let _serial = serial();
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new("sh-script");
+        let script = dir.join("script");
+        std::fs::write(&script, "echo \"$0 $# $1\"\nf\nexit 5\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let binary = dir.join("binary");
+        std::fs::write(&binary, b"\x7fELF\0\0\n").unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        // `#!` のない実行ファイルは新しいシェルのスクリプトとして実行する (呼び出し元の関数は引き継がない)
+        let mut shell = Shell::new(false);
+        run(&mut shell, "f() { echo inherited; }");
+        let output = capture(&mut shell, &format!("{} 'a b' 2>/dev/null; echo $?", script.display()));
+        assert_eq!(output, format!("{} 1 a b\n5\n", script.display()));
+        assert_eq!(run(&mut shell, &format!("{} 2>/dev/null", binary.display())), 126);
+    }
+
+    /// scripts/ のビルドスクリプトを、構文の検査 (`-n`) と、外部のツールを呼び出しを記録するスタブに置き換えた実行で確かめる
+    #[test]
+    fn test_build_scripts() {
+        let _serial = serial();
+        use std::os::unix::fs::PermissionsExt;
+
+        let repo = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../..").canonicalize().unwrap();
+        for script in ["scripts/build_kernel.sh", "scripts/build_rootfs.sh", "scripts/build_iso.sh", "rootfs/etc/init.d/rcS"] {
+            let mut shell = Shell::new(false);
+            shell.noexec = true;
+            let mut input = Input::file(&repo.join(script)).unwrap();
+            assert_eq!(source::run(&mut shell, &mut input), Ok(0), "{}", script);
+        }
+
+        let dir = TempDir::new("sh-build-scripts");
+        let work = dir.join("work");
+        let stubs = dir.join("stubs");
+        std::fs::create_dir_all(work.join("horiz-core")).unwrap();
+        std::fs::create_dir(&stubs).unwrap();
+        let config = std::fs::read_to_string(repo.join("build_config.ini")).unwrap();
+        let version = config.lines().find_map(|line| line.strip_prefix("version=")).unwrap().trim();
+        std::fs::create_dir_all(work.join(format!("build/linux-{}", version))).unwrap();
+        let release = "target/x86_64-unknown-linux-musl/release";
+        let stub = |name: &str, body: &str| {
+            let path = stubs.join(name);
+            std::fs::write(&path, format!("#!/bin/sh\necho \"{} $*\" >> \"$LOG\"\n{}", name, body)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        stub("curl", "");
+        stub("nproc", "echo 4\n");
+        stub("make", "mkdir -p arch/x86/boot && : > arch/x86/boot/bzImage\n");
+        stub("cargo", &format!("mkdir -p {r} && for b in init sh pkg utils passwd account doas totp; do : > {r}/horiz-$b; done\n", r = release));
+        stub("cpio", "cat > /dev/null\n");
+        stub("grub-mkrescue", "");
+
+        let mut shell = Shell::new(false);
+        for script in ["build_kernel.sh", "build_rootfs.sh", "build_iso.sh"] {
+            let command = format!(
+                "unset ARCH NJOBS USE_NIGHTLY; cp -r {repo}/rootfs {repo}/build_config.ini {work} && cd {work} && \
+                 PATH={stubs}:$PATH LOG={work}/log; export PATH LOG; . {repo}/scripts/{script}",
+                repo = repo.display(),
+                work = work.display(),
+                stubs = stubs.display(),
+            );
+            let output = shell.command_output(&parser::parse(&command).unwrap()).unwrap();
+            assert_eq!(shell.substitution_status, Some(0), "{}: {}", script, output);
+        }
+        let log = std::fs::read_to_string(work.join("log")).unwrap();
+        assert_eq!(
+            log,
+            format!(
+                "curl -L -o linux.tar.xz https://cdn.kernel.org/pub/linux/kernel/v6.x/linux-{v}.tar.xz\n\
+                 make ARCH=x86_64 CROSS_COMPILE= defconfig\nnproc \nmake ARCH=x86_64 CROSS_COMPILE= -j4\n\
+                 cargo build --release --target x86_64-unknown-linux-musl\ncpio -H newc -o\n\
+                 grub-mkrescue -o horiz-x86_64.iso build/iso/root -- -as mkisofs -r\n",
+                v = version
+            )
+        );
+
+        // rootfs テンプレート (`cp -r rootfs/*`) とバイナリの権限 (`chmod 755 bin/*`)
+        let rootfs = work.join("build/rootfs");
+        let mode = |path: &str| std::fs::metadata(rootfs.join(path)).unwrap().permissions().mode() & 0o7777;
+        assert!(rootfs.join("home/horiz").is_dir());
+        assert_eq!(mode("etc/shadow"), 0o600);
+        assert_eq!(std::fs::read(rootfs.join("etc/hostname")).unwrap(), std::fs::read(repo.join("rootfs/etc/hostname")).unwrap());
+        assert_eq!(mode("bin/horiz-utils"), 0o755);
+        assert_eq!(mode("bin/passwd"), 0o4755);
+        assert!(work.join("horiz-rootfs.tar.gz").is_file());
+        let grub = std::fs::read_to_string(work.join("build/iso/root/boot/grub/grub.cfg")).unwrap();
+        assert!(grub.contains("menuentry \"HorizOS (x86_64)\""), "{}", grub);
+        assert!(work.join("build/iso/root/boot/initrd.img").is_file());
+    }
+
+    #[test]
+    fn test_find_command() {
+        assert!(find_command("sh", None).is_some());
+        assert_eq!(find_command("./x", None), Some(PathBuf::from("./x")));
+        assert!(find_command("no-such-command-horiz", None).is_none());
+        // PATH に見つからなくても /bin は探す
+        assert!(find_command("sh", Some("/nonexistent")).is_some());
+    }
+}
@@ -0,0 +1,630 @@
+// --- 単語の展開 ---
+//
+// パラメータ展開・コマンド置換・算術展開を行い、クォートを取り除いて文字列にする。コマンドの引数では、クォートされていない
+// 展開結果を IFS (既定は空白・タブ・改行) で複数のフィールドに分割する。`"$@"` は位置パラメータを
+// それぞれ 1 つのフィールドにする。コマンド置換の結果 (出力の末尾の改行を取り除いたもの) もパラメータ展開の
+// 結果と同様に扱う。算術展開は、式の中のパラメータ展開とコマンド置換を済ませてから評価した整数を結果とする。
+//
+// 単語の先頭のクォートされていない `~` (最初の `/` まで) はチルダ展開としてホームディレクトリに置き換える。
+// `~` は変数 HOME の値、`~user` は /etc/passwd のそのユーザーのホームディレクトリとなる。変数への代入の値では、
+// 先頭に加えてクォートされていない `:` の後の `~` も展開する (`PATH=~/bin:~user/bin`)。
+//
+// コマンドの引数では、分割したフィールドのうちクォートされていない `*` `?` `[...]` を含むものをパス名展開する。
+// `/` で区切った要素ごとにディレクトリを読み、一致したパスを並べ替えて複数のフィールドにする。一致するものが
+// なければフィールドをそのまま残す。`.` で始まる名前は、パターンの要素が `.` で始まる場合だけ一致する。
+// `set -f` (noglob) ではパス名展開を行わない。
+//
+// リダイレクトの対象や変数への代入の値は分割せずに 1 つの文字列にする。
+// ヒアドキュメントの本文は、区切り文字がクォートされていなければダブルクォートの内側と同様に扱う
+// (`\$` `\\` `` \` `` のエスケープと `\` + 改行の行継続を処理し、パラメータを展開する)。
+
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use horiz_auth::passwd;
+
+use crate::arith;
+use crate::exec::Shell;
+use crate::lexer::{self, HereDoc, Param, ParamOp, Word, WordPart};
+use crate::pattern;
+
+/// IFS が設定されていない場合の区切り文字
+const DEFAULT_IFS: &str = " \t\n";
+
+/// 展開の失敗 (`${var?message}` など)。メッセージをそのまま表示する。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpandError(pub String);
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+type Result<T> = std::result::Result<T, ExpandError>;
+
+/// 展開結果を組み立てる
+struct Fields {
+    fields: Vec<String>,
+    current: String,
+    /// 現在のフィールドが (空であっても) 存在するか (`""` は空のフィールドになる)
+    started: bool,
+    /// クォートされていない展開結果をフィールドに分割する区切り文字 (None なら 1 つの文字列にする)
+    ifs: Option<String>,
+    /// 空白以外の区切り文字の直前が空白による区切りだったか (`a : b` で空のフィールドを作らないため)
+    after_blank: bool,
+    /// パターンとして使う (クォートされた文字を `\` でエスケープする)
+    pattern: bool,
+    /// パス名展開に使う、現在のフィールドのパターン (None ならパス名展開を行わない)
+    glob: Option<String>,
+}
+
+impl Fields {
+    fn new(ifs: Option<String>, pattern: bool) -> Self {
+        Fields { fields: Vec::new(), current: String::new(), started: false, ifs, after_blank: false, pattern, glob: None }
+    }
+
+    /// 文字どおりの (パターンとしては特別な意味を持たない) 文字列
+    fn push_quoted(&mut self, s: &str) {
+        if self.pattern {
+            self.current.push_str(&escape(s));
+        } else {
+            self.current.push_str(s);
+        }
+        if let Some(glob) = &mut self.glob {
+            glob.push_str(&escape(s));
+        }
+        self.started = true;
+        self.after_blank = false;
+    }
+
+    /// クォートされていない文字列 (入力に書かれたもの)
+    fn push_literal(&mut self, s: &str) {
+        self.current.push_str(s);
+        if let Some(glob) = &mut self.glob {
+            glob.push_str(s);
+        }
+        self.started = true;
+        self.after_blank = false;
+    }
+
+    /// クォートされていない展開結果 (フィールド分割の対象)
+    fn push_expansion(&mut self, s: &str) {
+        let Some(ifs) = self.ifs.clone() else {
+            self.push_literal(s);
+            return;
+        };
+        for c in s.chars() {
+            if !ifs.contains(c) {
+                self.current.push(c);
+                if let Some(glob) = &mut self.glob {
+                    glob.push(c);
+                }
+                self.started = true;
+                self.after_blank = false;
+            } else if c.is_whitespace() {
+                if self.started {
+                    self.break_field();
+                    self.after_blank = true;
+                }
+            } else {
+                if self.started || !self.after_blank {
+                    self.break_field();
+                }
+                self.after_blank = false;
+            }
+        }
+    }
+
+    /// 現在のフィールドを終える (分割しない場合は空白でつなぐ)
+    fn break_field(&mut self) {
+        if self.ifs.is_none() {
+            self.current.push(' ');
+            return;
+        }
+        let field = std::mem::take(&mut self.current);
+        match self.glob.as_mut().map(std::mem::take) {
+            Some(glob) if is_glob(&glob) => match pathname_expansion(&glob) {
+                paths if paths.is_empty() => self.fields.push(field),
+                paths => self.fields.extend(paths),
+            },
+            _ => self.fields.push(field),
+        }
+        self.started = false;
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        if self.started {
+            self.break_field();
+        }
+        self.fields
+    }
+
+    fn finish_string(self) -> String {
+        debug_assert!(self.ifs.is_none());
+        self.current
+    }
+}
+
+/// コマンドの引数を展開する (フィールド分割を行う)
+pub fn expand_words(shell: &mut Shell, words: &[Word]) -> Result<Vec<String>> {
+    // IFS が空の場合は分割しないが、単語ごとのフィールドにはする
+    let ifs = shell.vars.get("IFS").unwrap_or(DEFAULT_IFS).to_string();
+    let mut fields = Fields::new(Some(ifs), false);
+    if !shell.noglob {
+        fields.glob = Some(String::new());
+    }
+    for word in words {
+        fields.after_blank = false;
+        expand_word_parts(shell, word, &mut fields)?;
+        if fields.started {
+            fields.break_field();
+        }
+    }
+    Ok(fields.finish())
+}
+
+/// 1 つの文字列に展開する (フィールド分割を行わない)
+pub fn expand_word(shell: &mut Shell, word: &Word) -> Result<String> {
+    let mut fields = Fields::new(None, false);
+    expand_word_parts(shell, word, &mut fields)?;
+    Ok(fields.finish_string())
+}
+
+/// パターンとして展開する (クォートされた文字はエスケープする)
+pub fn expand_pattern(shell: &mut Shell, word: &Word) -> Result<String> {
+    let mut fields = Fields::new(None, true);
+    expand_word_parts(shell, word, &mut fields)?;
+    Ok(fields.finish_string())
+}
+
+pub fn expand_heredoc(shell: &mut Shell, heredoc: &HereDoc) -> Result<String> {
+    if !heredoc.expand {
+        return Ok(heredoc.body.clone());
+    }
+    let parts = lexer::heredoc_parts(&heredoc.body).map_err(|e| ExpandError(e.to_string()))?;
+    let mut fields = Fields::new(None, false);
+    expand_parts(shell, &parts, true, &mut fields)?;
+    Ok(fields.finish_string())
+}
+
+/// チルダ接頭辞 (`~` または `~user`) のホームディレクトリ。`home` は変数 HOME の値。
+pub fn home_dir(user: &str, home: Option<&str>) -> Option<String> {
+    if user.is_empty() {
+        return home.map(String::from);
+    }
+    passwd::getpwnam(user).ok().flatten().map(|pw| pw.home)
+}
+
+/// 単語の先頭のチルダ展開を行ってから、単語の部品を展開する
+fn expand_word_parts(shell: &mut Shell, word: &Word, fields: &mut Fields) -> Result<()> {
+    let parts = expand_tilde(shell, &word.0, false);
+    expand_parts(shell, &parts, false, fields)
+}
+
+/// チルダ接頭辞をホームディレクトリ (クォートされた文字列) に置き換える。`assignment` では
+/// クォートされていない `:` の後の接頭辞も対象にする。接頭辞の途中にクォートや展開がある場合
+/// (`~"user"`)、ユーザーが存在しない場合、HOME が未設定の場合はそのまま残す。
+fn expand_tilde<'a>(shell: &Shell, parts: &'a [WordPart], assignment: bool) -> Cow<'a, [WordPart]> {
+    let mut expanded = Vec::new();
+    let mut changed = false;
+    for (i, part) in parts.iter().enumerate() {
+        let WordPart::Literal(s) = part else {
+            expanded.push(part.clone());
+            continue;
+        };
+        // 接頭辞になりうる区間: 単語の先頭と、代入の値では `:` の後
+        let segments: Vec<&str> = if assignment { s.split(':').collect() } else { vec![s] };
+        let mut literal = String::new();
+        for (j, segment) in segments.iter().enumerate() {
+            if j > 0 {
+                literal.push(':');
+            }
+            let eligible = j > 0 || i == 0;
+            let end_of_prefix = j + 1 < segments.len() || i + 1 == parts.len();
+            let prefix = match segment.strip_prefix('~').filter(|_| eligible) {
+                Some(rest) => match rest.find('/') {
+                    Some(k) => Some((&rest[..k], &rest[k..])),
+                    None if end_of_prefix => Some((rest, "")),
+                    None => None,
+                },
+                None => None,
+            };
+            match prefix.and_then(|(user, tail)| Some((home_dir(user, shell.vars.get("HOME"))?, tail))) {
+                Some((home, tail)) => {
+                    if !literal.is_empty() {
+                        expanded.push(WordPart::Literal(std::mem::take(&mut literal)));
+                    }
+                    expanded.push(WordPart::Quoted(home));
+                    literal.push_str(tail);
+                    changed = true;
+                }
+                None => literal.push_str(segment),
+            }
+        }
+        if !literal.is_empty() {
+            expanded.push(WordPart::Literal(literal));
+        }
+    }
+    if changed { Cow::Owned(expanded) } else { Cow::Borrowed(parts) }
+}
+
+/// 変数への代入の値を展開する (フィールド分割を行わず、`:` の後のチルダ接頭辞も展開する)
+pub fn expand_assignment(shell: &mut Shell, word: &Word) -> Result<String> {
+    let mut fields = Fields::new(None, false);
+    let parts = expand_tilde(shell, &word.0, true);
+    expand_parts(shell, &parts, false, &mut fields)?;
+    Ok(fields.finish_string())
+}
+
+fn expand_parts(shell: &mut Shell, parts: &[WordPart], quoted: bool, fields: &mut Fields) -> Result<()> {
+    for part in parts {
+        match part {
+            WordPart::Literal(s) if quoted => fields.push_quoted(s),
+            WordPart::Literal(s) => fields.push_literal(s),
+            WordPart::Quoted(s) => fields.push_quoted(s),
+            WordPart::DoubleQuoted(inner) => {
+                // 位置パラメータがない場合の "$@" はフィールドを作らない
+                let only_at = matches!(inner.as_slice(), [WordPart::Param(Param { name, op: ParamOp::Value })] if name == "@");
+                if !only_at {
+                    fields.push_quoted("");
+                }
+                expand_parts(shell, inner, true, fields)?;
+            }
+            WordPart::Param(Param { name, op: ParamOp::Value }) if name == "@" || (name == "*" && !quoted) => {
+                let params = shell.positional.clone();
+                for (i, value) in params.iter().enumerate() {
+                    if i > 0 {
+                        fields.break_field();
+                    }
+                    if quoted { fields.push_quoted(value) } else { fields.push_expansion(value) }
+                }
+            }
+            WordPart::Param(param) => {
+                let value = expand_param(shell, param)?;
+                push_value(fields, &value, quoted);
+            }
+            WordPart::Command { program, .. } => {
+                let output = shell.command_output(program).map_err(|e| ExpandError(format!("コマンド置換: {}", e)))?;
+                push_value(fields, &output, quoted);
+            }
+            WordPart::Arith(inner) => {
+                let mut expr = Fields::new(None, false);
+                expand_parts(shell, inner, true, &mut expr)?;
+                let expr = expr.finish_string();
+                let value = arith::evaluate(&expr, &mut shell.vars, shell.nounset)
+                    .map_err(|e| ExpandError(format!("{}: {}", expr.trim(), e)))?;
+                push_value(fields, &value.to_string(), quoted);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// パターンの特別な文字を `\` でエスケープする
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        if "*?[]\\".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// エスケープされていない `*` `?` か、閉じの `]` のある `[` を含むか
+fn is_glob(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' => return true,
+            '[' if chars.as_str().contains(']') => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// パターンに一致するパスを並べ替えて返す
+fn pathname_expansion(pattern: &str) -> Vec<String> {
+    let (mut paths, rest) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_string()], rest),
+        None => (vec![String::new()], pattern),
+    };
+    let components: Vec<&str> = rest.split('/').collect();
+    for (i, component) in components.iter().enumerate() {
+        let last = i + 1 == components.len();
+        let mut next = Vec::new();
+        for path in &paths {
+            // `dir*/` の末尾の `/` はディレクトリだけに一致させる
+            if component.is_empty() {
+                if Path::new(path).is_dir() {
+                    next.push(format!("{}/", path));
+                }
+                continue;
+            }
+            if !is_glob(component) {
+                let joined = join(path, &pattern::unescape(component));
+                if fs::symlink_metadata(&joined).is_ok() {
+                    next.push(joined);
+                }
+                continue;
+            }
+            let Ok(entries) = fs::read_dir(if path.is_empty() { "." } else { path }) else {
+                continue;
+            };
+            let dot = component.starts_with('.') || component.starts_with("\\.");
+            for entry in entries.flatten() {
+                let Ok(name) = entry.file_name().into_string() else {
+                    continue;
+                };
+                if (name.starts_with('.') && !dot) || !pattern::matches(component, &name) {
+                    continue;
+                }
+                let joined = join(path, &name);
+                if last || Path::new(&joined).is_dir() {
+                    next.push(joined);
+                }
+            }
+        }
+        paths = next;
+    }
+    paths.sort();
+    paths
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() || dir.ends_with('/') { format!("{}{}", dir, name) } else { format!("{}/{}", dir, name) }
+}
+
+/// パラメータ展開やコマンド置換の結果を加える
+fn push_value(fields: &mut Fields, value: &str, quoted: bool) {
+    if quoted {
+        fields.push_quoted(value);
+    } else if fields.pattern {
+        // パターンの中のクォートされていない展開結果はパターンとして働く
+        fields.push_literal(value);
+    } else {
+        fields.push_expansion(value);
+    }
+}
+
+fn expand_param(shell: &mut Shell, param: &Param) -> Result<String> {
+    let name = &param.name;
+    let value = shell.param(name);
+    let is_set = |colon: bool| value.as_ref().is_some_and(|v| !(colon && v.is_empty()));
+    // `set -u`: 既定値などを指定していない、設定されていないパラメータの展開はエラー
+    let checked = matches!(param.op, ParamOp::Value | ParamOp::Length | ParamOp::RemovePrefix { .. } | ParamOp::RemoveSuffix { .. });
+    if shell.nounset && checked && value.is_none() && name != "@" && name != "*" {
+        return Err(ExpandError(format!("{}: パラメータが設定されていません", name)));
+    }
+    match &param.op {
+        ParamOp::Value => Ok(value.unwrap_or_default()),
+        ParamOp::Length => Ok(value.unwrap_or_default().chars().count().to_string()),
+        ParamOp::Default { colon, word } => match is_set(*colon) {
+            true => Ok(value.unwrap_or_default()),
+            false => expand_word(shell, word),
+        },
+        ParamOp::Assign { colon, word } => {
+            if is_set(*colon) {
+                return Ok(value.unwrap_or_default());
+            }
+            if !lexer::is_name(name) {
+                return Err(ExpandError(format!("${}: この方法では代入できません", name)));
+            }
+            let new = expand_word(shell, word)?;
+            shell.vars.set(name, &new).map_err(|e| ExpandError(e.to_string()))?;
+            Ok(new)
+        }
+        ParamOp::Error { colon, word } => {
+            if is_set(*colon) {
+                return Ok(value.unwrap_or_default());
+            }
+            let message = expand_word(shell, word)?;
+            let message = if !message.is_empty() {
+                message
+            } else if value.is_some() {
+                "パラメータが空です".to_string()
+            } else {
+                "パラメータが設定されていません".to_string()
+            };
+            Err(ExpandError(format!("{}: {}", name, message)))
+        }
+        ParamOp::Alternative { colon, word } => match is_set(*colon) {
+            true => expand_word(shell, word),
+            false => Ok(String::new()),
+        },
+        ParamOp::RemovePrefix { longest, pattern } => {
+            let value = value.unwrap_or_default();
+            let pattern = expand_pattern(shell, pattern)?;
+            Ok(pattern::remove_prefix(&value, &pattern, *longest).to_string())
+        }
+        ParamOp::RemoveSuffix { longest, pattern } => {
+            let value = value.unwrap_or_default();
+            let pattern = expand_pattern(shell, pattern)?;
+            Ok(pattern::remove_suffix(&value, &pattern, *longest).to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use horiz_auth::testutil::TempDir;
+
+    use super::*;
+    use crate::lexer::Token;
+
+    fn words(shell: &mut Shell, input: &str) -> Vec<String> {
+        let words: Vec<Word> = lexer::tokenize(input)
+            .unwrap()
+            .into_iter()
+            .filter_map(|t| if let Token::Word(w) = t { Some(w) } else { None })
+            .collect();
+        expand_words(shell, &words).unwrap()
+    }
+
+    /// 最初の単語の展開に失敗したときのメッセージ
+    fn error(shell: &mut Shell, input: &str) -> String {
+        let word = lexer::tokenize(input).unwrap().into_iter().find_map(|t| if let Token::Word(w) = t { Some(w) } else { None });
+        expand_word(shell, &word.unwrap()).unwrap_err().0
+    }
+
+    fn heredoc(shell: &mut Shell, input: &str) -> String {
+        let tokens = lexer::tokenize(input).unwrap();
+        let Some(Token::HereDoc(h)) = tokens.iter().find(|t| matches!(t, Token::HereDoc(_))) else {
+            panic!("ヒアドキュメントがありません");
+        };
+        expand_heredoc(shell, h).unwrap()
+    }
+
+    #[test]
+    fn test_heredoc() {
+        let mut shell = Shell::new(false);
+        shell.last_status = 3;
+        shell.vars.set("X", "x y").unwrap();
+        assert_eq!(heredoc(&mut shell, "cat <<E\n$? \\$? \\\\ \\x \"$?\"\nab\\\ncd $X\nE\n"), "3 $? \\ \\x \"3\"\nabcd x y\n");
+        assert_eq!(heredoc(&mut shell, "cat <<'E'\n$? \\$?\nE\n"), "$? \\$?\n");
+    }
+
+    #[test]
+    fn test_tilde() {
+        let mut shell = Shell::new(false);
+        shell.vars.set("HOME", "/home/a b").unwrap();
+        assert_eq!(words(&mut shell, "~ ~/x a~ '~' ~\"/x\" ~/\"$HOME\""), ["/home/a b", "/home/a b/x", "a~", "~", "~/x", "/home/a b//home/a b"]);
+        assert_eq!(words(&mut shell, "~no-such-user/x"), ["~no-such-user/x"]);
+        assert_eq!(error(&mut shell, "~/'a'${X?unset}"), "X: unset");
+        shell.vars.unset("HOME").unwrap();
+        assert_eq!(words(&mut shell, "~"), ["~"]);
+    }
+
+    #[test]
+    fn test_tilde_assignment() {
+        let mut shell = Shell::new(false);
+        shell.vars.set("HOME", "/home/a").unwrap();
+        shell.vars.set("P", "/bin").unwrap();
+        let assignment = |shell: &mut Shell, input: &str| {
+            let word = lexer::tokenize(input).unwrap().into_iter().find_map(|t| if let Token::Word(w) = t { Some(w) } else { None });
+            expand_assignment(shell, &word.unwrap()).unwrap()
+        };
+        assert_eq!(assignment(&mut shell, "~/bin:~:$P"), "/home/a/bin:/home/a:/bin");
+        assert_eq!(assignment(&mut shell, "/x:a~:'~':\\~/z:~/"), "/x:a~:~:~/z:/home/a/");
+        assert_eq!(assignment(&mut shell, "a:~\"b\":~no-such-user"), "a:~b:~no-such-user");
+        // 代入以外の単語では `:` の後を展開しない
+        assert_eq!(words(&mut shell, "a:~"), ["a:~"]);
+    }
+
+    #[test]
+    fn test_field_splitting() {
+        let mut shell = Shell::new(false);
+        shell.vars.set("A", "  one  two ").unwrap();
+        shell.vars.set("E", "").unwrap();
+        assert_eq!(words(&mut shell, "$A"), ["one", "two"]);
+        assert_eq!(words(&mut shell, "\"$A\""), ["  one  two "]);
+        assert_eq!(words(&mut shell, "x${A}y"), ["x", "one", "two", "y"]);
+        // 空の展開結果はフィールドを作らないが、クォートされた空文字列は作る
+        assert_eq!(words(&mut shell, "$E $UNSET \"$E\" ''"), ["", ""]);
+
+        shell.vars.set("IFS", ":").unwrap();
+        shell.vars.set("P", "a::b:").unwrap();
+        assert_eq!(words(&mut shell, "$P"), ["a", "", "b"]);
+        shell.vars.set("IFS", " :").unwrap();
+        shell.vars.set("P", "a : b").unwrap();
+        assert_eq!(words(&mut shell, "$P"), ["a", "b"]);
+        shell.vars.set("IFS", "").unwrap();
+        assert_eq!(words(&mut shell, "$A"), ["  one  two "]);
+    }
+
+    #[test]
+    fn test_positional() {
+        let mut shell = Shell::new(false);
+        assert_eq!(words(&mut shell, "\"$@\""), Vec::<String>::new());
+        assert_eq!(words(&mut shell, "$# \"$*\""), ["0", ""]);
+        shell.positional = vec!["a b".into(), "c".into()];
+        assert_eq!(words(&mut shell, "\"$@\""), ["a b", "c"]);
+        assert_eq!(words(&mut shell, "\"x$@y\""), ["xa b", "cy"]);
+        assert_eq!(words(&mut shell, "$@"), ["a", "b", "c"]);
+        assert_eq!(words(&mut shell, "\"$*\" $# $1 ${2} $3."), ["a b c", "2", "a", "b", "c", "."]);
+        shell.vars.set("IFS", ",").unwrap();
+        assert_eq!(words(&mut shell, "\"$*\""), ["a b,c"]);
+    }
+
+    #[test]
+    fn test_param_ops() {
+        let mut shell = Shell::new(false);
+        shell.vars.set("E", "").unwrap();
+        shell.vars.set("F", "/usr/lib/libc.so.6").unwrap();
+        let mut word = |input: &str| words(&mut shell, &format!("\"{}\"", input))[0].clone();
+        assert_eq!(word("${U-d} ${E-d} ${E:-d} ${F:-d}"), "d  d /usr/lib/libc.so.6");
+        assert_eq!(word("${U+a} ${E+a} ${E:+a} ${F:+a}"), " a  a");
+        assert_eq!(word("${#F} ${#U}"), "18 0");
+        assert_eq!(word("${F#*/} ${F##*/} ${F%.*} ${F%%.*}"), "usr/lib/libc.so.6 libc.so.6 /usr/lib/libc.so /usr/lib/libc");
+        // クォートされたパターンの文字は文字どおり
+        assert_eq!(word("${F#'*'/}"), "/usr/lib/libc.so.6");
+        assert_eq!(word("${U:-$F}"), "/usr/lib/libc.so.6");
+
+        assert_eq!(word("${N:=new} $N"), "new new");
+        assert_eq!(shell.vars.get("N"), Some("new"));
+
+        assert_eq!(error(&mut shell, "${U?}"), "U: パラメータが設定されていません");
+        assert_eq!(error(&mut shell, "${E:?空です}"), "E: 空です");
+        assert_eq!(error(&mut shell, "${1:=x}"), "$1: この方法では代入できません");
+        shell.vars.set_readonly("R");
+        assert_eq!(error(&mut shell, "${R:=x}"), "R: 読み取り専用の変数です");
+    }
+
+    #[test]
+    fn test_nounset() {
+        let mut shell = Shell::new(false);
+        shell.nounset = true;
+        shell.vars.set("E", "").unwrap();
+        assert_eq!(error(&mut shell, "$U"), "U: パラメータが設定されていません");
+        assert_eq!(error(&mut shell, "${#U}"), "U: パラメータが設定されていません");
+        assert_eq!(error(&mut shell, "$1"), "1: パラメータが設定されていません");
+        assert_eq!(words(&mut shell, "${U-default} ${U:+x} [$E] $@ \"$*\""), ["default", "[]", ""]);
+    }
+
+    #[test]
+    fn test_pathname_expansion() {
+        let dir = TempDir::new("sh-glob");
+        for file in ["b.txt", "a.txt", ".hidden.txt", "x*y", "sub/c.txt", "sub/d.rs", "empty/"] {
+            let path = dir.join(file);
+            fs::create_dir_all(if file.ends_with('/') { &path } else { path.parent().unwrap() }).unwrap();
+            if !file.ends_with('/') {
+                fs::write(&path, "").unwrap();
+            }
+        }
+        let mut shell = Shell::new(false);
+        let d = dir.path().to_str().unwrap().to_string();
+        shell.vars.set("D", &d).unwrap();
+        let glob = |shell: &mut Shell, input: &str| -> Vec<String> {
+            words(shell, input).into_iter().map(|w| w.replace(&d, "D")).collect()
+        };
+        assert_eq!(glob(&mut shell, "$D/*.txt"), ["D/a.txt", "D/b.txt"]);
+        assert_eq!(glob(&mut shell, "$D/.*.txt $D/[ab].t?t"), ["D/.hidden.txt", "D/a.txt", "D/b.txt"]);
+        assert_eq!(glob(&mut shell, "$D/*/c.* $D/*/"), ["D/sub/c.txt", "D/empty/", "D/sub/"]);
+        assert_eq!(glob(&mut shell, "$D/x*y $D/x\\*y"), ["D/x*y", "D/x*y"]);
+        // 一致しなければそのまま、クォートされた文字は文字どおり
+        assert_eq!(glob(&mut shell, "$D/*.rs \"$D\"/'*'.txt [ x ]"), ["D/*.rs", "D/*.txt", "[", "x", "]"]);
+        // クォートされていない展開結果もパス名展開する
+        shell.vars.set("P", &format!("{}/sub/*.rs {}/sub/*.c", d, d)).unwrap();
+        assert_eq!(glob(&mut shell, "$P \"$P\""), ["D/sub/d.rs", "D/sub/*.c", "D/sub/*.rs D/sub/*.c"]);
+        shell.noglob = true;
+        assert_eq!(glob(&mut shell, "$D/*.txt"), ["D/*.txt"]);
+    }
+
+    #[test]
+    fn test_special_params() {
+        let mut shell = Shell::new(false);
+        shell.last_status = 42;
+        let pid = std::process::id().to_string();
+        assert_eq!(words(&mut shell, "$? $$ \"$!\" $0"), ["42", pid.as_str(), "", "horiz-sh"]);
+        shell.last_background = Some(123);
+        assert_eq!(words(&mut shell, "$!"), ["123"]);
+    }
+}
@@ -0,0 +1,151 @@
+// --- コマンド履歴 ---
+//
+// 対話モードで入力した行を `~/.horiz_history` に 1 行ずつ保存する。行を入力するたびにファイルへ
+// 追記し (同時に動いている別のシェルの履歴を上書きしないため)、起動時に読み込んだ行数が上限を
+// 超えていれば古い行を捨ててファイルを書き直す。
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// 履歴ファイルの名前 (ホームディレクトリからの相対パス)
+pub const HISTORY_FILE: &str = ".horiz_history";
+
+/// `HISTSIZE` を指定しない場合に保存する行数
+pub const DEFAULT_SIZE: usize = 1000;
+
+#[derive(Debug, Default)]
+pub struct History {
+    entries: Vec<String>,
+    /// 保存先 (None ならファイルに保存しない)
+    path: Option<PathBuf>,
+    /// 保存する行数の上限
+    size: usize,
+}
+
+impl History {
+    /// ファイルに保存しない履歴
+    pub fn new(size: usize) -> Self {
+        History { entries: Vec::new(), path: None, size }
+    }
+
+    /// ファイルから履歴を読み込む。ファイルがなければ空の履歴から始める。
+    pub fn load(path: PathBuf, size: usize) -> Self {
+        let mut history = History::new(size);
+        if let Ok(bytes) = fs::read(&path) {
+            let text = String::from_utf8_lossy(&bytes);
+            history.entries = text.lines().filter(|line| !line.is_empty()).map(String::from).collect();
+            if history.entries.len() > size {
+                history.entries.drain(..history.entries.len() - size);
+                if let Err(e) = rewrite(&path, &history.entries) {
+                    eprintln!("horiz-sh: {}: 履歴を保存できません: {}", path.display(), e);
+                }
+            }
+        }
+        history.path = Some(path);
+        history
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    /// 行を履歴に加える。空白だけの行、空白で始まる行、直前と同じ行は加えない。
+    pub fn add(&mut self, line: &str) {
+        if line.trim().is_empty() || line.starts_with(char::is_whitespace) || self.entries.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+        if self.entries.len() > self.size {
+            self.entries.remove(0);
+        }
+        if let Some(path) = &self.path
+            && let Err(e) = append(path, line)
+        {
+            eprintln!("horiz-sh: {}: 履歴を保存できません: {}", path.display(), e);
+        }
+    }
+
+    /// `before` より前の行から、`query` を含む最も新しい行を探す。見つかれば行番号と、行の中で
+    /// `query` が現れる位置 (バイト単位) を返す。
+    pub fn search(&self, query: &str, before: usize) -> Option<(usize, usize)> {
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(index, entry)| entry.rfind(query).map(|pos| (index, pos)))
+    }
+}
+
+/// 所有者だけが読み書きできるファイルとして開く (コマンドラインにはパスワードなどを含むことがある)
+fn open(path: &Path, options: &mut OpenOptions) -> io::Result<fs::File> {
+    options.mode(0o600).open(path)
+}
+
+fn append(path: &Path, line: &str) -> io::Result<()> {
+    let mut f = open(path, OpenOptions::new().create(true).append(true))?;
+    writeln!(f, "{}", line)
+}
+
+/// 一時ファイルに書いてから置き換える
+fn rewrite(path: &Path, entries: &[String]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".{}", std::process::id()));
+    let tmp = PathBuf::from(tmp);
+    let mut f = open(&tmp, OpenOptions::new().write(true).create(true).truncate(true))?;
+    for entry in entries {
+        writeln!(f, "{}", entry)?;
+    }
+    drop(f);
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use horiz_auth::testutil::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn test_add() {
+        let mut history = History::new(3);
+        for line in ["a", "", "  ", " secret", "b", "b", "c", "d"] {
+            history.add(line);
+        }
+        assert_eq!(history.entries, ["b", "c", "d"]);
+    }
+
+    #[test]
+    fn test_search() {
+        let mut history = History::new(10);
+        for line in ["echo abc", "ls", "cat abc.txt", "pwd"] {
+            history.add(line);
+        }
+        assert_eq!(history.search("abc", 4), Some((2, 4)));
+        assert_eq!(history.search("abc", 2), Some((0, 5)));
+        assert_eq!(history.search("abc", 0), None);
+        assert_eq!(history.search("", 4), Some((3, 3)));
+        assert_eq!(history.search("none", 4), None);
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = TempDir::new("sh-history");
+        let path = dir.join("history");
+        fs::write(&path, "1\n2\n\n3\n4\n").unwrap();
+        // 上限を超えた古い行はファイルからも捨てる
+        let mut history = History::load(path.clone(), 3);
+        assert_eq!(history.entries, ["2", "3", "4"]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2\n3\n4\n");
+
+        history.add("5");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2\n3\n4\n5\n");
+        let history = History::load(path.clone(), 10);
+        assert_eq!(history.entries, ["2", "3", "4", "5"]);
+    }
+}
@@ -0,0 +1,484 @@
+// --- ジョブ ---
+//
+// `&` で起動したコマンドと、Ctrl-Z で停止したフォアグラウンドのパイプラインをジョブとして記録する。
+// ジョブ制御が有効な場合 (対話モードで端末を操作できる場合)、ジョブはそれぞれ独自のプロセスグループに
+// 属し、シグナルはプロセスグループ全体に送る。ジョブ制御が無効な場合はシェルと同じプロセスグループの
+// まま実行し、各プロセスに送る。
+//
+// ジョブは `%n` (番号)、`%+` `%%` `%` (カレントジョブ)、`%-` (その前のジョブ)、`%name` (コマンドが name で
+// 始まる)、`%?str` (コマンドに str を含む) で指定する。カレントジョブは最後に停止したか
+// バックグラウンドで実行を始めたジョブ。
+
+use std::io;
+
+use crate::ast::{AndOr, Command, CompoundCommand, Pipeline};
+
+/// `kill` で名前を指定できるシグナル
+pub const SIGNALS: &[(&str, i32)] = &[
+    ("HUP", libc::SIGHUP),
+    ("INT", libc::SIGINT),
+    ("QUIT", libc::SIGQUIT),
+    ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1),
+    ("USR2", libc::SIGUSR2),
+    ("PIPE", libc::SIGPIPE),
+    ("ALRM", libc::SIGALRM),
+    ("TERM", libc::SIGTERM),
+    ("CHLD", libc::SIGCHLD),
+    ("CONT", libc::SIGCONT),
+    ("STOP", libc::SIGSTOP),
+    ("TSTP", libc::SIGTSTP),
+    ("TTIN", libc::SIGTTIN),
+    ("TTOU", libc::SIGTTOU),
+    ("WINCH", libc::SIGWINCH),
+];
+
+/// シグナルの名前 (`TERM` / `SIGTERM`、大文字と小文字は区別しない) または番号
+pub fn parse_signal(name: &str) -> Option<i32> {
+    if let Ok(number) = name.parse::<i32>() {
+        return (0..=64).contains(&number).then_some(number);
+    }
+    let upper = name.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    SIGNALS.iter().find(|(n, _)| *n == name).map(|(_, sig)| *sig)
+}
+
+/// シグナル番号の名前 (`SIG` を除いたもの)
+pub fn signal_name(sig: i32) -> Option<&'static str> {
+    SIGNALS.iter().find(|(_, s)| *s == sig).map(|(name, _)| *name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Running,
+    /// 停止したシグナル
+    Stopped(i32),
+    /// 終了ステータス
+    Exited(i32),
+    /// 終了させたシグナル
+    Signaled(i32),
+}
+
+impl State {
+    /// 終了ステータス (シグナルで終了・停止した場合は 128 + シグナル番号)
+    pub fn status(self) -> i32 {
+        match self {
+            State::Running => 0,
+            State::Exited(status) => status,
+            State::Stopped(sig) | State::Signaled(sig) => 128 + sig,
+        }
+    }
+
+    pub fn is_finished(self) -> bool {
+        matches!(self, State::Exited(_) | State::Signaled(_))
+    }
+
+    /// `jobs` で表示する状態
+    fn describe(self) -> String {
+        match self {
+            State::Running => "Running".to_string(),
+            State::Stopped(_) => "Stopped".to_string(),
+            State::Exited(0) => "Done".to_string(),
+            State::Exited(status) => format!("Exit {}", status),
+            State::Signaled(libc::SIGHUP) => "Hangup".to_string(),
+            State::Signaled(libc::SIGINT) => "Interrupt".to_string(),
+            State::Signaled(libc::SIGQUIT) => "Quit".to_string(),
+            State::Signaled(libc::SIGKILL) => "Killed".to_string(),
+            State::Signaled(libc::SIGTERM) => "Terminated".to_string(),
+            State::Signaled(sig) => format!("Signal {}", sig),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Process {
+    pub pid: i32,
+    pub state: State,
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    /// ジョブ番号 (`%n`)。ジョブ表に加えたときに決まる。
+    pub id: usize,
+    /// ジョブのプロセスグループ (ジョブ制御が無効な場合は None)
+    pub pgid: Option<i32>,
+    /// パイプラインの各段のプロセス
+    pub processes: Vec<Process>,
+    /// 表示に使うコマンド
+    pub command: String,
+    /// 停止したときの端末の設定 (再開するときに戻す)
+    pub modes: Option<libc::termios>,
+    /// 状態の変化をまだ表示していない
+    pub changed: bool,
+}
+
+impl Job {
+    pub fn new(pgid: Option<i32>, pids: &[i32], command: String) -> Self {
+        let processes = pids.iter().map(|&pid| Process { pid, state: State::Running }).collect();
+        Job { id: 0, pgid, processes, command, modes: None, changed: false }
+    }
+
+    /// ジョブ全体の状態: 実行中のプロセスがあれば実行中、なければ停止したプロセスがあれば停止、
+    /// すべて終了していれば最後の段の状態
+    pub fn state(&self) -> State {
+        let states = || self.processes.iter().map(|p| p.state);
+        if states().any(|s| s == State::Running) {
+            return State::Running;
+        }
+        states()
+            .find(|s| matches!(s, State::Stopped(_)))
+            .or_else(|| states().next_back())
+            .unwrap_or(State::Exited(0))
+    }
+
+    /// 終了ステータス。`pipefail` では 0 以外で終了した最も右の段のもの。
+    pub fn status(&self, pipefail: bool) -> i32 {
+        let state = self.state();
+        if pipefail && state.is_finished() {
+            let statuses = self.processes.iter().map(|p| p.state.status());
+            return statuses.rev().find(|&s| s != 0).unwrap_or(0);
+        }
+        state.status()
+    }
+
+    /// 最後の段のプロセス ID (`$!`)
+    pub fn last_pid(&self) -> i32 {
+        self.processes.last().map_or(0, |p| p.pid)
+    }
+
+    /// すべてのプロセスが終了するか停止するまで待つ。`untraced` でなければ停止は待たない。
+    /// シグナルで待機が中断されたときに `interrupted` が真を返せば、待つのをやめる。
+    pub fn wait(&mut self, untraced: bool, interrupted: fn() -> bool) {
+        let options = if untraced { libc::WUNTRACED } else { 0 };
+        for process in &mut self.processes {
+            if process.state != State::Running {
+                continue;
+            }
+            match wait_pid(process.pid, options, interrupted) {
+                Some(state) => process.state = state,
+                None => return,
+            }
+        }
+    }
+
+    /// 待たずに状態の変化を調べる。変化があれば true。
+    pub fn poll(&mut self) -> bool {
+        let mut changed = false;
+        for process in &mut self.processes {
+            if !process.state.is_finished()
+                && let Some(state) = wait_pid(process.pid, libc::WNOHANG | libc::WUNTRACED | libc::WCONTINUED, || false)
+                && state != process.state
+            {
+                process.state = state;
+                changed = true;
+            }
+        }
+        self.changed |= changed;
+        changed
+    }
+
+    /// ジョブのプロセスにシグナルを送る
+    pub fn kill(&self, sig: i32) -> io::Result<()> {
+        let targets = match self.pgid {
+            Some(pgid) => vec![-pgid],
+            None => self.processes.iter().filter(|p| !p.state.is_finished()).map(|p| p.pid).collect(),
+        };
+        for target in targets {
+            if unsafe { libc::kill(target, sig) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// 停止したプロセスを実行中として扱う (SIGCONT を送った後)
+    pub fn resume(&mut self) {
+        for process in &mut self.processes {
+            if let State::Stopped(_) = process.state {
+                process.state = State::Running;
+            }
+        }
+    }
+}
+
+/// プロセスの状態の変化を待つ。WNOHANG で変化がない場合と、待機の中断で `interrupted` が真を返した場合は None。
+fn wait_pid(pid: i32, options: i32, interrupted: fn() -> bool) -> Option<State> {
+    let mut status = 0;
+    loop {
+        match unsafe { libc::waitpid(pid, &mut status, options) } {
+            0 => return None,
+            n if n > 0 => break,
+            // すでに回収されたプロセスなど
+            _ if io::Error::last_os_error().kind() != io::ErrorKind::Interrupted => return Some(State::Exited(1)),
+            _ if interrupted() => return None,
+            _ => {}
+        }
+    }
+    Some(if libc::WIFEXITED(status) {
+        State::Exited(libc::WEXITSTATUS(status))
+    } else if libc::WIFSIGNALED(status) {
+        State::Signaled(libc::WTERMSIG(status))
+    } else if libc::WIFSTOPPED(status) {
+        State::Stopped(libc::WSTOPSIG(status))
+    } else {
+        State::Running
+    })
+}
+
+/// ジョブ表
+#[derive(Debug, Default)]
+pub struct Jobs {
+    /// 番号順のジョブ
+    jobs: Vec<Job>,
+    /// ジョブ番号を操作した順に並べたもの (最後がカレントジョブ)
+    order: Vec<usize>,
+}
+
+impl Jobs {
+    /// ジョブを加えて番号を返す。番号がまだなければ、使われている最大の番号の次にする
+    /// (`fg` で再開したジョブが再び停止した場合は元の番号のまま)。
+    pub fn add(&mut self, mut job: Job) -> usize {
+        if job.id == 0 {
+            job.id = self.jobs.last().map_or(1, |last| last.id + 1);
+        }
+        let id = job.id;
+        let index = self.jobs.partition_point(|other| other.id < id);
+        self.jobs.insert(index, job);
+        self.make_current(id);
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Job> {
+        self.order.retain(|&i| i != id);
+        let index = self.jobs.iter().position(|job| job.id == id)?;
+        Some(self.jobs.remove(index))
+    }
+
+    pub fn get(&self, id: usize) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Job> {
+        self.jobs.iter_mut().find(|job| job.id == id)
+    }
+
+    /// 番号順のジョブ番号
+    pub fn ids(&self) -> Vec<usize> {
+        self.jobs.iter().map(|job| job.id).collect()
+    }
+
+    /// ジョブをカレントジョブにする
+    pub fn make_current(&mut self, id: usize) {
+        self.order.retain(|&i| i != id);
+        self.order.push(id);
+    }
+
+    /// プロセス ID のプロセスを含むジョブ
+    pub fn find_pid(&self, pid: i32) -> Option<usize> {
+        self.jobs.iter().find(|job| job.processes.iter().any(|p| p.pid == pid)).map(|job| job.id)
+    }
+
+    /// ジョブの指定 (`%n` など) をジョブ番号にする。`spec` が None ならカレントジョブ。
+    pub fn find(&self, spec: Option<&str>) -> Result<usize, String> {
+        let spec = spec.unwrap_or("%");
+        let Some(body) = spec.strip_prefix('%') else {
+            return Err(format!("{}: ジョブの指定が正しくありません", spec));
+        };
+        let found = match body {
+            "" | "+" | "%" => self.order.last().copied(),
+            "-" => self.order.iter().rev().nth(1).copied(),
+            _ if body.bytes().all(|b| b.is_ascii_digit()) => body.parse().ok().filter(|&id| self.get(id).is_some()),
+            _ => {
+                let matches: Vec<usize> = match body.strip_prefix('?') {
+                    Some(s) => self.jobs.iter().filter(|job| job.command.contains(s)).map(|job| job.id).collect(),
+                    None => self.jobs.iter().filter(|job| job.command.starts_with(body)).map(|job| job.id).collect(),
+                };
+                if matches.len() > 1 {
+                    return Err(format!("{}: 複数のジョブに一致します", spec));
+                }
+                matches.first().copied()
+            }
+        };
+        found.ok_or_else(|| format!("{}: ジョブがありません", spec))
+    }
+
+    /// `+` (カレントジョブ)、`-` (その前のジョブ)、または空白
+    fn mark(&self, id: usize) -> char {
+        let mut recent = self.order.iter().rev();
+        match (recent.next(), recent.next()) {
+            (Some(&current), _) if current == id => '+',
+            (_, Some(&previous)) if previous == id => '-',
+            _ => ' ',
+        }
+    }
+
+    /// `jobs` の形式の 1 行 (`long` ならプロセス ID も表示する)
+    pub fn format(&self, id: usize, long: bool) -> String {
+        let Some(job) = self.get(id) else {
+            return String::new();
+        };
+        let state = job.state();
+        let pid = if long { format!("{} ", job.processes.first().map_or(0, |p| p.pid)) } else { String::new() };
+        let background = if state == State::Running { " &" } else { "" };
+        format!("[{}]{}  {}{:<24}{}{}", id, self.mark(id), pid, state.describe(), job.command, background)
+    }
+
+    /// すべてのジョブの状態の変化を調べる
+    pub fn poll(&mut self) {
+        for job in &mut self.jobs {
+            job.poll();
+        }
+    }
+
+    /// ジョブの状態を表示したものとする。終了したジョブは表から取り除く。
+    pub fn mark_reported(&mut self, id: usize) {
+        let Some(job) = self.get_mut(id) else {
+            return;
+        };
+        job.changed = false;
+        if job.state().is_finished() {
+            self.remove(id);
+        }
+    }
+
+    /// 状態が変化したジョブの行を返し、終了したジョブを表から取り除く
+    pub fn take_changes(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for id in self.ids() {
+            if self.get(id).is_some_and(|job| job.changed) {
+                lines.push(self.format(id, false));
+            }
+            self.mark_reported(id);
+        }
+        lines
+    }
+}
+
+/// ジョブの表示に使う and-or リスト (複合コマンドは先頭の予約語などだけを表示する)
+pub fn describe_and_or(and_or: &AndOr) -> String {
+    let mut out = describe_pipeline(&and_or.first);
+    for (connector, pipeline) in &and_or.rest {
+        out += if *connector == crate::ast::Connector::And { " && " } else { " || " };
+        out += &describe_pipeline(pipeline);
+    }
+    out
+}
+
+fn describe_pipeline(pipeline: &Pipeline) -> String {
+    let commands: Vec<String> = pipeline.commands.iter().map(describe_command).collect();
+    format!("{}{}", if pipeline.negate { "! " } else { "" }, commands.join(" | "))
+}
+
+pub fn describe_command(command: &Command) -> String {
+    match command {
+        Command::Simple(simple) => simple
+            .assignments
+            .iter()
+            .map(|a| format!("{}={}", a.name, a.value.unquoted()))
+            .chain(simple.words.iter().map(|w| w.unquoted()))
+            .collect::<Vec<_>>()
+            .join(" "),
+        Command::Compound(compound, _) => match compound {
+            CompoundCommand::Group(_) => "{ ... }".to_string(),
+            CompoundCommand::Subshell(_) => "( ... )".to_string(),
+            CompoundCommand::If { .. } => "if ... fi".to_string(),
+            CompoundCommand::Loop { until: false, .. } => "while ... done".to_string(),
+            CompoundCommand::Loop { until: true, .. } => "until ... done".to_string(),
+            CompoundCommand::For { name, .. } => format!("for {} ... done", name),
+            CompoundCommand::Case { word, .. } => format!("case {} ... esac", word.unquoted()),
+        },
+        Command::FunctionDef { name, .. } => format!("{}() ...", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+
+    fn job(command: &str, states: &[State]) -> Job {
+        let pids: Vec<i32> = (1..=states.len() as i32).collect();
+        let mut job = Job::new(None, &pids, command.to_string());
+        for (process, state) in job.processes.iter_mut().zip(states) {
+            process.state = *state;
+        }
+        job
+    }
+
+    #[test]
+    fn test_state() {
+        use State::*;
+        assert_eq!(job("a", &[Exited(0), Running]).state(), Running);
+        assert_eq!(job("a", &[Stopped(20), Exited(0)]).state(), Stopped(20));
+        assert_eq!(job("a", &[Exited(1), Exited(0)]).state(), Exited(0));
+        assert_eq!(job("a", &[Exited(1), Signaled(15)]).status(false), 143);
+        assert_eq!(job("a", &[Exited(3), Exited(2), Exited(0)]).status(true), 2);
+        assert_eq!(job("a", &[Stopped(20)]).status(true), 148);
+    }
+
+    #[test]
+    fn test_find() {
+        let mut jobs = Jobs::default();
+        assert_eq!(jobs.find(None), Err("%: ジョブがありません".into()));
+        let a = jobs.add(job("sleep 10", &[State::Running]));
+        let b = jobs.add(job("vi notes", &[State::Stopped(20)]));
+        let c = jobs.add(job("sleep 20", &[State::Running]));
+        assert_eq!((a, b, c), (1, 2, 3));
+
+        assert_eq!(jobs.find(None), Ok(3));
+        assert_eq!(jobs.find(Some("%-")), Ok(2));
+        assert_eq!(jobs.find(Some("%1")), Ok(1));
+        assert_eq!(jobs.find(Some("%vi")), Ok(2));
+        assert_eq!(jobs.find(Some("%?notes")), Ok(2));
+        assert_eq!(jobs.find(Some("%sleep")), Err("%sleep: 複数のジョブに一致します".into()));
+        assert_eq!(jobs.find(Some("%4")), Err("%4: ジョブがありません".into()));
+        assert!(jobs.find(Some("1")).is_err());
+
+        jobs.make_current(1);
+        assert_eq!(jobs.find(Some("%+")), Ok(1));
+        assert_eq!(jobs.find(Some("%-")), Ok(3));
+        jobs.remove(3);
+        // 最大の番号の次の番号を使う
+        assert_eq!(jobs.add(job("x", &[State::Running])), 3);
+        jobs.remove(3);
+        let stopped = jobs.remove(2).unwrap();
+        assert_eq!(jobs.add(job("y", &[State::Running])), 2);
+        // 番号の付いたジョブは元の番号の位置に戻す
+        jobs.remove(1);
+        assert_eq!(jobs.add(Job { id: 1, ..stopped }), 1);
+        assert_eq!(jobs.ids(), [1, 2]);
+        assert_eq!(jobs.find(None), Ok(1));
+    }
+
+    #[test]
+    fn test_format() {
+        let mut jobs = Jobs::default();
+        jobs.add(job("sleep 10", &[State::Running]));
+        jobs.add(job("vi", &[State::Stopped(20)]));
+        assert_eq!(jobs.format(1, false), "[1]-  Running                 sleep 10 &");
+        assert_eq!(jobs.format(2, true), "[2]+  1 Stopped                 vi");
+
+        // 終了したジョブは変化を表示した後で取り除く
+        jobs.get_mut(1).unwrap().processes[0].state = State::Signaled(libc::SIGTERM);
+        jobs.get_mut(1).unwrap().changed = true;
+        assert_eq!(jobs.take_changes(), ["[1]-  Terminated              sleep 10"]);
+        assert_eq!(jobs.ids(), [2]);
+        assert!(jobs.take_changes().is_empty());
+    }
+
+    #[test]
+    fn test_describe() {
+        let program = parser::parse("A=1 echo \"$x\" 'y' | { a; } && (b) || if c; then d; fi").unwrap();
+        assert_eq!(describe_and_or(&program[0]), "A=1 echo ${x} y | { ... } && ( ... ) || if ... fi");
+    }
+
+    #[test]
+    fn test_parse_signal() {
+        assert_eq!(parse_signal("TERM"), Some(libc::SIGTERM));
+        assert_eq!(parse_signal("sigkill"), Some(libc::SIGKILL));
+        assert_eq!(parse_signal("9"), Some(9));
+        assert_eq!(parse_signal("0"), Some(0));
+        assert_eq!(parse_signal("NOPE"), None);
+        assert_eq!(signal_name(libc::SIGTSTP), Some("TSTP"));
+    }
+}
@@ -0,0 +1,951 @@
+// --- 字句解析 (POSIX シェルのトークン分割) ---
+//
+// 入力を単語と演算子に分割する。クォートは取り除かずに単語の部品 (WordPart) として残し、
+// 後の展開や単語分割の段階でクォートされていたかどうかを区別できるようにする。
+//
+// - シングルクォートの内側はすべて文字どおりに扱う
+// - ダブルクォートの内側では `\` は `$` `` ` `` `"` `\` と改行の前でのみエスケープとして働く
+// - クォートの外の `\` は次の 1 文字をクォートする。`\` + 改行は行の継続として取り除く
+// - 単語の先頭の `#` から行末まではコメントとして読み飛ばす
+// - クォートの外とダブルクォートの内側の `$name` `$?` `${...}` はパラメータ展開 (WordPart::Param) とする。
+//   `${name:-word}` などの word は、クォートや入れ子の展開を含む単語として `}` まで読む
+// - `$(...)` と `` `...` `` はコマンド置換 (WordPart::Command) とし、中身をその場で構文解析する。`$(...)` は対応する
+//   `)` までを入れ子のトークンとして読み (case の `pattern)` は閉じとみなさない)、`` `...` `` は次のクォートされていない
+//   `` ` `` までの `\$` `` \` `` `\\` (ダブルクォートの内側では `\"` も) のエスケープを取り除いてから読む
+// - `$((...))` は算術展開 (WordPart::Arith) とし、対応する `))` までをダブルクォートの内側と同様に (ただし `"` は
+//   特別な意味を持たない) 部品に分割する。括弧の対応が `))` で閉じない場合 (`$((cd /; ls) | wc)`) はコマンド置換として読み直す
+// - `<<` / `<<-` の後の単語はヒアドキュメントの区切り文字とし、次の改行の後から区切り文字だけの行までを本文とする
+
+use std::fmt;
+use std::rc::Rc;
+
+use crate::ast::Program;
+use crate::parser::{self, ParseError};
+
+/// 単語の部品
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WordPart {
+    /// クォートされていない文字列
+    Literal(String),
+    /// シングルクォート・バックスラッシュでクォートされた文字列
+    Quoted(String),
+    /// ダブルクォートの内側
+    DoubleQuoted(Vec<WordPart>),
+    /// パラメータ展開
+    Param(Param),
+    /// コマンド置換 (`text` は表示に使う中身の文字列)
+    Command { text: String, program: Rc<Program> },
+    /// 算術展開 (`$((...))` の中の式。展開してから評価する)
+    Arith(Vec<WordPart>),
+}
+
+/// パラメータ展開 (`$name` / `${name<op>word}`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    /// 変数名、位置パラメータの番号、または特殊パラメータ (`?` `$` `!` `#` `@` `*` `-` `0`)
+    pub name: String,
+    pub op: ParamOp,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamOp {
+    /// `$name` / `${name}`
+    Value,
+    /// `${#name}`: 値の文字数
+    Length,
+    /// `${name-word}` / `${name:-word}`: 未設定 (`:` 付きは空も) なら word
+    Default { colon: bool, word: Word },
+    /// `${name=word}` / `${name:=word}`: 未設定なら word を代入する
+    Assign { colon: bool, word: Word },
+    /// `${name?word}` / `${name:?word}`: 未設定ならエラー
+    Error { colon: bool, word: Word },
+    /// `${name+word}` / `${name:+word}`: 設定されていれば word
+    Alternative { colon: bool, word: Word },
+    /// `${name#pattern}` / `${name##pattern}`: 先頭の最短 (最長) 一致を取り除く
+    RemovePrefix { longest: bool, pattern: Word },
+    /// `${name%pattern}` / `${name%%pattern}`: 末尾の最短 (最長) 一致を取り除く
+    RemoveSuffix { longest: bool, pattern: Word },
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (op, word) = match &self.op {
+            ParamOp::Value => return write!(f, "${{{}}}", self.name),
+            ParamOp::Length => return write!(f, "${{#{}}}", self.name),
+            ParamOp::Default { colon, word } => (if *colon { ":-" } else { "-" }, word),
+            ParamOp::Assign { colon, word } => (if *colon { ":=" } else { "=" }, word),
+            ParamOp::Error { colon, word } => (if *colon { ":?" } else { "?" }, word),
+            ParamOp::Alternative { colon, word } => (if *colon { ":+" } else { "+" }, word),
+            ParamOp::RemovePrefix { longest, pattern } => (if *longest { "##" } else { "#" }, pattern),
+            ParamOp::RemoveSuffix { longest, pattern } => (if *longest { "%%" } else { "%" }, pattern),
+        };
+        write!(f, "${{{}{}{}}}", self.name, op, word.unquoted())
+    }
+}
+
+/// 1 文字の特殊パラメータ
+fn is_special_param(c: char) -> bool {
+    "?$!#@*-".contains(c) || c.is_ascii_digit()
+}
+
+/// 変数名として使える文字列 (英字か `_` で始まり、英数字と `_` が続く)
+pub fn is_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Word(pub Vec<WordPart>);
+
+impl Word {
+    /// クォートを取り除いた文字列 (パラメータ展開は `${...}` の形で残す)
+    pub fn unquoted(&self) -> String {
+        fn collect(parts: &[WordPart], out: &mut String) {
+            for part in parts {
+                match part {
+                    WordPart::Literal(s) | WordPart::Quoted(s) => out.push_str(s),
+                    WordPart::DoubleQuoted(inner) => collect(inner, out),
+                    WordPart::Param(param) => out.push_str(&param.to_string()),
+                    WordPart::Command { text, .. } => {
+                        out.push_str("$(");
+                        out.push_str(text);
+                        out.push(')');
+                    }
+                    WordPart::Arith(inner) => {
+                        out.push_str("$((");
+                        collect(inner, out);
+                        out.push_str("))");
+                    }
+                }
+            }
+        }
+        let mut out = String::new();
+        collect(&self.0, &mut out);
+        out
+    }
+
+    /// クォートを含まない単語であればその文字列 (予約語の判定などに使う)
+    pub fn as_literal(&self) -> Option<&str> {
+        match self.0.as_slice() {
+            [WordPart::Literal(s)] => Some(s),
+            _ => None,
+        }
+    }
+
+    fn push_literal(&mut self, c: char) {
+        match self.0.last_mut() {
+            Some(WordPart::Literal(s)) => s.push(c),
+            _ => self.0.push(WordPart::Literal(c.to_string())),
+        }
+    }
+
+    fn push_quoted(&mut self, s: &str) {
+        match self.0.last_mut() {
+            Some(WordPart::Quoted(q)) => q.push_str(s),
+            _ => self.0.push(WordPart::Quoted(s.to_string())),
+        }
+    }
+}
+
+/// 演算子
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Pipe,
+    OrIf,
+    Amp,
+    AndIf,
+    Semi,
+    DSemi,
+    LParen,
+    RParen,
+    Less,
+    Great,
+    DGreat,
+    LessAnd,
+    GreatAnd,
+    LessGreat,
+    DLess,
+    DLessDash,
+    Clobber,
+    AndGreat,
+}
+
+/// 最長一致で判定するため、長いものから並べる
+const OPERATORS: &[(&str, Op)] = &[
+    ("<<-", Op::DLessDash),
+    ("||", Op::OrIf),
+    ("&&", Op::AndIf),
+    (";;", Op::DSemi),
+    (">>", Op::DGreat),
+    ("<&", Op::LessAnd),
+    (">&", Op::GreatAnd),
+    ("<>", Op::LessGreat),
+    ("<<", Op::DLess),
+    (">|", Op::Clobber),
+    ("&>", Op::AndGreat),
+    ("|", Op::Pipe),
+    ("&", Op::Amp),
+    (";", Op::Semi),
+    ("(", Op::LParen),
+    (")", Op::RParen),
+    ("<", Op::Less),
+    (">", Op::Great),
+];
+
+impl Op {
+    pub fn as_str(&self) -> &'static str {
+        OPERATORS.iter().find(|(_, op)| op == self).map(|(s, _)| *s).unwrap_or("")
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// ヒアドキュメント
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HereDoc {
+    /// `<<-`: 本文と区切り行の先頭のタブを取り除く
+    pub strip_tabs: bool,
+    /// 区切り文字がクォートされていなければ、本文の中の `$` などを展開する
+    pub expand: bool,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Word(Word),
+    /// `<<` / `<<-` と区切り文字 (本文は次の改行の後で読み取ったもの)
+    HereDoc(HereDoc),
+    /// リダイレクトの直前のファイル記述子番号 (`2>` の 2)
+    IoNumber(u32),
+    Op(Op),
+    Newline,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LexError {
+    /// クォートが閉じられていない、行末が `\` で終わっている、またはヒアドキュメントの区切り行がない (続きの行が必要)
+    Incomplete,
+    /// `${...}` の書式が正しくない
+    BadSubstitution(String),
+    /// コマンド置換の中の予期しないトークン
+    Unexpected(String),
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexError::Incomplete => write!(f, "予期しない入力の終わりです (クォートが閉じられていません)"),
+            LexError::BadSubstitution(text) => write!(f, "{}: 不正な置換です", text),
+            LexError::Unexpected(token) => write!(f, "予期しないトークン `{}` があります", token),
+        }
+    }
+}
+
+impl From<ParseError> for LexError {
+    fn from(e: ParseError) -> Self {
+        match e {
+            ParseError::Incomplete => LexError::Incomplete,
+            ParseError::Unexpected(token) => LexError::Unexpected(token),
+            ParseError::BadSubstitution(text) => LexError::BadSubstitution(text),
+        }
+    }
+}
+
+/// コマンド置換の中身の構文エラー。閉じた後なので、中身が途中で終わっていても続きの行は読まない。
+fn closed(e: ParseError, close: &str) -> LexError {
+    match e {
+        ParseError::Incomplete => LexError::Unexpected(close.to_string()),
+        e => e.into(),
+    }
+}
+
+/// 並びの区切りの後など、コマンドの先頭 (予約語を認識する位置) になるトークン
+fn starts_command(previous: Option<&Token>) -> bool {
+    match previous {
+        None | Some(Token::Newline) => true,
+        Some(Token::Op(op)) => matches!(op, Op::Semi | Op::Amp | Op::AndIf | Op::OrIf | Op::Pipe | Op::LParen | Op::DSemi),
+        Some(Token::Word(word)) => word.as_literal().is_some_and(|w| ["then", "do", "else", "elif", "{", "!"].contains(&w)),
+        _ => false,
+    }
+}
+
+fn is_blank(c: char) -> bool {
+    c == ' ' || c == '\t'
+}
+
+/// クォートされていなければ単語を区切る文字
+fn is_delimiter(c: char) -> bool {
+    is_blank(c) || c == '\n' || "|&;()<>".contains(c)
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    tokens: Vec<Token>,
+    /// 本文をまだ読んでいないヒアドキュメント (トークンの位置と区切り文字)
+    pending: Vec<(usize, String)>,
+}
+
+impl Lexer {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars().enumerate().all(|(i, c)| self.peek_at(i) == Some(c))
+    }
+
+    /// `\` + 改行 (行の継続) を読み飛ばす。入力の最後であれば続きの行が必要になる。
+    fn skip_continuation(&mut self) -> Result<bool, LexError> {
+        if self.peek() != Some('\\') || self.peek_at(1) != Some('\n') {
+            return Ok(false);
+        }
+        self.pos += 2;
+        if self.peek().is_none() {
+            return Err(LexError::Incomplete);
+        }
+        Ok(true)
+    }
+
+    /// 空白・行の継続・コメントを読み飛ばす
+    fn skip_blanks(&mut self) -> Result<(), LexError> {
+        loop {
+            if self.skip_continuation()? {
+                continue;
+            }
+            match self.peek() {
+                Some(c) if is_blank(c) => self.pos += 1,
+                Some('#') => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn operator(&mut self) -> Option<Op> {
+        let (text, op) = OPERATORS.iter().find(|(text, _)| self.starts_with(text))?;
+        self.pos += text.chars().count();
+        Some(*op)
+    }
+
+    fn word(&mut self) -> Result<Word, LexError> {
+        let mut word = Word::default();
+        while let Some(c) = self.peek() {
+            if self.skip_continuation()? {
+                continue;
+            }
+            if is_delimiter(c) {
+                break;
+            }
+            self.pos += 1;
+            match c {
+                '\'' => {
+                    let start = self.pos;
+                    while self.peek().ok_or(LexError::Incomplete)? != '\'' {
+                        self.pos += 1;
+                    }
+                    let text: String = self.chars[start..self.pos].iter().collect();
+                    self.pos += 1;
+                    word.push_quoted(&text);
+                }
+                '"' => {
+                    let inner = self.double_quoted(false)?;
+                    word.0.push(WordPart::DoubleQuoted(inner.0));
+                }
+                '\\' => match self.peek() {
+                    None => return Err(LexError::Incomplete),
+                    Some(next) => {
+                        self.pos += 1;
+                        word.push_quoted(&next.to_string());
+                    }
+                },
+                '$' => self.dollar(&mut word, false)?,
+                '`' => word.0.push(self.backquoted(false)?),
+                _ => word.push_literal(c),
+            }
+        }
+        Ok(word)
+    }
+
+    /// `$` の直後から。パラメータ展開とコマンド置換でなければ `$` をそのまま文字として扱う。
+    fn dollar(&mut self, word: &mut Word, in_dquote: bool) -> Result<(), LexError> {
+        let name = match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                if self.peek() == Some('(') {
+                    let start = self.pos;
+                    self.pos += 1;
+                    if let Some(part) = self.arith()? {
+                        word.0.push(part);
+                        return Ok(());
+                    }
+                    self.pos = start;
+                }
+                let part = self.command_subst()?;
+                word.0.push(part);
+                return Ok(());
+            }
+            Some('{') => {
+                self.pos += 1;
+                let param = self.braced_param(in_dquote)?;
+                word.0.push(WordPart::Param(param));
+                return Ok(());
+            }
+            Some(c) if is_special_param(c) => {
+                self.pos += 1;
+                c.to_string()
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => self.name(),
+            _ => {
+                word.push_literal('$');
+                return Ok(());
+            }
+        };
+        word.0.push(WordPart::Param(Param { name, op: ParamOp::Value }));
+        Ok(())
+    }
+
+    /// `$(` の直後から、対応する `)` まで
+    fn command_subst(&mut self) -> Result<WordPart, LexError> {
+        let start = self.pos;
+        let outer = (std::mem::take(&mut self.tokens), std::mem::take(&mut self.pending));
+        let inner = self.nested_tokens();
+        let tokens = std::mem::replace(&mut self.tokens, outer.0);
+        self.pending = outer.1;
+        inner?;
+        let text: String = self.chars[start..self.pos - 1].iter().collect();
+        let program = parser::parse_tokens(tokens).map_err(|e| closed(e, ")"))?;
+        Ok(WordPart::Command { text, program: Rc::new(program) })
+    }
+
+    /// `$((` の直後から、対応する `))` まで。`))` で閉じない場合は None (コマンド置換として読み直す)
+    fn arith(&mut self) -> Result<Option<WordPart>, LexError> {
+        let mut inner = Word::default();
+        let mut depth = 0;
+        loop {
+            let c = self.peek().ok_or(LexError::Incomplete)?;
+            self.pos += 1;
+            match c {
+                '(' => {
+                    depth += 1;
+                    inner.push_literal(c);
+                }
+                ')' if depth > 0 => {
+                    depth -= 1;
+                    inner.push_literal(c);
+                }
+                ')' if self.peek() == Some(')') => {
+                    self.pos += 1;
+                    return Ok(Some(WordPart::Arith(inner.0)));
+                }
+                ')' => return Ok(None),
+                '\\' => match self.peek() {
+                    Some('\n') => self.pos += 1,
+                    Some(next @ ('$' | '`' | '\\')) => {
+                        self.pos += 1;
+                        inner.push_quoted(&next.to_string());
+                    }
+                    None => return Err(LexError::Incomplete),
+                    _ => inner.push_literal('\\'),
+                },
+                '$' => self.dollar(&mut inner, true)?,
+                '`' => inner.0.push(self.backquoted(false)?),
+                _ => inner.push_literal(c),
+            }
+        }
+    }
+
+    /// 対応する `)` までのトークンを self.tokens に読む (`)` は読み飛ばす)
+    fn nested_tokens(&mut self) -> Result<(), LexError> {
+        let (mut depth, mut cases) = (0, 0);
+        loop {
+            let command_start = starts_command(self.tokens.last());
+            let token = self.next_token()?.ok_or(LexError::Incomplete)?;
+            match &token {
+                Token::Op(Op::LParen) => depth += 1,
+                Token::Op(Op::RParen) if depth > 0 => depth -= 1,
+                Token::Op(Op::RParen) if cases == 0 => break,
+                Token::Word(word) if command_start && word.as_literal() == Some("case") => cases += 1,
+                Token::Word(word) if command_start && cases > 0 && word.as_literal() == Some("esac") => cases -= 1,
+                _ => {}
+            }
+            let newline = token == Token::Newline;
+            self.tokens.push(token);
+            if newline {
+                self.read_heredoc_bodies()?;
+            }
+        }
+        if !self.pending.is_empty() {
+            return Err(LexError::Incomplete);
+        }
+        Ok(())
+    }
+
+    /// 開きの `` ` `` の直後から、閉じの `` ` `` まで
+    fn backquoted(&mut self, in_dquote: bool) -> Result<WordPart, LexError> {
+        let mut text = String::new();
+        loop {
+            let c = self.peek().ok_or(LexError::Incomplete)?;
+            self.pos += 1;
+            match c {
+                '`' => break,
+                '\\' => match self.peek() {
+                    Some(next @ ('$' | '`' | '\\')) => {
+                        self.pos += 1;
+                        text.push(next);
+                    }
+                    Some('"') if in_dquote => {
+                        self.pos += 1;
+                        text.push('"');
+                    }
+                    _ => text.push('\\'),
+                },
+                _ => text.push(c),
+            }
+        }
+        let program = parser::parse(&text).map_err(|e| closed(e, "`"))?;
+        Ok(WordPart::Command { text, program: Rc::new(program) })
+    }
+
+    fn name(&mut self) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_') {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// `${` の直後から、閉じの `}` まで
+    fn braced_param(&mut self, in_dquote: bool) -> Result<Param, LexError> {
+        let start = self.pos;
+        let bad = |lexer: &mut Self| {
+            while lexer.peek().is_some_and(|c| c != '}') {
+                lexer.pos += 1;
+            }
+            let text: String = lexer.chars[start..lexer.pos].iter().collect();
+            LexError::BadSubstitution(format!("${{{}}}", text))
+        };
+        // `${#name}` は長さ。`${#}` と `${#-word}` などは特殊パラメータ `#`
+        let length = self.peek() == Some('#') && self.peek_at(1).is_some_and(|c| c != '}' && !"-=?+:%#".contains(c));
+        if length {
+            self.pos += 1;
+        }
+        let name = match self.peek().ok_or(LexError::Incomplete)? {
+            c if c.is_ascii_digit() => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+                self.chars[start..self.pos].iter().collect()
+            }
+            c if is_special_param(c) => {
+                self.pos += 1;
+                c.to_string()
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => self.name(),
+            _ => return Err(bad(self)),
+        };
+        if self.peek().is_none() {
+            return Err(LexError::Incomplete);
+        }
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            let op = if length { ParamOp::Length } else { ParamOp::Value };
+            return Ok(Param { name, op });
+        }
+        if length {
+            return Err(bad(self));
+        }
+        let colon = self.peek() == Some(':');
+        if colon {
+            self.pos += 1;
+        }
+        let Some(c) = self.peek() else {
+            return Err(LexError::Incomplete);
+        };
+        if !"-=?+#%".contains(c) || (colon && "#%".contains(c)) {
+            return Err(bad(self));
+        }
+        self.pos += 1;
+        let longest = "#%".contains(c) && self.peek() == Some(c);
+        if longest {
+            self.pos += 1;
+        }
+        let word = self.param_word(in_dquote)?;
+        let op = match c {
+            '-' => ParamOp::Default { colon, word },
+            '=' => ParamOp::Assign { colon, word },
+            '?' => ParamOp::Error { colon, word },
+            '+' => ParamOp::Alternative { colon, word },
+            '#' => ParamOp::RemovePrefix { longest, pattern: word },
+            _ => ParamOp::RemoveSuffix { longest, pattern: word },
+        };
+        Ok(Param { name, op })
+    }
+
+    /// `${name:-word}` の word (閉じの `}` まで。空白も含む)
+    fn param_word(&mut self, in_dquote: bool) -> Result<Word, LexError> {
+        let mut word = Word::default();
+        loop {
+            if self.skip_continuation()? {
+                continue;
+            }
+            let c = self.peek().ok_or(LexError::Incomplete)?;
+            self.pos += 1;
+            match c {
+                '}' => return Ok(word),
+                '\'' if !in_dquote => {
+                    let start = self.pos;
+                    while self.peek().ok_or(LexError::Incomplete)? != '\'' {
+                        self.pos += 1;
+                    }
+                    let text: String = self.chars[start..self.pos].iter().collect();
+                    self.pos += 1;
+                    word.push_quoted(&text);
+                }
+                '"' => {
+                    let inner = self.double_quoted(false)?;
+                    word.0.push(WordPart::DoubleQuoted(inner.0));
+                }
+                '\\' => {
+                    let next = self.peek().ok_or(LexError::Incomplete)?;
+                    if in_dquote && !"$`\"\\}".contains(next) {
+                        word.push_literal('\\');
+                    } else {
+                        self.pos += 1;
+                        word.push_quoted(&next.to_string());
+                    }
+                }
+                '$' => self.dollar(&mut word, in_dquote)?,
+                '`' => word.0.push(self.backquoted(in_dquote)?),
+                _ => word.push_literal(c),
+            }
+        }
+    }
+
+    /// 開きのダブルクォートの直後から、閉じのダブルクォートまで。
+    /// `heredoc` が真の場合はヒアドキュメントの本文として入力の最後まで読む (`"` は特別な意味を持たない)。
+    fn double_quoted(&mut self, heredoc: bool) -> Result<Word, LexError> {
+        let mut inner = Word::default();
+        loop {
+            let Some(c) = self.peek() else {
+                return if heredoc { Ok(inner) } else { Err(LexError::Incomplete) };
+            };
+            self.pos += 1;
+            match c {
+                '"' if !heredoc => return Ok(inner),
+                '\\' => match self.peek() {
+                    Some('\n') => self.pos += 1,
+                    Some(next @ ('$' | '`' | '\\')) => {
+                        self.pos += 1;
+                        inner.push_quoted(&next.to_string());
+                    }
+                    Some('"') if !heredoc => {
+                        self.pos += 1;
+                        inner.push_quoted("\"");
+                    }
+                    None if !heredoc => return Err(LexError::Incomplete),
+                    _ => inner.push_literal('\\'),
+                },
+                '$' => self.dollar(&mut inner, true)?,
+                '`' => inner.0.push(self.backquoted(!heredoc)?),
+                _ => inner.push_literal(c),
+            }
+        }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, LexError> {
+        self.skip_blanks()?;
+        let Some(c) = self.peek() else {
+            return Ok(None);
+        };
+        if c == '\n' {
+            self.pos += 1;
+            return Ok(Some(Token::Newline));
+        }
+        if let Some(op) = self.operator() {
+            if matches!(op, Op::DLess | Op::DLessDash) {
+                self.skip_blanks()?;
+                if self.peek().is_some_and(|c| !is_delimiter(c)) {
+                    let delimiter = self.word()?;
+                    let quoted = delimiter.0.iter().any(|part| !matches!(part, WordPart::Literal(_)));
+                    self.pending.push((self.tokens.len(), delimiter.unquoted()));
+                    let strip_tabs = op == Op::DLessDash;
+                    return Ok(Some(Token::HereDoc(HereDoc { strip_tabs, expand: !quoted, body: String::new() })));
+                }
+            }
+            return Ok(Some(Token::Op(op)));
+        }
+        let word = self.word()?;
+        // 数字のみの単語の直後に < か > が続く場合はファイル記述子番号
+        if let Some(n) = word.as_literal().filter(|s| s.bytes().all(|b| b.is_ascii_digit())).and_then(|s| s.parse().ok())
+            && matches!(self.peek(), Some('<' | '>'))
+        {
+            return Ok(Some(Token::IoNumber(n)));
+        }
+        Ok(Some(Token::Word(word)))
+    }
+
+    /// 改行の直後で、保留中のヒアドキュメントの本文を順に読み取る
+    fn read_heredoc_bodies(&mut self) -> Result<(), LexError> {
+        for (index, delimiter) in std::mem::take(&mut self.pending) {
+            let strip_tabs = matches!(&self.tokens[index], Token::HereDoc(h) if h.strip_tabs);
+            let mut body = String::new();
+            loop {
+                if self.peek().is_none() {
+                    return Err(LexError::Incomplete);
+                }
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+                let mut line: String = self.chars[start..self.pos].iter().collect();
+                self.pos += 1; // 改行
+                if strip_tabs {
+                    line = line.trim_start_matches('\t').to_string();
+                }
+                if line == delimiter {
+                    break;
+                }
+                body.push_str(&line);
+                body.push('\n');
+            }
+            if let Token::HereDoc(heredoc) = &mut self.tokens[index] {
+                heredoc.body = body;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 展開するヒアドキュメントの本文を、ダブルクォートの内側と同じ規則で部品に分割する
+pub fn heredoc_parts(body: &str) -> Result<Vec<WordPart>, LexError> {
+    let mut lexer = Lexer { chars: body.chars().collect(), pos: 0, tokens: Vec::new(), pending: Vec::new() };
+    Ok(lexer.double_quoted(true)?.0)
+}
+
+/// 入力全体をトークンに分割する
+pub fn tokenize(input: &str) -> Result<Vec<Token>, LexError> {
+    let mut lexer = Lexer { chars: input.chars().collect(), pos: 0, tokens: Vec::new(), pending: Vec::new() };
+    while let Some(token) = lexer.next_token()? {
+        let newline = token == Token::Newline;
+        lexer.tokens.push(token);
+        if newline {
+            lexer.read_heredoc_bodies()?;
+        }
+    }
+    if !lexer.pending.is_empty() {
+        return Err(LexError::Incomplete);
+    }
+    Ok(lexer.tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 単語はクォートを取り除いた文字列、演算子はそのままの表記で並べる
+    fn lex(input: &str) -> Vec<String> {
+        tokenize(input)
+            .unwrap()
+            .iter()
+            .map(|t| match t {
+                Token::Word(w) => w.unquoted(),
+                Token::HereDoc(h) => format!("heredoc:{}", h.body),
+                Token::IoNumber(n) => format!("io:{}", n),
+                Token::Op(op) => op.to_string(),
+                Token::Newline => "\\n".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_quotes() {
+        assert_eq!(lex(r#"echo "hello world""#), ["echo", "hello world"]);
+        assert_eq!(lex("cat 'my file'"), ["cat", "my file"]);
+        assert_eq!(lex(r#"a"b c"'d e'f"#), ["ab cd ef"]);
+        assert_eq!(lex("'' \"\""), ["", ""]);
+        // シングルクォートの内側ではバックスラッシュも文字どおり
+        assert_eq!(lex(r"'a\b' 'it'\''s'"), [r"a\b", "it's"]);
+        assert_eq!(lex(r#"'"' "'""#), ["\"", "'"]);
+        assert_eq!(lex("'a|b;c'"), ["a|b;c"]);
+    }
+
+    #[test]
+    fn test_backslash() {
+        assert_eq!(lex(r"a\ b c"), ["a b", "c"]);
+        assert_eq!(lex(r#"\'\"\\"#), [r#"'"\"#]);
+        // ダブルクォートの内側では特定の文字の前でのみエスケープになる
+        assert_eq!(lex(r#""a\"b" "c\\d" "e\f" "\$x""#), ["a\"b", r"c\d", r"e\f", "$x"]);
+        assert_eq!(lex(r"a\|b"), ["a|b"]);
+
+        let tokens = tokenize(r"\a").unwrap();
+        assert_eq!(tokens, [Token::Word(Word(vec![WordPart::Quoted("a".into())]))]);
+        let tokens = tokenize(r#"x"y\$""#).unwrap();
+        assert_eq!(
+            tokens,
+            [Token::Word(Word(vec![
+                WordPart::Literal("x".into()),
+                WordPart::DoubleQuoted(vec![WordPart::Literal("y".into()), WordPart::Quoted("$".into())]),
+            ]))]
+        );
+    }
+
+    #[test]
+    fn test_comments_and_continuation() {
+        assert_eq!(lex("echo a # comment | b\nls"), ["echo", "a", "\\n", "ls"]);
+        assert_eq!(lex("# only comment"), Vec::<String>::new());
+        // 単語の途中の # はコメントではない
+        assert_eq!(lex("a#b '#c' \"#d\""), ["a#b", "#c", "#d"]);
+        assert_eq!(lex("echo a\\\nb \\\n c"), ["echo", "ab", "c"]);
+        assert_eq!(lex("\"a\\\nb\""), ["ab"]);
+        // シングルクォートの内側では継続にならない
+        assert_eq!(lex("'a\\\nb'"), ["a\\\nb"]);
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(lex("a|b&&c||d;e&"), ["a", "|", "b", "&&", "c", "||", "d", ";", "e", "&"]);
+        assert_eq!(lex("(a);;b"), ["(", "a", ")", ";;", "b"]);
+        assert_eq!(lex("a>f>>g<h<>i>|j&>k"), ["a", ">", "f", ">>", "g", "<", "h", "<>", "i", ">|", "j", "&>", "k"]);
+        assert_eq!(lex("cmd 2>&1 >&2 <&0"), ["cmd", "io:2", ">&", "1", ">&", "2", "<&", "0"]);
+        // 数字のみでない単語や、空白を挟んだ数字はファイル記述子番号ではない
+        assert_eq!(lex("a2>f 2 >g '2'>h"), ["a2", ">", "f", "2", ">", "g", "2", ">", "h"]);
+    }
+
+    #[test]
+    fn test_heredoc() {
+        assert_eq!(lex("cat <<EOF\nhello\n  world\nEOF\necho x\n"), ["cat", "heredoc:hello\n  world\n", "\\n", "echo", "x", "\\n"]);
+        // 同じ行の複数のヒアドキュメントは順に読む
+        assert_eq!(lex("a <<A; b <<-B\n1\nA\n\t2\n\tB\n"), ["a", "heredoc:1\n", ";", "b", "heredoc:2\n", "\\n"]);
+        assert_eq!(lex("cat <<EOF\nEOF"), ["cat", "heredoc:", "\\n"]);
+        // 本文の中のクォートやコメントはそのまま
+        assert_eq!(lex("cat <<E\n'a' # b\nE\n"), ["cat", "heredoc:'a' # b\n", "\\n"]);
+
+        let tokens = tokenize("cat <<'EOF' <<E\"F\" <<EOF\nx\nEOF\nEF\nEOF\n").unwrap();
+        let expand: Vec<bool> = tokens.iter().filter_map(|t| if let Token::HereDoc(h) = t { Some(h.expand) } else { None }).collect();
+        assert_eq!(expand, [false, false, true]);
+
+        assert_eq!(tokenize("cat <<EOF\nabc\n"), Err(LexError::Incomplete));
+        assert_eq!(tokenize("cat <<EOF"), Err(LexError::Incomplete));
+        // 区切り文字がない場合は演算子のまま (構文エラーは構文解析で報告する)
+        assert_eq!(lex("cat <<\n"), ["cat", "<<", "\\n"]);
+    }
+
+    #[test]
+    fn test_incomplete() {
+        for input in ["echo \"abc", "echo 'abc", "echo abc\\", "\"a\\", "echo a\\\n", "echo a \\\n"] {
+            assert_eq!(tokenize(input), Err(LexError::Incomplete), "{}", input);
+        }
+        assert!(tokenize("echo \"abc\ndef\"").is_ok());
+    }
+
+    fn param(name: &str, op: ParamOp) -> WordPart {
+        WordPart::Param(Param { name: name.into(), op })
+    }
+
+    fn literal(s: &str) -> Word {
+        Word(vec![WordPart::Literal(s.into())])
+    }
+
+    #[test]
+    fn test_params() {
+        let word = |input: &str| match tokenize(input).unwrap().as_slice() {
+            [Token::Word(w)] => w.0.clone(),
+            tokens => panic!("{:?}", tokens),
+        };
+        assert_eq!(word("$HOME/x"), [param("HOME", ParamOp::Value), WordPart::Literal("/x".into())]);
+        assert_eq!(word("$12"), [param("1", ParamOp::Value), WordPart::Literal("2".into())]);
+        assert_eq!(word("${12}"), [param("12", ParamOp::Value)]);
+        assert_eq!(word("$?$$$#"), [param("?", ParamOp::Value), param("$", ParamOp::Value), param("#", ParamOp::Value)]);
+        assert_eq!(lex("a$ $ $%x"), ["a$", "$", "$%x"]);
+        assert_eq!(word("$-x"), [param("-", ParamOp::Value), WordPart::Literal("x".into())]);
+        assert_eq!(word("${#PATH}"), [param("PATH", ParamOp::Length)]);
+        assert_eq!(word("${#}"), [param("#", ParamOp::Value)]);
+        assert_eq!(word("${x:-a b}"), [param("x", ParamOp::Default { colon: true, word: literal("a b") })]);
+        assert_eq!(word("${x=}"), [param("x", ParamOp::Assign { colon: false, word: Word::default() })]);
+        assert_eq!(word("${x##*/}"), [param("x", ParamOp::RemovePrefix { longest: true, pattern: literal("*/") })]);
+        assert_eq!(word("${x%.*}"), [param("x", ParamOp::RemoveSuffix { longest: false, pattern: literal(".*") })]);
+        assert_eq!(
+            word("${x:+'}'$y}"),
+            [param(
+                "x",
+                ParamOp::Alternative {
+                    colon: true,
+                    word: Word(vec![WordPart::Quoted("}".into()), param("y", ParamOp::Value)])
+                }
+            )]
+        );
+        // ダブルクォートの内側、シングルクォートの内側
+        assert_eq!(word("\"$x\"'$y'"), [WordPart::DoubleQuoted(vec![param("x", ParamOp::Value)]), WordPart::Quoted("$y".into())]);
+        assert_eq!(lex("${x:-a b}c ${y}"), ["${x:-a b}c", "${y}"]);
+
+        assert_eq!(tokenize("${x"), Err(LexError::Incomplete));
+        assert_eq!(tokenize("${x:-abc"), Err(LexError::Incomplete));
+        assert_eq!(tokenize("${x!y}"), Err(LexError::BadSubstitution("${x!y}".into())));
+        assert_eq!(tokenize("${}"), Err(LexError::BadSubstitution("${}".into())));
+        assert_eq!(tokenize("${x:#y}"), Err(LexError::BadSubstitution("${x:#y}".into())));
+
+        assert_eq!(heredoc_parts("a \"$x\"\\$\n").unwrap(), [
+            WordPart::Literal("a \"".into()),
+            param("x", ParamOp::Value),
+            WordPart::Literal("\"".into()),
+            WordPart::Quoted("$".into()),
+            WordPart::Literal("\n".into()),
+        ]);
+    }
+
+    #[test]
+    fn test_command_subst() {
+        let command = |input: &str| match tokenize(input).unwrap().as_slice() {
+            [Token::Word(Word(parts))] => match parts.as_slice() {
+                [WordPart::Command { text, program }] | [WordPart::DoubleQuoted(_), WordPart::Command { text, program }] => {
+                    (text.clone(), program.len())
+                }
+                parts => panic!("{:?}", parts),
+            },
+            tokens => panic!("{:?}", tokens),
+        };
+        assert_eq!(command("$(echo a; echo b)"), ("echo a; echo b".into(), 2));
+        assert_eq!(command("\"\"$( (cd /; ls) | wc -l )"), (" (cd /; ls) | wc -l ".into(), 1));
+        assert_eq!(command("$(case $x in a) echo \")\";; esac)"), ("case $x in a) echo \")\";; esac".into(), 1));
+        assert_eq!(command("`echo \\`date\\``"), ("echo `date`".into(), 1));
+        assert_eq!(lex("a$(echo b)c \"$(echo 'd e')\" `echo f`"), ["a$(echo b)c", "$(echo 'd e')", "$(echo f)"]);
+        assert_eq!(lex("${n:-$(nproc)}"), ["${n:-$(nproc)}"]);
+        assert_eq!(lex("x=$(cat <<EOF\nbody\nEOF\n)"), ["x=$(cat <<EOF\nbody\nEOF\n)"]);
+
+        assert_eq!(tokenize("echo $(echo"), Err(LexError::Incomplete));
+        assert_eq!(tokenize("echo `echo"), Err(LexError::Incomplete));
+        assert_eq!(tokenize("echo $(if)"), Err(LexError::Unexpected(")".into())));
+    }
+
+    #[test]
+    fn test_arith() {
+        assert_eq!(tokenize("$((1 + (2 * $x)))").unwrap(), [Token::Word(Word(vec![WordPart::Arith(vec![
+            WordPart::Literal("1 + (2 * ".into()),
+            WordPart::Param(Param { name: "x".into(), op: ParamOp::Value }),
+            WordPart::Literal(")".into()),
+        ])]))]);
+        assert_eq!(lex("i=$((i+1)) \"$(( ${#a} * `echo 2` ))\""), ["i=$((i+1))", "$(( ${#a} * $(echo 2) ))"]);
+        // `))` で閉じないものはコマンド置換
+        assert_eq!(lex("$((cd /; ls) | wc -l)"), ["$((cd /; ls) | wc -l)"]);
+        assert!(matches!(tokenize("$((cd /; ls) | wc -l)").unwrap().as_slice(), [Token::Word(Word(parts))] if matches!(parts.as_slice(), [WordPart::Command { .. }])));
+        assert_eq!(tokenize("echo $((1 + 2"), Err(LexError::Incomplete));
+    }
+
+    #[test]
+    fn test_multibyte() {
+        assert_eq!(lex("echo 'こんにちは 世界' 日本語\\ テキスト"), ["echo", "こんにちは 世界", "日本語 テキスト"]);
+    }
+}
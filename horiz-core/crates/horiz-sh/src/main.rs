@@ -1,10 +1,174 @@
+// --- horiz-sh: HorizOS のシェル ---
+//
+// `sh file [引数...]` はファイルを、`sh -c 'コマンド' [名前 [引数...]]` は文字列を実行する。どちらも
+// 指定しない場合は標準入力からコマンドを読み、標準入力が端末であれば (または `-i` を指定すれば)
+// 対話モードとしてプロンプトを表示する。対話モードで標準入力が端末の場合は、行エディタで入力を編集し、
+// 履歴を `~/.horiz_history` に保存する。Tab でコマンド名・ファイル名・変数名を補完する。
+// 対話モードではジョブ制御を行い、Ctrl-C や Ctrl-Z はシェルではなく実行中のジョブに届く。
+
 use std::env;
 use std::fs;
-use std::io::{self, Write};
+use std::io;
 use std::path::Path;
-use std::process::Command;
+use std::process;
+
+mod arith;
+mod ast;
+mod builtins;
+mod complete;
+mod cond;
+mod editor;
+mod exec;
+mod expand;
+mod history;
+mod jobs;
+mod lexer;
+mod parser;
+mod pattern;
+mod redirect;
+mod source;
+mod vars;
+
+use editor::Editor;
+use exec::{Interrupt, Shell};
+use history::History;
+use source::Input;
+
+const USAGE: &str = "Usage: horiz-sh [-efinux] [-o option] [-c command [name [args ...]] | -s [args ...] | file [args ...]]";
+
+/// スクリプトのファイルが見つからない場合の終了ステータス
+const NOT_FOUND_STATUS: i32 = 127;
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Args {
+    /// `-c`: 実行するコマンド文字列
+    command: Option<String>,
+    /// `-s`: 引数があっても標準入力からコマンドを読む
+    stdin: bool,
+    /// `-i`: 対話モード
+    interactive: bool,
+    /// `-e` `+x` `-o pipefail` などで変更するオプション (名前と、有効にするか)
+    options: Vec<(String, bool)>,
+    /// オプション以外の引数 (スクリプトのファイル名または `$0`、続く位置パラメータ)
+    operands: Vec<String>,
+}
+
+// --- カスタム引数パーサー ---
+// 最初のオプション以外の引数以降は、スクリプトのファイル名 (`-c` では `$0`) と位置パラメータとして扱う。
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let enable = arg.starts_with('-');
+        let flags = match arg.strip_prefix('-').or_else(|| arg.strip_prefix('+')) {
+            _ if arg == "--" => break,
+            Some(flags) if !flags.is_empty() => flags,
+            _ => {
+                parsed.operands.push(arg.clone());
+                break;
+            }
+        };
+        for flag in flags.chars() {
+            match flag {
+                'c' if enable => parsed.command = Some(String::new()),
+                's' if enable => parsed.stdin = true,
+                'i' if enable => parsed.interactive = true,
+                'o' => {
+                    let name = iter.next().ok_or("Missing value for -o")?;
+                    if !exec::OPTIONS.iter().any(|(option, _)| option == name) {
+                        return Err(format!("Unknown option: {}", name));
+                    }
+                    parsed.options.push((name.clone(), enable));
+                }
+                _ => match exec::OPTIONS.iter().find(|(_, f)| *f == Some(flag)) {
+                    Some((name, _)) => parsed.options.push((name.to_string(), enable)),
+                    None => return Err(format!("Unknown argument: {}{}", &arg[..1], flag)),
+                },
+            }
+        }
+    }
+    parsed.operands.extend(iter.cloned());
+    if parsed.command.is_some() {
+        if parsed.operands.is_empty() {
+            return Err("-c にはコマンドを指定してください".into());
+        }
+        parsed.command = Some(parsed.operands.remove(0));
+    }
+    Ok(parsed)
+}
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = match parse_args(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("horiz-sh: {}", e);
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    let reads_stdin = args.command.is_none() && (args.stdin || args.operands.is_empty());
+    let terminal = reads_stdin && unsafe { libc::isatty(0) } == 1;
+    let interactive = args.interactive || terminal;
+
+    // 入力元と、`$0` / 位置パラメータ
+    let mut operands = args.operands.into_iter();
+    let (mut input, name) = if let Some(command) = args.command {
+        (Input::text(command, Some("-c".into())), operands.next())
+    } else if reads_stdin {
+        (Input::stdin(), None)
+    } else {
+        let path = operands.next().unwrap_or_default();
+        match Input::file(Path::new(&path)) {
+            Ok(input) => (input, Some(path)),
+            Err(e) => {
+                eprintln!("horiz-sh: {}: {}", path, e);
+                process::exit(if e.kind() == io::ErrorKind::NotFound { NOT_FOUND_STATUS } else { 126 });
+            }
+        }
+    };
+
+    let mut shell = Shell::new(interactive);
+    if let Some(name) = name {
+        shell.name = name;
+    }
+    shell.positional = operands.collect();
+    for (name, enable) in &args.options {
+        if let Some(option) = shell.option_mut(name) {
+            *option = *enable;
+        }
+    }
+
+    if interactive && terminal {
+        input = Input::terminal(Editor::new(load_history(&shell)));
+    }
+
+    let status = if interactive { interact(&mut shell, input) } else { run(&mut shell, input) };
+    shell.release_terminal();
+    process::exit(status);
+}
+
+/// `$HOME` の履歴ファイルを読み込む。保存する行数は `$HISTSIZE` で変更できる。
+fn load_history(shell: &Shell) -> History {
+    let size = shell.vars.get("HISTSIZE").and_then(|s| s.parse().ok()).unwrap_or(history::DEFAULT_SIZE);
+    match shell.vars.get("HOME") {
+        Some(home) if !home.is_empty() => History::load(Path::new(home).join(history::HISTORY_FILE), size),
+        _ => History::new(size),
+    }
+}
+
+/// 非対話モード: 入力を最後まで (または exit まで) 実行する
+fn run(shell: &mut Shell, mut input: Input) -> i32 {
+    match source::run(shell, &mut input) {
+        Ok(status) | Err(Interrupt::Exit(status)) => status,
+        // 関数の外の break / continue / return は組み込みコマンドがエラーにする
+        Err(_) => shell.last_status,
+    }
+}
+
+/// 対話モード: プロンプトを表示してコマンドを 1 つずつ実行する。構文エラーではシェルを終了しない。
+fn interact(shell: &mut Shell, mut input: Input) -> i32 {
     let hostname = fs::read_to_string("/etc/hostname")
         .map(|s| s.trim().to_string())
         .unwrap_or_else(|_| "horiz".to_string());
@@ -12,57 +176,78 @@ fn main() {
     println!("--- Horiz-sh (Custom Enhanced) ---");
 
     loop {
-        let user = env::var("USER").unwrap_or_else(|_| "root".to_string());
+        // 前回のプロンプトの後に終了・停止したバックグラウンドのジョブを知らせる
+        shell.notify_jobs();
+
+        let user = shell.vars.get("USER").unwrap_or("root").to_string();
         let cwd = env::current_dir().unwrap_or_else(|_| Path::new("/").to_path_buf());
         let cwd_display = cwd.to_string_lossy();
 
-        print!("[{}@{}] {} # ", user, hostname, cwd_display);
-        io::stdout().flush().unwrap();
-
-        let mut input = String::new();
-        if io::stdin().read_line(&mut input).unwrap() == 0 {
-            break; // ファイル終端 (EOF)
+        if let Some(editor) = input.editor() {
+            editor.context = complete::Context::new(shell);
         }
+        let prompt = format!("[{}@{}] {} # ", user, hostname, cwd_display);
+        let program = match input.read_command(Some(&prompt)) {
+            None => break, // ファイル終端 (EOF)
+            Some(Ok(program)) => program,
+            Some(Err(e)) => {
+                input.report(&e);
+                shell.last_status = 2;
+                continue;
+            }
+        };
 
-        let input = input.trim();
-        if input.is_empty() {
-            continue;
+        // 入力を待つ間の Ctrl-C でこれから実行するコマンドを打ち切らないようにする
+        exec::clear_interrupt();
+        match shell.run_program(&program) {
+            Err(Interrupt::Exit(status)) => return status,
+            Err(Interrupt::Cancel) => {
+                // `^C` の後でプロンプトを次の行に表示する
+                eprintln!();
+                shell.last_status = 128 + libc::SIGINT;
+            }
+            _ => {}
         }
+    }
+    shell.last_status
+}
 
-        let parts: Vec<&str> = input.split_whitespace().collect();
-        let cmd = parts[0];
-        let args = &parts[1..];
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        match cmd {
-            "exit" => break,
-            "cd" => {
-                let new_dir = args.get(0).copied().unwrap_or("/");
-                if let Err(e) = env::set_current_dir(Path::new(new_dir)) {
-                    eprintln!("cd: {}", e);
-                }
-            }
-            "whoami" => {
-                println!("{}", user);
-            }
-            "version" => {
-                println!("HorizOS Shell v1.2.1 (Custom Ownership Edition)");
-            }
-            _ => {
-                match Command::new(cmd).args(args).status() {
-                    Ok(_) => {}
-                    Err(e) => {
-                        // /bin 下を確認し、既存のユーティリティがあるか試行
-                        let bin_cmd = format!("/bin/{}", cmd);
-                        if Path::new(&bin_cmd).exists() {
-                            if let Err(e2) = Command::new(&bin_cmd).args(args).status() {
-                                eprintln!("{}: 実行エラー ({})", cmd, e2);
-                            }
-                        } else {
-                            eprintln!("{}: コマンドが見つかりません ({})", cmd, e);
-                        }
-                    }
-                }
+    fn args(s: &str) -> Args {
+        parse_args(&s.split_whitespace().map(String::from).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(args(""), Args::default());
+        assert_eq!(args("script.sh a -x"), Args { operands: vec!["script.sh".into(), "a".into(), "-x".into()], ..Default::default() });
+        assert_eq!(
+            args("-eu +x -o pipefail -c cmd name a"),
+            Args {
+                command: Some("cmd".into()),
+                options: vec![
+                    ("errexit".into(), true),
+                    ("nounset".into(), true),
+                    ("xtrace".into(), false),
+                    ("pipefail".into(), true),
+                ],
+                operands: vec!["name".into(), "a".into()],
+                ..Default::default()
             }
-        }
+        );
+        // -c の後のオプションも解析する
+        assert_eq!(args("-c -e cmd").command, Some("cmd".into()));
+        assert_eq!(args("-is -- -a").operands, ["-a"]);
+        assert!(args("-s").stdin);
+        assert_eq!(args("-n script.sh").options, [("noexec".to_string(), true)]);
+
+        let error = |s: &str| parse_args(&s.split_whitespace().map(String::from).collect::<Vec<_>>()).unwrap_err();
+        assert_eq!(error("-c"), "-c にはコマンドを指定してください");
+        assert_eq!(error("-q"), "Unknown argument: -q");
+        assert_eq!(error("-o nothing"), "Unknown option: nothing");
+        assert_eq!(error("-o"), "Missing value for -o");
     }
 }
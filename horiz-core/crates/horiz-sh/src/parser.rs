@@ -0,0 +1,701 @@
+// --- 構文解析 ---
+//
+// トークン列を再帰下降で構文木 (ast) に変換する。
+//
+//   program  := list
+//   list     := newline* (and_or (';' | '&' | newline) newline*)* and_or?   (')' ';;' と終わりの予約語の前で終わる)
+//   and_or   := pipeline (('&&' | '||') newline* pipeline)*
+//   pipeline := '!'? command ('|' newline* command)*
+//   command  := simple | compound redirect* | NAME '(' ')' newline* compound redirect*
+//   compound := '{' list '}' | '(' list ')'
+//             | 'if' list 'then' list ('elif' list 'then' list)* ('else' list)? 'fi'
+//             | ('while' | 'until') list 'do' list 'done'
+//             | 'for' NAME newline* ('in' WORD* (';' | newline))? newline* 'do' list 'done'
+//             | 'case' WORD newline* 'in' newline* (case_item)* 'esac'
+//   case_item := '('? WORD ('|' WORD)* ')' list (';;' newline*)?
+//   simple   := (ASSIGNMENT | redirect)* (WORD | redirect)*   (少なくとも 1 つ)
+//   redirect := IO_NUMBER? ('<' | '>' | '>|' | '>>' | '<>' | '<&' | '>&' | '&>') WORD
+//             | IO_NUMBER? HEREDOC
+
+use std::fmt;
+
+use std::rc::Rc;
+
+use crate::ast::{
+    AndOr, Assignment, CaseItem, Command, CompoundCommand, Connector, List, Pipeline, Program, Redirect, RedirectKind,
+    SimpleCommand,
+};
+use crate::lexer::{self, LexError, Op, Token, Word, WordPart};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// 入力が途中で終わっている (対話モードでは続きの行を読む)
+    Incomplete,
+    /// 予期しないトークン
+    Unexpected(String),
+    /// `${...}` の書式が正しくない
+    BadSubstitution(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Incomplete => write!(f, "予期しない入力の終わりです"),
+            ParseError::Unexpected(token) => write!(f, "予期しないトークン `{}` があります", token),
+            ParseError::BadSubstitution(text) => write!(f, "{}: 不正な置換です", text),
+        }
+    }
+}
+
+impl From<LexError> for ParseError {
+    fn from(e: LexError) -> Self {
+        match e {
+            LexError::Incomplete => ParseError::Incomplete,
+            LexError::BadSubstitution(text) => ParseError::BadSubstitution(text),
+            LexError::Unexpected(token) => ParseError::Unexpected(token),
+        }
+    }
+}
+
+/// 並びの終わりを表す予約語
+const TERMINATORS: &[&str] = &["}", "then", "elif", "else", "fi", "do", "done", "esac"];
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(word) => word.unquoted(),
+        Token::HereDoc(heredoc) => if heredoc.strip_tabs { "<<-" } else { "<<" }.to_string(),
+        Token::IoNumber(n) => n.to_string(),
+        Token::Op(op) => op.to_string(),
+        Token::Newline => "改行".to_string(),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat_op(&mut self, op: Op) -> bool {
+        if self.peek() == Some(&Token::Op(op)) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.peek() == Some(&Token::Newline) {
+            self.pos += 1;
+        }
+    }
+
+    /// コマンドの先頭で予約語 (`{` `}` `!`) として扱うクォートされていない単語
+    fn peek_reserved(&self, reserved: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(word)) if word.as_literal() == Some(reserved))
+    }
+
+    /// 予約語を読む。なければ構文エラー
+    fn expect_reserved(&mut self, reserved: &str) -> Result<(), ParseError> {
+        if !self.peek_reserved(reserved) {
+            return Err(self.unexpected());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn at_list_end(&self) -> bool {
+        match self.peek() {
+            None | Some(Token::Op(Op::RParen | Op::DSemi)) => true,
+            Some(Token::Word(word)) => word.as_literal().is_some_and(|w| TERMINATORS.contains(&w)),
+            _ => false,
+        }
+    }
+
+    fn unexpected(&self) -> ParseError {
+        match self.peek() {
+            Some(token) => ParseError::Unexpected(describe(token)),
+            None => ParseError::Incomplete,
+        }
+    }
+
+    fn program(&mut self) -> Result<Program, ParseError> {
+        let list = self.list()?;
+        match self.peek() {
+            None => Ok(list),
+            Some(_) => Err(self.unexpected()),
+        }
+    }
+
+    /// 複合コマンドの終わり (`}` `)` `fi` `done` など) か入力の終わりまで
+    fn list(&mut self) -> Result<List, ParseError> {
+        let mut list = Vec::new();
+        self.skip_newlines();
+        while !self.at_list_end() {
+            list.push(self.and_or()?);
+            match self.peek() {
+                Some(Token::Newline) => self.skip_newlines(),
+                Some(Token::Op(Op::Amp)) => {
+                    if let Some(and_or) = list.last_mut() {
+                        and_or.background = true;
+                    }
+                    self.pos += 1;
+                    self.skip_newlines();
+                }
+                Some(Token::Op(Op::Semi)) => {
+                    self.pos += 1;
+                    self.skip_newlines();
+                }
+                _ => break,
+            }
+        }
+        Ok(list)
+    }
+
+    /// 空でない list と、それを閉じる予約語
+    fn compound_list(&mut self, close: &str) -> Result<List, ParseError> {
+        let list = self.nonempty_list()?;
+        self.expect_reserved(close)?;
+        Ok(list)
+    }
+
+    fn nonempty_list(&mut self) -> Result<List, ParseError> {
+        let list = self.list()?;
+        if list.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(list)
+    }
+
+    fn and_or(&mut self) -> Result<AndOr, ParseError> {
+        let first = self.pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let connector = if self.eat_op(Op::AndIf) {
+                Connector::And
+            } else if self.eat_op(Op::OrIf) {
+                Connector::Or
+            } else {
+                break;
+            };
+            self.skip_newlines();
+            rest.push((connector, self.pipeline()?));
+        }
+        Ok(AndOr { first, rest, background: false })
+    }
+
+    fn pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let negate = self.peek_reserved("!");
+        if negate {
+            self.pos += 1;
+        }
+        let mut commands = vec![self.command()?];
+        while self.eat_op(Op::Pipe) {
+            self.skip_newlines();
+            commands.push(self.command()?);
+        }
+        Ok(Pipeline { negate, commands })
+    }
+
+    fn command(&mut self) -> Result<Command, ParseError> {
+        if let Some(name) = self.function_name() {
+            self.pos += 3;
+            self.skip_newlines();
+            let body = self.command()?;
+            if !matches!(body, Command::Compound(..)) {
+                return Err(ParseError::Unexpected(name));
+            }
+            return Ok(Command::FunctionDef { name, body: Rc::new(body) });
+        }
+        let reserved = match self.peek() {
+            Some(Token::Word(word)) => word.as_literal().unwrap_or("").to_string(),
+            _ => String::new(),
+        };
+        let compound = match reserved.as_str() {
+            "{" => {
+                self.pos += 1;
+                CompoundCommand::Group(self.compound_list("}")?)
+            }
+            "if" => self.if_clause()?,
+            "while" | "until" => {
+                self.pos += 1;
+                let condition = self.compound_list("do")?;
+                let body = self.compound_list("done")?;
+                CompoundCommand::Loop { until: reserved == "until", condition, body }
+            }
+            "for" => self.for_clause()?,
+            "case" => self.case_clause()?,
+            _ if self.eat_op(Op::LParen) => {
+                let list = self.nonempty_list()?;
+                if !self.eat_op(Op::RParen) {
+                    return Err(self.unexpected());
+                }
+                CompoundCommand::Subshell(list)
+            }
+            _ => return self.simple_command(),
+        };
+        let mut redirects = Vec::new();
+        while let Some(redirect) = self.try_redirect()? {
+            redirects.push(redirect);
+        }
+        Ok(Command::Compound(compound, redirects))
+    }
+
+    /// `name ( )` で始まる関数定義であれば、その名前
+    fn function_name(&self) -> Option<String> {
+        let Some(Token::Word(word)) = self.peek() else {
+            return None;
+        };
+        let name = word.as_literal().filter(|name| lexer::is_name(name) && !TERMINATORS.contains(name))?;
+        let paren = |offset: usize, op: Op| self.tokens.get(self.pos + offset) == Some(&Token::Op(op));
+        (paren(1, Op::LParen) && paren(2, Op::RParen)).then(|| name.to_string())
+    }
+
+    fn if_clause(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.pos += 1; // if
+        let mut branches = Vec::new();
+        loop {
+            let condition = self.compound_list("then")?;
+            let body = self.nonempty_list()?;
+            branches.push((condition, body));
+            if self.peek_reserved("elif") {
+                self.pos += 1;
+                continue;
+            }
+            let otherwise = if self.peek_reserved("else") {
+                self.pos += 1;
+                Some(self.nonempty_list()?)
+            } else {
+                None
+            };
+            self.expect_reserved("fi")?;
+            return Ok(CompoundCommand::If { branches, otherwise });
+        }
+    }
+
+    fn for_clause(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.pos += 1; // for
+        let name = match self.next() {
+            Some(Token::Word(word)) if word.as_literal().is_some_and(lexer::is_name) => word.unquoted(),
+            Some(token) => return Err(ParseError::Unexpected(describe(&token))),
+            None => return Err(ParseError::Incomplete),
+        };
+        self.skip_newlines();
+        let mut words = None;
+        if self.peek_reserved("in") {
+            self.pos += 1;
+            let mut list = Vec::new();
+            while let Some(Token::Word(word)) = self.peek() {
+                list.push(word.clone());
+                self.pos += 1;
+            }
+            words = Some(list);
+            if !self.eat_op(Op::Semi) && self.peek() != Some(&Token::Newline) {
+                return Err(self.unexpected());
+            }
+        } else {
+            self.eat_op(Op::Semi);
+        }
+        self.skip_newlines();
+        self.expect_reserved("do")?;
+        let body = self.compound_list("done")?;
+        Ok(CompoundCommand::For { name, words, body })
+    }
+
+    fn case_clause(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.pos += 1; // case
+        let word = match self.next() {
+            Some(Token::Word(word)) => word,
+            Some(token) => return Err(ParseError::Unexpected(describe(&token))),
+            None => return Err(ParseError::Incomplete),
+        };
+        self.skip_newlines();
+        self.expect_reserved("in")?;
+        self.skip_newlines();
+        let mut items = Vec::new();
+        while !self.peek_reserved("esac") {
+            self.eat_op(Op::LParen);
+            let mut patterns = Vec::new();
+            loop {
+                match self.peek() {
+                    Some(Token::Word(word)) => {
+                        patterns.push(word.clone());
+                        self.pos += 1;
+                    }
+                    _ => return Err(self.unexpected()),
+                }
+                if !self.eat_op(Op::Pipe) {
+                    break;
+                }
+            }
+            if !self.eat_op(Op::RParen) {
+                return Err(self.unexpected());
+            }
+            let body = self.list()?;
+            items.push(CaseItem { patterns, body });
+            if !self.eat_op(Op::DSemi) {
+                // 最後の項目は `;;` を省略できる
+                if !self.peek_reserved("esac") {
+                    return Err(self.unexpected());
+                }
+                break;
+            }
+            self.skip_newlines();
+        }
+        self.pos += 1; // esac
+        Ok(CompoundCommand::Case { word, items })
+    }
+
+    fn simple_command(&mut self) -> Result<Command, ParseError> {
+        let mut assignments = Vec::new();
+        let mut words = Vec::new();
+        let mut redirects = Vec::new();
+        loop {
+            if let Some(redirect) = self.try_redirect()? {
+                redirects.push(redirect);
+                continue;
+            }
+            let Some(Token::Word(_)) = self.peek() else {
+                break;
+            };
+            if let Some(Token::Word(word)) = self.next() {
+                // コマンド名より前の `NAME=value` は代入
+                match assignment(&word) {
+                    Some(assignment) if words.is_empty() => assignments.push(assignment),
+                    _ => words.push(word),
+                }
+            }
+        }
+        if assignments.is_empty() && words.is_empty() && redirects.is_empty() {
+            return Err(self.unexpected());
+        }
+        Ok(Command::Simple(SimpleCommand { assignments, words, redirects }))
+    }
+
+    /// 次がリダイレクトであれば読む
+    fn try_redirect(&mut self) -> Result<Option<Redirect>, ParseError> {
+        match self.peek() {
+            Some(Token::IoNumber(n)) => {
+                let fd = *n as i32;
+                self.pos += 1;
+                Ok(Some(self.redirect(Some(fd))?))
+            }
+            Some(Token::HereDoc(_)) => Ok(Some(self.redirect(None)?)),
+            Some(Token::Op(op)) if is_redirect_op(*op) => Ok(Some(self.redirect(None)?)),
+            _ => Ok(None),
+        }
+    }
+
+    fn redirect(&mut self, fd: Option<i32>) -> Result<Redirect, ParseError> {
+        let op = match self.peek() {
+            Some(Token::HereDoc(heredoc)) => {
+                let kind = RedirectKind::HereDoc(heredoc.clone());
+                self.pos += 1;
+                return Ok(Redirect { fd: fd.unwrap_or(0), kind });
+            }
+            Some(Token::Op(op)) if is_redirect_op(*op) => *op,
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        let Some(Token::Word(target)) = self.peek().cloned() else {
+            return Err(self.unexpected());
+        };
+        self.pos += 1;
+        let (default_fd, kind) = match op {
+            Op::Less => (0, RedirectKind::Input(target)),
+            Op::LessGreat => (0, RedirectKind::ReadWrite(target)),
+            Op::LessAnd => (0, RedirectKind::DupInput(target)),
+            Op::DGreat => (1, RedirectKind::Append(target)),
+            Op::GreatAnd => (1, RedirectKind::DupOutput(target)),
+            Op::AndGreat => (1, RedirectKind::OutputBoth(target)),
+            _ => (1, RedirectKind::Output(target)),
+        };
+        Ok(Redirect { fd: fd.unwrap_or(default_fd), kind })
+    }
+}
+
+/// クォートされていない `NAME=` で始まる単語を代入として解釈する
+fn assignment(word: &Word) -> Option<Assignment> {
+    let Some(WordPart::Literal(first)) = word.0.first() else {
+        return None;
+    };
+    let (name, rest) = first.split_once('=')?;
+    if !lexer::is_name(name) {
+        return None;
+    }
+    let mut value = Vec::new();
+    if !rest.is_empty() {
+        value.push(WordPart::Literal(rest.to_string()));
+    }
+    value.extend(word.0[1..].iter().cloned());
+    Some(Assignment { name: name.to_string(), value: Word(value) })
+}
+
+/// ヒアドキュメント以外のリダイレクト演算子 (区切り文字のない `<<` は構文エラーとする)
+fn is_redirect_op(op: Op) -> bool {
+    matches!(op, Op::Less | Op::Great | Op::Clobber | Op::DGreat | Op::LessGreat | Op::LessAnd | Op::GreatAnd | Op::AndGreat)
+}
+
+/// 入力全体を解析する
+pub fn parse(input: &str) -> Result<Program, ParseError> {
+    parse_tokens(lexer::tokenize(input)?)
+}
+
+/// 字句解析済みのトークン列を構文解析する (コマンド置換の中身)
+pub fn parse_tokens(tokens: Vec<Token>) -> Result<Program, ParseError> {
+    Parser { tokens, pos: 0 }.program()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn simple(command: &Command) -> &SimpleCommand {
+        match command {
+            Command::Simple(simple) => simple,
+            _ => panic!("単純コマンドではありません: {:?}", command),
+        }
+    }
+
+    /// 各パイプラインを、コマンドごとの単語の並びで表す
+    fn shape(input: &str) -> Vec<Vec<Vec<String>>> {
+        parse(input)
+            .unwrap()
+            .iter()
+            .map(|and_or| {
+                and_or.first.commands.iter().map(|c| simple(c).words.iter().map(|w| w.unquoted()).collect()).collect()
+            })
+            .collect()
+    }
+
+    /// 構文木を、区切りを正規化した文字列で表す
+    fn render(input: &str) -> String {
+        fn list(list: &List) -> String {
+            list.iter().map(and_or).collect::<Vec<_>>().join("; ")
+        }
+        fn and_or(and_or: &AndOr) -> String {
+            let mut out = pipeline(&and_or.first);
+            for (connector, p) in &and_or.rest {
+                out += if *connector == Connector::And { " && " } else { " || " };
+                out += &pipeline(p);
+            }
+            if and_or.background {
+                out += " &";
+            }
+            out
+        }
+        fn pipeline(pipeline: &Pipeline) -> String {
+            let commands: Vec<String> = pipeline.commands.iter().map(command).collect();
+            format!("{}{}", if pipeline.negate { "! " } else { "" }, commands.join(" | "))
+        }
+        fn words(words: &[Word]) -> String {
+            words.iter().map(|w| w.unquoted()).collect::<Vec<_>>().join(" ")
+        }
+        fn command(c: &Command) -> String {
+            match c {
+                Command::Simple(simple) => words(&simple.words),
+                Command::Compound(c, r) => format!("{}{}", compound(c), ">".repeat(r.len())),
+                Command::FunctionDef { name, body } => format!("{}() {}", name, command(body)),
+            }
+        }
+        fn compound(compound: &CompoundCommand) -> String {
+            match compound {
+                CompoundCommand::Group(l) => format!("{{ {}; }}", list(l)),
+                CompoundCommand::Subshell(l) => format!("({})", list(l)),
+                CompoundCommand::If { branches, otherwise } => {
+                    let branches: Vec<String> =
+                        branches.iter().map(|(c, b)| format!("{}; then {}; ", list(c), list(b))).collect();
+                    let otherwise = otherwise.as_ref().map_or(String::new(), |l| format!("else {}; ", list(l)));
+                    format!("if {}{}fi", branches.join("elif "), otherwise)
+                }
+                CompoundCommand::Loop { until, condition, body } => {
+                    format!("{} {}; do {}; done", if *until { "until" } else { "while" }, list(condition), list(body))
+                }
+                CompoundCommand::For { name, words: w, body } => match w {
+                    Some(w) => format!("for {} in {}; do {}; done", name, words(w), list(body)),
+                    None => format!("for {}; do {}; done", name, list(body)),
+                },
+                CompoundCommand::Case { word, items } => {
+                    let items: Vec<String> = items
+                        .iter()
+                        .map(|item| format!("{}) {};; ", words(&item.patterns).replace(' ', "|"), list(&item.body)))
+                        .collect();
+                    format!("case {} in {}esac", word.unquoted(), items.concat())
+                }
+            }
+        }
+        list(&parse(input).unwrap())
+    }
+
+    #[test]
+    fn test_pipelines() {
+        assert_eq!(shape("cat /var/log/system.log | grep ERROR"), [[vec!["cat", "/var/log/system.log"], vec!["grep", "ERROR"]]]);
+        assert_eq!(shape("a | b | c 'd e'").concat().len(), 3);
+        assert_eq!(shape("\n\na\n\nb | c\n"), vec![vec![vec!["a"]], vec![vec!["b"], vec!["c"]]]);
+        // | の後の改行は継続
+        assert_eq!(shape("a |\n\n b"), [[vec!["a"], vec!["b"]]]);
+        assert!(shape("").is_empty());
+    }
+
+    fn redirects(input: &str) -> Vec<(i32, String)> {
+        let program = parse(input).unwrap();
+        simple(&program[0].first.commands[0])
+            .redirects
+            .iter()
+            .map(|r| {
+                let target = match &r.kind {
+                    RedirectKind::Input(w) => format!("< {}", w.unquoted()),
+                    RedirectKind::Output(w) => format!("> {}", w.unquoted()),
+                    RedirectKind::Append(w) => format!(">> {}", w.unquoted()),
+                    RedirectKind::ReadWrite(w) => format!("<> {}", w.unquoted()),
+                    RedirectKind::DupInput(w) => format!("<& {}", w.unquoted()),
+                    RedirectKind::DupOutput(w) => format!(">& {}", w.unquoted()),
+                    RedirectKind::OutputBoth(w) => format!("&> {}", w.unquoted()),
+                    RedirectKind::HereDoc(h) => format!("<< {}", h.body),
+                };
+                (r.fd, target)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_redirects() {
+        assert_eq!(shape("echo a > out b"), [[vec!["echo", "a", "b"]]]);
+        assert_eq!(
+            redirects("cmd <in >out 2>>log 3<>rw 2>&1 >|f <&- &>all"),
+            [
+                (0, "< in".to_string()),
+                (1, "> out".into()),
+                (2, ">> log".into()),
+                (3, "<> rw".into()),
+                (2, ">& 1".into()),
+                (1, "> f".into()),
+                (0, "<& -".into()),
+                (1, "&> all".into()),
+            ]
+        );
+        assert_eq!(redirects("cat <<EOF | wc\nx\nEOF\n"), [(0, "<< x\n".to_string())]);
+        assert_eq!(redirects("cat 3<<EOF\nEOF\n"), [(3, "<< ".to_string())]);
+        // リダイレクトだけのコマンド
+        assert_eq!(redirects("> file"), [(1, "> file".to_string())]);
+        assert!(shape("> file")[0][0].is_empty());
+    }
+
+    #[test]
+    fn test_assignments() {
+        let program = parse("A=1 B='x y'$C cmd D=2 > f").unwrap();
+        let assignments: Vec<(&str, String)> = simple(&program[0].first.commands[0])
+            .assignments.iter().map(|a| (a.name.as_str(), a.value.unquoted())).collect();
+        assert_eq!(assignments, [("A", "1".to_string()), ("B", "x y${C}".to_string())]);
+        assert_eq!(shape("A=1 B='x y'$C cmd D=2 > f"), [[vec!["cmd", "D=2"]]]);
+        // 代入だけのコマンド。名前として正しくないものやクォートされたものは単語
+        assert_eq!(shape("A= B=2"), [[Vec::<String>::new()]]);
+        assert_eq!(shape("1A=x 'B'=y"), [[vec!["1A=x", "B=y"]]]);
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(render("a; b\nc;"), "a; b; c");
+        assert_eq!(render("a && b || c | d"), "a && b || c | d");
+        assert_eq!(render("a &&\n\n b ||\n c"), "a && b || c");
+        assert_eq!(render("! a | b && ! c"), "! a | b && ! c");
+        assert_eq!(render("a & b && c &\nd"), "a &; b && c &; d");
+        assert_eq!(render("{ a & }; (b &)"), "{ a &; }; (b &)");
+        // 予約語は単語の先頭でのみ
+        assert_eq!(render("echo ! { }"), "echo ! { }");
+        assert_eq!(render("'!' a"), "! a");
+    }
+
+    #[test]
+    fn test_compound() {
+        assert_eq!(render("{ a; b; }"), "{ a; b; }");
+        assert_eq!(render("{\na\nb\n} > f 2>&1 | c"), "{ a; b; }>> | c");
+        assert_eq!(render("(a && b) || (c; { d; })"), "(a && b) || (c; { d; })");
+        assert_eq!(render("(a)>f; x"), "(a)>; x");
+        assert_eq!(render("{ { a; }; }"), "{ { a; }; }");
+    }
+
+    #[test]
+    fn test_if() {
+        assert_eq!(render("if a; then b; fi"), "if a; then b; fi");
+        assert_eq!(render("if a\nthen\n b\nelif c; d; then e\nelse f; fi > out"), "if a; then b; elif c; d; then e; else f; fi>");
+        assert_eq!(render("if if a; then b; fi; then c; fi"), "if if a; then b; fi; then c; fi");
+        // 予約語は引数の位置では普通の単語
+        assert_eq!(render("if echo then fi; then :; fi"), "if echo then fi; then :; fi");
+    }
+
+    #[test]
+    fn test_loops() {
+        assert_eq!(render("while a; do b; done"), "while a; do b; done");
+        assert_eq!(render("until a\ndo\nb\ndone | c"), "until a; do b; done | c");
+        assert_eq!(render("for x in a 'b c'; do echo $x; done"), "for x in a b c; do echo ${x}; done");
+        assert_eq!(render("for x; do :; done"), "for x; do :; done");
+        assert_eq!(render("for x\ndo :; done"), "for x; do :; done");
+        assert_eq!(render("for x in; do :; done"), "for x in ; do :; done");
+        assert_eq!(render("for do in do; do :; done"), "for do in do; do :; done");
+    }
+
+    #[test]
+    fn test_case() {
+        assert_eq!(render("case $x in a|b) c;; *) d;; esac"), "case ${x} in a|b) c;; *) d;; esac");
+        assert_eq!(render("case x in\n(a) b\n;;\n esac"), "case x in a) b;; esac");
+        // 最後の項目の ;; は省略できる
+        assert_eq!(render("case x in a) b\nesac"), "case x in a) b;; esac");
+        assert_eq!(render("case x in esac"), "case x in esac");
+        assert_eq!(render("case x in a) ;; esac"), "case x in a) ;; esac");
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(render("f() { a; }"), "f() { a; }");
+        assert_eq!(render("f ( ) (a) > out"), "f() (a)>");
+        assert_eq!(render("f()\n\n{ a; }; f"), "f() { a; }; f");
+        assert_eq!(render("f() if a; then b; fi"), "f() if a; then b; fi");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(parse("a |"), Err(ParseError::Incomplete));
+        assert_eq!(parse("echo 'x"), Err(ParseError::Incomplete));
+        assert_eq!(parse("| a"), Err(ParseError::Unexpected("|".into())));
+        assert_eq!(parse("a | | b"), Err(ParseError::Unexpected("|".into())));
+        assert_eq!(parse("a |\n| b"), Err(ParseError::Unexpected("|".into())));
+        assert_eq!(parse("echo >"), Err(ParseError::Incomplete));
+        assert_eq!(parse("echo > | a"), Err(ParseError::Unexpected("|".into())));
+        assert_eq!(parse("cat <<\n"), Err(ParseError::Unexpected("<<".into())));
+        assert_eq!(parse("echo ${a/b}"), Err(ParseError::BadSubstitution("${a/b}".into())));
+        assert_eq!(parse("a &&"), Err(ParseError::Incomplete));
+        assert_eq!(parse("{ a; } }"), Err(ParseError::Unexpected("}".into())));
+        assert_eq!(parse("{ a }"), Err(ParseError::Incomplete));
+        assert_eq!(parse("(a"), Err(ParseError::Incomplete));
+        assert_eq!(parse("{ }"), Err(ParseError::Unexpected("}".into())));
+        assert_eq!(parse("()"), Err(ParseError::Unexpected(")".into())));
+        assert_eq!(parse("a; ; b"), Err(ParseError::Unexpected(";".into())));
+        assert_eq!(parse("& a"), Err(ParseError::Unexpected("&".into())));
+        assert_eq!(parse("a & ; b"), Err(ParseError::Unexpected(";".into())));
+        assert_eq!(parse("a && & b"), Err(ParseError::Unexpected("&".into())));
+        assert_eq!(parse("a && || b"), Err(ParseError::Unexpected("||".into())));
+        assert_eq!(parse("a)"), Err(ParseError::Unexpected(")".into())));
+        assert_eq!(parse("(a) b"), Err(ParseError::Unexpected("b".into())));
+        assert_eq!(parse("if a; then b; fi x"), Err(ParseError::Unexpected("x".into())));
+        assert_eq!(parse("if a; then b"), Err(ParseError::Incomplete));
+        assert_eq!(parse("if a; fi"), Err(ParseError::Unexpected("fi".into())));
+        assert_eq!(parse("if a; then fi"), Err(ParseError::Unexpected("fi".into())));
+        assert_eq!(parse("while a; done"), Err(ParseError::Unexpected("done".into())));
+        assert_eq!(parse("for 1x in a; do b; done"), Err(ParseError::Unexpected("1x".into())));
+        assert_eq!(parse("case x in a) b;; c"), Err(ParseError::Incomplete));
+        assert_eq!(parse("case x a) b;; esac"), Err(ParseError::Unexpected("a".into())));
+        assert_eq!(parse("f() a"), Err(ParseError::Unexpected("f".into())));
+        assert_eq!(parse("done"), Err(ParseError::Unexpected("done".into())));
+    }
+}
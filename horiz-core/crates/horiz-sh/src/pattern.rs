@@ -0,0 +1,202 @@
+// --- パターン照合 ---
+//
+// `case` や `${var#pattern}`、パス名展開で使うシェルのパターン。`*` は任意の文字列、`?` は任意の 1 文字、
+// `[abc]` `[a-z]` `[!a-z]` は文字の集合に一致する。`\` の次の文字は文字どおりに扱う
+// (展開の段階でクォートされていた文字は `\` でエスケープして渡す)。
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `[...]` (否定と、文字の範囲の並び)
+    Class { negate: bool, ranges: Vec<(char, char)> },
+}
+
+fn compile(pattern: &str) -> Vec<Token> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        i += 1;
+        match c {
+            '*' => tokens.push(Token::Star),
+            '?' => tokens.push(Token::Any),
+            '\\' if i < chars.len() => {
+                tokens.push(Token::Char(chars[i]));
+                i += 1;
+            }
+            '[' => match class(&chars[i..]) {
+                Some((token, len)) => {
+                    tokens.push(token);
+                    i += len;
+                }
+                // 閉じの `]` がなければ文字どおり
+                None => tokens.push(Token::Char('[')),
+            },
+            _ => tokens.push(Token::Char(c)),
+        }
+    }
+    tokens
+}
+
+/// `[` の直後から `]` までを読み、トークンと読んだ文字数を返す
+fn class(chars: &[char]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negate = matches!(chars.first(), Some('!' | '^'));
+    if negate {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let mut c = *chars.get(i)?;
+        i += 1;
+        // 先頭の `]` は文字として扱う
+        if c == ']' && !first {
+            return Some((Token::Class { negate, ranges }, i));
+        }
+        first = false;
+        if c == '\\' {
+            c = *chars.get(i)?;
+            i += 1;
+        }
+        if chars.get(i) == Some(&'-') && chars.get(i + 1).is_some_and(|&end| end != ']') {
+            let mut end = chars[i + 1];
+            i += 2;
+            if end == '\\' {
+                end = *chars.get(i)?;
+                i += 1;
+            }
+            ranges.push((c, end));
+        } else {
+            ranges.push((c, c));
+        }
+    }
+}
+
+fn match_tokens(tokens: &[Token], text: &[char]) -> bool {
+    // `*` の位置と、そこから試している文字の位置を覚えておき、失敗したら 1 文字ずらして再試行する
+    let (mut t, mut p) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        let matched = match tokens.get(p) {
+            Some(Token::Star) => {
+                backtrack = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some(Token::Char(c)) => *c == text[t],
+            Some(Token::Any) => true,
+            Some(Token::Class { negate, ranges }) => ranges.iter().any(|&(lo, hi)| lo <= text[t] && text[t] <= hi) != *negate,
+            None => false,
+        };
+        if matched {
+            t += 1;
+            p += 1;
+        } else if let Some((star, start)) = backtrack {
+            p = star + 1;
+            t = start + 1;
+            backtrack = Some((star, start + 1));
+        } else {
+            return false;
+        }
+    }
+    tokens[p..].iter().all(|token| *token == Token::Star)
+}
+
+/// パターンが文字列全体に一致するか
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    match_tokens(&compile(pattern), &text)
+}
+
+/// `\` のエスケープを取り除く (特別な文字を含まないパターンを文字列に戻す)
+pub fn unescape(pattern: &str) -> String {
+    let mut text = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+/// 先頭からパターンに一致する部分を取り除く (`longest` なら最長一致、そうでなければ最短一致)
+pub fn remove_prefix<'a>(value: &'a str, pattern: &str, longest: bool) -> &'a str {
+    let tokens = compile(pattern);
+    let chars: Vec<char> = value.chars().collect();
+    let mut ends: Vec<usize> = (0..=chars.len()).collect();
+    if longest {
+        ends.reverse();
+    }
+    match ends.into_iter().find(|&end| match_tokens(&tokens, &chars[..end])) {
+        Some(end) => &value[byte_offset(value, end)..],
+        None => value,
+    }
+}
+
+/// 末尾からパターンに一致する部分を取り除く
+pub fn remove_suffix<'a>(value: &'a str, pattern: &str, longest: bool) -> &'a str {
+    let tokens = compile(pattern);
+    let chars: Vec<char> = value.chars().collect();
+    let mut starts: Vec<usize> = (0..=chars.len()).collect();
+    if !longest {
+        starts.reverse();
+    }
+    match starts.into_iter().find(|&start| match_tokens(&tokens, &chars[start..])) {
+        Some(start) => &value[..byte_offset(value, start)],
+        None => value,
+    }
+}
+
+fn byte_offset(s: &str, chars: usize) -> usize {
+    s.char_indices().nth(chars).map_or(s.len(), |(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.rs.bak"));
+        assert!(matches("a*b*c", "aXXbYYbc"));
+        assert!(matches("?", "日"));
+        assert!(!matches("?", ""));
+        assert!(matches("*", ""));
+        assert!(matches("[a-c]x", "bx"));
+        assert!(matches("[!a-c]x", "dx"));
+        assert!(!matches("[!a-c]x", "ax"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[", "["));
+        // エスケープされた文字は文字どおり
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+        assert!(matches("[\\]]", "]"));
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape("a\\*b\\\\c"), "a*b\\c");
+        assert_eq!(unescape("日本\\"), "日本");
+    }
+
+    #[test]
+    fn test_remove() {
+        assert_eq!(remove_prefix("/usr/local/bin", "*/", false), "usr/local/bin");
+        assert_eq!(remove_prefix("/usr/local/bin", "*/", true), "bin");
+        assert_eq!(remove_suffix("archive.tar.gz", ".*", false), "archive.tar");
+        assert_eq!(remove_suffix("archive.tar.gz", ".*", true), "archive");
+        assert_eq!(remove_prefix("abc", "x", true), "abc");
+        assert_eq!(remove_suffix("日本語.txt", ".txt", false), "日本語");
+        assert_eq!(remove_prefix("abc", "", false), "abc");
+    }
+}
@@ -0,0 +1,173 @@
+// --- リダイレクト ---
+//
+// リダイレクトは書かれた順に適用する (`> out 2>&1` は両方を out へ、`2>&1 > out` は標準エラー出力を元の
+// 標準出力へ)。開いたファイルは close-on-exec 付きで開き、dup2 で対象の記述子へ複製してから閉じるため、
+// exec したコマンドには対象の記述子だけが引き継がれる。
+//
+// 子プロセスではそのまま適用する。シェル自身で実行する組み込みコマンドでは、変更する記述子を
+// 10 以上の番号へ退避 (close-on-exec 付き) しておき、実行後に元へ戻す。
+
+use std::ffi::CString;
+use std::io;
+
+use crate::ast::{Redirect, RedirectKind};
+use crate::exec::Shell;
+use crate::expand;
+use crate::lexer::Word;
+
+/// 退避した記述子の最小の番号 (利用者が使う 0〜9 と重ならないようにする)
+const SAVE_FD_MIN: i32 = 10;
+
+/// 新しく作成するファイルの許可属性 (umask が適用される)
+const CREATE_MODE: libc::c_uint = 0o666;
+
+/// 変更前の記述子 (元の番号と、退避先。元が閉じていた場合は None)
+#[derive(Default)]
+pub struct SavedFds(Vec<(i32, Option<i32>)>);
+
+impl SavedFds {
+    fn save(&mut self, fd: i32) {
+        if self.0.iter().any(|&(saved, _)| saved == fd) {
+            return;
+        }
+        let copy = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, SAVE_FD_MIN) };
+        self.0.push((fd, (copy >= 0).then_some(copy)));
+    }
+
+    /// 退避した記述子を元の番号へ戻す (後から変更したものから順に)
+    pub fn restore(self) {
+        for (fd, copy) in self.0.into_iter().rev() {
+            unsafe {
+                match copy {
+                    Some(copy) => {
+                        libc::dup2(copy, fd);
+                        libc::close(copy);
+                    }
+                    None => {
+                        libc::close(fd);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 記述子の付け替え方
+enum Action {
+    /// 開いたファイル (またはヒアドキュメント) を対象の記述子へ移す
+    Open(i32),
+    /// 既存の記述子を複製する
+    Dup(i32),
+    /// 対象の記述子を閉じる
+    Close,
+}
+
+/// リダイレクトを順に適用する。`save` が真の場合は変更前の記述子を退避し、戻り値で元に戻せるようにする。
+/// 失敗した場合はエラーメッセージを返す (退避していた記述子はその時点で元に戻す)。
+pub fn apply(shell: &mut Shell, redirects: &[Redirect], save: bool) -> Result<SavedFds, String> {
+    let mut saved = SavedFds::default();
+    for redirect in redirects {
+        if let Err(e) = apply_one(shell, redirect, save.then_some(&mut saved)) {
+            saved.restore();
+            return Err(e);
+        }
+    }
+    Ok(saved)
+}
+
+fn apply_one(shell: &mut Shell, redirect: &Redirect, mut saved: Option<&mut SavedFds>) -> Result<(), String> {
+    let mut targets = vec![redirect.fd];
+    let action = match &redirect.kind {
+        RedirectKind::Input(word) => Action::Open(open_word(shell, word, libc::O_RDONLY)?),
+        RedirectKind::Output(word) => Action::Open(open_word(shell, word, libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC)?),
+        RedirectKind::Append(word) => Action::Open(open_word(shell, word, libc::O_WRONLY | libc::O_CREAT | libc::O_APPEND)?),
+        RedirectKind::ReadWrite(word) => Action::Open(open_word(shell, word, libc::O_RDWR | libc::O_CREAT)?),
+        RedirectKind::OutputBoth(word) => {
+            targets = vec![1, 2];
+            Action::Open(open_word(shell, word, libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC)?)
+        }
+        RedirectKind::DupInput(word) | RedirectKind::DupOutput(word) => {
+            let target = expand::expand_word(shell, word).map_err(|e| e.to_string())?;
+            if target == "-" {
+                Action::Close
+            } else {
+                match target.parse::<i32>() {
+                    Ok(fd) if fd >= 0 && unsafe { libc::fcntl(fd, libc::F_GETFD) } != -1 => Action::Dup(fd),
+                    Ok(_) => return Err(format!("{}: 不正なファイル記述子です", target)),
+                    Err(_) => return Err(format!("{}: ファイル記述子の番号を指定してください", target)),
+                }
+            }
+        }
+        RedirectKind::HereDoc(heredoc) => {
+            let body = expand::expand_heredoc(shell, heredoc).map_err(|e| e.to_string())?;
+            Action::Open(heredoc_fd(&body).map_err(|e| format!("ヒアドキュメントを作成できません: {}", e))?)
+        }
+    };
+
+    for &target in &targets {
+        if let Some(saved) = saved.as_deref_mut() {
+            saved.save(target);
+        }
+        unsafe {
+            match action {
+                Action::Open(fd) | Action::Dup(fd) if fd == target => {
+                    // 開いた記述子がたまたま対象と同じ番号の場合は、close-on-exec だけを外す
+                    libc::fcntl(fd, libc::F_SETFD, 0);
+                }
+                Action::Open(fd) | Action::Dup(fd) => {
+                    libc::dup2(fd, target);
+                }
+                Action::Close => {
+                    libc::close(target);
+                }
+            }
+        }
+    }
+    if let Action::Open(fd) = action
+        && !targets.contains(&fd)
+    {
+        unsafe { libc::close(fd) };
+    }
+    Ok(())
+}
+
+/// 対象の単語を展開し、そのファイルを開く
+fn open_word(shell: &mut Shell, word: &Word, flags: i32) -> Result<i32, String> {
+    let path = expand::expand_word(shell, word).map_err(|e| e.to_string())?;
+    open(&path, flags)
+}
+
+/// ファイルを close-on-exec 付きで開く
+fn open(path: &str, flags: i32) -> Result<i32, String> {
+    let c_path = CString::new(path).map_err(|_| format!("{}: 不正なファイル名です", path))?;
+    let fd = unsafe { libc::open(c_path.as_ptr(), flags | libc::O_CLOEXEC, CREATE_MODE) };
+    if fd < 0 {
+        return Err(format!("{}: {}", path, io::Error::last_os_error()));
+    }
+    Ok(fd)
+}
+
+/// ヒアドキュメントの本文を書き込んだ、先頭から読める記述子を作る。
+/// パイプでは本文が大きい場合に書き込みが詰まるため、メモリ上の無名ファイル (memfd) を使う。
+fn heredoc_fd(body: &str) -> io::Result<i32> {
+    let fd = unsafe { libc::memfd_create(c"heredoc".as_ptr(), libc::MFD_CLOEXEC) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut written = 0;
+    let bytes = body.as_bytes();
+    while written < bytes.len() {
+        let n = unsafe { libc::write(fd, bytes[written..].as_ptr().cast(), bytes.len() - written) };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            unsafe { libc::close(fd) };
+            return Err(e);
+        }
+        written += n as usize;
+    }
+    unsafe { libc::lseek(fd, 0, libc::SEEK_SET) };
+    Ok(fd)
+}
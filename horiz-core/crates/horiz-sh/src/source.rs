@@ -0,0 +1,237 @@
+// --- 入力の読み込み ---
+//
+// 入力を 1 行ずつ読み、構文が完結するまで (クォートや複合コマンドが閉じられていない、行末が `|` など)
+// 続きの行を読んでから解析して実行する。前のコマンドを実行してから次のコマンドを読むため、スクリプトの
+// 途中で定義した関数や `exit` はそれ以降の行に作用し、後ろの行の構文エラーは前の行の実行を妨げない。
+//
+// 対話モードで標準入力が端末の場合は、行エディタで 1 行ずつ読む。それ以外の標準入力は 1 バイトずつ読む。まとめてバッファに読み込むと、スクリプトの続きを入力とする子プロセス
+// (`sh < script` の中の `cat` など) から入力を奪ってしまうため。
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::ast::Program;
+use crate::editor::Editor;
+use crate::exec::{ExecResult, Shell};
+use crate::parser::{self, ParseError};
+
+/// 構文エラーの終了ステータス
+const SYNTAX_ERROR_STATUS: i32 = 2;
+
+/// 続きの行を読むときのプロンプト
+const CONTINUATION_PROMPT: &str = "> ";
+
+pub struct Input {
+    source: Source,
+    /// エラーメッセージに表示する名前 (スクリプトのファイル名など)
+    name: Option<String>,
+    /// 読んだ行数
+    line: usize,
+}
+
+enum Source {
+    Stdin,
+    Text { text: String, pos: usize },
+    Terminal(Editor),
+}
+
+/// Ctrl-C で入力中の行が取り消された
+struct Cancelled;
+
+impl Input {
+    pub fn stdin() -> Self {
+        Input { source: Source::Stdin, name: None, line: 0 }
+    }
+
+    pub fn text(text: impl Into<String>, name: Option<String>) -> Self {
+        Input { source: Source::Text { text: text.into(), pos: 0 }, name, line: 0 }
+    }
+
+    /// 行エディタで端末から読む
+    pub fn terminal(editor: Editor) -> Self {
+        Input { source: Source::Terminal(editor), name: None, line: 0 }
+    }
+
+    /// 行エディタで読む場合はそのエディタ
+    pub fn editor(&mut self) -> Option<&mut Editor> {
+        match &mut self.source {
+            Source::Terminal(editor) => Some(editor),
+            _ => None,
+        }
+    }
+
+    /// ファイルの内容 (UTF-8 として正しくない部分は置き換える)
+    pub fn file(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let name = path.display().to_string();
+        Ok(Input::text(String::from_utf8_lossy(&bytes), Some(name)))
+    }
+
+    /// 最初の行に NUL 文字を含む (シェルスクリプトではない実行ファイル)
+    pub fn is_binary(&self) -> bool {
+        match &self.source {
+            Source::Text { text, .. } => text.lines().next().is_some_and(|line| line.contains('\0')),
+            Source::Stdin | Source::Terminal(_) => false,
+        }
+    }
+
+    /// `prompt` を表示して 1 行を改行まで読む (最後の行は改行がない場合もある)。EOF では None。
+    fn read_line(&mut self, prompt: Option<&str>) -> Result<Option<String>, Cancelled> {
+        if let Some(prompt) = prompt
+            && !matches!(self.source, Source::Terminal(_))
+        {
+            print!("{}", prompt);
+            let _ = io::stdout().flush();
+        }
+        let line = match &mut self.source {
+            Source::Text { text, pos } => {
+                let rest = &text[*pos..];
+                if rest.is_empty() {
+                    return Ok(None);
+                }
+                let len = rest.find('\n').map_or(rest.len(), |i| i + 1);
+                *pos += len;
+                rest[..len].to_string()
+            }
+            Source::Stdin => match read_stdin_line() {
+                Some(line) => line,
+                None => return Ok(None),
+            },
+            Source::Terminal(editor) => match editor.read_line(prompt.unwrap_or_default()) {
+                Ok(Some(line)) => line + "\n",
+                Ok(None) => return Ok(None),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => return Err(Cancelled),
+                Err(e) => {
+                    eprintln!("horiz-sh: 端末から読み込めません: {}", e);
+                    return Ok(None);
+                }
+            },
+        };
+        self.line += 1;
+        Ok(Some(line))
+    }
+
+    /// 構文が完結したコマンドを読んで解析する。`prompt` を指定した場合は、最初の行を読む前にそれを、
+    /// 続きの行を読む前に `> ` をプロンプトとして表示する。EOF では None。Ctrl-C で入力を取り消した
+    /// 場合は空のコマンドを返す。
+    pub fn read_command(&mut self, prompt: Option<&str>) -> Option<Result<Program, ParseError>> {
+        let Ok(first) = self.read_line(prompt) else {
+            return Some(Ok(Program::new()));
+        };
+        let mut input = first?;
+        loop {
+            match parser::parse(&input) {
+                Err(ParseError::Incomplete) => match self.read_line(prompt.map(|_| CONTINUATION_PROMPT)) {
+                    Ok(Some(line)) => input += &line,
+                    Ok(None) => return Some(Err(ParseError::Incomplete)),
+                    Err(Cancelled) => return Some(Ok(Program::new())),
+                },
+                result => return Some(result),
+            }
+        }
+    }
+
+    /// 構文エラーを、入力の名前と行番号を付けて表示する
+    pub fn report(&self, e: &ParseError) {
+        match &self.name {
+            Some(name) => eprintln!("horiz-sh: {}: {} 行目: 構文エラー: {}", name, self.line, e),
+            None => eprintln!("horiz-sh: 構文エラー: {}", e),
+        }
+    }
+}
+
+/// 標準入力から 1 バイトずつ、改行まで読む
+fn read_stdin_line() -> Option<String> {
+    let mut line = Vec::new();
+    loop {
+        let mut byte = 0u8;
+        let n = unsafe { libc::read(0, (&raw mut byte).cast(), 1) };
+        if n < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            eprintln!("horiz-sh: 標準入力を読み込めません: {}", e);
+            break;
+        }
+        if n == 0 {
+            break;
+        }
+        line.push(byte);
+        if byte == b'\n' {
+            break;
+        }
+    }
+    if line.is_empty() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&line).into_owned())
+}
+
+/// 非対話モードで入力を最後まで実行し、最後のコマンドの終了ステータスを返す。
+/// 構文エラーがあればそこで実行をやめ、ステータス 2 を返す。`set -n` では構文の検査だけを行う。
+pub fn run(shell: &mut Shell, input: &mut Input) -> ExecResult {
+    while let Some(program) = input.read_command(None) {
+        match program {
+            Ok(_) if shell.noexec && !shell.interactive => {}
+            Ok(program) => {
+                shell.run_program(&program)?;
+            }
+            Err(e) => {
+                input.report(&e);
+                shell.last_status = SYNTAX_ERROR_STATUS;
+                return Ok(SYNTAX_ERROR_STATUS);
+            }
+        }
+    }
+    Ok(shell.last_status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exec::Interrupt;
+
+    #[test]
+    fn test_read_command() {
+        let mut input = Input::text("a; b\nif x\nthen y\nfi\n\ncat <<EOF\nbody\nEOF\nlast", None);
+        let mut lines = Vec::new();
+        while let Some(program) = input.read_command(None) {
+            lines.push((program.unwrap().len(), input.line));
+        }
+        assert_eq!(lines, [(2, 1), (1, 4), (0, 5), (1, 8), (1, 9)]);
+
+        let mut input = Input::text("echo 'x\n", None);
+        assert_eq!(input.read_command(None), Some(Err(ParseError::Incomplete)));
+        assert_eq!(input.read_command(None), None);
+    }
+
+    #[test]
+    fn test_run() {
+        let mut shell = Shell::new(false);
+        // 前の行で定義した関数と変数を後の行で使える
+        let mut input = Input::text("f() { A=$1; }\nf x; B=$A\nfalse", None);
+        assert_eq!(run(&mut shell, &mut input), Ok(1));
+        assert_eq!(shell.vars.get("B"), Some("x"));
+
+        // 構文エラーの前の行は実行される
+        let mut input = Input::text("C=1\nfi\nC=2\n", Some("script".into()));
+        assert_eq!(run(&mut shell, &mut input), Ok(2));
+        assert_eq!(shell.vars.get("C"), Some("1"));
+        assert_eq!(input.line, 2);
+
+        let mut input = Input::text("exit 3\nD=1", None);
+        assert_eq!(run(&mut shell, &mut input), Err(Interrupt::Exit(3)));
+        assert_eq!(shell.vars.get("D"), None);
+
+        // `set -n` では実行せずに構文だけを検査する
+        shell.noexec = true;
+        let mut input = Input::text("exit 4\nD=$(echo x)\n", None);
+        assert_eq!(run(&mut shell, &mut input), Ok(shell.last_status));
+        assert_eq!(shell.vars.get("D"), None);
+        let mut input = Input::text("D=1\necho $(if)\n", None);
+        assert_eq!(run(&mut shell, &mut input), Ok(2));
+        assert_eq!(shell.vars.get("D"), None);
+    }
+}
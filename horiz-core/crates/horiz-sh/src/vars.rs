@@ -0,0 +1,145 @@
+// --- シェル変数 ---
+//
+// 起動時の環境変数はすべてエクスポート済みのシェル変数として取り込む。外部コマンドには
+// エクスポートされた変数だけを環境として渡す (シェル自身の環境 (std::env) は変更しない)。
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Var {
+    /// `export NAME` だけを実行した場合などは未設定
+    pub value: Option<String>,
+    pub exported: bool,
+    pub readonly: bool,
+}
+
+/// 読み取り専用の変数への代入・削除
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadonlyError(pub String);
+
+impl fmt::Display for ReadonlyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: 読み取り専用の変数です", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Variables {
+    vars: BTreeMap<String, Var>,
+}
+
+impl Variables {
+    /// 現在のプロセスの環境変数を取り込む
+    pub fn from_env() -> Self {
+        let mut vars = Variables::default();
+        for (name, value) in env::vars_os() {
+            let (Some(name), Some(value)) = (name.to_str(), value.to_str()) else {
+                continue;
+            };
+            vars.vars.insert(name.to_string(), Var { value: Some(value.to_string()), exported: true, readonly: false });
+        }
+        vars
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars.get(name)?.value.as_deref()
+    }
+
+    pub fn var(&self, name: &str) -> Option<&Var> {
+        self.vars.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), ReadonlyError> {
+        let var = self.vars.entry(name.to_string()).or_default();
+        if var.readonly {
+            return Err(ReadonlyError(name.to_string()));
+        }
+        var.value = Some(value.to_string());
+        Ok(())
+    }
+
+    pub fn unset(&mut self, name: &str) -> Result<(), ReadonlyError> {
+        if self.vars.get(name).is_some_and(|var| var.readonly) {
+            return Err(ReadonlyError(name.to_string()));
+        }
+        self.vars.remove(name);
+        Ok(())
+    }
+
+    pub fn export(&mut self, name: &str) {
+        self.vars.entry(name.to_string()).or_default().exported = true;
+    }
+
+    pub fn set_readonly(&mut self, name: &str) {
+        self.vars.entry(name.to_string()).or_default().readonly = true;
+    }
+
+    /// 一時的な代入 (`FOO=1 builtin`) の前の状態に戻す (読み取り専用かどうかは確認しない)
+    pub fn restore(&mut self, name: &str, var: Option<Var>) {
+        match var {
+            Some(var) => self.vars.insert(name.to_string(), var),
+            None => self.vars.remove(name),
+        };
+    }
+
+    /// 名前順のすべての変数
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Var)> {
+        self.vars.iter().map(|(name, var)| (name.as_str(), var))
+    }
+
+    /// 外部コマンドに渡す環境 (`NAME=value` の並び)
+    pub fn environ(&self) -> Vec<String> {
+        self.iter()
+            .filter(|(_, var)| var.exported)
+            .filter_map(|(name, var)| Some(format!("{}={}", name, var.value.as_deref()?)))
+            .collect()
+    }
+}
+
+/// 値をシングルクォートで囲む (`set` `export -p` などの出力を、シェルの入力として読み直せるようにする)
+pub fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+/// シェルの入力として特別な意味を持つ文字を含む場合だけ、値をクォートする (`set -x` の表示など)
+pub fn quote_if_needed(value: &str) -> String {
+    let plain = |c: char| c.is_alphanumeric() || "_-+./:=,@%^".contains(c);
+    match !value.is_empty() && value.chars().all(plain) {
+        true => value.to_string(),
+        false => quote(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_variables() {
+        let mut vars = Variables::default();
+        vars.set("A", "1").unwrap();
+        vars.set("B", "2").unwrap();
+        vars.export("B");
+        vars.export("C");
+        assert_eq!(vars.environ(), ["B=2"]);
+
+        vars.set_readonly("A");
+        assert_eq!(vars.set("A", "x"), Err(ReadonlyError("A".into())));
+        assert_eq!(vars.unset("A"), Err(ReadonlyError("A".into())));
+        assert_eq!(vars.get("A"), Some("1"));
+
+        let saved = vars.var("B").cloned();
+        vars.set("B", "tmp").unwrap();
+        vars.restore("B", saved);
+        assert_eq!(vars.get("B"), Some("2"));
+        vars.unset("B").unwrap();
+        assert_eq!(vars.get("B"), None);
+
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote_if_needed("/usr/bin:x=1"), "/usr/bin:x=1");
+        assert_eq!(quote_if_needed("a b"), "'a b'");
+        assert_eq!(quote_if_needed(""), "''");
+    }
+}
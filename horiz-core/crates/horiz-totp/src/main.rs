@@ -0,0 +1,148 @@
+// --- horiz-totp: 2 要素認証 (TOTP) の登録・削除 ---
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use horiz_auth::audit::audit_log;
+use horiz_auth::totp::{self, Algorithm, TotpConfig, TotpEntry};
+
+const USAGE: &str = "Usage: totp enroll [-f] [-a SHA1|SHA256] [-d DIGITS] [-p PERIOD] USER\n       totp remove USER";
+
+#[derive(Debug, PartialEq, Eq)]
+enum Command {
+    Enroll {
+        user: String,
+        /// `-f`: 既存の登録を置き換える
+        force: bool,
+        algorithm: Option<Algorithm>,
+        digits: Option<u32>,
+        period: Option<u64>,
+    },
+    Remove { user: String },
+}
+
+// --- カスタム引数パーサー ---
+fn parse_args(args: &[String]) -> Result<Command, String> {
+    let (sub, rest) = args.split_first().ok_or("サブコマンドを指定してください")?;
+    let mut user = None;
+    let mut force = false;
+    let mut algorithm = None;
+    let mut digits = None;
+    let mut period = None;
+
+    let mut iter = rest.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| iter.next().cloned().ok_or_else(|| format!("Missing value for {}", name));
+        match arg.as_str() {
+            "-f" if sub == "enroll" => force = true,
+            "-a" if sub == "enroll" => {
+                let v = value("-a")?;
+                algorithm = Some(Algorithm::from_name(&v).ok_or_else(|| format!("不明なアルゴリズム: {}", v))?);
+            }
+            "-d" if sub == "enroll" => {
+                let v = value("-d")?;
+                digits = Some(v.parse().ok().filter(|d| (6..=8).contains(d)).ok_or_else(|| format!("桁数は 6〜8 です: {}", v))?);
+            }
+            "-p" if sub == "enroll" => {
+                let v = value("-p")?;
+                period = Some(v.parse().ok().filter(|p| *p > 0).ok_or_else(|| format!("不正な期間: {}", v))?);
+            }
+            s if s.starts_with('-') => return Err(format!("Unknown argument: {}", s)),
+            s => {
+                if user.is_some() {
+                    return Err("Too many arguments".into());
+                }
+                user = Some(s.to_string());
+            }
+        }
+    }
+    let user = user.ok_or("ユーザー名を指定してください")?;
+    match sub.as_str() {
+        "enroll" => Ok(Command::Enroll { user, force, algorithm, digits, period }),
+        "remove" => Ok(Command::Remove { user }),
+        other => Err(format!("不明なサブコマンド: {}", other)),
+    }
+}
+
+fn run(command: Command) -> Result<(), String> {
+    let path = Path::new(totp::TOTP_PATH);
+    match command {
+        Command::Enroll { user, force, algorithm, digits, period } => {
+            if horiz_auth::passwd::getpwnam(&user).map_err(|e| e.to_string())?.is_none() {
+                return Err(format!("ユーザー {} は存在しません", user));
+            }
+            let cfg = TotpConfig::load();
+            let algorithm = algorithm.unwrap_or(cfg.algorithm);
+            let key = totp::generate_key(algorithm).map_err(|e| e.to_string())?;
+            let entry = TotpEntry::new(&user, &key, algorithm, digits.unwrap_or(cfg.digits), period.unwrap_or(cfg.period));
+
+            let replaced = totp::enroll(path, entry.clone(), force).map_err(|e| match e.kind() {
+                std::io::ErrorKind::AlreadyExists => format!("{} (置き換えるには -f を指定してください)", e),
+                _ => e.to_string(),
+            })?;
+            audit_log(&format!("totp: {} user: {}", if replaced { "re-enrolled" } else { "enrolled" }, user));
+
+            println!("ユーザー {} の 2 要素認証を登録しました。認証アプリに以下を登録してください。", user);
+            println!();
+            println!("secret: {}", entry.secret);
+            println!("uri:    {}", entry.uri(&cfg.issuer));
+            Ok(())
+        }
+        Command::Remove { user } => {
+            if !totp::remove(path, &user).map_err(|e| e.to_string())? {
+                return Err(format!("ユーザー {} は登録されていません", user));
+            }
+            audit_log(&format!("totp: removed user: {}", user));
+            println!("ユーザー {} の 2 要素認証を削除しました。", user);
+            Ok(())
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|a| a == "-h" || a == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    let command = match parse_args(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("totp: {}", e);
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if unsafe { libc::geteuid() } != 0 {
+        eprintln!("totp: 権限がありません (root で実行してください)");
+        process::exit(1);
+    }
+    if let Err(e) = run(command) {
+        eprintln!("totp: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Result<Command, String> {
+        parse_args(&list.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(
+            args(&["enroll", "-f", "-a", "sha256", "-d", "8", "horiz"]).unwrap(),
+            Command::Enroll { user: "horiz".into(), force: true, algorithm: Some(Algorithm::Sha256), digits: Some(8), period: None }
+        );
+        assert_eq!(args(&["remove", "horiz"]).unwrap(), Command::Remove { user: "horiz".into() });
+        assert!(args(&["enroll", "-d", "9", "horiz"]).is_err());
+        assert!(args(&["enroll", "-a", "md5", "horiz"]).is_err());
+        assert!(args(&["remove", "-f", "horiz"]).is_err());
+        assert!(args(&["enroll"]).is_err());
+        assert!(args(&["show", "horiz"]).is_err());
+    }
+}
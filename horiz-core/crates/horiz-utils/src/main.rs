@@ -1,4 +1,3 @@
-use horiz_utils;
 use std::env;
 
 fn main() {
@@ -16,10 +15,8 @@ fn main() {
             }
         }
         "cat" => {
-            if args.len() > 1 {
-                if let Err(e) = horiz_utils::cat(args[1..].to_vec()) {
-                    eprintln!("cat: {}", e);
-                }
+            if args.len() > 1 && let Err(e) = horiz_utils::cat(args[1..].to_vec()) {
+                eprintln!("cat: {}", e);
             }
         }
         "echo" => {
@@ -11,7 +11,9 @@ rm -rf "$ROOTFS_DIR"
 mkdir -p "$ROOTFS_DIR"
 
 # 必須ディレクトリの作成 (FHS 準拠)
-mkdir -p "$ROOTFS_DIR"/{bin,dev,etc,proc,sys,tmp,var,root,home/horiz}
+for dir in bin dev etc proc sys tmp var root home/horiz; do
+    mkdir -p "$ROOTFS_DIR/$dir"
+done
 
 # Rustバイナリのビルド (musl ターゲットでスタティックリンク)
 case "$ARCH" in
@@ -58,18 +60,28 @@ cp "${TARGET_DIR}/horiz-init" "$BIN_DIR/init"
 cp "${TARGET_DIR}/horiz-sh" "$BIN_DIR/sh"
 cp "${TARGET_DIR}/horiz-pkg" "$BIN_DIR/horiz-pkg"
 cp "${TARGET_DIR}/horiz-utils" "$BIN_DIR/horiz-utils"
+cp "${TARGET_DIR}/horiz-passwd" "$BIN_DIR/passwd"
+cp "${TARGET_DIR}/horiz-account" "$BIN_DIR/horiz-account"
+cp "${TARGET_DIR}/horiz-doas" "$BIN_DIR/doas"
+cp "${TARGET_DIR}/horiz-totp" "$BIN_DIR/totp"
 
 # ユーティリティのシンボリックリンク作成
 ln -sf horiz-pkg "$BIN_DIR/pkg"
 ln -sf horiz-utils "$BIN_DIR/ls"
 ln -sf horiz-utils "$BIN_DIR/cat"
 ln -sf horiz-utils "$BIN_DIR/echo"
+ln -sf horiz-account "$BIN_DIR/useradd"
+ln -sf horiz-account "$BIN_DIR/userdel"
+ln -sf horiz-account "$BIN_DIR/usermod"
+ln -sf horiz-account "$BIN_DIR/groupadd"
 
 # rootfs スケルトン (設定ファイル等) の適用
 if [ -d "rootfs" ]; then
     echo "rootfs テンプレートを適用中..."
     cp -r rootfs/* "$ROOTFS_DIR/"
 fi
+# useradd がホームディレクトリにコピーする雛形
+mkdir -p "$ROOTFS_DIR/etc/skel"
 
 # HTTPS 通信のための CA 証明書の配置
 echo "CA 証明書を配置中..."
@@ -82,12 +94,26 @@ fi
 # 権限設定の強化 (VULN-004 の解消)
 echo "ファイル権限を強化中..."
 [ -f "$ROOTFS_DIR/etc/shadow" ] && chmod 600 "$ROOTFS_DIR/etc/shadow"
+[ -f "$ROOTFS_DIR/etc/horiz/totp" ] && chmod 600 "$ROOTFS_DIR/etc/horiz/totp"
 [ -f "$ROOTFS_DIR/etc/passwd" ] && chmod 644 "$ROOTFS_DIR/etc/passwd"
 [ -d "$ROOTFS_DIR/root" ] && chmod 700 "$ROOTFS_DIR/root"
 [ -d "$ROOTFS_DIR/tmp" ] && chmod 1777 "$ROOTFS_DIR/tmp"
 chmod 755 "$ROOTFS_DIR/bin"/*
+# 一般ユーザーが /etc/shadow を更新するため setuid root とする
+chmod 4755 "$ROOTFS_DIR/bin/passwd"
+chmod 4755 "$ROOTFS_DIR/bin/doas"
 [ -d "$ROOTFS_DIR/etc/horiz" ] && chmod 755 "$ROOTFS_DIR/etc/horiz"
 [ -f "$ROOTFS_DIR/etc/horiz/pubkey" ] && chmod 644 "$ROOTFS_DIR/etc/horiz/pubkey"
+# doas は root 以外が書き込める設定ファイルを読み込まない
+[ -f "$ROOTFS_DIR/etc/horiz/doas.conf" ] && chmod 644 "$ROOTFS_DIR/etc/horiz/doas.conf"
+# 認証スタックの設定は setuid の passwd / doas も読み込むため、root 以外は書き込めないようにする
+[ -f "$ROOTFS_DIR/etc/horiz/auth.conf" ] && chmod 644 "$ROOTFS_DIR/etc/horiz/auth.conf"
+[ -f "$ROOTFS_DIR/etc/horiz/tty.allow" ] && chmod 644 "$ROOTFS_DIR/etc/horiz/tty.allow"
+[ -f "$ROOTFS_DIR/etc/horiz/pwquality.conf" ] && chmod 644 "$ROOTFS_DIR/etc/horiz/pwquality.conf"
+# 過去のパスワードのハッシュ履歴は root のみが読み書きできる
+mkdir -p "$ROOTFS_DIR/etc/security"
+chmod 700 "$ROOTFS_DIR/etc/security"
+[ -f "$ROOTFS_DIR/etc/security/opasswd" ] && chmod 600 "$ROOTFS_DIR/etc/security/opasswd"
 
 echo "Rootfs パッケージング中..."
 cd "$ROOTFS_DIR"